@@ -0,0 +1,316 @@
+use std::{
+    fs,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{bail, Context};
+use rayon::prelude::*;
+use serde::Serialize;
+use stfs::{ExtractOptions, Progress, StfsPackage};
+use structopt::StructOpt;
+use walkdir::WalkDir;
+
+use super::{is_package, open_package};
+use crate::{
+    error::VerificationFailed,
+    output::{self, OutputFormat},
+    progress::{ProgressBars, ProgressOpt},
+};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Operation {
+    Verify,
+    Rehash,
+    Extract,
+}
+
+fn parse_operation(operation: &str) -> anyhow::Result<Operation> {
+    match operation {
+        "verify" => Ok(Operation::Verify),
+        "rehash" => Ok(Operation::Rehash),
+        "extract" => Ok(Operation::Extract),
+        _ => bail!("expected one of verify, rehash, or extract"),
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct BatchOpt {
+    /// Directory to search for packages
+    #[structopt(name = "DIR", parse(from_os_str))]
+    dir: PathBuf,
+
+    /// What to do with each package: verify, rehash (in place), or extract
+    #[structopt(long, parse(try_from_str = parse_operation))]
+    op: Operation,
+
+    /// Number of packages to process at once. Defaults to one per CPU.
+    #[structopt(short, long)]
+    jobs: Option<usize>,
+
+    /// Directory to extract into with `--op extract`. Each package's files are
+    /// written to a folder named after its path relative to DIR.
+    #[structopt(short, long, parse(from_os_str), default_value = "extracted")]
+    output: PathBuf,
+
+    /// Also write the summary report to this file as JSON
+    #[structopt(long, parse(from_os_str))]
+    report: Option<PathBuf>,
+
+    #[structopt(flatten)]
+    progress: ProgressOpt,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Status {
+    Ok,
+    /// The package's hashes didn't match
+    Failed,
+    /// The package couldn't be read, parsed, or written
+    Error,
+}
+
+#[derive(Debug, Serialize)]
+struct PackageResult {
+    path: PathBuf,
+    status: Status,
+    detail: String,
+}
+
+#[derive(Debug, Serialize)]
+struct BatchReport<'a> {
+    ok: usize,
+    failed: usize,
+    errors: usize,
+    packages: &'a [PackageResult],
+}
+
+/// Runs `op` on the package at `path`. Returns `None` for files which aren't
+/// packages.
+fn process(opt: &BatchOpt, path: &Path) -> anyhow::Result<Option<(Status, String)>> {
+    match opt.op {
+        Operation::Verify => {
+            let mmap = open_package(path)?;
+            if !is_package(&mmap) {
+                return Ok(None);
+            }
+
+            let mismatches = StfsPackage::parse_lazy(&mmap[..])?.verify_hashes()?;
+            if mismatches.is_empty() {
+                Ok(Some((Status::Ok, "OK".to_owned())))
+            } else {
+                let detail = format!("{} hashes did not match", mismatches.len());
+                Ok(Some((Status::Failed, detail)))
+            }
+        }
+        Operation::Rehash => {
+            let mut data =
+                fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+            if !is_package(&data) {
+                return Ok(None);
+            }
+
+            let original = data.clone();
+            stfs::rehash(&mut data)?;
+            if data == original {
+                return Ok(Some((Status::Ok, "unchanged".to_owned())));
+            }
+
+            fs::write(path, &data)
+                .with_context(|| format!("failed to write {}", path.display()))?;
+            Ok(Some((Status::Ok, "rehashed".to_owned())))
+        }
+        Operation::Extract => {
+            let mmap = open_package(path)?;
+            if !is_package(&mmap) {
+                return Ok(None);
+            }
+
+            let package = StfsPackage::try_from(&mmap[..])?;
+            let relative = path.strip_prefix(&opt.dir).unwrap_or(path);
+            let written = package.extract_entries(
+                &opt.output.join(relative),
+                package.walk_entries(),
+                &ExtractOptions::default(),
+            )?;
+            Ok(Some((Status::Ok, format!("{} files", written.len()))))
+        }
+    }
+}
+
+/// Runs `f`, reporting errors and panics as [`Status::Error`] so that one
+/// damaged package can't take down the rest of the batch
+fn isolate(
+    f: impl FnOnce() -> anyhow::Result<Option<(Status, String)>>,
+) -> Option<(Status, String)> {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(result)) => result,
+        Ok(Err(e)) => Some((Status::Error, format!("{:#}", e))),
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .copied()
+                .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("unknown error");
+            Some((Status::Error, format!("panicked: {}", message)))
+        }
+    }
+}
+
+/// Verifies, rehashes, or extracts every package under a directory in
+/// parallel, then prints a summary with one row per package
+pub fn run(opt: BatchOpt, format: OutputFormat) -> anyhow::Result<()> {
+    let mut files = Vec::new();
+    for entry in WalkDir::new(&opt.dir).sort_by_file_name() {
+        let entry = entry?;
+        if entry.file_type().is_file() {
+            files.push((entry.path().to_path_buf(), entry.metadata()?.len()));
+        }
+    }
+
+    let mut pool = rayon::ThreadPoolBuilder::new();
+    if let Some(jobs) = opt.jobs {
+        pool = pool.num_threads(jobs);
+    }
+    let pool = pool.build()?;
+
+    let bars = ProgressBars::new(&opt.progress, "files");
+    let bytes_total = files.iter().map(|(_, size)| size).sum();
+    let done = Mutex::new((0, 0));
+    let results: Vec<PackageResult> = pool.install(|| {
+        files
+            .par_iter()
+            .filter_map(|(path, size)| {
+                let result = isolate(|| process(&opt, path));
+
+                let mut done = done.lock().unwrap();
+                done.0 += 1;
+                done.1 += size;
+                let path_str = path.to_string_lossy();
+                bars.update(Progress {
+                    entry: Some(&path_str),
+                    entries_done: done.0,
+                    entries_total: files.len(),
+                    bytes_done: done.1,
+                    bytes_total,
+                });
+                drop(done);
+
+                let (status, detail) = result?;
+                Some(PackageResult {
+                    path: path.clone(),
+                    status,
+                    detail,
+                })
+            })
+            .collect()
+    });
+    bars.finish();
+
+    let count = |status| {
+        results
+            .iter()
+            .filter(|result| result.status == status)
+            .count()
+    };
+    let report = BatchReport {
+        ok: count(Status::Ok),
+        failed: count(Status::Failed),
+        errors: count(Status::Error),
+        packages: &results,
+    };
+
+    if let Some(path) = &opt.report {
+        fs::write(path, serde_json::to_string_pretty(&report)?)
+            .with_context(|| format!("failed to write {}", path.display()))?;
+    }
+
+    match format {
+        OutputFormat::Json => output::print_json(&report)?,
+        OutputFormat::Csv => output::print_csv(&results)?,
+        OutputFormat::Table => {
+            for result in &results {
+                let status = match result.status {
+                    Status::Ok => "OK",
+                    Status::Failed => "FAILED",
+                    Status::Error => "ERROR",
+                };
+                println!(
+                    "{:<6} {} ({})",
+                    status,
+                    result.path.display(),
+                    result.detail
+                );
+            }
+            println!(
+                "{} ok, {} failed, {} errors",
+                report.ok, report.failed, report.errors
+            );
+        }
+    }
+
+    if report.errors > 0 {
+        bail!("{} packages could not be processed", report.errors);
+    }
+    if report.failed > 0 {
+        return Err(
+            VerificationFailed(format!("{} packages failed verification", report.failed)).into(),
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stfs::StfsPackageBuilder;
+
+    #[test]
+    fn panics_are_reported_as_errors() {
+        let result = isolate(|| panic!("block {} is out of range", 7));
+        assert_eq!(
+            result,
+            Some((
+                Status::Error,
+                "panicked: block 7 is out of range".to_owned()
+            ))
+        );
+    }
+
+    #[test]
+    fn damaged_packages_dont_stop_the_batch() {
+        let dir = std::env::temp_dir().join(format!("acceleration-batch-{}", std::process::id()));
+        let packages = dir.join("packages");
+        fs::create_dir_all(&packages).unwrap();
+
+        let mut builder = StfsPackageBuilder::new();
+        builder.add_file("save.bin", vec![0x5A; 0x1800]).unwrap();
+        let good = builder.build().unwrap();
+        // Point the file table far past the end of the volume
+        let mut damaged = good.clone();
+        damaged[0x37E..0x381].copy_from_slice(&[0xFF, 0xFF, 0x00]);
+        fs::write(packages.join("good"), &good).unwrap();
+        fs::write(packages.join("damaged"), &damaged).unwrap();
+
+        // The edit breaks the header hash, and leaves nothing to extract
+        for (op, expected) in [("verify", Status::Failed), ("extract", Status::Error)] {
+            let opt = BatchOpt::from_iter_safe([
+                "batch".as_ref(),
+                packages.as_os_str(),
+                "--op".as_ref(),
+                op.as_ref(),
+                "--output".as_ref(),
+                dir.join("extracted").as_os_str(),
+            ])
+            .unwrap();
+            let status = |name| isolate(|| process(&opt, &packages.join(name))).map(|r| r.0);
+            assert_eq!(status("good"), Some(Status::Ok), "{}", op);
+            assert_eq!(status("damaged"), Some(expected), "{}", op);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,48 @@
+use std::{
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+};
+
+use anyhow::{anyhow, bail};
+use stfs::StfsPackage;
+use structopt::StructOpt;
+
+use super::open_package;
+
+#[derive(Debug, StructOpt)]
+pub struct CatOpt {
+    #[structopt(name = "FILE", parse(from_os_str))]
+    file_name: PathBuf,
+
+    /// Path of the file inside the package
+    #[structopt(name = "INTERNAL_PATH")]
+    internal_path: String,
+}
+
+pub fn run(opt: CatOpt) -> anyhow::Result<()> {
+    let mmap = open_package(&opt.file_name)?;
+    let package = StfsPackage::try_from(&mmap[..])?;
+
+    let path = opt.internal_path.replace('\\', "/");
+    let path = path.trim_matches('/');
+    let (_, entry) = package
+        .walk_entries()
+        .into_iter()
+        .find(|(entry_path, _)| entry_path == path)
+        .ok_or_else(|| anyhow!("{} does not exist in the package", path))?;
+    if entry.is_folder() {
+        bail!("{} is a folder", path);
+    }
+
+    let stdout = io::stdout();
+    let mut writer = BufWriter::new(stdout.lock());
+    let result = package
+        .extract_file(&mut writer, &entry)
+        .and_then(|_| writer.flush());
+
+    match result {
+        // The reader went away (e.g. `| head`), which isn't an error
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        result => Ok(result?),
+    }
+}
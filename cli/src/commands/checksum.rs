@@ -0,0 +1,66 @@
+use std::{
+    io::{self, Read, Write},
+    path::PathBuf,
+};
+
+use anyhow::bail;
+use sha1::{Digest, Sha1};
+use sha2::Sha256;
+use stfs::StfsPackage;
+use structopt::StructOpt;
+
+use super::open_package;
+
+#[derive(Debug, Copy, Clone)]
+enum Algorithm {
+    Sha1,
+    Sha256,
+}
+
+fn parse_algorithm(algorithm: &str) -> anyhow::Result<Algorithm> {
+    match algorithm.to_ascii_lowercase().as_str() {
+        "sha1" => Ok(Algorithm::Sha1),
+        "sha256" => Ok(Algorithm::Sha256),
+        _ => bail!("unknown hash algorithm {:?}", algorithm),
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ChecksumOpt {
+    #[structopt(name = "FILE", parse(from_os_str))]
+    file_name: PathBuf,
+
+    /// Hash algorithm, either `sha1` or `sha256`
+    #[structopt(short, long, default_value = "sha1", parse(try_from_str = parse_algorithm))]
+    algorithm: Algorithm,
+}
+
+/// Hashes everything `reader` produces without holding it all in memory
+fn digest<D: Digest + Write>(mut reader: impl Read) -> io::Result<Vec<u8>> {
+    let mut hasher = D::new();
+    io::copy(&mut reader, &mut hasher)?;
+    Ok(hasher.finalize().to_vec())
+}
+
+/// Prints a digest of every file in the package in the same format as
+/// `sha1sum`/`sha256sum`, with paths relative to the package root. The output
+/// can be checked against an extracted copy with `sha1sum -c`.
+pub fn run(opt: ChecksumOpt) -> anyhow::Result<()> {
+    let mmap = open_package(&opt.file_name)?;
+    let package = StfsPackage::try_from(&mmap[..])?;
+
+    for (path, entry) in package.walk_entries() {
+        if entry.is_folder() {
+            continue;
+        }
+
+        let reader = package.file_reader(&entry)?;
+        let digest = match opt.algorithm {
+            Algorithm::Sha1 => digest::<Sha1>(reader)?,
+            Algorithm::Sha256 => digest::<Sha256>(reader)?,
+        };
+        println!("{}  {}", super::hex(&digest).to_ascii_lowercase(), path);
+    }
+
+    Ok(())
+}
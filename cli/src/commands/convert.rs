@@ -0,0 +1,41 @@
+use std::path::PathBuf;
+
+use anyhow::bail;
+use stfs::PackageType;
+use structopt::StructOpt;
+
+use super::{load_key_vault, read_package, OutputOpt};
+
+#[derive(Debug, StructOpt)]
+pub struct ConvertOpt {
+    #[structopt(name = "FILE", parse(from_os_str))]
+    file_name: PathBuf,
+
+    /// Package type to convert to: `con`, `live`, or `pirs`
+    #[structopt(long)]
+    to: PackageType,
+
+    /// Decrypted key vault to sign the converted package with. Only CON
+    /// packages can be signed; without a key vault they're left unsigned.
+    #[structopt(long, parse(from_os_str))]
+    kv: Option<PathBuf>,
+
+    #[structopt(flatten)]
+    output: OutputOpt,
+}
+
+pub fn run(opt: ConvertOpt) -> anyhow::Result<()> {
+    if opt.kv.is_some() && opt.to != PackageType::Con {
+        bail!("only CON packages can be signed with a key vault");
+    }
+    let key_vault = opt.kv.as_deref().map(load_key_vault).transpose()?;
+
+    let mut data = read_package(&opt.file_name)?;
+
+    stfs::convert(&mut data, opt.to)?;
+    if let Some(key_vault) = &key_vault {
+        stfs::resign(&mut data, key_vault)?;
+    }
+
+    opt.output.write(&opt.file_name, &data)
+}
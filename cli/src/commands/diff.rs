@@ -0,0 +1,109 @@
+use std::path::PathBuf;
+
+use serde::Serialize;
+use stfs::{Change, EntrySummary, PackageDiff, StfsPackage};
+use structopt::StructOpt;
+
+use super::open_package;
+use crate::output::{self, OutputFormat};
+
+#[derive(Debug, StructOpt)]
+pub struct DiffOpt {
+    #[structopt(name = "A", parse(from_os_str))]
+    a: PathBuf,
+
+    #[structopt(name = "B", parse(from_os_str))]
+    b: PathBuf,
+
+    /// Only compare the contained files
+    #[structopt(long)]
+    files_only: bool,
+}
+
+/// A differing field or entry as printed by `--format csv`
+#[derive(Debug, Serialize)]
+struct DiffRow<'a> {
+    path: &'a str,
+    change: Change,
+    a: String,
+    b: String,
+}
+
+/// The differing fields and entries of `diff`
+fn rows(diff: &PackageDiff) -> Vec<DiffRow<'_>> {
+    let fields = diff
+        .metadata
+        .iter()
+        .filter(|field| field.differs())
+        .map(|field| DiffRow {
+            path: field.name,
+            change: Change::Modified,
+            a: field.a.clone(),
+            b: field.b.clone(),
+        });
+    let entries = diff
+        .entries
+        .iter()
+        .filter(|entry| entry.change() != Change::Unchanged)
+        .map(|entry| DiffRow {
+            path: &entry.path,
+            change: entry.change(),
+            a: entry.a.as_ref().map(describe).unwrap_or_default(),
+            b: entry.b.as_ref().map(describe).unwrap_or_default(),
+        });
+
+    fields.chain(entries).collect()
+}
+
+fn describe(summary: &EntrySummary) -> String {
+    match summary {
+        EntrySummary::Folder => "folder".to_owned(),
+        EntrySummary::File { size, digest } => {
+            format!("{} bytes, sha1 {}", size, super::hex(digest))
+        }
+    }
+}
+
+/// Prints the differences between two packages. Exits with status 1 if any
+/// were found, like `diff(1)`. `--format json` prints every field and entry of
+/// both packages, not just the differences.
+pub fn run(opt: DiffOpt, format: OutputFormat) -> anyhow::Result<()> {
+    let a_mmap = open_package(&opt.a)?;
+    let a = StfsPackage::try_from(&a_mmap[..])?;
+    let b_mmap = open_package(&opt.b)?;
+    let b = StfsPackage::try_from(&b_mmap[..])?;
+
+    let mut diff = stfs::diff(&a, &b)?;
+    if opt.files_only {
+        diff.metadata.clear();
+    }
+
+    match format {
+        OutputFormat::Json => output::print_json(&diff)?,
+        OutputFormat::Csv => output::print_csv(rows(&diff))?,
+        OutputFormat::Table => {
+            for field in diff.metadata.iter().filter(|field| field.differs()) {
+                println!("~ {}: {:?} -> {:?}", field.name, field.a, field.b);
+            }
+
+            for entry in &diff.entries {
+                match (entry.change(), &entry.a, &entry.b) {
+                    (Change::Removed, Some(a), _) => {
+                        println!("- {} ({})", entry.path, describe(a))
+                    }
+                    (Change::Modified, Some(a), Some(b)) => {
+                        println!("~ {} ({} -> {})", entry.path, describe(a), describe(b))
+                    }
+                    (Change::Added, _, Some(b)) => println!("+ {} ({})", entry.path, describe(b)),
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    if diff.differs() {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,66 @@
+use std::path::PathBuf;
+
+use anyhow::{bail, Context};
+use globset::{Glob, GlobSetBuilder};
+use stfs::{ExtractOptions, StfsPackage};
+use structopt::StructOpt;
+
+use super::open_package;
+
+#[derive(Debug, StructOpt)]
+pub struct ExtractOpt {
+    #[structopt(name = "FILE", parse(from_os_str))]
+    file_name: PathBuf,
+
+    /// Paths or glob patterns of the files to extract (e.g. `*.sav`, `savegames/**`).
+    /// Matching a folder extracts everything inside of it.
+    #[structopt(name = "PATTERN", required = true)]
+    patterns: Vec<String>,
+
+    /// Directory to extract files into
+    #[structopt(short, long, parse(from_os_str), default_value = ".")]
+    output: PathBuf,
+}
+
+pub fn run(opt: ExtractOpt) -> anyhow::Result<()> {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in &opt.patterns {
+        builder.add(Glob::new(pattern).with_context(|| format!("invalid pattern {:?}", pattern))?);
+    }
+    let patterns = builder.build()?;
+
+    let mmap = open_package(&opt.file_name)?;
+    let package = StfsPackage::try_from(&mmap[..])?;
+
+    // Folders which matched a pattern, so that their contents are included too
+    let mut matched_folders: Vec<String> = Vec::new();
+    let mut entries = Vec::new();
+    for (path, entry) in package.walk_entries() {
+        let in_matched_folder = matched_folders.iter().any(|folder| {
+            path.starts_with(folder.as_str()) && path[folder.len()..].starts_with('/')
+        });
+        if !in_matched_folder && !patterns.is_match(&path) {
+            continue;
+        }
+
+        if entry.is_folder() {
+            matched_folders.push(path.clone());
+        }
+        entries.push((path, entry));
+    }
+
+    if entries.is_empty() {
+        bail!("no files matched {:?}", opt.patterns);
+    }
+
+    let options = ExtractOptions {
+        copy_method: mmap.copy_method(),
+        ..Default::default()
+    };
+    let written = package.extract_entries(&opt.output, entries, &options)?;
+    for path in written {
+        println!("{}", path.display());
+    }
+
+    Ok(())
+}
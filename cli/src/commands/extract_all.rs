@@ -0,0 +1,68 @@
+use std::path::PathBuf;
+
+use stfs::{CancellationToken, ExtractOptions, OverwritePolicy, StfsPackage};
+use structopt::StructOpt;
+
+use super::open_package;
+use crate::progress::{ProgressBars, ProgressOpt};
+
+#[derive(Debug, StructOpt)]
+pub struct ExtractAllOpt {
+    #[structopt(name = "FILE", parse(from_os_str))]
+    file_name: PathBuf,
+
+    /// Directory to extract files into
+    #[structopt(short, long, parse(from_os_str), default_value = ".")]
+    output: PathBuf,
+
+    /// What to do with files that already exist: overwrite, skip, or error
+    #[structopt(long, default_value = "overwrite", parse(try_from_str = parse_overwrite_policy))]
+    overwrite: OverwritePolicy,
+
+    /// Write every file directly into the output directory instead of recreating
+    /// the package's folders
+    #[structopt(long)]
+    flatten: bool,
+
+    /// Set extracted files' modification times from the package's timestamps
+    #[structopt(long)]
+    preserve_timestamps: bool,
+
+    #[structopt(flatten)]
+    progress: ProgressOpt,
+}
+
+fn parse_overwrite_policy(policy: &str) -> anyhow::Result<OverwritePolicy> {
+    match policy {
+        "overwrite" => Ok(OverwritePolicy::Overwrite),
+        "skip" => Ok(OverwritePolicy::Skip),
+        "error" => Ok(OverwritePolicy::Error),
+        _ => anyhow::bail!("expected one of overwrite, skip, or error"),
+    }
+}
+
+pub fn run(opt: ExtractAllOpt) -> anyhow::Result<()> {
+    let mmap = open_package(&opt.file_name)?;
+    let package = StfsPackage::try_from(&mmap[..])?;
+
+    let options = ExtractOptions {
+        overwrite: opt.overwrite,
+        flatten: opt.flatten,
+        preserve_timestamps: opt.preserve_timestamps,
+        copy_method: mmap.copy_method(),
+    };
+    let bars = ProgressBars::new(&opt.progress, "files");
+    let written = package.extract_all_with_progress(
+        &opt.output,
+        &options,
+        &bars,
+        &CancellationToken::default(),
+    )?;
+    bars.finish();
+
+    for path in written {
+        println!("{}", path.display());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use std::path::PathBuf;
+
+use structopt::StructOpt;
+
+use super::{load_key_vault, read_package, OutputOpt};
+use crate::output::{self, OutputFormat};
+
+#[derive(Debug, StructOpt)]
+pub struct FixOpt {
+    #[structopt(name = "FILE", parse(from_os_str))]
+    file_name: PathBuf,
+
+    /// Decrypted key vault to resign the package with after repairing it
+    #[structopt(long, parse(from_os_str))]
+    kv: Option<PathBuf>,
+
+    #[structopt(flatten)]
+    output: OutputOpt,
+}
+
+/// Repairs damaged metadata, hash tables, and block allocation, then prints
+/// what was changed. With `--format json` the changes are printed as a list.
+pub fn run(opt: FixOpt, format: OutputFormat) -> anyhow::Result<()> {
+    let key_vault = opt.kv.as_deref().map(load_key_vault).transpose()?;
+
+    let mut data = read_package(&opt.file_name)?;
+
+    let repairs = stfs::repair(&mut data)?;
+    if let Some(key_vault) = &key_vault {
+        stfs::resign(&mut data, key_vault)?;
+    }
+
+    opt.output.write(&opt.file_name, &data)?;
+
+    // Keep the report out of the package when it's written to stdout
+    if opt.output.is_stdout() {
+        for repair in &repairs {
+            eprintln!("{}", repair);
+        }
+        return Ok(());
+    }
+
+    if format == OutputFormat::Json {
+        return output::print_json(&repairs);
+    }
+    if repairs.is_empty() {
+        println!("No problems found");
+    }
+    for repair in &repairs {
+        println!("{}", repair);
+    }
+
+    Ok(())
+}
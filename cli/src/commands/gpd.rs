@@ -0,0 +1,385 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail};
+use serde::Serialize;
+use stfs::{
+    Achievement, AchievementTotals, Namespace, ProfileSummary, StfsPackage, TitleId, TitleRecord,
+    Xdbf, DASHBOARD_GPD,
+};
+use structopt::StructOpt;
+
+use super::{format_title_id, is_package, open_package};
+use crate::output::{self, OutputFormat};
+
+#[derive(Debug, StructOpt)]
+pub enum GpdOpt {
+    /// Print the gamercard, title history, and achievements stored in a GPD
+    Show(ShowOpt),
+    /// Print the achievements of every title in a profile package
+    Achievements(AchievementsOpt),
+    /// Print the title history and gamerscore of a profile package
+    Titles(TitlesOpt),
+}
+
+#[derive(Debug, StructOpt)]
+pub struct ShowOpt {
+    /// A GPD file, or a profile package to read one from
+    #[structopt(name = "FILE", parse(from_os_str))]
+    file_name: PathBuf,
+
+    /// GPD to read when FILE is a package. Defaults to the dashboard GPD,
+    /// which holds the gamercard and every title played.
+    #[structopt(long)]
+    gpd: Option<String>,
+
+    /// Look up title names in the bundled title database
+    #[structopt(long)]
+    resolve_titles: bool,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct AchievementsOpt {
+    /// A profile package, or a single title's GPD file
+    #[structopt(name = "FILE", parse(from_os_str))]
+    file_name: PathBuf,
+
+    /// Only print the achievements of this title
+    #[structopt(long)]
+    title: Option<TitleId>,
+
+    /// Look up title names in the bundled title database
+    #[structopt(long)]
+    resolve_titles: bool,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct TitlesOpt {
+    /// A profile package, or its dashboard GPD file
+    #[structopt(name = "FILE", parse(from_os_str))]
+    file_name: PathBuf,
+
+    /// Look up title names in the bundled title database
+    #[structopt(long)]
+    resolve_titles: bool,
+}
+
+/// Everything decoded from a GPD, as printed by `--format json`
+#[derive(Debug, Serialize)]
+struct GpdReport {
+    /// Only present for GPDs with settings or title records, such as the
+    /// dashboard GPD
+    #[serde(skip_serializing_if = "Option::is_none")]
+    profile: Option<ProfileSummary>,
+    achievements: Vec<Achievement>,
+    achievement_totals: AchievementTotals,
+}
+
+/// A title's achievements, as printed by `gpd achievements --format json`
+#[derive(Debug, Serialize)]
+struct TitleAchievements {
+    title_id: TitleId,
+    name: String,
+    totals: AchievementTotals,
+    achievements: Vec<Achievement>,
+}
+
+/// One achievement, as printed by `gpd achievements --format csv`
+#[derive(Debug, Serialize)]
+struct AchievementRow<'a> {
+    title_id: TitleId,
+    id: u32,
+    name: &'a str,
+    gamerscore: u32,
+    unlocked: bool,
+    unlock_time: Option<String>,
+}
+
+/// The contents of the GPD named `name` in `package`
+fn read_gpd(package: &StfsPackage<'_>, name: &str) -> anyhow::Result<Vec<u8>> {
+    let (_, entry) = package
+        .walk_entries()
+        .into_iter()
+        .find(|(path, _)| path.eq_ignore_ascii_case(name))
+        .ok_or_else(|| anyhow!("{} does not exist in the package", name))?;
+    if entry.is_folder() {
+        bail!("{} is a folder", name);
+    }
+
+    let mut gpd = Vec::with_capacity(entry.file_size);
+    package.extract_file(&mut gpd, &entry)?;
+    Ok(gpd)
+}
+
+/// Reads `FILE` as a GPD, taking the GPD named `name` from it if it's a package
+fn read_file_gpd(file_name: &Path, name: &str) -> anyhow::Result<Vec<u8>> {
+    let mmap = open_package(file_name)?;
+    if is_package(&mmap) {
+        read_gpd(&StfsPackage::try_from(&mmap[..])?, name)
+    } else {
+        Ok(mmap.to_vec())
+    }
+}
+
+fn print_totals(totals: &AchievementTotals) {
+    println!(
+        "{}/{} achievements unlocked, {}/{} gamerscore",
+        totals.achievements_unlocked,
+        totals.achievement_count,
+        totals.gamerscore_unlocked,
+        totals.gamerscore_total
+    );
+}
+
+fn print_titles(titles: &[TitleRecord], resolve_titles: bool) {
+    println!(
+        "{:<10}{:>14}{:>12}  {:<21}NAME",
+        "TITLE ID", "ACHIEVEMENTS", "GAMERSCORE", "LAST PLAYED"
+    );
+    for title in titles {
+        let last_played = title
+            .last_played
+            .map(|time| time.to_string())
+            .unwrap_or_else(|| "never".to_owned());
+        println!(
+            "{:<10}{:>14}{:>12}  {:<21}{}",
+            format_title_id(title.title_id, resolve_titles),
+            format!(
+                "{}/{}",
+                title.achievements_unlocked, title.achievement_count
+            ),
+            format!("{}/{}", title.gamerscore_unlocked, title.gamerscore_total),
+            last_played,
+            title.name
+        );
+    }
+}
+
+fn print_achievements(achievements: &[Achievement]) {
+    println!("{:<10}{:>4}  {:<21}NAME", "ID", "GS", "UNLOCKED");
+    for achievement in achievements {
+        let unlocked = match (achievement.is_unlocked(), achievement.unlock_time) {
+            (true, Some(time)) => time.to_string(),
+            (true, None) => "yes".to_owned(),
+            (false, _) => String::new(),
+        };
+        let secret = if achievement.is_secret() {
+            " (secret)"
+        } else {
+            ""
+        };
+        println!(
+            "{:<10}{:>4}  {:<21}{}{}",
+            achievement.id, achievement.gamerscore, unlocked, achievement.name, secret
+        );
+    }
+}
+
+fn print(report: &GpdReport, resolve_titles: bool) {
+    if let Some(profile) = &report.profile {
+        let settings = &profile.settings;
+        let rows = [
+            ("Name", settings.name.clone()),
+            ("Motto", settings.motto.clone()),
+            ("Location", settings.location.clone()),
+            ("URL", settings.url.clone()),
+            ("Bio", settings.bio.clone()),
+            (
+                "Zone",
+                settings.gamer_zone.map(|zone| format!("{:?}", zone)),
+            ),
+            ("Region", settings.region.map(|region| region.to_string())),
+            (
+                "Gamerscore",
+                settings.gamerscore.map(|score| score.to_string()),
+            ),
+            (
+                "Reputation",
+                settings.reputation.map(|rep| format!("{:.1}%", rep)),
+            ),
+            ("Picture", settings.picture_key.clone()),
+        ];
+        for (label, value) in rows {
+            if let Some(value) = value {
+                println!("{:<12}{}", format!("{}:", label), value);
+            }
+        }
+
+        let totals = &profile.totals;
+        println!(
+            "{:<12}{}/{} achievements, {}/{} gamerscore",
+            "Totals:",
+            totals.achievements_unlocked,
+            totals.achievement_count,
+            totals.gamerscore_unlocked,
+            totals.gamerscore_total
+        );
+
+        if !profile.titles.is_empty() {
+            println!();
+            print_titles(&profile.titles, resolve_titles);
+        }
+    }
+
+    if report.achievements.is_empty() {
+        return;
+    }
+    if report.profile.is_some() {
+        println!();
+    }
+    print_totals(&report.achievement_totals);
+    print_achievements(&report.achievements);
+}
+
+pub fn run(opt: GpdOpt, format: OutputFormat) -> anyhow::Result<()> {
+    match opt {
+        GpdOpt::Show(opt) => show(opt, format),
+        GpdOpt::Achievements(opt) => achievements(opt, format),
+        GpdOpt::Titles(opt) => titles(opt, format),
+    }
+}
+
+/// Prints the gamercard, title history, and achievements stored in a GPD.
+/// `--format csv` prints the achievements, or the title records if the GPD
+/// has none.
+fn show(opt: ShowOpt, format: OutputFormat) -> anyhow::Result<()> {
+    let name = opt.gpd.as_deref().unwrap_or(DASHBOARD_GPD);
+    let data = read_file_gpd(&opt.file_name, name)?;
+    let xdbf = Xdbf::try_from(&data[..])?;
+
+    let has_profile = xdbf.namespace(Namespace::SETTING).next().is_some()
+        || xdbf.namespace(Namespace::TITLE).next().is_some();
+    let profile = has_profile
+        .then(|| ProfileSummary::new(&xdbf))
+        .transpose()?;
+    let achievements = xdbf.achievements().collect::<Result<Vec<_>, _>>()?;
+    let report = GpdReport {
+        profile,
+        achievement_totals: AchievementTotals::from_achievements(&achievements),
+        achievements,
+    };
+
+    match format {
+        OutputFormat::Json => output::print_json(&report)?,
+        OutputFormat::Csv => match &report.profile {
+            Some(profile) if report.achievements.is_empty() => output::print_csv(&profile.titles)?,
+            _ => output::print_csv(&report.achievements)?,
+        },
+        OutputFormat::Table => print(&report, opt.resolve_titles),
+    }
+
+    Ok(())
+}
+
+/// Reads the achievements of every title in the profile `package`'s play
+/// history, or just of `only`. Titles whose GPD is missing are skipped.
+fn profile_achievements(
+    package: &StfsPackage<'_>,
+    only: Option<TitleId>,
+) -> anyhow::Result<Vec<TitleAchievements>> {
+    let dashboard = read_gpd(package, DASHBOARD_GPD)?;
+    let mut titles = Vec::new();
+    for title in Xdbf::try_from(&dashboard[..])?.play_history()? {
+        if only.is_some_and(|only| only != title.title_id) {
+            continue;
+        }
+
+        let name = format!("{}.gpd", title.title_id);
+        let data = match read_gpd(package, &name) {
+            Ok(data) => data,
+            Err(e) => {
+                eprintln!("skipping {}: {:#}", title.title_id, e);
+                continue;
+            }
+        };
+        let achievements = Xdbf::try_from(&data[..])?
+            .achievements()
+            .collect::<Result<Vec<_>, _>>()?;
+        titles.push(TitleAchievements {
+            title_id: title.title_id,
+            name: title.name,
+            totals: AchievementTotals::from_achievements(&achievements),
+            achievements,
+        });
+    }
+
+    Ok(titles)
+}
+
+/// Prints each title's achievements. A bare GPD file is printed as a single
+/// title named after the file.
+fn achievements(opt: AchievementsOpt, format: OutputFormat) -> anyhow::Result<()> {
+    let mmap = open_package(&opt.file_name)?;
+    let titles = if is_package(&mmap) {
+        profile_achievements(&StfsPackage::try_from(&mmap[..])?, opt.title)?
+    } else {
+        let xdbf = Xdbf::try_from(&mmap[..])?;
+        let achievements = xdbf.achievements().collect::<Result<Vec<_>, _>>()?;
+        let stem = opt
+            .file_name
+            .file_stem()
+            .unwrap_or_default()
+            .to_string_lossy();
+        vec![TitleAchievements {
+            title_id: stem.parse().unwrap_or(TitleId(0)),
+            name: String::new(),
+            totals: AchievementTotals::from_achievements(&achievements),
+            achievements,
+        }]
+    };
+    if let Some(title) = opt.title {
+        if titles.is_empty() {
+            bail!("{} isn't in the profile's title history", title);
+        }
+    }
+
+    match format {
+        OutputFormat::Json => return output::print_json(&titles),
+        OutputFormat::Csv => {
+            let rows = titles.iter().flat_map(|title| {
+                title.achievements.iter().map(|achievement| AchievementRow {
+                    title_id: title.title_id,
+                    id: achievement.id,
+                    name: &achievement.name,
+                    gamerscore: achievement.gamerscore,
+                    unlocked: achievement.is_unlocked(),
+                    unlock_time: achievement.unlock_time.map(|time| time.to_string()),
+                })
+            });
+            return output::print_csv(rows);
+        }
+        OutputFormat::Table => {}
+    }
+
+    for (i, title) in titles.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        let title_id = format_title_id(title.title_id, opt.resolve_titles);
+        if title.name.is_empty() {
+            println!("{}", title_id);
+        } else {
+            println!("{} {}", title_id, title.name);
+        }
+        print_totals(&title.totals);
+        print_achievements(&title.achievements);
+    }
+
+    Ok(())
+}
+
+/// Prints every title in the profile's play history with its gamerscore
+fn titles(opt: TitlesOpt, format: OutputFormat) -> anyhow::Result<()> {
+    let data = read_file_gpd(&opt.file_name, DASHBOARD_GPD)?;
+    let titles = Xdbf::try_from(&data[..])?.play_history()?;
+
+    match format {
+        OutputFormat::Json => output::print_json(&titles)?,
+        OutputFormat::Csv => output::print_csv(&titles)?,
+        OutputFormat::Table => {
+            print_totals(&AchievementTotals::from_titles(&titles));
+            print_titles(&titles, opt.resolve_titles);
+        }
+    }
+
+    Ok(())
+}
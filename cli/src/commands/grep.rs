@@ -0,0 +1,149 @@
+use std::{io::Read, path::PathBuf};
+
+use anyhow::{bail, Context};
+use serde::Serialize;
+use stfs::StfsPackage;
+use structopt::StructOpt;
+
+use super::open_package;
+use crate::output::{self, OutputFormat};
+
+/// How much of each file is read at a time
+const CHUNK_SIZE: usize = 0x10000;
+
+#[derive(Debug, StructOpt)]
+pub struct GrepOpt {
+    #[structopt(name = "FILE", parse(from_os_str))]
+    file_name: PathBuf,
+
+    /// Text to search for, or hex bytes with `--hex`
+    #[structopt(name = "PATTERN")]
+    pattern: String,
+
+    /// Treat PATTERN as hex bytes, e.g. `DEADBEEF` or `de ad be ef`
+    #[structopt(long, conflicts_with = "ignore-case")]
+    hex: bool,
+
+    /// Ignore ASCII case when matching text
+    #[structopt(short, long)]
+    ignore_case: bool,
+
+    /// Search UTF-16 big-endian text, as used by most game strings
+    #[structopt(long, conflicts_with = "hex")]
+    utf16: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct Match<'a> {
+    path: &'a str,
+    offset: u64,
+}
+
+fn parse_hex_pattern(pattern: &str) -> anyhow::Result<Vec<u8>> {
+    let digits: String = pattern.chars().filter(|c| !c.is_whitespace()).collect();
+    let digits = digits
+        .strip_prefix("0x")
+        .or_else(|| digits.strip_prefix("0X"))
+        .unwrap_or(&digits);
+    if digits.is_empty() || !digits.len().is_multiple_of(2) || !digits.is_ascii() {
+        bail!("expected an even number of hex digits, got {:?}", pattern);
+    }
+
+    (0..digits.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&digits[i..i + 2], 16)
+                .with_context(|| format!("invalid hex pattern {:?}", pattern))
+        })
+        .collect()
+}
+
+/// Returns the offset of every occurrence of `needle` in `reader`. The input is
+/// read in chunks, keeping enough of the previous chunk to catch matches that
+/// span two of them.
+fn find_all(mut reader: impl Read, needle: &[u8], ignore_case: bool) -> std::io::Result<Vec<u64>> {
+    let mut offsets = Vec::new();
+    let mut window = Vec::with_capacity(CHUNK_SIZE + needle.len());
+    // File offset of window[0]
+    let mut window_start = 0u64;
+    let mut chunk = vec![0u8; CHUNK_SIZE];
+
+    loop {
+        let read = reader.read(&mut chunk)?;
+        if read == 0 {
+            break;
+        }
+        window.extend_from_slice(&chunk[..read]);
+
+        for (i, candidate) in window.windows(needle.len()).enumerate() {
+            let is_match = if ignore_case {
+                candidate.eq_ignore_ascii_case(needle)
+            } else {
+                candidate == needle
+            };
+            if is_match {
+                offsets.push(window_start + i as u64);
+            }
+        }
+
+        // Keep the tail which could still be the start of a match
+        let keep = (needle.len() - 1).min(window.len());
+        let consumed = window.len() - keep;
+        window.drain(..consumed);
+        window_start += consumed as u64;
+    }
+
+    Ok(offsets)
+}
+
+/// Searches every file in the package for a pattern, printing the path and
+/// offset of each match. Exits with status 1 if nothing matched, like grep.
+pub fn run(opt: GrepOpt, format: OutputFormat) -> anyhow::Result<()> {
+    let needle = if opt.hex {
+        parse_hex_pattern(&opt.pattern)?
+    } else if opt.utf16 {
+        opt.pattern
+            .encode_utf16()
+            .flat_map(|c| c.to_be_bytes())
+            .collect()
+    } else {
+        opt.pattern.clone().into_bytes()
+    };
+    if needle.is_empty() {
+        bail!("the pattern can't be empty");
+    }
+
+    let mmap = open_package(&opt.file_name)?;
+    let package = StfsPackage::try_from(&mmap[..])?;
+
+    let entries = package.walk_entries();
+    let mut matches = Vec::new();
+    for (path, entry) in &entries {
+        if entry.is_folder() {
+            continue;
+        }
+
+        let reader = package
+            .file_reader(entry)
+            .with_context(|| format!("failed to read {}", path))?;
+        let offsets = find_all(reader, &needle, opt.ignore_case)
+            .with_context(|| format!("failed to read {}", path))?;
+        matches.extend(offsets.into_iter().map(|offset| Match { path, offset }));
+    }
+
+    match format {
+        OutputFormat::Json => output::print_json(&matches)?,
+        OutputFormat::Csv => output::print_csv(&matches)?,
+        OutputFormat::Table => {
+            for m in &matches {
+                println!("{}:{:#X}", m.path, m.offset);
+            }
+        }
+    }
+
+    if matches.is_empty() {
+        std::process::exit(1);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,88 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::Context;
+use stfs::{ImageKind, StfsPackage};
+use structopt::StructOpt;
+
+use super::{load_key_vault, open_package, read_package, OutputOpt};
+
+#[derive(Debug, StructOpt)]
+pub enum ImagesOpt {
+    /// Write the thumbnail and title images to `thumbnail.png` and `title.png`
+    Export {
+        #[structopt(name = "FILE", parse(from_os_str))]
+        file_name: PathBuf,
+
+        /// Directory to write the images to
+        #[structopt(short, long, parse(from_os_str), default_value = ".")]
+        output: PathBuf,
+    },
+    /// Replace the thumbnail image, title image, or both with PNG files
+    Set {
+        #[structopt(name = "FILE", parse(from_os_str))]
+        file_name: PathBuf,
+
+        /// New thumbnail image, at most 16 KiB
+        #[structopt(long, parse(from_os_str), required_unless = "title")]
+        thumbnail: Option<PathBuf>,
+
+        /// New title image, at most 16 KiB
+        #[structopt(long, parse(from_os_str))]
+        title: Option<PathBuf>,
+
+        /// Decrypted key vault to resign the package with after editing it
+        #[structopt(long, parse(from_os_str))]
+        kv: Option<PathBuf>,
+
+        #[structopt(flatten)]
+        output: OutputOpt,
+    },
+}
+
+pub fn run(opt: ImagesOpt) -> anyhow::Result<()> {
+    match opt {
+        ImagesOpt::Export { file_name, output } => {
+            let mmap = open_package(&file_name)?;
+            let package = StfsPackage::parse_lazy(&mmap[..])?;
+
+            fs::create_dir_all(&output)
+                .with_context(|| format!("failed to create {}", output.display()))?;
+            for kind in ImageKind::ALL {
+                let image = kind.get(&package.header);
+                if image.is_empty() {
+                    continue;
+                }
+
+                let path = output.join(format!("{}.png", kind.name()));
+                fs::write(&path, image)
+                    .with_context(|| format!("failed to write {}", path.display()))?;
+                println!("{}", path.display());
+            }
+        }
+        ImagesOpt::Set {
+            file_name,
+            thumbnail,
+            title,
+            kv,
+            output,
+        } => {
+            let key_vault = kv.as_deref().map(load_key_vault).transpose()?;
+
+            let mut data = read_package(&file_name)?;
+
+            for (kind, path) in [(ImageKind::Thumbnail, thumbnail), (ImageKind::Title, title)] {
+                let Some(path) = path else { continue };
+                let image = fs::read(&path)
+                    .with_context(|| format!("failed to read {}", path.display()))?;
+                stfs::set_image(&mut data, kind, &image)?;
+            }
+            if let Some(key_vault) = &key_vault {
+                stfs::resign(&mut data, key_vault)?;
+            }
+
+            output.write(&file_name, &data)?;
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,112 @@
+use std::path::PathBuf;
+
+use stfs::{PackageInfo, StfsPackage};
+use structopt::StructOpt;
+
+use super::open_package;
+use crate::output::{self, OutputFormat};
+
+#[derive(Debug, StructOpt)]
+pub struct InfoOpt {
+    /// Print the metadata as JSON. Shorthand for `--format json`.
+    #[structopt(long)]
+    json: bool,
+
+    /// Look up the game's name from the title ID
+    #[structopt(long)]
+    resolve_titles: bool,
+
+    #[structopt(name = "FILE", parse(from_os_str))]
+    file_name: PathBuf,
+}
+
+/// The package's metadata, with the game's name only filled in when asked for
+pub(super) fn package_info(package: &StfsPackage<'_>, resolve_titles: bool) -> PackageInfo {
+    let mut info = PackageInfo::new(package);
+    if !resolve_titles {
+        info.resolved_title = None;
+    }
+
+    info
+}
+
+/// Prints the package metadata as a table
+fn print(info: &PackageInfo) {
+    let rows = [
+        ("Title", info.title_name.to_owned()),
+        ("Display name", info.display_name.to_owned()),
+        ("Description", info.display_description.to_owned()),
+        ("Publisher", info.publisher_name.to_owned()),
+        ("Package type", info.package_type.to_string()),
+        ("Signature", format!("{:?}", info.signature)),
+        ("Content type", info.content_type.to_string()),
+        (
+            "Title ID",
+            match &info.resolved_title {
+                Some(name) => format!("{} ({})", info.title_id, name),
+                None => info.title_id.to_string(),
+            },
+        ),
+        ("Media ID", info.media_id.to_string()),
+        (
+            "Version",
+            format!("{} (base {})", info.version, info.base_version),
+        ),
+        ("Console ID", info.console_id.clone()),
+        ("Profile ID", info.profile_id.clone()),
+        ("Device ID", info.device_id.clone()),
+        ("Content size", format!("{:#X} bytes", info.content_size)),
+        (
+            "Hash tables",
+            format!("{:?}, top level {:?}", info.sex, info.top_hash_table_level),
+        ),
+        (
+            "Data blocks",
+            format!(
+                "{} ({} free)",
+                info.allocated_block_count, info.unallocated_block_count
+            ),
+        ),
+    ];
+
+    for (label, value) in rows {
+        println!("{:<14}{}", format!("{}:", label), value);
+    }
+}
+
+pub fn run(opt: InfoOpt, format: OutputFormat) -> anyhow::Result<()> {
+    let mmap = open_package(&opt.file_name)?;
+    let package = StfsPackage::parse_lazy(&mmap[..])?;
+    let info = package_info(&package, opt.resolve_titles);
+
+    match format {
+        _ if opt.json => output::print_json(&info)?,
+        OutputFormat::Json => output::print_json(&info)?,
+        OutputFormat::Csv => output::print_csv([&info])?,
+        OutputFormat::Table => print(&info),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stfs::StfsPackageBuilder;
+
+    #[test]
+    fn damaged_file_tables_dont_stop_info() {
+        let mut builder = StfsPackageBuilder::new();
+        builder.title_id(0x4D5307E6);
+        let mut data = builder.build().unwrap();
+        // Point the file table past the end of the volume
+        data[0x37E..0x381].copy_from_slice(&[0xFF, 0xFF, 0x00]);
+
+        let package = StfsPackage::parse_lazy(&data).unwrap();
+        assert!(package.try_files().is_err());
+
+        let info = package_info(&package, false);
+        let json = serde_json::to_value(&info).unwrap();
+        assert_eq!(json["title_id"], 0x4D5307E6);
+    }
+}
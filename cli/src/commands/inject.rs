@@ -0,0 +1,42 @@
+use std::{fs, path::PathBuf};
+
+use anyhow::Context;
+use structopt::StructOpt;
+
+use super::{load_key_vault, read_package, OutputOpt};
+
+#[derive(Debug, StructOpt)]
+pub struct InjectOpt {
+    #[structopt(name = "FILE", parse(from_os_str))]
+    file_name: PathBuf,
+
+    /// Path of the file inside the package. Missing folders are created.
+    #[structopt(name = "INTERNAL_PATH")]
+    internal_path: String,
+
+    /// File whose contents should be written to the package
+    #[structopt(name = "LOCAL_FILE", parse(from_os_str))]
+    local_file: PathBuf,
+
+    /// Decrypted key vault to resign the package with after editing it
+    #[structopt(long, parse(from_os_str))]
+    kv: Option<PathBuf>,
+
+    #[structopt(flatten)]
+    output: OutputOpt,
+}
+
+pub fn run(opt: InjectOpt) -> anyhow::Result<()> {
+    let key_vault = opt.kv.as_deref().map(load_key_vault).transpose()?;
+
+    let mut data = read_package(&opt.file_name)?;
+    let contents = fs::read(&opt.local_file)
+        .with_context(|| format!("failed to read {}", opt.local_file.display()))?;
+
+    stfs::inject_file(&mut data, &opt.internal_path, &contents)?;
+    if let Some(key_vault) = &key_vault {
+        stfs::resign(&mut data, key_vault)?;
+    }
+
+    opt.output.write(&opt.file_name, &data)
+}
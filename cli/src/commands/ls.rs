@@ -0,0 +1,130 @@
+use std::path::PathBuf;
+
+use stfs::{EntryInfo, StfsFileEntry, StfsPackage};
+use structopt::StructOpt;
+
+use super::{format_title_id, open_package};
+use crate::output::{self, OutputFormat};
+
+#[derive(Debug, StructOpt)]
+pub struct LsOpt {
+    /// Show the folder hierarchy as a tree
+    #[structopt(long)]
+    tree: bool,
+
+    /// Include block and file offset details
+    #[structopt(short, long)]
+    long: bool,
+
+    /// Print the package's title ID and game name before its contents
+    #[structopt(long)]
+    resolve_titles: bool,
+
+    #[structopt(name = "FILE", parse(from_os_str))]
+    file_name: PathBuf,
+}
+
+/// `d` for folders, `c` for files stored in consecutive blocks
+fn flags(entry: &StfsFileEntry) -> String {
+    let folder = if entry.is_folder() { 'd' } else { '-' };
+    let consecutive = if entry.has_consecutive_blocks() {
+        'c'
+    } else {
+        '-'
+    };
+
+    format!("{}{}", folder, consecutive)
+}
+
+fn timestamp(entry: &StfsFileEntry) -> String {
+    entry
+        .created()
+        .map(|created| created.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|| "-".to_owned())
+}
+
+/// Computes the name column for each entry. In tree mode the name is prefixed
+/// with box-drawing characters connecting it to its parent folder.
+fn names(entries: &[(String, StfsFileEntry)], tree: bool) -> Vec<String> {
+    if !tree {
+        return entries.iter().map(|(path, _)| path.clone()).collect();
+    }
+
+    let depths: Vec<usize> = entries
+        .iter()
+        .map(|(path, _)| path.matches('/').count())
+        .collect();
+
+    // Walk backwards to find which entries are the last child of their folder
+    let mut is_last = vec![false; entries.len()];
+    let mut has_later_sibling = Vec::<bool>::new();
+    for (i, depth) in depths.iter().copied().enumerate().rev() {
+        has_later_sibling.resize(depth + 1, false);
+        is_last[i] = !has_later_sibling[depth];
+        has_later_sibling[depth] = true;
+    }
+
+    let mut ancestors_last = Vec::<bool>::new();
+    entries
+        .iter()
+        .zip(depths)
+        .zip(is_last)
+        .map(|(((_, entry), depth), is_last)| {
+            ancestors_last.truncate(depth);
+
+            let mut name = String::new();
+            for ancestor_last in &ancestors_last {
+                name.push_str(if *ancestor_last { "    " } else { "│   " });
+            }
+            name.push_str(if is_last { "└── " } else { "├── " });
+            name.push_str(&entry.name);
+
+            ancestors_last.push(is_last);
+            name
+        })
+        .collect()
+}
+
+pub fn run(opt: LsOpt, format: OutputFormat) -> anyhow::Result<()> {
+    let mmap = open_package(&opt.file_name)?;
+    let package = StfsPackage::try_from(&mmap[..])?;
+
+    if format != OutputFormat::Table {
+        let rows = EntryInfo::list(&package);
+        return match format {
+            OutputFormat::Json => output::print_json(&rows),
+            _ => output::print_csv(rows),
+        };
+    }
+
+    if opt.resolve_titles {
+        println!("Title: {}", format_title_id(package.header.title_id, true));
+    }
+
+    let entries = package.walk_entries();
+    let names = names(&entries, opt.tree);
+
+    for ((_, entry), name) in entries.iter().zip(names) {
+        let mut line = format!(
+            "{} {:>10} {:>19}",
+            flags(entry),
+            entry.file_size,
+            timestamp(entry)
+        );
+
+        if opt.long {
+            let offset = match package.block_to_addr(entry.starting_block_num) {
+                Ok(address) if entry.block_count > 0 => format!("{:#010X}", address),
+                _ => "-".to_owned(),
+            };
+            line.push_str(&format!(
+                " {:>8} {:>6} {:>10}",
+                entry.starting_block_num, entry.block_count, offset
+            ));
+        }
+
+        println!("{} {}", line, name);
+    }
+
+    Ok(())
+}
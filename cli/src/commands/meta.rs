@@ -0,0 +1,114 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use stfs::{MetadataField, PackageMetadata, StfsPackage};
+use structopt::StructOpt;
+
+use super::{load_key_vault, open_package, read_package, OutputOpt};
+
+#[derive(Debug, StructOpt)]
+pub enum MetaOpt {
+    /// Print the value of a single header field, or every field as JSON
+    Get {
+        #[structopt(name = "FILE", parse(from_os_str))]
+        file_name: PathBuf,
+
+        /// One of display_name, display_description, publisher_name, title_name,
+        /// title_id, media_id, console_id, profile_id, device_id, transfer_flags.
+        /// Omit it to print every field, along with the content type, as JSON
+        /// which `meta set --from` and `pack --metadata` accept.
+        #[structopt(name = "FIELD")]
+        field: Option<MetadataField>,
+    },
+    /// Overwrite one or more header fields
+    Set {
+        #[structopt(name = "FILE", parse(from_os_str))]
+        file_name: PathBuf,
+
+        /// Assignments such as `display_name="My Save"` or `title_id=4D5307E6`.
+        /// IDs are given in hex.
+        #[structopt(
+            name = "FIELD=VALUE",
+            required_unless = "from",
+            parse(try_from_str = parse_assignment)
+        )]
+        assignments: Vec<(MetadataField, String)>,
+
+        /// JSON file of fields to set, as printed by `meta get` without a
+        /// FIELD. Fields left out of the file are unchanged, and assignments
+        /// override the file.
+        #[structopt(long, parse(from_os_str))]
+        from: Option<PathBuf>,
+
+        /// Decrypted key vault to resign the package with after editing it
+        #[structopt(long, parse(from_os_str))]
+        kv: Option<PathBuf>,
+
+        #[structopt(flatten)]
+        output: OutputOpt,
+    },
+}
+
+fn parse_assignment(assignment: &str) -> anyhow::Result<(MetadataField, String)> {
+    let (field, value) = assignment
+        .split_once('=')
+        .with_context(|| format!("expected FIELD=VALUE, got {:?}", assignment))?;
+
+    Ok((field.parse()?, value.to_owned()))
+}
+
+/// Reads a JSON metadata file as printed by `meta get`
+pub fn read_metadata(path: &Path) -> anyhow::Result<PackageMetadata> {
+    let json =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+
+    serde_json::from_str(&json).with_context(|| format!("invalid metadata in {}", path.display()))
+}
+
+pub fn run(opt: MetaOpt) -> anyhow::Result<()> {
+    match opt {
+        MetaOpt::Get { file_name, field } => {
+            let mmap = open_package(&file_name)?;
+            let package = StfsPackage::parse_lazy(&mmap[..])?;
+
+            match field {
+                Some(field) => println!("{}", field.get(&package.header)),
+                None => println!(
+                    "{}",
+                    serde_json::to_string_pretty(&PackageMetadata::from_header(&package.header))?
+                ),
+            }
+        }
+        MetaOpt::Set {
+            file_name,
+            assignments,
+            from,
+            kv,
+            output,
+        } => {
+            let key_vault = kv.as_deref().map(load_key_vault).transpose()?;
+
+            let mut metadata = from
+                .as_deref()
+                .map(read_metadata)
+                .transpose()?
+                .unwrap_or_default();
+            for (field, value) in assignments {
+                *metadata.field_mut(field) = Some(value);
+            }
+
+            let mut data = read_package(&file_name)?;
+            metadata.apply(&mut data)?;
+            if let Some(key_vault) = &key_vault {
+                stfs::resign(&mut data, key_vault)?;
+            }
+
+            output.write(&file_name, &data)?;
+        }
+    }
+
+    Ok(())
+}
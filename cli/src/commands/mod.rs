@@ -0,0 +1,181 @@
+use std::{
+    fmt::Write as _,
+    fs::{self, File},
+    io::{self, Read, Write},
+    ops::Deref,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{bail, Context};
+use memmap::{Mmap, MmapOptions};
+use stfs::{CopyMethod, KeyVault, TitleId};
+use structopt::StructOpt;
+
+pub mod batch;
+pub mod cat;
+pub mod checksum;
+pub mod convert;
+pub mod diff;
+pub mod extract;
+pub mod extract_all;
+pub mod fix;
+pub mod gpd;
+pub mod grep;
+pub mod images;
+pub mod info;
+pub mod inject;
+pub mod ls;
+pub mod meta;
+#[cfg(all(unix, feature = "mount"))]
+pub mod mount;
+pub mod pack;
+pub mod regions;
+pub mod rehash;
+pub mod resign;
+pub mod rm;
+pub mod scan;
+pub mod serve;
+pub mod verify;
+pub mod xex;
+pub mod zip;
+
+/// Whether `path` is `-`, meaning stdin or stdout
+fn is_stdio(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// Whether `data` starts with one of the package magics
+fn is_package(data: &[u8]) -> bool {
+    matches!(data.get(..4), Some(b"CON " | b"LIVE" | b"PIRS"))
+}
+
+/// The bytes of a package opened with [`open_package`]
+pub enum PackageData {
+    /// The map along with the file it maps, which extraction copies from
+    Mapped(Mmap, Arc<File>),
+    /// Packages read from stdin can't be mapped and are read into memory instead
+    Buffered(Vec<u8>),
+}
+
+impl Deref for PackageData {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            PackageData::Mapped(mmap, _) => mmap,
+            PackageData::Buffered(data) => data,
+        }
+    }
+}
+
+impl PackageData {
+    /// The fastest way to extract files from this package
+    pub fn copy_method(&self) -> CopyMethod {
+        match self {
+            PackageData::Mapped(_, file) => CopyMethod::FromFile(Arc::clone(file)),
+            PackageData::Buffered(_) => CopyMethod::Direct,
+        }
+    }
+}
+
+/// Memory-maps the package at `path` so that it can be parsed without reading
+/// the whole file up front. A path of `-` reads the package from stdin.
+pub fn open_package(path: &Path) -> anyhow::Result<PackageData> {
+    if is_stdio(path) {
+        return read_package(path).map(PackageData::Buffered);
+    }
+
+    let file = File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    let mmap = unsafe { MmapOptions::new().map(&file)? };
+
+    Ok(PackageData::Mapped(mmap, Arc::new(file)))
+}
+
+/// Reads the whole package at `path` for modification. A path of `-` reads the
+/// package from stdin.
+pub fn read_package(path: &Path) -> anyhow::Result<Vec<u8>> {
+    if is_stdio(path) {
+        let mut data = Vec::new();
+        io::stdin()
+            .lock()
+            .read_to_end(&mut data)
+            .context("failed to read package from stdin")?;
+        return Ok(data);
+    }
+
+    fs::read(path).with_context(|| format!("failed to read {}", path.display()))
+}
+
+/// Formats `bytes` as contiguous uppercase hex
+pub fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut out, b| {
+        let _ = write!(out, "{:02X}", b);
+        out
+    })
+}
+
+/// Formats a title ID as hex, followed by the game's name when `resolve` is
+/// set and the title is in the bundled database
+pub fn format_title_id(title_id: TitleId, resolve: bool) -> String {
+    match title_id.name().filter(|_| resolve) {
+        Some(name) => format!("{} ({})", title_id, name),
+        None => title_id.to_string(),
+    }
+}
+
+/// Where to write a modified package
+#[derive(Debug, StructOpt)]
+pub struct OutputOpt {
+    /// Overwrite the input package
+    #[structopt(long, required_unless = "output")]
+    in_place: bool,
+
+    /// Path to write the modified package to, or `-` for stdout
+    #[structopt(short, long, parse(from_os_str), conflicts_with = "in-place")]
+    output: Option<PathBuf>,
+}
+
+impl OutputOpt {
+    /// Whether the package is being written to stdout
+    pub fn is_stdout(&self) -> bool {
+        !self.in_place && self.output.as_deref().is_some_and(is_stdio)
+    }
+
+    /// Writes `data` to the output path, or to `input` when modifying in place
+    pub fn write(&self, input: &Path, data: &[u8]) -> anyhow::Result<()> {
+        let path = match &self.output {
+            Some(output) if !self.in_place => output.as_path(),
+            _ if is_stdio(input) => bail!("packages read from stdin can't be modified in place"),
+            _ => input,
+        };
+
+        if is_stdio(path) {
+            let mut stdout = io::stdout().lock();
+            stdout.write_all(data)?;
+            return Ok(stdout.flush()?);
+        }
+
+        fs::write(path, data).with_context(|| format!("failed to write {}", path.display()))
+    }
+}
+
+/// Reads and parses the decrypted key vault at `path`
+pub fn load_key_vault(path: &Path) -> anyhow::Result<KeyVault> {
+    let key_vault = fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+
+    Ok(KeyVault::parse(&key_vault)?)
+}
+
+/// Parses an integer given either in hex with a `0x` prefix or in decimal
+pub fn parse_u32(value: &str) -> anyhow::Result<u32> {
+    let parsed = match value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+    {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+
+    parsed.with_context(|| format!("invalid number {:?}", value))
+}
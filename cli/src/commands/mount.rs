@@ -0,0 +1,43 @@
+use std::{os::unix::fs::MetadataExt, path::PathBuf};
+
+use anyhow::Context;
+use fuser::MountOption;
+use stfs::StfsPackage;
+use structopt::StructOpt;
+
+use super::open_package;
+use crate::fuse::PackageFs;
+
+#[derive(Debug, StructOpt)]
+pub struct MountOpt {
+    #[structopt(name = "FILE", parse(from_os_str))]
+    file_name: PathBuf,
+
+    /// Empty directory to mount the package on
+    #[structopt(name = "MOUNTPOINT", parse(from_os_str))]
+    mountpoint: PathBuf,
+}
+
+/// Mounts the package read-only and serves it until the filesystem is
+/// unmounted, e.g. with `fusermount -u` or `umount`
+pub fn run(opt: MountOpt) -> anyhow::Result<()> {
+    let mmap = open_package(&opt.file_name)?;
+    let package = StfsPackage::try_from(&mmap[..])?;
+
+    // Files are owned by whoever owns the mountpoint so that they're readable
+    // without `allow_other`
+    let metadata = std::fs::metadata(&opt.mountpoint)
+        .with_context(|| format!("failed to read {}", opt.mountpoint.display()))?;
+
+    let options = [
+        MountOption::RO,
+        MountOption::FSName("stfs".to_owned()),
+        MountOption::Subtype("acceleration".to_owned()),
+    ];
+    fuser::mount2(
+        PackageFs::new(&package, metadata.uid(), metadata.gid()),
+        &opt.mountpoint,
+        &options,
+    )
+    .with_context(|| format!("failed to mount on {}", opt.mountpoint.display()))
+}
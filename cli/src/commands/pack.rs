@@ -0,0 +1,114 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use stfs::{StfsPackage, StfsPackageBuilder, StfsPackageSex};
+use structopt::StructOpt;
+
+use super::{meta::read_metadata, parse_u32};
+use crate::progress::{ProgressBars, ProgressOpt};
+
+#[derive(Debug, StructOpt)]
+pub struct PackOpt {
+    /// Directory whose contents become the root of the package
+    #[structopt(name = "DIR", parse(from_os_str))]
+    dir: PathBuf,
+
+    /// Path to write the new package to
+    #[structopt(short, long, parse(from_os_str))]
+    output: PathBuf,
+
+    /// Existing package to copy header metadata (title, content type, images,
+    /// etc.) from
+    #[structopt(long, parse(from_os_str))]
+    template: Option<PathBuf>,
+
+    /// JSON file of header metadata, as printed by `meta get`, applied after
+    /// the template
+    #[structopt(long, parse(from_os_str))]
+    metadata: Option<PathBuf>,
+
+    /// Display name of the package
+    #[structopt(long)]
+    name: Option<String>,
+
+    /// Title ID, in hex with a `0x` prefix or decimal
+    #[structopt(long, parse(try_from_str = parse_u32))]
+    title_id: Option<u32>,
+
+    /// Use the "male" hash table layout, which keeps two copies of every hash table
+    #[structopt(long)]
+    male: bool,
+
+    #[structopt(flatten)]
+    progress: ProgressOpt,
+}
+
+/// Adds the contents of `dir` to the builder under `prefix`, in name order so
+/// that the output is reproducible
+fn add_dir(builder: &mut StfsPackageBuilder, dir: &Path, prefix: &str) -> anyhow::Result<()> {
+    let mut entries = fs::read_dir(dir)
+        .with_context(|| format!("failed to read {}", dir.display()))?
+        .collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let name = entry.file_name();
+        let name = name
+            .to_str()
+            .with_context(|| format!("{} is not valid UTF-8", entry.path().display()))?;
+        let path = format!("{}{}", prefix, name);
+
+        if entry.file_type()?.is_dir() {
+            builder.add_folder(&path)?;
+            add_dir(builder, &entry.path(), &format!("{}/", path))?;
+        } else {
+            let data = fs::read(entry.path())
+                .with_context(|| format!("failed to read {}", entry.path().display()))?;
+            builder.add_file(&path, data)?;
+        }
+    }
+
+    Ok(())
+}
+
+pub fn run(opt: PackOpt) -> anyhow::Result<()> {
+    let template_data = opt
+        .template
+        .as_ref()
+        .map(|template| {
+            fs::read(template).with_context(|| format!("failed to read {}", template.display()))
+        })
+        .transpose()?;
+
+    let mut builder = match &template_data {
+        Some(template_data) => {
+            StfsPackageBuilder::from_template(&StfsPackage::try_from(template_data.as_slice())?)
+        }
+        None => StfsPackageBuilder::new(),
+    };
+
+    if let Some(metadata) = &opt.metadata {
+        builder.metadata(&read_metadata(metadata)?)?;
+    }
+    if opt.male {
+        builder.sex(StfsPackageSex::Male);
+    }
+    if let Some(name) = &opt.name {
+        builder.display_name(name)?;
+    }
+    if let Some(title_id) = opt.title_id {
+        builder.title_id(title_id);
+    }
+
+    add_dir(&mut builder, &opt.dir, "")?;
+
+    let bars = ProgressBars::new(&opt.progress, "files");
+    let data = builder.build_with_progress(|progress| bars.update(progress))?;
+    bars.finish();
+
+    fs::write(&opt.output, data)
+        .with_context(|| format!("failed to write {}", opt.output.display()))
+}
@@ -0,0 +1,65 @@
+use std::path::PathBuf;
+
+use serde::Serialize;
+use stfs::{RegionKind, StfsPackage};
+use structopt::StructOpt;
+
+use super::open_package;
+use crate::output::{self, OutputFormat};
+
+#[derive(Debug, StructOpt)]
+pub struct RegionsOpt {
+    #[structopt(name = "FILE", parse(from_os_str))]
+    file_name: PathBuf,
+}
+
+/// A region as printed by `--format json` and `--format csv`
+#[derive(Debug, Serialize)]
+struct RegionRow {
+    kind: String,
+    start: usize,
+    end: usize,
+}
+
+fn kind_name(kind: RegionKind) -> String {
+    match kind {
+        RegionKind::Magic => "magic".to_owned(),
+        RegionKind::Certificate => "certificate".to_owned(),
+        RegionKind::Signature => "signature".to_owned(),
+        RegionKind::LicenseTable => "license_table".to_owned(),
+        RegionKind::HeaderHash => "header_hash".to_owned(),
+        RegionKind::Metadata(field) => field.name().to_owned(),
+        RegionKind::VolumeDescriptor => "volume_descriptor".to_owned(),
+        RegionKind::ThumbnailImage => "thumbnail_image".to_owned(),
+        RegionKind::TitleImage => "title_image".to_owned(),
+        RegionKind::HashTable(level) => format!("{:?}_hash_table", level).to_lowercase(),
+        RegionKind::FileTable => "file_table".to_owned(),
+    }
+}
+
+pub fn run(opt: RegionsOpt, format: OutputFormat) -> anyhow::Result<()> {
+    let mmap = open_package(&opt.file_name)?;
+    let package = StfsPackage::try_from(&mmap[..])?;
+    let rows = package.region_map().into_iter().map(|region| RegionRow {
+        kind: kind_name(region.kind),
+        start: region.range.start,
+        end: region.range.end,
+    });
+
+    match format {
+        OutputFormat::Json => output::print_json(&rows.collect::<Vec<_>>())?,
+        OutputFormat::Csv => output::print_csv(rows)?,
+        OutputFormat::Table => {
+            for row in rows {
+                println!(
+                    "{:#010X} {:>8X} {}",
+                    row.start,
+                    row.end - row.start,
+                    row.kind
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,22 @@
+use std::path::PathBuf;
+
+use structopt::StructOpt;
+
+use super::{read_package, OutputOpt};
+
+#[derive(Debug, StructOpt)]
+pub struct RehashOpt {
+    #[structopt(name = "FILE", parse(from_os_str))]
+    file_name: PathBuf,
+
+    #[structopt(flatten)]
+    output: OutputOpt,
+}
+
+pub fn run(opt: RehashOpt) -> anyhow::Result<()> {
+    let mut data = read_package(&opt.file_name)?;
+
+    stfs::rehash(&mut data)?;
+
+    opt.output.write(&opt.file_name, &data)
+}
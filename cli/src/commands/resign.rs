@@ -0,0 +1,28 @@
+use std::path::PathBuf;
+
+use structopt::StructOpt;
+
+use super::{load_key_vault, read_package, OutputOpt};
+
+#[derive(Debug, StructOpt)]
+pub struct ResignOpt {
+    #[structopt(name = "FILE", parse(from_os_str))]
+    file_name: PathBuf,
+
+    /// Decrypted key vault of the console to sign the package with
+    #[structopt(long, parse(from_os_str))]
+    kv: PathBuf,
+
+    #[structopt(flatten)]
+    output: OutputOpt,
+}
+
+pub fn run(opt: ResignOpt) -> anyhow::Result<()> {
+    let key_vault = load_key_vault(&opt.kv)?;
+
+    let mut data = read_package(&opt.file_name)?;
+
+    stfs::resign(&mut data, &key_vault)?;
+
+    opt.output.write(&opt.file_name, &data)
+}
@@ -0,0 +1,89 @@
+use std::path::PathBuf;
+
+use anyhow::bail;
+use stfs::StfsPackage;
+use structopt::StructOpt;
+
+use super::{load_key_vault, read_package, OutputOpt};
+
+#[derive(Debug, StructOpt)]
+pub struct RmOpt {
+    #[structopt(name = "FILE", parse(from_os_str))]
+    file_name: PathBuf,
+
+    /// Path of the file or folder inside the package
+    #[structopt(name = "INTERNAL_PATH")]
+    internal_path: String,
+
+    /// Remove folders along with everything inside of them
+    #[structopt(short, long)]
+    recursive: bool,
+
+    /// Decrypted key vault to resign the package with after editing it
+    #[structopt(long, parse(from_os_str))]
+    kv: Option<PathBuf>,
+
+    #[structopt(flatten)]
+    output: OutputOpt,
+}
+
+/// Fails if `path` is a folder with anything in it. The path is split the same
+/// way [`stfs::remove_entry`] splits it, on either separator.
+fn ensure_empty(package: &StfsPackage<'_>, path: &str) -> anyhow::Result<()> {
+    let path = path
+        .split(['/', '\\'])
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("/");
+    let files = package.files();
+    if let Some(id) = files.find(&path) {
+        if !files.children(id).is_empty() {
+            bail!("{} is not empty, pass --recursive to remove it", path);
+        }
+    }
+
+    Ok(())
+}
+
+pub fn run(opt: RmOpt) -> anyhow::Result<()> {
+    let key_vault = opt.kv.as_deref().map(load_key_vault).transpose()?;
+
+    let mut data = read_package(&opt.file_name)?;
+
+    if !opt.recursive {
+        let package = StfsPackage::try_from(data.as_slice())?;
+        ensure_empty(&package, &opt.internal_path)?;
+    }
+
+    stfs::remove_entry(&mut data, &opt.internal_path)?;
+    if let Some(key_vault) = &key_vault {
+        stfs::resign(&mut data, key_vault)?;
+    }
+
+    opt.output.write(&opt.file_name, &data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stfs::StfsPackageBuilder;
+
+    #[test]
+    fn non_empty_folders_are_found_with_either_separator() {
+        let mut builder = StfsPackageBuilder::new();
+        builder
+            .add_file("saves/slot0/data.bin", vec![1; 0x10])
+            .unwrap()
+            .add_folder("empty")
+            .unwrap();
+        let data = builder.build().unwrap();
+        let package = StfsPackage::try_from(data.as_slice()).unwrap();
+
+        for path in ["saves", "/saves/", "saves\\slot0", "\\saves//slot0\\"] {
+            assert!(ensure_empty(&package, path).is_err(), "{}", path);
+        }
+        for path in ["empty", "\\empty\\", "saves\\slot0\\data.bin", "missing"] {
+            assert!(ensure_empty(&package, path).is_ok(), "{}", path);
+        }
+    }
+}
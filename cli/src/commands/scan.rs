@@ -0,0 +1,112 @@
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use stfs::{ContentType, TitleId, XContentHeader};
+use structopt::StructOpt;
+use walkdir::WalkDir;
+
+use super::{hex, is_package, open_package};
+use crate::{
+    error,
+    output::{self, OutputFormat},
+};
+
+#[derive(Debug, StructOpt)]
+pub struct ScanOpt {
+    /// Print the results as JSON. Shorthand for `--format json`.
+    #[structopt(long)]
+    json: bool,
+
+    /// Look up game names from title IDs
+    #[structopt(long)]
+    resolve_titles: bool,
+
+    /// A console's `Content` directory, or any directory containing packages
+    #[structopt(name = "DIR", parse(from_os_str))]
+    dir: PathBuf,
+}
+
+#[derive(Debug, Serialize)]
+pub(super) struct ScannedPackage {
+    pub(super) path: PathBuf,
+    profile_id: String,
+    title_id: TitleId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resolved_title: Option<&'static str>,
+    content_type: ContentType,
+    display_name: String,
+    title_name: String,
+    size: u64,
+}
+
+/// Reads just the header of the package at `path`. Returns `None` for files
+/// which aren't packages.
+pub(super) fn scan_file(
+    path: &Path,
+    resolve_titles: bool,
+) -> anyhow::Result<Option<ScannedPackage>> {
+    let mmap = open_package(path)?;
+    if !is_package(&mmap) {
+        return Ok(None);
+    }
+
+    let header = XContentHeader::parse(&mmap[..])?;
+    Ok(Some(ScannedPackage {
+        path: path.to_path_buf(),
+        profile_id: hex(&header.profile_id),
+        title_id: header.title_id,
+        resolved_title: header.title_id.name().filter(|_| resolve_titles),
+        content_type: header.content_type,
+        display_name: header.display_name,
+        title_name: header.title_name,
+        size: mmap.len() as u64,
+    }))
+}
+
+/// Walks a `Content/<profile>/<title>/<type>/` tree and lists every package in
+/// it. Files which fail to parse are reported on stderr and skipped.
+pub fn run(opt: ScanOpt, format: OutputFormat, errors_json: bool) -> anyhow::Result<()> {
+    let mut packages = Vec::new();
+    for entry in WalkDir::new(&opt.dir).sort_by_file_name() {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        match scan_file(entry.path(), opt.resolve_titles) {
+            Ok(Some(package)) => packages.push(package),
+            Ok(None) => {}
+            Err(e) => error::report(&e, Some(entry.path()), errors_json),
+        }
+    }
+
+    match format {
+        _ if opt.json => return output::print_json(&packages),
+        OutputFormat::Json => return output::print_json(&packages),
+        OutputFormat::Csv => return output::print_csv(&packages),
+        OutputFormat::Table => {}
+    }
+
+    println!(
+        "{:<16} {:<8} {:<20} {:>12} NAME",
+        "PROFILE", "TITLE", "TYPE", "SIZE"
+    );
+    for package in &packages {
+        let title_name = package.resolved_title.unwrap_or(&package.title_name);
+        let name = if title_name.is_empty() {
+            package.display_name.clone()
+        } else {
+            format!("{} ({})", package.display_name, title_name)
+        };
+        println!(
+            "{:<16} {} {:<20} {:>12} {}",
+            package.profile_id,
+            package.title_id,
+            package.content_type.to_string(),
+            package.size,
+            name
+        );
+    }
+
+    Ok(())
+}
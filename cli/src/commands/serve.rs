@@ -0,0 +1,302 @@
+use std::{
+    collections::BTreeMap,
+    io::Cursor,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use serde::Serialize;
+use stfs::{EntryInfo, PackageInfo, StfsPackage};
+use structopt::StructOpt;
+use tiny_http::{Header, Method, Request, Response, Server, StatusCode};
+use walkdir::WalkDir;
+
+use super::{info::package_info, open_package, scan};
+
+#[derive(Debug, StructOpt)]
+pub struct ServeOpt {
+    /// A package, or a directory to search for packages
+    #[structopt(name = "PATH", parse(from_os_str))]
+    path: PathBuf,
+
+    #[structopt(long, default_value = "8080")]
+    port: u16,
+
+    /// Address to listen on. Use 0.0.0.0 to allow connections from other machines.
+    #[structopt(long, default_value = "127.0.0.1")]
+    bind: String,
+
+    /// Look up game names from title IDs
+    #[structopt(long)]
+    resolve_titles: bool,
+}
+
+/// Packages being served, keyed by their path relative to the served directory
+type Index = BTreeMap<String, PathBuf>;
+
+type HttpResponse = Response<Cursor<Vec<u8>>>;
+
+#[derive(Debug, Serialize)]
+struct PackageDetails {
+    info: PackageInfo,
+    files: Vec<EntryInfo>,
+}
+
+/// Finds every package under `root`, or just `root` itself if it's a file
+fn build_index(
+    root: &Path,
+    resolve_titles: bool,
+) -> anyhow::Result<(Index, Vec<scan::ScannedPackage>)> {
+    let mut index = Index::new();
+    let mut packages = Vec::new();
+    for entry in WalkDir::new(root).sort_by_file_name() {
+        let entry = entry?;
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let mut package = match scan::scan_file(entry.path(), resolve_titles) {
+            Ok(Some(package)) => package,
+            Ok(None) => continue,
+            Err(e) => {
+                eprintln!("{}: {:#}", entry.path().display(), e);
+                continue;
+            }
+        };
+
+        // A single package is served under its file name
+        let relative = match entry.path().strip_prefix(root) {
+            Ok(relative) if !relative.as_os_str().is_empty() => relative,
+            _ => Path::new(entry.file_name()),
+        };
+        let key = relative.to_string_lossy().replace('\\', "/");
+
+        index.insert(key.clone(), entry.path().to_path_buf());
+        package.path = PathBuf::from(key);
+        packages.push(package);
+    }
+
+    Ok((index, packages))
+}
+
+/// Decodes a `%`-encoded query string component
+fn percent_decode(value: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut input = value.bytes();
+    while let Some(b) = input.next() {
+        match b {
+            b'%' => {
+                let hex = [input.next()?, input.next()?];
+                bytes.push(u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()?);
+            }
+            b'+' => bytes.push(b' '),
+            b => bytes.push(b),
+        }
+    }
+
+    String::from_utf8(bytes).ok()
+}
+
+/// Splits a request URL into its path and decoded query parameters
+fn parse_url(url: &str) -> (&str, BTreeMap<String, String>) {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let params = query
+        .split('&')
+        .filter_map(|pair| {
+            let (key, value) = pair.split_once('=')?;
+            Some((percent_decode(key)?, percent_decode(value)?))
+        })
+        .collect();
+
+    (path, params)
+}
+
+/// Builds a `Content-Disposition` value for downloading `name`. Package entry
+/// names can be any UTF-8, so the plain `filename` is an ASCII fallback and the
+/// real name is given percent-encoded in `filename*` (RFC 6266).
+fn content_disposition(name: &str) -> String {
+    let fallback: String = name
+        .chars()
+        .map(|c| match c {
+            ' '..='~' if c != '"' && c != '\\' => c,
+            _ => '_',
+        })
+        .collect();
+
+    let mut encoded = String::with_capacity(name.len());
+    for b in name.bytes() {
+        match b {
+            b'A'..=b'Z'
+            | b'a'..=b'z'
+            | b'0'..=b'9'
+            | b'!'
+            | b'#'
+            | b'$'
+            | b'&'
+            | b'+'
+            | b'-'
+            | b'.'
+            | b'^'
+            | b'_'
+            | b'`'
+            | b'|'
+            | b'~' => encoded.push(b as char),
+            _ => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+
+    format!(
+        "attachment; filename=\"{}\"; filename*=UTF-8''{}",
+        fallback, encoded
+    )
+}
+
+fn json_response<T: Serialize>(value: &T) -> anyhow::Result<HttpResponse> {
+    Ok(Response::from_data(serde_json::to_vec_pretty(value)?)
+        .with_header(Header::from_bytes("Content-Type", "application/json").expect("valid header")))
+}
+
+fn error_response(status: u16, message: String) -> HttpResponse {
+    #[derive(Serialize)]
+    struct Error {
+        error: String,
+    }
+
+    json_response(&Error { error: message })
+        .expect("errors always serialize")
+        .with_status_code(StatusCode(status))
+}
+
+/// Looks up the package named by the `path` parameter. It may be omitted when
+/// only one package is being served.
+fn find_package<'i>(
+    index: &'i Index,
+    params: &BTreeMap<String, String>,
+) -> Result<&'i Path, HttpResponse> {
+    let package = match params.get("path") {
+        Some(path) => index.get(path),
+        None if index.len() == 1 => index.values().next(),
+        None => return Err(error_response(400, "missing path parameter".to_owned())),
+    };
+
+    package
+        .map(PathBuf::as_path)
+        .ok_or_else(|| error_response(404, "no such package".to_owned()))
+}
+
+fn handle(
+    request: &Request,
+    index: &Index,
+    packages: &[scan::ScannedPackage],
+    resolve_titles: bool,
+) -> anyhow::Result<HttpResponse> {
+    if *request.method() != Method::Get {
+        return Ok(error_response(405, "only GET is supported".to_owned()));
+    }
+
+    let (path, params) = parse_url(request.url());
+    match path {
+        "/" | "/api/packages" => json_response(&packages),
+        "/api/package" => {
+            let file_name = match find_package(index, &params) {
+                Ok(file_name) => file_name,
+                Err(response) => return Ok(response),
+            };
+            let mmap = open_package(file_name)?;
+            let package = StfsPackage::try_from(&mmap[..])?;
+
+            json_response(&PackageDetails {
+                info: package_info(&package, resolve_titles),
+                files: EntryInfo::list(&package),
+            })
+        }
+        "/api/file" => {
+            let file_name = match find_package(index, &params) {
+                Ok(file_name) => file_name,
+                Err(response) => return Ok(response),
+            };
+            let Some(entry_path) = params.get("entry") else {
+                return Ok(error_response(400, "missing entry parameter".to_owned()));
+            };
+
+            let mmap = open_package(file_name)?;
+            let package = StfsPackage::try_from(&mmap[..])?;
+            let entry = package
+                .walk_entries()
+                .into_iter()
+                .find(|(path, entry)| path == entry_path && !entry.is_folder());
+            let Some((_, entry)) = entry else {
+                return Ok(error_response(404, "no such file".to_owned()));
+            };
+
+            let mut contents = Vec::with_capacity(entry.file_size);
+            package.extract_file(&mut contents, &entry)?;
+
+            let disposition =
+                Header::from_bytes("Content-Disposition", content_disposition(&entry.name))
+                    .map_err(|_| anyhow!("couldn't build a Content-Disposition header"))?;
+            Ok(Response::from_data(contents)
+                .with_header(
+                    Header::from_bytes("Content-Type", "application/octet-stream")
+                        .expect("valid header"),
+                )
+                .with_header(disposition))
+        }
+        _ => Ok(error_response(404, "not found".to_owned())),
+    }
+}
+
+/// Serves package metadata as JSON and package contents for download:
+///
+/// - `GET /api/packages` lists every package
+/// - `GET /api/package?path=PATH` returns a package's metadata and files
+/// - `GET /api/file?path=PATH&entry=ENTRY` downloads a file from a package
+///
+/// `path` may be omitted when serving a single package.
+pub fn run(opt: ServeOpt) -> anyhow::Result<()> {
+    let (index, packages) = build_index(&opt.path, opt.resolve_titles)?;
+
+    let address = format!("{}:{}", opt.bind, opt.port);
+    let server = Server::http(&address)
+        .map_err(|e| anyhow!(e))
+        .with_context(|| format!("failed to listen on {}", address))?;
+    eprintln!("Serving {} packages on http://{}", packages.len(), address);
+
+    for request in server.incoming_requests() {
+        let response = handle(&request, &index, &packages, opt.resolve_titles)
+            .unwrap_or_else(|e| error_response(500, format!("{:#}", e)));
+        if let Err(e) = request.respond(response) {
+            eprintln!("failed to send response: {}", e);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn disposition_header(name: &str) -> Header {
+        Header::from_bytes("Content-Disposition", content_disposition(name)).unwrap()
+    }
+
+    #[test]
+    fn non_ascii_names_are_encoded() {
+        let header = disposition_header("セーブ é.bin");
+        assert_eq!(
+            header.value.as_str(),
+            "attachment; filename=\"___ _.bin\"; \
+             filename*=UTF-8''%E3%82%BB%E3%83%BC%E3%83%96%20%C3%A9.bin"
+        );
+    }
+
+    #[test]
+    fn control_characters_are_stripped() {
+        let header = disposition_header("a\r\nSet-Cookie: \"x\".bin");
+        let value = header.value.as_str();
+        assert!(!value.contains(['\r', '\n']));
+        assert!(value.starts_with("attachment; filename=\"a__Set-Cookie: _x_.bin\";"));
+        assert!(value.ends_with("filename*=UTF-8''a%0D%0ASet-Cookie%3A%20%22x%22.bin"));
+    }
+}
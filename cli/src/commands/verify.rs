@@ -0,0 +1,105 @@
+use std::path::PathBuf;
+
+use serde::Serialize;
+use stfs::{CancellationToken, HashLocation, StfsPackage};
+use structopt::StructOpt;
+
+use super::{hex, open_package};
+use crate::{
+    error::VerificationFailed,
+    output::{self, OutputFormat},
+    progress::{ProgressBars, ProgressOpt},
+};
+
+#[derive(Debug, StructOpt)]
+pub struct VerifyOpt {
+    #[structopt(name = "FILE", parse(from_os_str))]
+    file_name: PathBuf,
+
+    #[structopt(flatten)]
+    progress: ProgressOpt,
+}
+
+fn describe(location: HashLocation) -> String {
+    match location {
+        HashLocation::DataBlock(block) => format!("data block {:#X}", block),
+        HashLocation::Level0Table(table) => format!("level 0 hash table {:#X}", table),
+        HashLocation::Level1Table(table) => format!("level 1 hash table {:#X}", table),
+        HashLocation::TopTable => "top hash table".to_owned(),
+        HashLocation::Header => "header".to_owned(),
+    }
+}
+
+/// A mismatched hash as printed by `--format json` and `--format csv`
+#[derive(Debug, Serialize)]
+struct MismatchRow {
+    location: String,
+    stored: String,
+    computed: String,
+}
+
+#[derive(Debug, Serialize)]
+struct VerifyReport {
+    ok: bool,
+    /// Set if the volume descriptor's free block count is wrong
+    unallocated_block_count_error: Option<String>,
+    mismatches: Vec<MismatchRow>,
+}
+
+/// Checks the package's hash tree, failing with [`VerificationFailed`] if any
+/// hash is wrong
+pub fn run(opt: VerifyOpt, format: OutputFormat) -> anyhow::Result<()> {
+    let mmap = open_package(&opt.file_name)?;
+    let package = StfsPackage::try_from(&mmap[..])?;
+
+    let bars = ProgressBars::new(&opt.progress, "hashes");
+    let mismatches = package.verify_hashes_with_progress(&bars, &CancellationToken::default())?;
+    bars.finish();
+
+    let report = VerifyReport {
+        ok: mismatches.is_empty(),
+        unallocated_block_count_error: package
+            .block_allocator()
+            .check_unallocated_block_count()
+            .err()
+            .map(|e| e.to_string()),
+        mismatches: mismatches
+            .iter()
+            .map(|mismatch| MismatchRow {
+                location: describe(mismatch.location),
+                stored: hex(&mismatch.stored),
+                computed: hex(&mismatch.computed),
+            })
+            .collect(),
+    };
+
+    match format {
+        OutputFormat::Json => output::print_json(&report)?,
+        OutputFormat::Csv => output::print_csv(&report.mismatches)?,
+        OutputFormat::Table => {
+            if let Some(error) = &report.unallocated_block_count_error {
+                println!("warning: {}", error);
+            }
+
+            for mismatch in &report.mismatches {
+                println!(
+                    "{}: stored {} but computed {}",
+                    mismatch.location, mismatch.stored, mismatch.computed
+                );
+            }
+            if report.ok {
+                println!("OK");
+            }
+        }
+    }
+
+    if !report.ok {
+        return Err(VerificationFailed(format!(
+            "{} hashes did not match",
+            report.mismatches.len()
+        ))
+        .into());
+    }
+
+    Ok(())
+}
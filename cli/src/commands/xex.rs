@@ -0,0 +1,173 @@
+use std::path::PathBuf;
+
+use anyhow::{anyhow, bail};
+use serde::Serialize;
+use stfs::{StfsPackage, Xex, XexInfo};
+use structopt::StructOpt;
+
+use super::{format_title_id, is_package, open_package};
+use crate::output::{self, OutputFormat};
+
+#[derive(Debug, StructOpt)]
+pub struct XexOpt {
+    /// An executable, or a package to read executables from
+    #[structopt(name = "FILE", parse(from_os_str))]
+    file_name: PathBuf,
+
+    /// Path of the executable inside the package. Defaults to every file
+    /// ending in `.xex`.
+    #[structopt(long)]
+    path: Option<String>,
+
+    /// Look up the game's name from the title ID
+    #[structopt(long)]
+    resolve_titles: bool,
+}
+
+/// An executable's headers, as printed by `--format json`
+#[derive(Debug, Serialize)]
+struct XexReport {
+    path: String,
+    #[serde(flatten)]
+    info: XexInfo,
+}
+
+/// An executable as printed by `--format csv`
+#[derive(Debug, Serialize)]
+struct XexRow<'a> {
+    path: &'a str,
+    title_id: String,
+    media_id: String,
+    version: String,
+    base_version: String,
+    disc: String,
+    module_flags: String,
+    base_address: String,
+    entry_point: String,
+    original_pe_name: &'a str,
+}
+
+fn address(address: Option<u32>) -> String {
+    address
+        .map(|address| format!("{:08X}", address))
+        .unwrap_or_default()
+}
+
+fn row(report: &XexReport, resolve_titles: bool) -> XexRow<'_> {
+    let info = &report.info;
+    let execution = info.execution_info.as_ref();
+    XexRow {
+        path: &report.path,
+        title_id: execution
+            .map(|execution| format_title_id(execution.title_id, resolve_titles))
+            .unwrap_or_default(),
+        media_id: execution
+            .map(|execution| execution.media_id.to_string())
+            .unwrap_or_default(),
+        version: execution
+            .map(|execution| execution.version.to_string())
+            .unwrap_or_default(),
+        base_version: execution
+            .map(|execution| execution.base_version.to_string())
+            .unwrap_or_default(),
+        disc: execution
+            .map(|execution| format!("{}/{}", execution.disc_number, execution.disc_count))
+            .unwrap_or_default(),
+        module_flags: format!("{:?}", info.module_flags),
+        base_address: address(info.base_address),
+        entry_point: address(info.entry_point),
+        original_pe_name: info.original_pe_name.as_deref().unwrap_or_default(),
+    }
+}
+
+fn print(reports: &[XexReport], resolve_titles: bool) {
+    for (i, report) in reports.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("{}:", report.path);
+
+        let row = row(report, resolve_titles);
+        let rows = [
+            ("Title ID", row.title_id),
+            ("Media ID", row.media_id),
+            ("Version", row.version),
+            ("Base version", row.base_version),
+            ("Disc", row.disc),
+            ("Module flags", row.module_flags),
+            ("Base address", row.base_address),
+            ("Entry point", row.entry_point),
+            ("PE name", row.original_pe_name.to_owned()),
+        ];
+        for (label, value) in rows {
+            if !value.is_empty() {
+                println!("  {:<14}{}", format!("{}:", label), value);
+            }
+        }
+
+        println!("  Optional headers:");
+        for header in &report.info.optional_headers {
+            let value = match (header.value, header.size) {
+                (Some(value), _) => format!("{:#X}", value),
+                (None, Some(size)) => format!("{} bytes", size),
+                (None, None) => String::new(),
+            };
+            println!(
+                "    {:08X}  {:<30}{}",
+                header.key.0,
+                header.name.unwrap_or("unknown"),
+                value
+            );
+        }
+    }
+}
+
+/// Prints the headers of an executable, or of the executables in a package
+pub fn run(opt: XexOpt, format: OutputFormat) -> anyhow::Result<()> {
+    let mmap = open_package(&opt.file_name)?;
+    let mut reports = Vec::new();
+    if is_package(&mmap) {
+        let package = StfsPackage::try_from(&mmap[..])?;
+        let executables = match &opt.path {
+            Some(path) => {
+                let path = path.replace('\\', "/");
+                let path = path.trim_matches('/');
+                let executable = package
+                    .walk_entries()
+                    .into_iter()
+                    .find(|(entry_path, _)| entry_path == path)
+                    .ok_or_else(|| anyhow!("{} does not exist in the package", path))?;
+                if executable.1.is_folder() {
+                    bail!("{} is a folder", path);
+                }
+                vec![executable]
+            }
+            None => package.executables(),
+        };
+        if executables.is_empty() {
+            bail!("the package doesn't contain any executables");
+        }
+
+        for (path, entry) in executables {
+            let headers = package.read_xex_headers(&entry)?;
+            let info = XexInfo::new(&Xex::try_from(headers.as_slice())?)?;
+            reports.push(XexReport { path, info });
+        }
+    } else {
+        let info = XexInfo::new(&Xex::try_from(&mmap[..])?)?;
+        reports.push(XexReport {
+            path: opt.file_name.display().to_string(),
+            info,
+        });
+    }
+
+    match format {
+        OutputFormat::Json => output::print_json(&reports)?,
+        OutputFormat::Csv => {
+            output::print_csv(reports.iter().map(|report| row(report, opt.resolve_titles)))?
+        }
+        OutputFormat::Table => print(&reports, opt.resolve_titles),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,88 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+use anyhow::{bail, Context};
+use stfs::{CancellationToken, Progress, StfsPackage, ZipCompression, ZipOptions};
+use structopt::StructOpt;
+
+use super::open_package;
+use crate::progress::{ProgressBars, ProgressOpt};
+
+#[derive(Debug, StructOpt)]
+pub struct ZipOpt {
+    #[structopt(name = "FILE", parse(from_os_str))]
+    file_name: PathBuf,
+
+    /// Path to write the zip archive to
+    #[structopt(short, long, parse(from_os_str))]
+    output: PathBuf,
+
+    /// Compression method, either `deflate` or `store`
+    #[structopt(long, default_value = "deflate", parse(try_from_str = parse_compression))]
+    method: ZipCompression,
+
+    /// Store files without compressing them. Shorthand for `--method store`.
+    #[structopt(long, conflicts_with_all = &["method", "level"])]
+    store: bool,
+
+    /// Compression level, from 0 (fastest) to 9 (smallest)
+    #[structopt(long)]
+    level: Option<i32>,
+
+    /// Print each file as it is added
+    #[structopt(short, long)]
+    verbose: bool,
+
+    #[structopt(flatten)]
+    progress: ProgressOpt,
+}
+
+fn parse_compression(method: &str) -> anyhow::Result<ZipCompression> {
+    match method {
+        "deflate" => Ok(ZipCompression::Deflated),
+        "store" => Ok(ZipCompression::Stored),
+        _ => bail!("unknown compression method {:?}", method),
+    }
+}
+
+pub fn run(opt: ZipOpt) -> anyhow::Result<()> {
+    if let Some(level) = opt.level {
+        if !(0..=9).contains(&level) {
+            bail!("compression level must be between 0 and 9");
+        }
+    }
+
+    let mmap = open_package(&opt.file_name)?;
+    let package = StfsPackage::try_from(&mmap[..])?;
+
+    let options = ZipOptions {
+        compression: if opt.store {
+            ZipCompression::Stored
+        } else {
+            opt.method
+        },
+        level: opt.level,
+    };
+
+    let file = File::create(&opt.output)
+        .with_context(|| format!("failed to create {}", opt.output.display()))?;
+    let bars = ProgressBars::new(&opt.progress, "files");
+    let mut writer = package.write_zip(
+        BufWriter::new(file),
+        &options,
+        |progress: Progress<'_>| {
+            bars.update(progress);
+            if let Some(path) = progress.entry.filter(|_| opt.verbose) {
+                bars.println(path);
+            }
+        },
+        &CancellationToken::default(),
+    )?;
+    writer.flush()?;
+    bars.finish();
+
+    Ok(())
+}
@@ -0,0 +1,122 @@
+//! Classifying errors into stable exit codes, and reporting them as JSON for
+//! scripts which need to tell failures apart.
+
+use std::{fmt, io, path::Path};
+
+use serde::Serialize;
+use stfs::{ErrorClass, StfsError};
+
+/// Broad classes of failure, each with its own stable exit code
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// Anything not covered by a more specific kind
+    Other,
+    /// The input isn't a valid package
+    Parse,
+    /// The package parsed but its hashes or signature are wrong
+    Verification,
+    /// Reading or writing a file failed
+    Io,
+}
+
+impl ErrorKind {
+    /// The process exit code for this kind of error. `diff` also exits with 1
+    /// when the packages differ, mirroring `diff(1)`.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorKind::Other => 1,
+            ErrorKind::Parse => 3,
+            ErrorKind::Verification => 4,
+            ErrorKind::Io => 5,
+        }
+    }
+
+    /// Works out the kind of an error from the first cause in its chain that
+    /// is recognized
+    pub fn of(error: &anyhow::Error) -> Self {
+        for cause in error.chain() {
+            if cause.is::<VerificationFailed>() {
+                return ErrorKind::Verification;
+            }
+            if cause.is::<io::Error>() {
+                return ErrorKind::Io;
+            }
+            if let Some(error) = cause.downcast_ref::<StfsError>() {
+                return match error.class() {
+                    ErrorClass::Io => ErrorKind::Io,
+                    ErrorClass::Parse => ErrorKind::Parse,
+                    ErrorClass::Integrity => ErrorKind::Verification,
+                    ErrorClass::InvalidInput | ErrorClass::Signing | ErrorClass::Cancelled => {
+                        ErrorKind::Other
+                    }
+                };
+            }
+        }
+
+        ErrorKind::Other
+    }
+}
+
+/// Returned by commands which checked a package and found problems
+#[derive(Debug)]
+pub struct VerificationFailed(pub String);
+
+impl fmt::Display for VerificationFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for VerificationFailed {}
+
+#[derive(Debug, Serialize)]
+struct ErrorReport<'a> {
+    kind: ErrorKind,
+    exit_code: i32,
+    /// The package being processed, for commands which handle many of them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<&'a Path>,
+    /// [`StfsError::code`] of the first package error in the chain
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<u16>,
+    /// Offset into the package of the data which caused the error, when known
+    #[serde(skip_serializing_if = "Option::is_none")]
+    offset: Option<u64>,
+    message: String,
+    /// Messages of the underlying errors, outermost first
+    causes: Vec<String>,
+}
+
+/// Prints `error` to stderr, either as text or as a single line of JSON
+pub fn report(error: &anyhow::Error, path: Option<&Path>, json: bool) {
+    if !json {
+        match path {
+            Some(path) => eprintln!("{}: {:#}", path.display(), error),
+            None => eprintln!("Error: {:?}", error),
+        }
+        return;
+    }
+
+    let kind = ErrorKind::of(error);
+    let stfs_error = error
+        .chain()
+        .find_map(|cause| cause.downcast_ref::<StfsError>());
+    let report = ErrorReport {
+        kind,
+        exit_code: kind.exit_code(),
+        path,
+        code: stfs_error.map(StfsError::code),
+        offset: stfs_error.and_then(StfsError::offset),
+        message: error.to_string(),
+        causes: error
+            .chain()
+            .skip(1)
+            .map(|cause| cause.to_string())
+            .collect(),
+    };
+    match serde_json::to_string(&report) {
+        Ok(report) => eprintln!("{}", report),
+        Err(_) => eprintln!("Error: {:?}", error),
+    }
+}
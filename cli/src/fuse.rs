@@ -0,0 +1,254 @@
+//! A read-only FUSE filesystem exposing the files inside of a package.
+
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use chrono::NaiveDateTime;
+use fuser::{
+    FileAttr, FileType, Filesystem, ReplyAttr, ReplyData, ReplyDirectory, ReplyEntry, ReplyOpen,
+    Request,
+};
+use stfs::{StfsFileEntry, StfsPackage};
+
+/// How long the kernel may cache attributes. The package can't change while
+/// mounted, so this can be long.
+const TTL: Duration = Duration::from_secs(60 * 60);
+const ROOT_INODE: u64 = 1;
+
+struct Node {
+    name: String,
+    /// `None` for the root folder
+    entry: Option<StfsFileEntry>,
+    parent: u64,
+    children: Vec<u64>,
+}
+
+impl Node {
+    fn kind(&self) -> FileType {
+        match &self.entry {
+            Some(entry) if !entry.is_folder() => FileType::RegularFile,
+            _ => FileType::Directory,
+        }
+    }
+}
+
+pub struct PackageFs<'p, 'a> {
+    package: &'p StfsPackage<'a>,
+    /// Indexed by inode number minus one
+    nodes: Vec<Node>,
+    /// Contents of open files, keyed by file handle
+    open_files: HashMap<u64, Vec<u8>>,
+    next_handle: u64,
+    uid: u32,
+    gid: u32,
+}
+
+fn system_time(datetime: Option<NaiveDateTime>) -> SystemTime {
+    datetime
+        .and_then(|datetime| u64::try_from(datetime.and_utc().timestamp()).ok())
+        .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+        .unwrap_or(UNIX_EPOCH)
+}
+
+impl<'p, 'a> PackageFs<'p, 'a> {
+    /// Builds the inode table for `package`. Files and folders are owned by
+    /// `uid` and `gid`.
+    pub fn new(package: &'p StfsPackage<'a>, uid: u32, gid: u32) -> Self {
+        let mut nodes = vec![Node {
+            name: String::new(),
+            entry: None,
+            parent: ROOT_INODE,
+            children: Vec::new(),
+        }];
+
+        // Entries are listed depth-first, so parents always come before their contents
+        let mut inodes = HashMap::new();
+        for (path, entry) in package.walk_entries() {
+            let inode = nodes.len() as u64 + 1;
+            let (parent, name) = match path.rsplit_once('/') {
+                Some((parent, name)) => (inodes[parent], name.to_owned()),
+                None => (ROOT_INODE, path.clone()),
+            };
+
+            nodes[parent as usize - 1].children.push(inode);
+            nodes.push(Node {
+                name,
+                entry: Some(entry),
+                parent,
+                children: Vec::new(),
+            });
+            inodes.insert(path, inode);
+        }
+
+        PackageFs {
+            package,
+            nodes,
+            open_files: HashMap::new(),
+            next_handle: 0,
+            uid,
+            gid,
+        }
+    }
+
+    fn node(&self, inode: u64) -> Option<&Node> {
+        self.nodes.get(usize::try_from(inode).ok()?.checked_sub(1)?)
+    }
+
+    fn attr(&self, inode: u64) -> Option<FileAttr> {
+        let node = self.node(inode)?;
+        let (size, created, accessed) = match &node.entry {
+            Some(entry) => (
+                entry.file_size as u64,
+                system_time(entry.created()),
+                system_time(entry.accessed()),
+            ),
+            None => (0, UNIX_EPOCH, UNIX_EPOCH),
+        };
+        let kind = node.kind();
+
+        Some(FileAttr {
+            ino: inode,
+            size,
+            blocks: size.div_ceil(512),
+            atime: accessed,
+            mtime: created,
+            ctime: created,
+            crtime: created,
+            kind,
+            perm: if kind == FileType::Directory {
+                0o555
+            } else {
+                0o444
+            },
+            nlink: if kind == FileType::Directory { 2 } else { 1 },
+            uid: self.uid,
+            gid: self.gid,
+            rdev: 0,
+            blksize: 0x1000,
+            flags: 0,
+        })
+    }
+}
+
+impl Filesystem for PackageFs<'_, '_> {
+    fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+        let child = self.node(parent).and_then(|parent| {
+            parent
+                .children
+                .iter()
+                .copied()
+                .find(|child| OsStr::new(&self.nodes[*child as usize - 1].name) == name)
+        });
+
+        match child.and_then(|child| self.attr(child)) {
+            Some(attr) => reply.entry(&TTL, &attr, 0),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn getattr(&mut self, _req: &Request<'_>, ino: u64, reply: ReplyAttr) {
+        match self.attr(ino) {
+            Some(attr) => reply.attr(&TTL, &attr),
+            None => reply.error(libc::ENOENT),
+        }
+    }
+
+    fn open(&mut self, _req: &Request<'_>, ino: u64, flags: i32, reply: ReplyOpen) {
+        if flags & libc::O_ACCMODE != libc::O_RDONLY {
+            return reply.error(libc::EROFS);
+        }
+
+        let entry = match self.node(ino) {
+            Some(Node {
+                entry: Some(entry), ..
+            }) if !entry.is_folder() => entry,
+            Some(_) => return reply.error(libc::EISDIR),
+            None => return reply.error(libc::ENOENT),
+        };
+
+        // Files are read into memory when opened so that reads at arbitrary
+        // offsets don't have to walk the block chain each time
+        let mut contents = Vec::with_capacity(entry.file_size);
+        if self.package.extract_file(&mut contents, entry).is_err() {
+            return reply.error(libc::EIO);
+        }
+
+        let handle = self.next_handle;
+        self.next_handle += 1;
+        self.open_files.insert(handle, contents);
+        reply.opened(handle, 0);
+    }
+
+    fn read(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        offset: i64,
+        size: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: ReplyData,
+    ) {
+        let Some(contents) = self.open_files.get(&fh) else {
+            return reply.error(libc::EBADF);
+        };
+
+        let start = usize::try_from(offset)
+            .unwrap_or_default()
+            .min(contents.len());
+        let end = start.saturating_add(size as usize).min(contents.len());
+        reply.data(&contents[start..end]);
+    }
+
+    fn release(
+        &mut self,
+        _req: &Request<'_>,
+        _ino: u64,
+        fh: u64,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        _flush: bool,
+        reply: fuser::ReplyEmpty,
+    ) {
+        self.open_files.remove(&fh);
+        reply.ok();
+    }
+
+    fn readdir(
+        &mut self,
+        _req: &Request<'_>,
+        ino: u64,
+        _fh: u64,
+        offset: i64,
+        mut reply: ReplyDirectory,
+    ) {
+        let Some(node) = self.node(ino) else {
+            return reply.error(libc::ENOENT);
+        };
+        if node.kind() != FileType::Directory {
+            return reply.error(libc::ENOTDIR);
+        }
+
+        let entries = [
+            (ino, FileType::Directory, "."),
+            (node.parent, FileType::Directory, ".."),
+        ]
+        .into_iter()
+        .chain(node.children.iter().map(|child| {
+            let child_node = &self.nodes[*child as usize - 1];
+            (*child, child_node.kind(), child_node.name.as_str())
+        }));
+        for (i, (inode, kind, name)) in entries.enumerate().skip(offset as usize) {
+            // The offset passed back to us is that of the next entry to return
+            if reply.add(inode, i as i64 + 1, kind, name) {
+                break;
+            }
+        }
+
+        reply.ok();
+    }
+}
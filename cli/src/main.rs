@@ -1,22 +1,176 @@
-use std::{fs::File, path::PathBuf};
+use std::io::IsTerminal;
 
-use memmap::MmapOptions;
-use stfs::StfsPackage;
 use structopt::StructOpt;
+use tracing_subscriber::{
+    filter::{LevelFilter, Targets},
+    fmt::format::FmtSpan,
+    layer::SubscriberExt,
+    util::SubscriberInitExt,
+};
+
+mod commands;
+mod error;
+#[cfg(all(unix, feature = "mount"))]
+mod fuse;
+mod output;
+mod progress;
+
+use output::OutputFormat;
 
 #[derive(Debug, StructOpt)]
 #[structopt(name = "acceleration-cli", about = "Xbox 360 STFS package tool")]
 struct Opt {
-    #[structopt(name = "FILE")]
-    file_name: PathBuf,
+    /// Output format for commands which print listings or reports: table, json, or csv
+    #[structopt(
+        long,
+        global = true,
+        default_value = "table",
+        parse(try_from_str = output::parse_format)
+    )]
+    format: OutputFormat,
+
+    /// Report errors on stderr as JSON objects with a `kind` and `exit_code`
+    #[structopt(long, global = true)]
+    errors_json: bool,
+
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Verify, rehash, or extract every package under a directory in parallel
+    Batch(commands::batch::BatchOpt),
+    /// Write a file from a package to stdout
+    Cat(commands::cat::CatOpt),
+    /// Print a sha1sum-compatible digest of every file in a package
+    Checksum(commands::checksum::ChecksumOpt),
+    /// Convert a package between the CON, LIVE, and PIRS types
+    Convert(commands::convert::ConvertOpt),
+    /// Compare the metadata and contents of two packages
+    Diff(commands::diff::DiffOpt),
+    /// Repair damaged metadata, hash tables, and block allocation
+    Fix(commands::fix::FixOpt),
+    /// Print the gamercard, title history, and achievements from a GPD or
+    /// profile package
+    Gpd(commands::gpd::GpdOpt),
+    /// Search the contents of every file in a package for text or bytes
+    Grep(commands::grep::GrepOpt),
+    /// Export or replace a package's thumbnail and title images
+    Images(commands::images::ImagesOpt),
+    /// Print a package's metadata
+    Info(commands::info::InfoOpt),
+    /// Extract files matching one or more glob patterns
+    Extract(commands::extract::ExtractOpt),
+    /// Extract every file in a package
+    ExtractAll(commands::extract_all::ExtractAllOpt),
+    /// Add or replace a file inside a package
+    Inject(commands::inject::InjectOpt),
+    /// List the files in a package
+    Ls(commands::ls::LsOpt),
+    /// Read or edit header metadata
+    Meta(commands::meta::MetaOpt),
+    /// Mount a package as a read-only filesystem
+    #[cfg(all(unix, feature = "mount"))]
+    Mount(commands::mount::MountOpt),
+    /// Build a new package from the contents of a directory
+    Pack(commands::pack::PackOpt),
+    /// List the byte ranges of a package's header fields, hash tables, and file table
+    Regions(commands::regions::RegionsOpt),
+    /// Recompute a package's hash tables and header hash
+    Rehash(commands::rehash::RehashOpt),
+    /// Rehash a CON package and sign it with a console's key vault
+    Resign(commands::resign::ResignOpt),
+    /// Remove a file or folder from a package
+    Rm(commands::rm::RmOpt),
+    /// List the packages in a console's Content directory
+    Scan(commands::scan::ScanOpt),
+    /// Serve package metadata and contents over HTTP
+    Serve(commands::serve::ServeOpt),
+    /// Check a package's hash tables and header hash
+    Verify(commands::verify::VerifyOpt),
+    /// Print the headers of an executable, or of the executables in a package
+    Xex(commands::xex::XexOpt),
+    /// Export a package's contents as a zip archive
+    Zip(commands::zip::ZipOpt),
 }
 
-fn main() -> anyhow::Result<()> {
-    let config = Opt::from_args();
-    let file = File::open(config.file_name)?;
-    let mmap = unsafe { MmapOptions::new().map(&file)? };
+/// Exit codes:
+///
+/// - 0: success
+/// - 1: any other error, `diff` found differences, or `grep` found no matches
+/// - 3: the input isn't a valid package
+/// - 4: `verify` found bad hashes
+/// - 5: reading or writing a file failed
+fn main() {
+    let Opt {
+        format,
+        errors_json,
+        command,
+    } = Opt::from_args();
+
+    init_logging();
+
+    if let Err(e) = run(command, format, errors_json) {
+        error::report(&e, None, errors_json);
+        std::process::exit(error::ErrorKind::of(&e).exit_code());
+    }
+}
+
+/// Logs the parser's spans and events to stderr when `RUST_LOG` is set, e.g.
+/// `RUST_LOG=stfs=debug`
+fn init_logging() {
+    let Ok(filter) = std::env::var("RUST_LOG") else {
+        return;
+    };
+
+    let targets = match filter.parse::<Targets>() {
+        Ok(targets) => targets,
+        Err(e) => {
+            eprintln!("ignoring invalid RUST_LOG: {}", e);
+            return;
+        }
+    };
+
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal())
+        .with_span_events(FmtSpan::CLOSE)
+        // Leave filtering to `targets`; the builder defaults to INFO
+        .with_max_level(LevelFilter::TRACE)
+        .finish()
+        .with(targets)
+        .init();
+}
 
-    let xcontent_package = StfsPackage::try_from(&mmap[..])?;
-    panic!("{:#X?}", xcontent_package);
-    Ok(())
+fn run(command: Command, format: OutputFormat, errors_json: bool) -> anyhow::Result<()> {
+    match command {
+        Command::Batch(opt) => commands::batch::run(opt, format),
+        Command::Cat(opt) => commands::cat::run(opt),
+        Command::Checksum(opt) => commands::checksum::run(opt),
+        Command::Convert(opt) => commands::convert::run(opt),
+        Command::Diff(opt) => commands::diff::run(opt, format),
+        Command::Fix(opt) => commands::fix::run(opt, format),
+        Command::Gpd(opt) => commands::gpd::run(opt, format),
+        Command::Grep(opt) => commands::grep::run(opt, format),
+        Command::Images(opt) => commands::images::run(opt),
+        Command::Info(opt) => commands::info::run(opt, format),
+        Command::Extract(opt) => commands::extract::run(opt),
+        Command::ExtractAll(opt) => commands::extract_all::run(opt),
+        Command::Inject(opt) => commands::inject::run(opt),
+        Command::Ls(opt) => commands::ls::run(opt, format),
+        Command::Meta(opt) => commands::meta::run(opt),
+        #[cfg(all(unix, feature = "mount"))]
+        Command::Mount(opt) => commands::mount::run(opt),
+        Command::Pack(opt) => commands::pack::run(opt),
+        Command::Regions(opt) => commands::regions::run(opt, format),
+        Command::Rehash(opt) => commands::rehash::run(opt),
+        Command::Resign(opt) => commands::resign::run(opt),
+        Command::Rm(opt) => commands::rm::run(opt),
+        Command::Scan(opt) => commands::scan::run(opt, format, errors_json),
+        Command::Serve(opt) => commands::serve::run(opt),
+        Command::Verify(opt) => commands::verify::run(opt, format),
+        Command::Xex(opt) => commands::xex::run(opt, format),
+        Command::Zip(opt) => commands::zip::run(opt),
+    }
 }
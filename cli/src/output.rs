@@ -0,0 +1,41 @@
+//! Machine-readable output shared by commands that print tabular data.
+
+use std::io;
+
+use anyhow::bail;
+use serde::Serialize;
+
+/// How commands like `ls` and `info` format their output
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Human-readable, aligned text
+    Table,
+    Json,
+    /// One header row followed by one row per record
+    Csv,
+}
+
+pub fn parse_format(format: &str) -> anyhow::Result<OutputFormat> {
+    match format {
+        "table" => Ok(OutputFormat::Table),
+        "json" => Ok(OutputFormat::Json),
+        "csv" => Ok(OutputFormat::Csv),
+        _ => bail!("expected one of table, json, or csv"),
+    }
+}
+
+pub fn print_json<T: Serialize + ?Sized>(value: &T) -> anyhow::Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+/// Prints `rows` as CSV. Every row must serialize to a flat record.
+pub fn print_csv<T: Serialize>(rows: impl IntoIterator<Item = T>) -> anyhow::Result<()> {
+    let mut writer = csv::Writer::from_writer(io::stdout().lock());
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
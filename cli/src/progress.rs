@@ -0,0 +1,83 @@
+//! Progress bars for long-running commands, drawn to stderr.
+
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
+use stfs::{Progress, ProgressSink};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+pub struct ProgressOpt {
+    /// Don't show progress bars
+    #[structopt(short, long)]
+    quiet: bool,
+}
+
+/// A pair of bars tracking how many entries and how many bytes have been processed
+pub struct ProgressBars {
+    _multi: MultiProgress,
+    entries: ProgressBar,
+    bytes: ProgressBar,
+}
+
+impl ProgressBars {
+    /// Creates the bars, labelling the first with what is being counted (e.g.
+    /// "files"). Nothing is drawn if `--quiet` was passed or stderr isn't a
+    /// terminal.
+    pub fn new(opt: &ProgressOpt, entries_label: &'static str) -> Self {
+        let multi = MultiProgress::new();
+        if opt.quiet {
+            multi.set_draw_target(indicatif::ProgressDrawTarget::hidden());
+        }
+
+        let entries = multi.add(ProgressBar::new(0));
+        entries.set_style(
+            ProgressStyle::with_template("{prefix:>7} [{bar:40}] {pos}/{len} {wide_msg}")
+                .expect("valid progress template")
+                .progress_chars("=> "),
+        );
+        entries.set_prefix(entries_label);
+
+        let bytes = multi.add(ProgressBar::new(0));
+        bytes.set_style(
+            ProgressStyle::with_template(
+                "{prefix:>7} [{bar:40}] {bytes}/{total_bytes} ({bytes_per_sec}, {eta})",
+            )
+            .expect("valid progress template")
+            .progress_chars("=> "),
+        );
+        bytes.set_prefix("bytes");
+
+        ProgressBars {
+            _multi: multi,
+            entries,
+            bytes,
+        }
+    }
+
+    pub fn update(&self, progress: Progress<'_>) {
+        self.entries.set_length(progress.entries_total as u64);
+        self.entries.set_position(progress.entries_done as u64);
+        if let Some(entry) = progress.entry {
+            self.entries.set_message(entry.to_owned());
+        }
+
+        self.bytes.set_length(progress.bytes_total);
+        self.bytes.set_position(progress.bytes_done);
+    }
+
+    /// Prints a line to stdout without it being overdrawn by the bars
+    pub fn println(&self, line: &str) {
+        self.entries.suspend(|| println!("{}", line));
+    }
+
+    /// Removes the bars so that they don't interleave with any output printed afterwards
+    pub fn finish(&self) {
+        self.entries.finish_and_clear();
+        self.bytes.finish_and_clear();
+    }
+}
+
+impl ProgressSink for &ProgressBars {
+    fn report(&mut self, progress: Progress<'_>) {
+        self.update(progress);
+    }
+}
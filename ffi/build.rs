@@ -0,0 +1,11 @@
+use std::{env, path::PathBuf};
+
+fn main() {
+    println!("cargo:rerun-if-changed=src/lib.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+
+    let crate_dir = PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap());
+    cbindgen::generate(&crate_dir)
+        .expect("failed to generate C header")
+        .write_to_file(crate_dir.join("include").join("stfs.h"));
+}
@@ -0,0 +1,193 @@
+#ifndef STFS_H
+#define STFS_H
+
+/* Generated by cbindgen from src/lib.rs. Don't edit by hand. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+/**
+ * The call succeeded
+ */
+#define STFS_OK 0
+
+/**
+ * A required pointer argument was null
+ */
+#define STFS_ERROR_NULL_ARGUMENT -1
+
+/**
+ * A string argument wasn't valid UTF-8
+ */
+#define STFS_ERROR_INVALID_UTF8 -2
+
+/**
+ * An entry index was past the end of the file list
+ */
+#define STFS_ERROR_OUT_OF_RANGE -3
+
+/**
+ * The library panicked, most likely on a malformed package
+ */
+#define STFS_ERROR_PANIC -4
+
+/**
+ * The outcome of checking a package's signature
+ */
+typedef enum StfsSignature {
+  STFS_SIGNATURE_VALID,
+  STFS_SIGNATURE_INVALID,
+  STFS_SIGNATURE_UNSIGNED,
+  /**
+   * LIVE and PIRS packages are signed by Microsoft, whose public key isn't
+   * available to check them with
+   */
+  STFS_SIGNATURE_UNVERIFIABLE,
+} StfsSignature;
+
+/**
+ * An opened package, created by [`stfs_open`] or [`stfs_open_file`] and freed
+ * with [`stfs_close`]
+ */
+typedef struct StfsHandle StfsHandle;
+
+/**
+ * A file or folder as described by [`stfs_entry`]
+ */
+typedef struct StfsEntryInfo {
+  /**
+   * `/`-separated path from the package root, owned by the handle and valid
+   * until it's closed
+   */
+  const char *path;
+  bool is_folder;
+  /**
+   * Size of the file's contents in bytes
+   */
+  uint64_t size;
+  uint64_t block_count;
+  /**
+   * Creation time as seconds since the Unix epoch, or 0 if it isn't set
+   */
+  int64_t created;
+} StfsEntryInfo;
+
+/**
+ * What [`stfs_verify`] found
+ */
+typedef struct StfsVerifyReport {
+  /**
+   * Whether every hash matched and the signature isn't invalid
+   */
+  bool ok;
+  /**
+   * Number of hashes which don't match the data they cover
+   */
+  uint64_t hash_mismatches;
+  enum StfsSignature signature;
+  /**
+   * Whether the volume descriptor's count of unallocated blocks is correct
+   */
+  bool unallocated_block_count_ok;
+} StfsVerifyReport;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Opens the package in the `len` bytes at `data`, which are copied and can be
+ * freed once this returns. On success `*out` is set to a handle which must be
+ * passed to [`stfs_close`].
+ *
+ * # Safety
+ *
+ * `data` must point to `len` readable bytes and `out` must be valid for writes.
+ */
+int32_t stfs_open(const uint8_t *data, size_t len, struct StfsHandle **out);
+
+/**
+ * Reads and opens the package at `path`. On success `*out` is set to a handle
+ * which must be passed to [`stfs_close`].
+ *
+ * # Safety
+ *
+ * `path` must be a nul-terminated string and `out` must be valid for writes.
+ */
+int32_t stfs_open_file(const char *path, struct StfsHandle **out);
+
+/**
+ * Frees a handle returned by [`stfs_open`] or [`stfs_open_file`]. Passing null
+ * does nothing.
+ *
+ * # Safety
+ *
+ * `handle` must not be used again after this returns.
+ */
+void stfs_close(struct StfsHandle *handle);
+
+/**
+ * Number of files and folders in the package
+ *
+ * # Safety
+ *
+ * `handle` must be a handle returned by [`stfs_open`] or [`stfs_open_file`].
+ */
+size_t stfs_entry_count(const struct StfsHandle *handle);
+
+/**
+ * Describes the entry at `index`. Entries are listed depth-first, with each
+ * folder preceding its contents.
+ *
+ * # Safety
+ *
+ * `handle` must be a handle returned by [`stfs_open`] or [`stfs_open_file`],
+ * and `out` must be valid for writes.
+ */
+int32_t stfs_entry(const struct StfsHandle *handle, size_t index, struct StfsEntryInfo *out);
+
+/**
+ * Writes the contents of the file at `index` to a new file at `dest`,
+ * replacing it if it exists
+ *
+ * # Safety
+ *
+ * `handle` must be a handle returned by [`stfs_open`] or [`stfs_open_file`],
+ * and `dest` must be a nul-terminated string.
+ */
+int32_t stfs_extract(const struct StfsHandle *handle, size_t index, const char *dest);
+
+/**
+ * Extracts every file and folder into the directory `root`, creating it if
+ * needed and replacing files which already exist
+ *
+ * # Safety
+ *
+ * `handle` must be a handle returned by [`stfs_open`] or [`stfs_open_file`],
+ * and `root` must be a nul-terminated string.
+ */
+int32_t stfs_extract_all(const struct StfsHandle *handle, const char *root);
+
+/**
+ * Checks the package's hash tree and signature. Problems are reported in
+ * `*out` rather than as a failed status.
+ *
+ * # Safety
+ *
+ * `handle` must be a handle returned by [`stfs_open`] or [`stfs_open_file`],
+ * and `out` must be valid for writes.
+ */
+int32_t stfs_verify(const struct StfsHandle *handle, struct StfsVerifyReport *out);
+
+/**
+ * Message describing the last failed call on this thread, or null if none has
+ * failed. The string is valid until the next call on this thread fails.
+ */
+const char *stfs_last_error(void);
+
+#ifdef __cplusplus
+} // extern "C"
+#endif // __cplusplus
+
+#endif /* STFS_H */
@@ -0,0 +1,436 @@
+//! C bindings for reading STFS packages.
+//!
+//! Every function which can fail returns an `int32_t` status: [`STFS_OK`] on
+//! success, one of the negative `STFS_ERROR_*` codes when it was called
+//! incorrectly, or the positive [`StfsError::code`] of the error the parser
+//! reported. A message describing the last failure on the calling thread is
+//! available from [`stfs_last_error`].
+//!
+//! Panics are caught at the boundary and reported as [`STFS_ERROR_PANIC`], so a
+//! malformed package can't unwind into C code.
+//!
+//! `build.rs` regenerates `include/stfs.h` from this file with cbindgen.
+
+use std::{
+    cell::RefCell,
+    ffi::{c_char, CStr, CString},
+    fs::{self, File},
+    io::{BufWriter, Write},
+    panic::{self, AssertUnwindSafe},
+    path::Path,
+    ptr, slice,
+};
+
+use stfs::{
+    verify_signature, ExtractOptions, SignatureStatus, StfsError, StfsFileEntry, StfsPackage,
+};
+
+/// The call succeeded
+pub const STFS_OK: i32 = 0;
+/// A required pointer argument was null
+pub const STFS_ERROR_NULL_ARGUMENT: i32 = -1;
+/// A string argument wasn't valid UTF-8
+pub const STFS_ERROR_INVALID_UTF8: i32 = -2;
+/// An entry index was past the end of the file list
+pub const STFS_ERROR_OUT_OF_RANGE: i32 = -3;
+/// The library panicked, most likely on a malformed package
+pub const STFS_ERROR_PANIC: i32 = -4;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Why a call failed, before it's turned into a status code
+enum Error {
+    Stfs(StfsError),
+    Ffi(i32, &'static str),
+}
+
+impl From<StfsError> for Error {
+    fn from(err: StfsError) -> Self {
+        Error::Stfs(err)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Stfs(err.into())
+    }
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message).unwrap_or_default();
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// Runs `f`, converting its result or panic into a status code and recording
+/// the message of any failure
+fn call(f: impl FnOnce() -> Result<(), Error>) -> i32 {
+    let (code, message) = match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => return STFS_OK,
+        Ok(Err(Error::Stfs(err))) => (i32::from(err.code()), err.to_string()),
+        Ok(Err(Error::Ffi(code, message))) => (code, message.to_owned()),
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_owned());
+            (STFS_ERROR_PANIC, format!("panic: {}", message))
+        }
+    };
+
+    set_last_error(message);
+    code
+}
+
+fn non_null<'p, T>(ptr: *const T) -> Result<&'p T, Error> {
+    // SAFETY: the caller promises that non-null pointers are valid
+    unsafe { ptr.as_ref() }.ok_or(Error::Ffi(
+        STFS_ERROR_NULL_ARGUMENT,
+        "a required argument was null",
+    ))
+}
+
+fn non_null_mut<'p, T>(ptr: *mut T) -> Result<&'p mut T, Error> {
+    // SAFETY: the caller promises that non-null pointers are valid
+    unsafe { ptr.as_mut() }.ok_or(Error::Ffi(
+        STFS_ERROR_NULL_ARGUMENT,
+        "a required argument was null",
+    ))
+}
+
+fn path_arg<'p>(path: *const c_char) -> Result<&'p Path, Error> {
+    if path.is_null() {
+        return Err(Error::Ffi(STFS_ERROR_NULL_ARGUMENT, "path was null"));
+    }
+
+    // SAFETY: the caller promises that `path` is nul-terminated
+    let path = unsafe { CStr::from_ptr(path) };
+    path.to_str()
+        .map(Path::new)
+        .map_err(|_| Error::Ffi(STFS_ERROR_INVALID_UTF8, "path is not valid UTF-8"))
+}
+
+/// An opened package, created by [`stfs_open`] or [`stfs_open_file`] and freed
+/// with [`stfs_close`]
+pub struct StfsHandle {
+    data: Vec<u8>,
+    /// Every file and folder, with folders preceding their contents
+    entries: Vec<(CString, StfsFileEntry)>,
+}
+
+impl StfsHandle {
+    fn new(data: Vec<u8>) -> Result<Self, Error> {
+        let entries = {
+            let package = StfsPackage::try_from(data.as_slice())?;
+            let files = package.files();
+            files
+                .walk()
+                .into_iter()
+                .map(|(path, id)| {
+                    // Names are read up to the first nul, so they never contain one
+                    let path = CString::new(path).unwrap_or_default();
+                    (path, files.entry(id).clone())
+                })
+                .collect()
+        };
+
+        Ok(StfsHandle { data, entries })
+    }
+
+    /// Parses the package without its file table, which was already read when
+    /// the handle was opened
+    fn package(&self) -> Result<StfsPackage<'_>, Error> {
+        Ok(StfsPackage::parse_lazy(&self.data)?)
+    }
+
+    fn entry(&self, index: usize) -> Result<&(CString, StfsFileEntry), Error> {
+        self.entries.get(index).ok_or(Error::Ffi(
+            STFS_ERROR_OUT_OF_RANGE,
+            "entry index is out of range",
+        ))
+    }
+}
+
+fn open(data: Vec<u8>, out: *mut *mut StfsHandle) -> Result<(), Error> {
+    let out = non_null_mut(out)?;
+    *out = Box::into_raw(Box::new(StfsHandle::new(data)?));
+
+    Ok(())
+}
+
+/// Opens the package in the `len` bytes at `data`, which are copied and can be
+/// freed once this returns. On success `*out` is set to a handle which must be
+/// passed to [`stfs_close`].
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn stfs_open(data: *const u8, len: usize, out: *mut *mut StfsHandle) -> i32 {
+    call(|| {
+        if data.is_null() {
+            return Err(Error::Ffi(STFS_ERROR_NULL_ARGUMENT, "data was null"));
+        }
+
+        open(slice::from_raw_parts(data, len).to_vec(), out)
+    })
+}
+
+/// Reads and opens the package at `path`. On success `*out` is set to a handle
+/// which must be passed to [`stfs_close`].
+///
+/// # Safety
+///
+/// `path` must be a nul-terminated string and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn stfs_open_file(path: *const c_char, out: *mut *mut StfsHandle) -> i32 {
+    call(|| open(fs::read(path_arg(path)?)?, out))
+}
+
+/// Frees a handle returned by [`stfs_open`] or [`stfs_open_file`]. Passing null
+/// does nothing.
+///
+/// # Safety
+///
+/// `handle` must not be used again after this returns.
+#[no_mangle]
+pub unsafe extern "C" fn stfs_close(handle: *mut StfsHandle) {
+    if !handle.is_null() {
+        drop(Box::from_raw(handle));
+    }
+}
+
+/// A file or folder as described by [`stfs_entry`]
+#[repr(C)]
+pub struct StfsEntryInfo {
+    /// `/`-separated path from the package root, owned by the handle and valid
+    /// until it's closed
+    pub path: *const c_char,
+    pub is_folder: bool,
+    /// Size of the file's contents in bytes
+    pub size: u64,
+    pub block_count: u64,
+    /// Creation time as seconds since the Unix epoch, or 0 if it isn't set
+    pub created: i64,
+}
+
+/// Number of files and folders in the package
+///
+/// # Safety
+///
+/// `handle` must be a handle returned by [`stfs_open`] or [`stfs_open_file`].
+#[no_mangle]
+pub unsafe extern "C" fn stfs_entry_count(handle: *const StfsHandle) -> usize {
+    handle.as_ref().map_or(0, |handle| handle.entries.len())
+}
+
+/// Describes the entry at `index`. Entries are listed depth-first, with each
+/// folder preceding its contents.
+///
+/// # Safety
+///
+/// `handle` must be a handle returned by [`stfs_open`] or [`stfs_open_file`],
+/// and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn stfs_entry(
+    handle: *const StfsHandle,
+    index: usize,
+    out: *mut StfsEntryInfo,
+) -> i32 {
+    call(|| {
+        let (path, entry) = non_null(handle)?.entry(index)?;
+        *non_null_mut(out)? = StfsEntryInfo {
+            path: path.as_ptr(),
+            is_folder: entry.is_folder(),
+            size: entry.file_size as u64,
+            block_count: entry.block_count as u64,
+            created: entry
+                .created()
+                .map_or(0, |created| created.and_utc().timestamp()),
+        };
+
+        Ok(())
+    })
+}
+
+/// Writes the contents of the file at `index` to a new file at `dest`,
+/// replacing it if it exists
+///
+/// # Safety
+///
+/// `handle` must be a handle returned by [`stfs_open`] or [`stfs_open_file`],
+/// and `dest` must be a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn stfs_extract(
+    handle: *const StfsHandle,
+    index: usize,
+    dest: *const c_char,
+) -> i32 {
+    call(|| {
+        let handle = non_null(handle)?;
+        let (path, entry) = handle.entry(index)?;
+        if entry.is_folder() {
+            return Err(Error::Stfs(StfsError::FileNotFound(
+                path.to_string_lossy().into_owned(),
+            )));
+        }
+
+        let mut writer = BufWriter::new(File::create(path_arg(dest)?)?);
+        handle.package()?.extract_file(&mut writer, entry)?;
+        writer.flush()?;
+
+        Ok(())
+    })
+}
+
+/// Extracts every file and folder into the directory `root`, creating it if
+/// needed and replacing files which already exist
+///
+/// # Safety
+///
+/// `handle` must be a handle returned by [`stfs_open`] or [`stfs_open_file`],
+/// and `root` must be a nul-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn stfs_extract_all(handle: *const StfsHandle, root: *const c_char) -> i32 {
+    call(|| {
+        let handle = non_null(handle)?;
+        let root = path_arg(root)?;
+        let entries = handle
+            .entries
+            .iter()
+            .map(|(path, entry)| (path.to_string_lossy().into_owned(), entry.clone()));
+        handle
+            .package()?
+            .extract_entries(root, entries, &ExtractOptions::default())?;
+
+        Ok(())
+    })
+}
+
+/// The outcome of checking a package's signature
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StfsSignature {
+    Valid,
+    Invalid,
+    Unsigned,
+    /// LIVE and PIRS packages are signed by Microsoft, whose public key isn't
+    /// available to check them with
+    Unverifiable,
+}
+
+impl From<SignatureStatus> for StfsSignature {
+    fn from(status: SignatureStatus) -> Self {
+        match status {
+            SignatureStatus::Valid => StfsSignature::Valid,
+            SignatureStatus::Invalid => StfsSignature::Invalid,
+            SignatureStatus::Unsigned => StfsSignature::Unsigned,
+            SignatureStatus::Unverifiable => StfsSignature::Unverifiable,
+        }
+    }
+}
+
+/// What [`stfs_verify`] found
+#[repr(C)]
+pub struct StfsVerifyReport {
+    /// Whether every hash matched and the signature isn't invalid
+    pub ok: bool,
+    /// Number of hashes which don't match the data they cover
+    pub hash_mismatches: u64,
+    pub signature: StfsSignature,
+    /// Whether the volume descriptor's count of unallocated blocks is correct
+    pub unallocated_block_count_ok: bool,
+}
+
+/// Checks the package's hash tree and signature. Problems are reported in
+/// `*out` rather than as a failed status.
+///
+/// # Safety
+///
+/// `handle` must be a handle returned by [`stfs_open`] or [`stfs_open_file`],
+/// and `out` must be valid for writes.
+#[no_mangle]
+pub unsafe extern "C" fn stfs_verify(handle: *const StfsHandle, out: *mut StfsVerifyReport) -> i32 {
+    call(|| {
+        let handle = non_null(handle)?;
+        let out = non_null_mut(out)?;
+        let package = handle.package()?;
+        let hash_mismatches = package.verify_hashes()?.len() as u64;
+        let signature = StfsSignature::from(verify_signature(&handle.data)?);
+
+        *out = StfsVerifyReport {
+            ok: hash_mismatches == 0 && signature != StfsSignature::Invalid,
+            hash_mismatches,
+            signature,
+            unallocated_block_count_ok: package
+                .block_allocator()
+                .check_unallocated_block_count()
+                .is_ok(),
+        };
+
+        Ok(())
+    })
+}
+
+/// Message describing the last failed call on this thread, or null if none has
+/// failed. The string is valid until the next call on this thread fails.
+#[no_mangle]
+pub extern "C" fn stfs_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use stfs::StfsPackageBuilder;
+
+    #[test]
+    fn open_list_extract_and_verify() {
+        let mut builder = StfsPackageBuilder::new();
+        builder.add_file("saves/a.sav", b"first".to_vec()).unwrap();
+        let data = builder.build().unwrap();
+
+        unsafe {
+            let mut handle = ptr::null_mut();
+            assert_eq!(stfs_open(data.as_ptr(), data.len(), &mut handle), STFS_OK);
+            assert_eq!(stfs_entry_count(handle), 2);
+
+            let mut info = std::mem::zeroed::<StfsEntryInfo>();
+            assert_eq!(stfs_entry(handle, 1, &mut info), STFS_OK);
+            assert_eq!(CStr::from_ptr(info.path).to_str().unwrap(), "saves/a.sav");
+            assert!(!info.is_folder);
+            assert_eq!(info.size, 5);
+            assert_eq!(stfs_entry(handle, 2, &mut info), STFS_ERROR_OUT_OF_RANGE);
+            assert!(!stfs_last_error().is_null());
+
+            let dest = std::env::temp_dir().join(format!("stfs-ffi-{}.sav", std::process::id()));
+            let dest_c = CString::new(dest.to_str().unwrap()).unwrap();
+            assert_eq!(stfs_extract(handle, 1, dest_c.as_ptr()), STFS_OK);
+            assert_eq!(fs::read(&dest).unwrap(), b"first");
+            let _ = fs::remove_file(&dest);
+            assert_eq!(
+                stfs_extract(handle, 1, ptr::null()),
+                STFS_ERROR_NULL_ARGUMENT
+            );
+
+            let mut report = std::mem::zeroed::<StfsVerifyReport>();
+            assert_eq!(stfs_verify(handle, &mut report), STFS_OK);
+            assert!(report.ok);
+            assert_eq!(report.hash_mismatches, 0);
+
+            stfs_close(handle);
+
+            let garbage = [0u8; 16];
+            let mut handle = ptr::null_mut();
+            let status = stfs_open(garbage.as_ptr(), garbage.len(), &mut handle);
+            assert!(status > 0);
+            assert!(handle.is_null());
+        }
+    }
+}
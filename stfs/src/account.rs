@@ -0,0 +1,350 @@
+//! Decrypting and re-encrypting the `Account` file of profile packages.
+//!
+//! The file is obfuscated the way `XeKeysUnObfuscate` expects: a 0x10 byte
+//! HMAC-SHA1 checksum, followed by an 8 byte confounder and the account data,
+//! both encrypted with RC4. The RC4 key is the HMAC of the checksum, and every
+//! HMAC is keyed with the console's 16 byte protected data key, which callers
+//! have to supply themselves.
+
+use byteorder::{BigEndian, ByteOrder};
+use hmac::{Hmac, Mac};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use sha1::Sha1;
+
+use crate::{
+    edit::inject_file,
+    stfs::{StfsError, StfsPackage},
+    write::write_utf16_str,
+    xdbf::utf16_be_str,
+};
+
+/// File name of the account within a profile package
+pub const ACCOUNT_FILE: &str = "Account";
+/// Size of the encrypted `Account` file
+pub const ACCOUNT_SIZE: usize = CHECKSUM_SIZE + CONFOUNDER_SIZE + ACCOUNT_DATA_SIZE;
+
+const CHECKSUM_SIZE: usize = 0x10;
+const CONFOUNDER_SIZE: usize = 8;
+/// Size of the decrypted `XAMACCOUNTINFO`
+const ACCOUNT_DATA_SIZE: usize = 0x17C;
+
+const GAMERTAG_OFFSET: usize = 0x8;
+const GAMERTAG_SIZE: usize = 0x20;
+const ONLINE_DOMAIN_OFFSET: usize = 0x3C;
+const ONLINE_DOMAIN_SIZE: usize = 0x14;
+const KERBEROS_REALM_OFFSET: usize = 0x50;
+const KERBEROS_REALM_SIZE: usize = 0x18;
+const ONLINE_KEY_OFFSET: usize = 0x68;
+const PASSPORT_MEMBER_NAME_OFFSET: usize = 0x78;
+const PASSPORT_MEMBER_NAME_SIZE: usize = 0x72;
+const PASSPORT_PASSWORD_OFFSET: usize = 0xEA;
+const PASSPORT_PASSWORD_SIZE: usize = 0x20;
+const OWNER_PASSPORT_MEMBER_NAME_OFFSET: usize = 0x10A;
+
+bitflags::bitflags! {
+    /// Known bits of [`Account::reserved_flags`]
+    #[derive(Default)]
+    #[cfg_attr(feature = "serde", derive(Serialize), serde(transparent))]
+    pub struct AccountFlags: u32 {
+        /// Signing in asks for the [`Account::passcode`]
+        const PASSCODE_ENABLED = 0x10000000;
+        /// The profile is tied to an Xbox Live account
+        const LIVE_ENABLED = 0x20000000;
+        /// The profile is being recovered from Xbox Live
+        const RECOVERING = 0x40000000;
+    }
+}
+
+/// The Xbox Live membership of an account, from its cached user flags
+#[derive(Debug, Copy, Clone, PartialEq, Eq, num_enum::TryFromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[repr(u8)]
+pub enum SubscriptionTier {
+    Silver = 3,
+    Gold = 6,
+    FamilyGold = 9,
+}
+
+/// The decrypted contents of a profile's `Account` file. Secrets such as the
+/// passcode and Passport password are left out when serialized.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Account {
+    /// See [`Account::flags`]. Kept whole so that bits without an
+    /// [`AccountFlags`] constant survive re-encryption.
+    pub reserved_flags: u32,
+    pub live_flags: u32,
+    pub gamertag: String,
+    /// Xbox Live user ID, or zero for offline profiles
+    pub xuid: u64,
+    /// Country and membership details cached from Xbox Live. See
+    /// [`Account::country`] and [`Account::subscription_tier`].
+    pub cached_user_flags: u32,
+    /// `PROD` for the retail Xbox Live network, or `PART` for PartnerNet
+    pub network_id: u32,
+    /// Controller buttons to press when signing in
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub passcode: [u8; 4],
+    pub online_domain: String,
+    pub kerberos_realm: String,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub online_key: [u8; 0x10],
+    pub passport_member_name: String,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub passport_password: [u8; PASSPORT_PASSWORD_SIZE],
+    pub owner_passport_member_name: String,
+    /// Random bytes encrypted ahead of the data. Keeping them means encrypting
+    /// an unchanged account gives back the original file.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub confounder: [u8; CONFOUNDER_SIZE],
+}
+
+impl Account {
+    /// Decrypts and parses an `Account` file, checking its checksum against
+    /// `key`. A mismatch usually means the key is wrong.
+    pub fn decrypt(data: &[u8], key: &[u8; 0x10]) -> Result<Self, StfsError> {
+        if data.len() != ACCOUNT_SIZE {
+            return Err(StfsError::InvalidAccount("unexpected account size"));
+        }
+
+        let (checksum, encrypted) = data.split_at(CHECKSUM_SIZE);
+        let mut decrypted = encrypted.to_vec();
+        rc4(&hmac_sha1(key, checksum)[..0x10], &mut decrypted);
+        if hmac_sha1(key, &decrypted)[..CHECKSUM_SIZE] != *checksum {
+            return Err(StfsError::AccountChecksumMismatch);
+        }
+
+        let (confounder, account) = decrypted.split_at(CONFOUNDER_SIZE);
+        Ok(Account {
+            reserved_flags: BigEndian::read_u32(account),
+            live_flags: BigEndian::read_u32(&account[0x4..]),
+            gamertag: utf16_be_str(&account[GAMERTAG_OFFSET..GAMERTAG_OFFSET + GAMERTAG_SIZE]),
+            xuid: BigEndian::read_u64(&account[0x28..]),
+            cached_user_flags: BigEndian::read_u32(&account[0x30..]),
+            network_id: BigEndian::read_u32(&account[0x34..]),
+            passcode: account[0x38..0x3C].try_into().unwrap(),
+            online_domain: ascii_str(&account[ONLINE_DOMAIN_OFFSET..][..ONLINE_DOMAIN_SIZE]),
+            kerberos_realm: ascii_str(&account[KERBEROS_REALM_OFFSET..][..KERBEROS_REALM_SIZE]),
+            online_key: account[ONLINE_KEY_OFFSET..][..0x10].try_into().unwrap(),
+            passport_member_name: ascii_str(
+                &account[PASSPORT_MEMBER_NAME_OFFSET..][..PASSPORT_MEMBER_NAME_SIZE],
+            ),
+            passport_password: account[PASSPORT_PASSWORD_OFFSET..][..PASSPORT_PASSWORD_SIZE]
+                .try_into()
+                .unwrap(),
+            owner_passport_member_name: ascii_str(
+                &account[OWNER_PASSPORT_MEMBER_NAME_OFFSET..][..PASSPORT_MEMBER_NAME_SIZE],
+            ),
+            confounder: confounder.try_into().unwrap(),
+        })
+    }
+
+    /// Serializes and encrypts the account for writing back to a profile
+    pub fn encrypt(&self, key: &[u8; 0x10]) -> Result<Vec<u8>, StfsError> {
+        let mut plain = vec![0u8; CONFOUNDER_SIZE + ACCOUNT_DATA_SIZE];
+        plain[..CONFOUNDER_SIZE].copy_from_slice(&self.confounder);
+        let account = &mut plain[CONFOUNDER_SIZE..];
+        BigEndian::write_u32(account, self.reserved_flags);
+        BigEndian::write_u32(&mut account[0x4..], self.live_flags);
+        write_utf16_str(
+            account,
+            GAMERTAG_OFFSET,
+            GAMERTAG_SIZE,
+            "gamertag",
+            &self.gamertag,
+        )?;
+        BigEndian::write_u64(&mut account[0x28..], self.xuid);
+        BigEndian::write_u32(&mut account[0x30..], self.cached_user_flags);
+        BigEndian::write_u32(&mut account[0x34..], self.network_id);
+        account[0x38..0x3C].copy_from_slice(&self.passcode);
+        write_ascii_str(
+            &mut account[ONLINE_DOMAIN_OFFSET..][..ONLINE_DOMAIN_SIZE],
+            "online domain",
+            &self.online_domain,
+        )?;
+        write_ascii_str(
+            &mut account[KERBEROS_REALM_OFFSET..][..KERBEROS_REALM_SIZE],
+            "Kerberos realm",
+            &self.kerberos_realm,
+        )?;
+        account[ONLINE_KEY_OFFSET..ONLINE_KEY_OFFSET + 0x10].copy_from_slice(&self.online_key);
+        write_ascii_str(
+            &mut account[PASSPORT_MEMBER_NAME_OFFSET..][..PASSPORT_MEMBER_NAME_SIZE],
+            "Passport member name",
+            &self.passport_member_name,
+        )?;
+        account[PASSPORT_PASSWORD_OFFSET..PASSPORT_PASSWORD_OFFSET + PASSPORT_PASSWORD_SIZE]
+            .copy_from_slice(&self.passport_password);
+        write_ascii_str(
+            &mut account[OWNER_PASSPORT_MEMBER_NAME_OFFSET..][..PASSPORT_MEMBER_NAME_SIZE],
+            "owner Passport member name",
+            &self.owner_passport_member_name,
+        )?;
+
+        let checksum = hmac_sha1(key, &plain);
+        rc4(
+            &hmac_sha1(key, &checksum[..CHECKSUM_SIZE])[..0x10],
+            &mut plain,
+        );
+
+        let mut data = Vec::with_capacity(ACCOUNT_SIZE);
+        data.extend_from_slice(&checksum[..CHECKSUM_SIZE]);
+        data.extend_from_slice(&plain);
+        Ok(data)
+    }
+
+    pub fn flags(&self) -> AccountFlags {
+        AccountFlags::from_bits_truncate(self.reserved_flags)
+    }
+
+    pub fn is_live_enabled(&self) -> bool {
+        self.flags().contains(AccountFlags::LIVE_ENABLED)
+    }
+
+    /// Xbox Live country code
+    pub fn country(&self) -> u8 {
+        (self.cached_user_flags >> 8) as u8
+    }
+
+    pub fn subscription_tier(&self) -> Option<SubscriptionTier> {
+        SubscriptionTier::try_from(((self.cached_user_flags >> 20) & 0xF) as u8).ok()
+    }
+}
+
+impl<'a> StfsPackage<'a> {
+    /// Decrypts the profile's `Account` file with `key`
+    pub fn account(&self, key: &[u8; 0x10]) -> Result<Account, StfsError> {
+        let files = self.files();
+        let id = files
+            .find(ACCOUNT_FILE)
+            .ok_or_else(|| StfsError::FileNotFound(ACCOUNT_FILE.to_owned()))?;
+
+        let mut data = Vec::with_capacity(ACCOUNT_SIZE);
+        self.extract_file(&mut data, files.entry(id))?;
+        Account::decrypt(&data, key)
+    }
+}
+
+/// Encrypts `account` with `key` and writes it over the `Account` file of the
+/// profile package `data`
+pub fn write_account(
+    data: &mut Vec<u8>,
+    account: &Account,
+    key: &[u8; 0x10],
+) -> Result<(), StfsError> {
+    inject_file(data, ACCOUNT_FILE, &account.encrypt(key)?)
+}
+
+fn hmac_sha1(key: &[u8], data: &[u8]) -> [u8; 20] {
+    let mut mac = Hmac::<Sha1>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+/// RC4, which only the account needs and so isn't worth a dependency
+fn rc4(key: &[u8], data: &mut [u8]) {
+    let mut state: [u8; 256] = std::array::from_fn(|i| i as u8);
+    let mut j = 0u8;
+    for i in 0..256 {
+        j = j.wrapping_add(state[i]).wrapping_add(key[i % key.len()]);
+        state.swap(i, j as usize);
+    }
+
+    let (mut i, mut j) = (0u8, 0u8);
+    for byte in data {
+        i = i.wrapping_add(1);
+        j = j.wrapping_add(state[i as usize]);
+        state.swap(i as usize, j as usize);
+        *byte ^= state[state[i as usize].wrapping_add(state[j as usize]) as usize];
+    }
+}
+
+/// Decodes the null-terminated ASCII string at the start of `bytes`
+fn ascii_str(bytes: &[u8]) -> String {
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..len]).into_owned()
+}
+
+/// Writes `value` as a null-terminated ASCII string, clearing the rest of
+/// `field_data`
+fn write_ascii_str(
+    field_data: &mut [u8],
+    field: &'static str,
+    value: &str,
+) -> Result<(), StfsError> {
+    // Leave room for the null terminator
+    if !value.is_ascii() || value.len() >= field_data.len() {
+        return Err(StfsError::FieldTooLong {
+            field,
+            max: field_data.len() - 1,
+        });
+    }
+
+    field_data.fill(0);
+    field_data[..value.len()].copy_from_slice(value.as_bytes());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StfsPackageBuilder;
+
+    const KEY: [u8; 0x10] = *b"0123456789abcdef";
+
+    fn account() -> Account {
+        Account {
+            reserved_flags: AccountFlags::LIVE_ENABLED.bits() | 0x1,
+            live_flags: 0x1,
+            gamertag: "Major Nelson".to_owned(),
+            xuid: 0x0009_0000_0123_4567,
+            cached_user_flags: (6 << 20) | (103 << 8),
+            network_id: u32::from_be_bytes(*b"PROD"),
+            passcode: [0; 4],
+            online_domain: "xboxlive.com".to_owned(),
+            kerberos_realm: "XBOXLIVE.COM".to_owned(),
+            online_key: [0x11; 0x10],
+            passport_member_name: "major@example.com".to_owned(),
+            passport_password: [0; PASSPORT_PASSWORD_SIZE],
+            owner_passport_member_name: String::new(),
+            confounder: [0x5A; CONFOUNDER_SIZE],
+        }
+    }
+
+    #[test]
+    fn rc4_matches_test_vector() {
+        let mut data = *b"Plaintext";
+        rc4(b"Key", &mut data);
+        assert_eq!(data, [0xBB, 0xF3, 0x16, 0xE8, 0xD9, 0x40, 0xAF, 0x0A, 0xD3]);
+    }
+
+    #[test]
+    fn account_round_trips_through_package() {
+        let account = account();
+        let encrypted = account.encrypt(&KEY).unwrap();
+        assert_eq!(encrypted.len(), ACCOUNT_SIZE);
+        assert_eq!(Account::decrypt(&encrypted, &KEY).unwrap(), account);
+        assert!(matches!(
+            Account::decrypt(&encrypted, b"fedcba9876543210"),
+            Err(StfsError::AccountChecksumMismatch)
+        ));
+
+        let mut builder = StfsPackageBuilder::new();
+        builder.add_file(ACCOUNT_FILE, encrypted.clone()).unwrap();
+        let mut data = builder.build().unwrap();
+        let mut edited = account.clone();
+        edited.gamertag = "Larry Hryb".to_owned();
+        write_account(&mut data, &edited, &KEY).unwrap();
+
+        let package = StfsPackage::try_from(data.as_slice()).unwrap();
+        let read = package.account(&KEY).unwrap();
+        assert_eq!(read, edited);
+        assert!(read.is_live_enabled());
+        assert_eq!(read.subscription_tier(), Some(SubscriptionTier::Gold));
+        assert_eq!(read.country(), 103);
+
+        edited.gamertag = "A gamertag that's too long".to_owned();
+        assert!(edited.encrypt(&KEY).is_err());
+    }
+}
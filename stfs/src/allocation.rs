@@ -0,0 +1,188 @@
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::{
+    edit::file_blocks,
+    stfs::{
+        HashTableLevel, StfsError, StfsFileEntry, StfsPackage, DATA_BLOCKS_PER_HASH_TREE_LEVEL,
+        HASHES_PER_HASH_TABLE,
+    },
+};
+
+/// Set in a level 0 hash entry's status byte when the data block it describes is in use
+pub(crate) const BLOCK_STATUS_ALLOCATED: u8 = 0x80;
+
+/// What a single block in the package's data area is used for
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum BlockState {
+    /// A data block which is in use by a file or the file table
+    Allocated,
+    /// A data block which is not in use
+    Free,
+    /// A hash table block at the given level of the hash tree
+    HashTable(HashTableLevel),
+}
+
+/// A read-only view of block allocation within a package.
+///
+/// Data blocks are addressed by their data block number, the same numbering used
+/// by file entries and hash entry `next_block` links. [`BlockAllocator::block_map`]
+/// additionally accounts for the hash table blocks interleaved with the data.
+pub struct BlockAllocator<'p, 'a> {
+    package: &'p StfsPackage<'a>,
+}
+
+impl<'p, 'a> BlockAllocator<'p, 'a> {
+    pub(crate) fn new(package: &'p StfsPackage<'a>) -> Self {
+        BlockAllocator { package }
+    }
+
+    /// Total number of data blocks in the package, whether in use or not
+    pub fn allocated_block_count(&self) -> usize {
+        self.package
+            .header
+            .volume_descriptor
+            .stfs_ref()
+            .allocated_block_count as usize
+    }
+
+    /// Returns whether the given data block is in use. Blocks whose hash entry
+    /// is missing from a truncated package count as free.
+    pub fn is_allocated(&self, block: usize) -> bool {
+        self.package
+            .try_block_hash_entry(block, self.package.input)
+            .is_ok_and(|entry| entry.status & BLOCK_STATUS_ALLOCATED != 0)
+    }
+
+    /// Iterates over the data block numbers that are in use
+    pub fn allocated_blocks(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.allocated_block_count()).filter(|block| self.is_allocated(*block))
+    }
+
+    /// Iterates over the data block numbers that are free
+    pub fn free_blocks(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.allocated_block_count()).filter(|block| !self.is_allocated(*block))
+    }
+
+    /// Counts the free data blocks according to the hash tables
+    pub fn unallocated_block_count(&self) -> usize {
+        self.free_blocks().count()
+    }
+
+    /// Checks that the volume descriptor's unallocated block count agrees with
+    /// the number of free blocks recorded in the hash tables
+    pub fn check_unallocated_block_count(&self) -> Result<(), StfsError> {
+        let expected = self
+            .package
+            .header
+            .volume_descriptor
+            .stfs_ref()
+            .unallocated_block_count as usize;
+        let actual = self.unallocated_block_count();
+
+        if expected == actual {
+            Ok(())
+        } else {
+            Err(StfsError::UnallocatedBlockCountMismatch { expected, actual })
+        }
+    }
+
+    /// Returns the state of every block following the header, indexed by "true"
+    /// block number (i.e. block offset from the first hash table).
+    pub fn block_map(&self) -> Vec<BlockState> {
+        let meta = &self.package.hash_table_meta;
+        let sex = self.package.sex;
+        let table_copies = 1usize << (sex as usize);
+
+        let mut map = Vec::new();
+        let mut mark = |true_block: usize, state: BlockState| {
+            if map.len() <= true_block {
+                map.resize(true_block + 1, BlockState::Free);
+            }
+            map[true_block] = state;
+        };
+
+        let table_blocks = [
+            (0..meta.tables_per_level[0])
+                .map(|table| {
+                    meta.compute_first_level_backing_hash_block_number(
+                        table * HASHES_PER_HASH_TABLE,
+                        sex,
+                    )
+                })
+                .collect::<Vec<_>>(),
+            (0..meta.tables_per_level[1])
+                .map(|table| {
+                    meta.compute_second_level_backing_hash_block_number(
+                        table * DATA_BLOCKS_PER_HASH_TREE_LEVEL[2],
+                        sex,
+                    )
+                })
+                .collect::<Vec<_>>(),
+            (0..meta.tables_per_level[2])
+                .map(|_| meta.compute_third_level_backing_hash_block_number())
+                .collect::<Vec<_>>(),
+        ];
+
+        let levels = [
+            HashTableLevel::First,
+            HashTableLevel::Second,
+            HashTableLevel::Third,
+        ];
+        for (level, blocks) in levels.into_iter().zip(table_blocks) {
+            for block in blocks {
+                for copy in 0..table_copies {
+                    mark(block + copy, BlockState::HashTable(level));
+                }
+            }
+        }
+
+        for block in 0..self.allocated_block_count() {
+            let state = if self.is_allocated(block) {
+                BlockState::Allocated
+            } else {
+                BlockState::Free
+            };
+            mark(self.package.compute_data_block_num(block) as usize, state);
+        }
+
+        map
+    }
+
+    /// Returns the blocks holding `entry`'s contents in order, numbered the
+    /// same way as the indexes of [`BlockAllocator::block_map`]
+    pub fn file_block_map_indexes(&self, entry: &StfsFileEntry) -> Vec<usize> {
+        file_blocks(self.package, entry)
+            .into_iter()
+            .map(|block| self.package.compute_data_block_num(block) as usize)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StfsPackageBuilder;
+
+    #[test]
+    fn file_blocks_are_allocated_in_block_map() {
+        let mut builder = StfsPackageBuilder::new();
+        builder
+            .add_file("data.bin", vec![0xAB; 0x1000 * 3 + 1])
+            .unwrap();
+        let data = builder.build().unwrap();
+        let package = StfsPackage::try_from(data.as_slice()).unwrap();
+
+        let allocator = package.block_allocator();
+        let map = allocator.block_map();
+        let (_, entry) = package.walk_entries().into_iter().next().unwrap();
+        let indexes = allocator.file_block_map_indexes(&entry);
+
+        assert_eq!(indexes.len(), 4);
+        assert!(indexes
+            .iter()
+            .all(|&index| map[index] == BlockState::Allocated));
+        assert_eq!(map[0], BlockState::HashTable(HashTableLevel::First));
+    }
+}
@@ -0,0 +1,216 @@
+//! Exporting package contents as a zip archive.
+
+use std::io::{Cursor, Seek, Write};
+
+use chrono::{Datelike, NaiveDateTime, Timelike};
+use zip::{write::FileOptions, CompressionMethod, DateTime, ZipArchive, ZipWriter};
+
+use crate::{
+    parallel,
+    progress::{CancellationToken, EntryProgress, ProgressSink},
+    stfs::{StfsError, StfsFileEntry, StfsPackage},
+};
+
+/// Files are compressed in batches of roughly this many bytes. This bounds how
+/// much compressed data is held in memory before being written to the archive.
+const COMPRESSION_BATCH_SIZE: u64 = 0x400_0000;
+
+/// How files are compressed inside of the archive
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum ZipCompression {
+    /// Store files as-is. Files are copied straight from the package into the
+    /// archive, making this much faster for data that's already compressed.
+    Stored,
+    #[default]
+    Deflated,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ZipOptions {
+    pub compression: ZipCompression,
+    /// Compression level passed to the compressor. `None` uses its default.
+    pub level: Option<i32>,
+}
+
+fn zip_datetime(datetime: NaiveDateTime) -> Option<DateTime> {
+    DateTime::from_date_and_time(
+        u16::try_from(datetime.year()).ok()?,
+        datetime.month() as u8,
+        datetime.day() as u8,
+        datetime.hour() as u8,
+        datetime.minute() as u8,
+        datetime.second() as u8,
+    )
+    .ok()
+}
+
+impl<'a> StfsPackage<'a> {
+    /// Compresses a single file into an archive of its own so that it can be
+    /// copied into the real archive without compressing it again
+    fn compress_file(
+        &self,
+        path: &str,
+        entry: &StfsFileEntry,
+        options: FileOptions,
+    ) -> Result<Vec<u8>, StfsError> {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file(
+            path,
+            options.large_file(entry.file_size > u32::MAX as usize),
+        )?;
+        self.extract_file(&mut zip, entry)?;
+
+        Ok(zip.finish()?.into_inner())
+    }
+
+    /// Writes every file and folder in the package to a zip archive, keeping
+    /// the package's folder hierarchy. `on_progress` is called after each entry
+    /// is added, and the archive is abandoned with [`StfsError::Cancelled`] if
+    /// `cancel` is cancelled.
+    ///
+    /// With the `parallel` feature, files are extracted and compressed on the
+    /// rayon thread pool while a single writer adds them to the archive in order.
+    pub fn write_zip<W: Write + Seek>(
+        &self,
+        writer: W,
+        options: &ZipOptions,
+        mut on_progress: impl ProgressSink,
+        cancel: &CancellationToken,
+    ) -> Result<W, StfsError> {
+        let method = match options.compression {
+            ZipCompression::Stored => CompressionMethod::Stored,
+            ZipCompression::Deflated => CompressionMethod::Deflated,
+        };
+        let base_options = FileOptions::default()
+            .compression_method(method)
+            .compression_level(options.level)
+            .unix_permissions(0o755);
+
+        let entries = self.walk_entries();
+        let mut progress = EntryProgress::new(
+            entries.len(),
+            entries
+                .iter()
+                .map(|(_, entry)| entry.file_size as u64)
+                .sum(),
+        );
+
+        let file_options = |entry: &StfsFileEntry| match entry.created().and_then(zip_datetime) {
+            Some(modified) => base_options.last_modified_time(modified),
+            None => base_options,
+        };
+
+        let mut zip = ZipWriter::new(writer);
+        if options.compression == ZipCompression::Stored {
+            // Nothing is gained by preparing stored files on other threads, so
+            // they're streamed from the package without any intermediate copy
+            for (path, entry) in &entries {
+                cancel.check()?;
+                if entry.is_folder() {
+                    zip.add_directory(path, file_options(entry))?;
+                } else {
+                    zip.start_file(
+                        path,
+                        file_options(entry).large_file(entry.file_size > u32::MAX as usize),
+                    )?;
+                    self.extract_file(&mut zip, entry)?;
+                }
+
+                on_progress.report(progress.advance(path, entry.file_size as u64));
+            }
+
+            return Ok(zip.finish()?);
+        }
+
+        let mut entries = entries.iter().peekable();
+        while entries.peek().is_some() {
+            cancel.check()?;
+            let mut batch = Vec::new();
+            let mut batch_size = 0;
+            while let Some((path, entry)) =
+                entries.next_if(|_| batch.is_empty() || batch_size < COMPRESSION_BATCH_SIZE)
+            {
+                batch_size += entry.file_size as u64;
+                batch.push((path, entry));
+            }
+
+            let compressed = parallel::map_collect(batch.clone(), |(path, entry)| {
+                if cancel.is_cancelled() {
+                    Err(StfsError::Cancelled)
+                } else if entry.is_folder() {
+                    Ok(None)
+                } else {
+                    self.compress_file(path, entry, file_options(entry))
+                        .map(Some)
+                }
+            });
+
+            for ((path, entry), compressed) in batch.into_iter().zip(compressed) {
+                match compressed? {
+                    Some(compressed) => {
+                        let mut archive = ZipArchive::new(Cursor::new(compressed))?;
+                        zip.raw_copy_file(archive.by_index(0)?)?;
+                    }
+                    None => zip.add_directory(path, file_options(entry))?,
+                }
+
+                on_progress.report(progress.advance(path, entry.file_size as u64));
+            }
+        }
+
+        Ok(zip.finish()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use zip::ZipArchive;
+
+    use super::*;
+    use crate::{progress::Progress, StfsPackageBuilder};
+
+    #[test]
+    fn zip_round_trip() {
+        let contents = (0..0x2345).map(|i| i as u8).collect::<Vec<_>>();
+
+        let mut builder = StfsPackageBuilder::new();
+        builder
+            .add_file("saves/slot1.bin", contents.clone())
+            .unwrap()
+            .add_file("readme.txt", b"hello".to_vec())
+            .unwrap();
+        let data = builder.build().unwrap();
+        let package = StfsPackage::try_from(data.as_slice()).unwrap();
+
+        for (compression, method) in [
+            (ZipCompression::Stored, CompressionMethod::Stored),
+            (ZipCompression::Deflated, CompressionMethod::Deflated),
+        ] {
+            let options = ZipOptions {
+                compression,
+                level: None,
+            };
+            let mut paths = Vec::new();
+            let zip = package
+                .write_zip(
+                    Cursor::new(Vec::new()),
+                    &options,
+                    |progress: Progress<'_>| paths.push(progress.entry.unwrap().to_owned()),
+                    &CancellationToken::default(),
+                )
+                .unwrap();
+            assert_eq!(paths, ["saves", "saves/slot1.bin", "readme.txt"]);
+
+            let mut archive = ZipArchive::new(zip).unwrap();
+            assert!(archive.by_name("saves/").unwrap().is_dir());
+
+            let mut extracted = Vec::new();
+            let mut file = archive.by_name("saves/slot1.bin").unwrap();
+            assert_eq!(file.compression(), method);
+            file.read_to_end(&mut extracted).unwrap();
+            assert_eq!(extracted, contents);
+        }
+    }
+}
@@ -0,0 +1,229 @@
+//! Reading packages from async sources, such as objects in S3 or files served
+//! over HTTP, without downloading them in full.
+
+use std::{io::SeekFrom, ops::Range};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+
+use crate::{
+    mirror::{file_hash_ranges, metadata_ranges, PartialMirror, INITIAL_FETCH_SIZE},
+    stfs::{StfsError, StfsFileEntry, StfsPackage},
+};
+
+/// A package read on demand from an [`AsyncRead`] + [`AsyncSeek`] source.
+///
+/// Opening the package fetches its header, hash tables, and file table. File
+/// contents are only read when [`StfsPackageAsync::read_file`] or
+/// [`StfsPackageAsync::copy_file`] asks for them, and are streamed rather than
+/// kept, so a package can be browsed while transferring little more than its
+/// metadata.
+///
+/// Blocks are stored at their offsets in a buffer the size of the package, so
+/// the package must still fit in the address space.
+pub struct StfsPackageAsync<R> {
+    reader: R,
+    mirror: PartialMirror,
+}
+
+impl<R: AsyncRead + AsyncSeek + Unpin> StfsPackageAsync<R> {
+    /// Fetches the header, hash tables, and file table from `reader`
+    pub async fn open(mut reader: R) -> Result<Self, StfsError> {
+        let len = reader.seek(SeekFrom::End(0)).await? as usize;
+        let mut package = StfsPackageAsync {
+            reader,
+            mirror: PartialMirror::new(len),
+        };
+        package.fetch(0..INITIAL_FETCH_SIZE.min(len)).await?;
+        package.ensure(metadata_ranges).await?;
+
+        Ok(package)
+    }
+
+    /// Parses the fetched metadata. The returned package's header and file
+    /// table can be used as normal, but file contents must be read through
+    /// [`StfsPackageAsync::read_file`] or [`StfsPackageAsync::copy_file`] since
+    /// they haven't been fetched.
+    pub fn package(&self) -> Result<StfsPackage<'_>, StfsError> {
+        StfsPackage::try_from(self.mirror.data.as_slice())
+    }
+
+    /// Reads the contents of the file described by `entry`
+    pub async fn read_file(&mut self, entry: &StfsFileEntry) -> Result<Vec<u8>, StfsError> {
+        let mut contents = Vec::with_capacity(entry.file_size);
+        self.copy_file(entry, &mut contents).await?;
+
+        Ok(contents)
+    }
+
+    /// Streams the contents of the file described by `entry` to `writer`,
+    /// reading each contiguous region from the source as it's written. Returns
+    /// the number of bytes written.
+    pub async fn copy_file<W: AsyncWrite + Unpin>(
+        &mut self,
+        entry: &StfsFileEntry,
+        writer: &mut W,
+    ) -> Result<u64, StfsError> {
+        self.ensure(|package| file_hash_ranges(package, entry))
+            .await?;
+
+        let regions = {
+            let package = StfsPackage::parse_lazy(&self.mirror.data)?;
+            let base = self.mirror.data.as_ptr() as usize;
+            let mut regions: Vec<Range<usize>> = Vec::new();
+            for mapping in package.file_mappings(entry)? {
+                let start = mapping.as_ptr() as usize - base;
+                let end = start + mapping.len();
+                match regions.last_mut() {
+                    Some(last) if last.end == start => last.end = end,
+                    _ => regions.push(start..end),
+                }
+            }
+
+            regions
+        };
+
+        let mut written = 0;
+        for region in regions {
+            self.reader
+                .seek(SeekFrom::Start(region.start as u64))
+                .await?;
+            let len = region.len() as u64;
+            if tokio::io::copy(&mut (&mut self.reader).take(len), writer).await? != len {
+                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+            }
+            written += len;
+        }
+        writer.flush().await?;
+
+        Ok(written)
+    }
+
+    /// Returns the source the package is read from
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    /// Reads `range` of the package into the mirror
+    async fn fetch(&mut self, range: Range<usize>) -> Result<(), StfsError> {
+        self.reader
+            .seek(SeekFrom::Start(range.start as u64))
+            .await?;
+        let mut body = vec![0; range.len()];
+        self.reader.read_exact(&mut body).await?;
+        self.mirror.fill(range.start, &body);
+
+        Ok(())
+    }
+
+    /// Fetches whatever `needed` asks for until parsing the package and calling
+    /// `needed` again asks for nothing new
+    async fn ensure(
+        &mut self,
+        needed: impl Fn(&StfsPackage<'_>) -> Result<Vec<Range<usize>>, StfsError>,
+    ) -> Result<(), StfsError> {
+        loop {
+            let missing = {
+                let package = StfsPackage::parse_lazy(&self.mirror.data)?;
+                self.mirror.missing(needed(&package)?)
+            };
+            if missing.is_empty() {
+                return Ok(());
+            }
+
+            for range in missing {
+                self.fetch(range).await?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        future::Future,
+        io::{self, Cursor},
+        pin::{pin, Pin},
+        task::{Context, Poll, Waker},
+    };
+
+    use super::*;
+    use crate::StfsPackageBuilder;
+
+    /// Runs a future whose I/O never blocks, as is the case for in-memory sources
+    fn block_on<F: Future>(future: F) -> F::Output {
+        let mut future = pin!(future);
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                return output;
+            }
+        }
+    }
+
+    /// Records every range read from the inner source
+    struct RecordingReader {
+        inner: Cursor<Vec<u8>>,
+        read: Vec<Range<u64>>,
+    }
+
+    impl AsyncRead for RecordingReader {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut tokio::io::ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            let start = self.inner.position();
+            let filled = buf.filled().len();
+            let result = Pin::new(&mut self.inner).poll_read(cx, buf);
+            let end = start + (buf.filled().len() - filled) as u64;
+            self.read.push(start..end);
+            result
+        }
+    }
+
+    impl AsyncSeek for RecordingReader {
+        fn start_seek(mut self: Pin<&mut Self>, position: SeekFrom) -> io::Result<()> {
+            Pin::new(&mut self.inner).start_seek(position)
+        }
+
+        fn poll_complete(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<u64>> {
+            Pin::new(&mut self.inner).poll_complete(cx)
+        }
+    }
+
+    #[test]
+    fn reads_files_without_fetching_others() {
+        let mut builder = StfsPackageBuilder::new();
+        let big = vec![0xAB; 0x3000];
+        builder.add_file("a.bin", vec![0xCD; 0x2000]).unwrap();
+        builder.add_file("saves/b.bin", big.clone()).unwrap();
+        let data = builder.build().unwrap();
+
+        let reader = RecordingReader {
+            inner: Cursor::new(data.clone()),
+            read: Vec::new(),
+        };
+        let mut remote = block_on(StfsPackageAsync::open(reader)).unwrap();
+
+        let a = {
+            let package = remote.package().unwrap();
+            let files = package.files();
+            files.entry(files.find("a.bin").unwrap()).clone()
+        };
+        let entry = {
+            let package = remote.package().unwrap();
+            let files = package.files();
+            files.entry(files.find("saves/b.bin").unwrap()).clone()
+        };
+        assert_eq!(block_on(remote.read_file(&entry)).unwrap(), big);
+
+        let local = StfsPackage::try_from(data.as_slice()).unwrap();
+        let a_start = local.block_to_addr(a.starting_block_num).unwrap();
+        let a_range = a_start..a_start + a.file_size as u64;
+        let reader = remote.into_inner();
+        assert!(reader
+            .read
+            .iter()
+            .all(|read| read.end <= a_range.start || read.start >= a_range.end));
+    }
+}
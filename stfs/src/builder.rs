@@ -0,0 +1,571 @@
+//! Creating new STFS packages, either from scratch or based on an existing package.
+
+use byteorder::{BigEndian, ByteOrder};
+
+use crate::{
+    layout::{HeaderLayout, LicenseLayout, StfsVolumeDescriptorLayout, VOLUME_DESCRIPTOR_SIZE},
+    metadata::PackageMetadata,
+    progress::{EntryProgress, Progress},
+    stfs::{
+        data_block_address, true_block_count, ContentType, EntryFlags, HashTableMeta, StfsError,
+        StfsFileEntry, StfsPackage, StfsPackageSex, BLOCK_SIZE, HASHES_PER_HASH_TABLE,
+        HASHES_PER_HASH_TABLE_LEVEL,
+    },
+    write::{self, *},
+};
+
+/// Header size used by packages created from scratch (metadata version 2
+/// without installer data)
+const DEFAULT_HEADER_SIZE: u32 = 0x971A;
+
+enum EntryKind {
+    Folder,
+    File(Vec<u8>),
+}
+
+struct BuilderEntry {
+    name: String,
+    /// Index of the containing folder in the builder's entry list, or `None`
+    /// for entries in the root of the package
+    parent: Option<usize>,
+    kind: EntryKind,
+}
+
+/// Builds a new STFS package from a set of files.
+///
+/// The header is kept as raw bytes so that packages based on a template retain
+/// every metadata field, including ones this crate does not parse. Setters
+/// overwrite individual fields in place. The file table, hash tables, and
+/// volume descriptor are generated by [`StfsPackageBuilder::build`].
+///
+/// Packages are not signed.
+pub struct StfsPackageBuilder {
+    header: Vec<u8>,
+    sex: StfsPackageSex,
+    timestamp: u32,
+    entries: Vec<BuilderEntry>,
+}
+
+impl Default for StfsPackageBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl StfsPackageBuilder {
+    /// Creates a builder for an empty `CON` saved game package
+    pub fn new() -> Self {
+        let first_table_address = ((DEFAULT_HEADER_SIZE as usize) + 0xFFF) & !0xFFF;
+        let mut header = vec![0u8; first_table_address];
+
+        header[..4].copy_from_slice(b"CON ");
+        let mut licenses = [LicenseLayout::default(); 16];
+        // First license: unrestricted
+        licenses[0].license = u64::MAX;
+        HeaderLayout {
+            licenses,
+            header_size: DEFAULT_HEADER_SIZE,
+            content_type: ContentType::SavedGame as u32,
+            metadata_version: 2,
+            ..Default::default()
+        }
+        .write_to(&mut header)
+        .expect("header is large enough for the fixed fields");
+
+        StfsPackageBuilder {
+            header,
+            sex: StfsPackageSex::Female,
+            timestamp: 0,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Creates a builder which copies all header metadata (title ID, content type,
+    /// images, licenses, etc.) from `template`. None of the template's files are
+    /// carried over.
+    pub fn from_template(template: &StfsPackage<'_>) -> Self {
+        let header_len = template.hash_table_meta.first_table_address;
+
+        StfsPackageBuilder {
+            header: template.input[..header_len].to_vec(),
+            sex: template.sex,
+            timestamp: 0,
+            entries: Vec::new(),
+        }
+    }
+
+    /// Sets which hash table layout the package uses
+    pub fn sex(&mut self, sex: StfsPackageSex) -> &mut Self {
+        self.sex = sex;
+        self
+    }
+
+    /// Sets the FAT-style timestamp used for the created and accessed times of
+    /// every entry added to the package
+    pub fn timestamp(&mut self, timestamp: u32) -> &mut Self {
+        self.timestamp = timestamp;
+        self
+    }
+
+    pub fn content_type(&mut self, content_type: ContentType) -> &mut Self {
+        BigEndian::write_u32(&mut self.header[CONTENT_TYPE_OFFSET..], content_type as u32);
+        self
+    }
+
+    pub fn title_id(&mut self, title_id: u32) -> &mut Self {
+        BigEndian::write_u32(&mut self.header[TITLE_ID_OFFSET..], title_id);
+        self
+    }
+
+    pub fn media_id(&mut self, media_id: u32) -> &mut Self {
+        BigEndian::write_u32(&mut self.header[MEDIA_ID_OFFSET..], media_id);
+        self
+    }
+
+    pub fn console_id(&mut self, console_id: [u8; 5]) -> &mut Self {
+        self.header[CONSOLE_ID_OFFSET..CONSOLE_ID_OFFSET + 5].copy_from_slice(&console_id);
+        self
+    }
+
+    pub fn profile_id(&mut self, profile_id: [u8; 8]) -> &mut Self {
+        self.header[PROFILE_ID_OFFSET..PROFILE_ID_OFFSET + 8].copy_from_slice(&profile_id);
+        self
+    }
+
+    pub fn device_id(&mut self, device_id: [u8; 0x14]) -> &mut Self {
+        self.header[DEVICE_ID_OFFSET..DEVICE_ID_OFFSET + 0x14].copy_from_slice(&device_id);
+        self
+    }
+
+    pub fn transfer_flags(&mut self, transfer_flags: u8) -> &mut Self {
+        self.header[TRANSFER_FLAGS_OFFSET] = transfer_flags;
+        self
+    }
+
+    /// Sets the display name for the default (English) locale
+    pub fn display_name(&mut self, name: &str) -> Result<&mut Self, StfsError> {
+        write::write_utf16_str(
+            &mut self.header,
+            DISPLAY_NAME_OFFSET,
+            LOCALIZED_STRING_SIZE,
+            "display_name",
+            name,
+        )?;
+        Ok(self)
+    }
+
+    /// Sets the display description for the default (English) locale
+    pub fn display_description(&mut self, description: &str) -> Result<&mut Self, StfsError> {
+        write::write_utf16_str(
+            &mut self.header,
+            DISPLAY_DESCRIPTION_OFFSET,
+            LOCALIZED_STRING_SIZE,
+            "display_description",
+            description,
+        )?;
+        Ok(self)
+    }
+
+    pub fn publisher_name(&mut self, name: &str) -> Result<&mut Self, StfsError> {
+        write::write_utf16_str(
+            &mut self.header,
+            PUBLISHER_NAME_OFFSET,
+            LOCALIZED_STRING_SIZE,
+            "publisher_name",
+            name,
+        )?;
+        Ok(self)
+    }
+
+    pub fn title_name(&mut self, name: &str) -> Result<&mut Self, StfsError> {
+        write::write_utf16_str(
+            &mut self.header,
+            TITLE_NAME_OFFSET,
+            LOCALIZED_STRING_SIZE,
+            "title_name",
+            name,
+        )?;
+        Ok(self)
+    }
+
+    /// Sets every field of `metadata` which isn't `None`
+    pub fn metadata(&mut self, metadata: &PackageMetadata) -> Result<&mut Self, StfsError> {
+        metadata.write(&mut self.header)?;
+        Ok(self)
+    }
+
+    pub fn thumbnail_image(&mut self, image: &[u8]) -> Result<&mut Self, StfsError> {
+        write::write_image(
+            &mut self.header,
+            THUMBNAIL_IMAGE_SIZE_OFFSET,
+            THUMBNAIL_IMAGE_OFFSET,
+            "thumbnail_image",
+            image,
+        )?;
+        Ok(self)
+    }
+
+    pub fn title_image(&mut self, image: &[u8]) -> Result<&mut Self, StfsError> {
+        write::write_image(
+            &mut self.header,
+            TITLE_THUMBNAIL_IMAGE_SIZE_OFFSET,
+            TITLE_THUMBNAIL_IMAGE_OFFSET,
+            "title_image",
+            image,
+        )?;
+        Ok(self)
+    }
+
+    /// Adds an empty folder, creating any missing parent folders. `path` is
+    /// separated by `/` or `\`.
+    pub fn add_folder(&mut self, path: &str) -> Result<&mut Self, StfsError> {
+        let mut parent = None;
+        for component in path_components(path)? {
+            parent = Some(self.folder(parent, component));
+        }
+
+        Ok(self)
+    }
+
+    /// Adds a file, creating any missing parent folders. `path` is separated by
+    /// `/` or `\`.
+    pub fn add_file(
+        &mut self,
+        path: &str,
+        data: impl Into<Vec<u8>>,
+    ) -> Result<&mut Self, StfsError> {
+        let mut components = path_components(path)?;
+        let name = components
+            .pop()
+            .ok_or_else(|| StfsError::InvalidFileName(path.to_owned()))?;
+
+        let mut parent = None;
+        for component in components {
+            parent = Some(self.folder(parent, component));
+        }
+
+        let data = data.into();
+        if u32::try_from(data.len()).is_err() {
+            return Err(StfsError::PackageTooLarge);
+        }
+
+        if let Some(existing) = self.find_entry(parent, name) {
+            if matches!(self.entries[existing].kind, EntryKind::Folder) {
+                return Err(StfsError::InvalidFileName(path.to_owned()));
+            }
+            self.entries[existing].kind = EntryKind::File(data);
+        } else {
+            self.entries.push(BuilderEntry {
+                name: name.to_owned(),
+                parent,
+                kind: EntryKind::File(data),
+            });
+        }
+
+        Ok(self)
+    }
+
+    fn find_entry(&self, parent: Option<usize>, name: &str) -> Option<usize> {
+        self.entries
+            .iter()
+            .position(|entry| entry.parent == parent && entry.name == name)
+    }
+
+    /// Returns the index of the folder `name` within `parent`, creating it if necessary
+    fn folder(&mut self, parent: Option<usize>, name: &str) -> usize {
+        if let Some(existing) = self.find_entry(parent, name) {
+            return existing;
+        }
+
+        self.entries.push(BuilderEntry {
+            name: name.to_owned(),
+            parent,
+            kind: EntryKind::Folder,
+        });
+        self.entries.len() - 1
+    }
+
+    /// Lays out the file table, file data, and hash tables and returns the bytes
+    /// of the finished package.
+    pub fn build(&self) -> Result<Vec<u8>, StfsError> {
+        self.build_with_progress(|_| {})
+    }
+
+    /// Same as [`StfsPackageBuilder::build`], calling `on_progress` after each
+    /// entry is written
+    pub fn build_with_progress(
+        &self,
+        mut on_progress: impl FnMut(Progress<'_>),
+    ) -> Result<Vec<u8>, StfsError> {
+        if self.entries.len() >= ROOT_PATH_INDICATOR as usize {
+            return Err(StfsError::PackageTooLarge);
+        }
+
+        let first_table_address = self.header.len();
+        let sex = self.sex;
+
+        // The file table occupies the first data blocks, followed by each file's
+        // data in consecutive blocks
+        let file_table_block_count =
+            std::cmp::max(1, self.entries.len().div_ceil(FILE_TABLE_ENTRIES_PER_BLOCK));
+        let mut next_block = file_table_block_count;
+        let mut file_blocks = Vec::with_capacity(self.entries.len());
+        for entry in &self.entries {
+            let block_count = match &entry.kind {
+                EntryKind::File(data) => data.len().div_ceil(BLOCK_SIZE),
+                EntryKind::Folder => 0,
+            };
+            let starting_block = if block_count > 0 { next_block } else { 0 };
+            file_blocks.push((starting_block, block_count));
+            next_block += block_count;
+        }
+
+        let allocated_block_count = next_block;
+        if allocated_block_count > HASHES_PER_HASH_TABLE_LEVEL[2] {
+            return Err(StfsError::PackageTooLarge);
+        }
+
+        let meta = HashTableMeta {
+            block_step: sex.block_step(),
+            ..Default::default()
+        };
+        let true_block_count = true_block_count(allocated_block_count, sex);
+
+        let mut data = vec![0u8; first_table_address + (true_block_count * BLOCK_SIZE)];
+        data[..first_table_address].copy_from_slice(&self.header);
+
+        // Records a data block as allocated and links it to the next block of its chain
+        let link_block = |data: &mut [u8], block: usize, next_block: Option<usize>| {
+            let address = first_table_address
+                + (meta.compute_first_level_backing_hash_block_number(block, sex) * BLOCK_SIZE)
+                + ((block % HASHES_PER_HASH_TABLE) * HASH_ENTRY_SIZE);
+            write::link_hash_entry(&mut data[address..address + HASH_ENTRY_SIZE], next_block);
+        };
+
+        for block in 0..file_table_block_count {
+            let next_block = if block + 1 < file_table_block_count {
+                Some(block + 1)
+            } else {
+                None
+            };
+            link_block(&mut data, block, next_block);
+        }
+
+        // Parents are always added before their children
+        let mut paths: Vec<String> = Vec::with_capacity(self.entries.len());
+        for entry in &self.entries {
+            let path = match entry.parent {
+                Some(parent) => format!("{}/{}", paths[parent], entry.name),
+                None => entry.name.clone(),
+            };
+            paths.push(path);
+        }
+        let mut progress = EntryProgress::new(
+            self.entries.len(),
+            self.entries
+                .iter()
+                .map(|entry| match &entry.kind {
+                    EntryKind::File(contents) => contents.len() as u64,
+                    EntryKind::Folder => 0,
+                })
+                .sum(),
+        );
+
+        for (index, (entry, (starting_block, block_count))) in
+            self.entries.iter().zip(file_blocks).enumerate()
+        {
+            let entry_address = data_block_address(
+                index / FILE_TABLE_ENTRIES_PER_BLOCK,
+                sex,
+                first_table_address,
+            )? as usize
+                + ((index % FILE_TABLE_ENTRIES_PER_BLOCK) * FILE_TABLE_ENTRY_SIZE);
+            let (flags, file_size) = match &entry.kind {
+                // Files are always written to consecutive blocks
+                EntryKind::File(contents) => (
+                    if block_count > 0 {
+                        EntryFlags::CONSECUTIVE_BLOCKS
+                    } else {
+                        EntryFlags::empty()
+                    },
+                    contents.len(),
+                ),
+                EntryKind::Folder => (EntryFlags::FOLDER, 0),
+            };
+
+            write::write_file_entry(
+                &mut data[entry_address..entry_address + FILE_TABLE_ENTRY_SIZE],
+                &StfsFileEntry {
+                    name: entry.name.clone(),
+                    flags,
+                    block_count,
+                    starting_block_num: starting_block,
+                    path_indicator: entry
+                        .parent
+                        .map(|parent| parent as u16)
+                        .unwrap_or(ROOT_PATH_INDICATOR),
+                    file_size,
+                    created_time_stamp: self.timestamp,
+                    access_time_stamp: self.timestamp,
+                    ..Default::default()
+                },
+            );
+
+            if let EntryKind::File(contents) = &entry.kind {
+                for (i, chunk) in contents.chunks(BLOCK_SIZE).enumerate() {
+                    let block = starting_block + i;
+                    let address = data_block_address(block, sex, first_table_address)? as usize;
+                    data[address..address + chunk.len()].copy_from_slice(chunk);
+
+                    let next_block = if i + 1 < block_count {
+                        Some(block + 1)
+                    } else {
+                        None
+                    };
+                    link_block(&mut data, block, next_block);
+                }
+            }
+
+            on_progress(progress.advance(&paths[index], file_size as u64));
+        }
+
+        // Fill out the volume descriptor. Only the first copy of each hash table
+        // is written, so the top table's copy bit is cleared.
+        StfsVolumeDescriptorLayout {
+            size: VOLUME_DESCRIPTOR_SIZE as u8,
+            block_separation: match sex {
+                StfsPackageSex::Female => 1,
+                StfsPackageSex::Male => 0,
+            },
+            file_table_block_count: file_table_block_count as u16,
+            file_table_block_num: 0,
+            allocated_block_count: allocated_block_count as u32,
+            unallocated_block_count: 0,
+            ..Default::default()
+        }
+        .write_to(&mut data)?;
+
+        BigEndian::write_u64(
+            &mut data[CONTENT_SIZE_OFFSET..],
+            (true_block_count * BLOCK_SIZE) as u64,
+        );
+
+        write::rehash(&mut data)?;
+
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{allocation::BlockState, TitleId};
+
+    fn find_file(package: &StfsPackage<'_>, path: &str) -> Option<crate::StfsFileEntry> {
+        let files = package.files();
+        files.find(path).map(|id| files.entry(id).clone())
+    }
+
+    fn extract(package: &StfsPackage<'_>, path: &str) -> Vec<u8> {
+        let entry = find_file(package, path).expect("file missing from package");
+        let mut contents = Vec::new();
+        package.extract_file(&mut contents, &entry).unwrap();
+        contents
+    }
+
+    fn pattern(len: usize, seed: u8) -> Vec<u8> {
+        (0..len)
+            .map(|i| (i as u8).wrapping_mul(31).wrapping_add(seed))
+            .collect()
+    }
+
+    fn round_trip(sex: StfsPackageSex) {
+        let files = [
+            ("small.txt", pattern(10, 1)),
+            ("saves/multi.bin", pattern(BLOCK_SIZE * 3 + 5, 2)),
+            // Starts partway through the first hash table and crosses into the next
+            ("saves/slot1/large.bin", pattern(BLOCK_SIZE * 200, 3)),
+            ("empty", Vec::new()),
+        ];
+
+        let mut builder = StfsPackageBuilder::new();
+        builder.sex(sex).title_id(0x4D53_07E6);
+        builder.add_folder("saves/slot2").unwrap();
+        for (path, contents) in &files {
+            builder.add_file(path, contents.clone()).unwrap();
+        }
+        let data = builder.build().unwrap();
+
+        let package = StfsPackage::try_from(data.as_slice()).unwrap();
+        assert_eq!(package.sex, sex);
+        assert_eq!(package.header.title_id, TitleId(0x4D53_07E6));
+        for (path, contents) in &files {
+            assert_eq!(&extract(&package, path), contents, "{}", path);
+        }
+        assert!(find_file(&package, "saves/slot2").is_some());
+
+        let allocator = package.block_allocator();
+        allocator.check_unallocated_block_count().unwrap();
+
+        let block_map = allocator.block_map();
+        let allocated = block_map
+            .iter()
+            .filter(|state| **state == BlockState::Allocated)
+            .count();
+        let tables = block_map
+            .iter()
+            .filter(|state| matches!(state, BlockState::HashTable(_)))
+            .count();
+        assert_eq!(allocated, allocator.allocated_block_count());
+        assert_eq!(
+            tables,
+            package
+                .hash_table_meta
+                .tables_per_level
+                .iter()
+                .sum::<usize>()
+                << (sex as usize)
+        );
+    }
+
+    #[test]
+    fn round_trip_female() {
+        round_trip(StfsPackageSex::Female);
+    }
+
+    #[test]
+    fn round_trip_male() {
+        round_trip(StfsPackageSex::Male);
+    }
+
+    #[test]
+    fn from_template_keeps_metadata() {
+        let mut builder = StfsPackageBuilder::new();
+        builder
+            .title_id(0x5841_0912)
+            .display_name("Template")
+            .unwrap();
+        builder.add_file("old.bin", pattern(100, 4)).unwrap();
+        let template_data = builder.build().unwrap();
+        let template = StfsPackage::try_from(template_data.as_slice()).unwrap();
+
+        let mut builder = StfsPackageBuilder::from_template(&template);
+        builder.add_file("new.bin", pattern(100, 5)).unwrap();
+        let data = builder.build().unwrap();
+        let package = StfsPackage::try_from(data.as_slice()).unwrap();
+
+        assert_eq!(package.header.title_id, TitleId(0x5841_0912));
+        assert_eq!(package.header.display_name, "Template");
+        assert!(find_file(&package, "old.bin").is_none());
+        assert_eq!(extract(&package, "new.bin"), pattern(100, 5));
+    }
+
+    #[test]
+    fn rejects_invalid_names() {
+        let mut builder = StfsPackageBuilder::new();
+        assert!(builder.add_file("", Vec::new()).is_err());
+        assert!(builder.add_file(&"a".repeat(0x29), Vec::new()).is_err());
+        builder.add_folder("dir").unwrap();
+        assert!(builder.add_file("dir", Vec::new()).is_err());
+    }
+}
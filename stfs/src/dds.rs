@@ -0,0 +1,329 @@
+//! Decoding DirectDraw Surface (DDS) images, which gamer picture and theme
+//! packages commonly contain, into RGBA pixels that can be displayed.
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::stfs::StfsError;
+
+const MAGIC: &[u8; 4] = b"DDS ";
+/// Size of the magic and header which precede the surface data
+const HEADER_SIZE: usize = 0x80;
+const HEIGHT_OFFSET: usize = 0xC;
+const WIDTH_OFFSET: usize = 0x10;
+const PIXEL_FORMAT_FLAGS_OFFSET: usize = 0x50;
+const FOURCC_OFFSET: usize = 0x54;
+const RGB_BIT_COUNT_OFFSET: usize = 0x58;
+const RED_MASK_OFFSET: usize = 0x5C;
+const GREEN_MASK_OFFSET: usize = 0x60;
+const BLUE_MASK_OFFSET: usize = 0x64;
+const ALPHA_MASK_OFFSET: usize = 0x68;
+
+const PIXEL_FORMAT_ALPHA_PIXELS: u32 = 0x1;
+const PIXEL_FORMAT_FOURCC: u32 = 0x4;
+const PIXEL_FORMAT_RGB: u32 = 0x40;
+
+/// Images larger than this in either dimension are rejected rather than
+/// allocating a buffer for them
+const MAX_DIMENSION: usize = 0x4000;
+
+/// An image decoded to 8-bit RGBA pixels, stored row by row from the top left
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RgbaImage {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+#[derive(Debug, Copy, Clone)]
+enum BlockFormat {
+    Dxt1,
+    Dxt3,
+    Dxt5,
+}
+
+impl BlockFormat {
+    fn block_size(self) -> usize {
+        match self {
+            BlockFormat::Dxt1 => 8,
+            BlockFormat::Dxt3 | BlockFormat::Dxt5 => 16,
+        }
+    }
+
+    /// Decodes one compressed block into its 4x4 texels
+    fn decode(self, block: &[u8]) -> [[u8; 4]; 16] {
+        match self {
+            BlockFormat::Dxt1 => decode_color_block(block, false),
+            BlockFormat::Dxt3 => {
+                let mut texels = decode_color_block(&block[8..], true);
+                let alpha = LittleEndian::read_u64(block);
+                for (i, texel) in texels.iter_mut().enumerate() {
+                    texel[3] = ((alpha >> (i * 4)) & 0xF) as u8 * 0x11;
+                }
+
+                texels
+            }
+            BlockFormat::Dxt5 => {
+                let mut texels = decode_color_block(&block[8..], true);
+                let palette = alpha_palette(block[0], block[1]);
+                let indices = LittleEndian::read_u48(&block[2..]);
+                for (i, texel) in texels.iter_mut().enumerate() {
+                    texel[3] = palette[((indices >> (i * 3)) & 0x7) as usize];
+                }
+
+                texels
+            }
+        }
+    }
+}
+
+/// Whether `data` starts with the DDS magic
+pub fn is_dds(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Decodes the top mip level of a DDS image. DXT1, DXT3, DXT5, and
+/// uncompressed RGB surfaces are supported.
+pub fn decode_dds(data: &[u8]) -> Result<RgbaImage, StfsError> {
+    if !is_dds(data) {
+        return Err(StfsError::InvalidImage("missing DDS magic"));
+    }
+    if data.len() < HEADER_SIZE {
+        return Err(StfsError::Truncated {
+            needed: HEADER_SIZE,
+            len: data.len(),
+        });
+    }
+
+    let width = LittleEndian::read_u32(&data[WIDTH_OFFSET..]) as usize;
+    let height = LittleEndian::read_u32(&data[HEIGHT_OFFSET..]) as usize;
+    if width == 0 || height == 0 || width > MAX_DIMENSION || height > MAX_DIMENSION {
+        return Err(StfsError::InvalidImage("invalid DDS dimensions"));
+    }
+
+    let flags = LittleEndian::read_u32(&data[PIXEL_FORMAT_FLAGS_OFFSET..]);
+    let pixels = if flags & PIXEL_FORMAT_FOURCC != 0 {
+        let format = match &data[FOURCC_OFFSET..FOURCC_OFFSET + 4] {
+            b"DXT1" => BlockFormat::Dxt1,
+            b"DXT3" => BlockFormat::Dxt3,
+            b"DXT5" => BlockFormat::Dxt5,
+            _ => {
+                return Err(StfsError::InvalidImage(
+                    "unsupported DDS compression format",
+                ))
+            }
+        };
+
+        decode_blocks(data, width, height, format)?
+    } else if flags & PIXEL_FORMAT_RGB != 0 {
+        decode_rgb(data, width, height, flags & PIXEL_FORMAT_ALPHA_PIXELS != 0)?
+    } else {
+        return Err(StfsError::InvalidImage("unsupported DDS pixel format"));
+    };
+
+    Ok(RgbaImage {
+        width,
+        height,
+        pixels,
+    })
+}
+
+/// Returns the surface data following the header, which must be at least `len` bytes
+fn surface(data: &[u8], len: usize) -> Result<&[u8], StfsError> {
+    data.get(HEADER_SIZE..HEADER_SIZE + len)
+        .ok_or(StfsError::Truncated {
+            needed: HEADER_SIZE + len,
+            len: data.len(),
+        })
+}
+
+fn decode_blocks(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    format: BlockFormat,
+) -> Result<Vec<u8>, StfsError> {
+    let blocks_wide = width.div_ceil(4);
+    let blocks_high = height.div_ceil(4);
+    let surface = surface(data, blocks_wide * blocks_high * format.block_size())?;
+
+    let mut pixels = vec![0; width * height * 4];
+    for (i, block) in surface.chunks_exact(format.block_size()).enumerate() {
+        let block_x = (i % blocks_wide) * 4;
+        let block_y = (i / blocks_wide) * 4;
+        for (j, texel) in format.decode(block).iter().enumerate() {
+            let x = block_x + (j % 4);
+            let y = block_y + (j / 4);
+            if x < width && y < height {
+                let offset = ((y * width) + x) * 4;
+                pixels[offset..offset + 4].copy_from_slice(texel);
+            }
+        }
+    }
+
+    Ok(pixels)
+}
+
+fn decode_rgb(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    has_alpha: bool,
+) -> Result<Vec<u8>, StfsError> {
+    let bytes_per_pixel = match LittleEndian::read_u32(&data[RGB_BIT_COUNT_OFFSET..]) {
+        16 => 2,
+        24 => 3,
+        32 => 4,
+        _ => return Err(StfsError::InvalidImage("unsupported DDS bit count")),
+    };
+    let masks = [
+        LittleEndian::read_u32(&data[RED_MASK_OFFSET..]),
+        LittleEndian::read_u32(&data[GREEN_MASK_OFFSET..]),
+        LittleEndian::read_u32(&data[BLUE_MASK_OFFSET..]),
+        if has_alpha {
+            LittleEndian::read_u32(&data[ALPHA_MASK_OFFSET..])
+        } else {
+            0
+        },
+    ];
+    let surface = surface(data, width * height * bytes_per_pixel)?;
+
+    let mut pixels = Vec::with_capacity(width * height * 4);
+    for pixel in surface.chunks_exact(bytes_per_pixel) {
+        let pixel = LittleEndian::read_uint(pixel, bytes_per_pixel) as u32;
+        pixels.extend(masks.iter().map(|&mask| channel(pixel, mask)));
+        if !has_alpha {
+            *pixels.last_mut().unwrap() = 0xFF;
+        }
+    }
+
+    Ok(pixels)
+}
+
+/// Extracts the channel selected by `mask` from `pixel`, scaled to 8 bits
+fn channel(pixel: u32, mask: u32) -> u8 {
+    if mask == 0 {
+        return 0;
+    }
+
+    let shift = mask.trailing_zeros();
+    let max = u64::from(mask >> shift);
+    let value = u64::from((pixel & mask) >> shift);
+
+    ((value * 0xFF) / max) as u8
+}
+
+fn rgb565(color: u16) -> [u8; 4] {
+    let scale = |value: u16, max: u16| ((u32::from(value) * 0xFF) / u32::from(max)) as u8;
+
+    [
+        scale(color >> 11, 0x1F),
+        scale((color >> 5) & 0x3F, 0x3F),
+        scale(color & 0x1F, 0x1F),
+        0xFF,
+    ]
+}
+
+/// Mixes the colors of `a` and `b` in the ratio `a_weight:b_weight`
+fn blend(a: [u8; 4], b: [u8; 4], a_weight: u32, b_weight: u32) -> [u8; 4] {
+    let mut color = [0xFF; 4];
+    for (channel, (a, b)) in color.iter_mut().zip(a.iter().zip(b.iter())).take(3) {
+        *channel = (((u32::from(*a) * a_weight) + (u32::from(*b) * b_weight))
+            / (a_weight + b_weight)) as u8;
+    }
+
+    color
+}
+
+/// Decodes the color half of a block. DXT1 blocks whose first color is not
+/// greater than the second use a three color palette plus transparent black,
+/// while DXT3 and DXT5 always use four colors.
+fn decode_color_block(block: &[u8], always_four_colors: bool) -> [[u8; 4]; 16] {
+    let first = LittleEndian::read_u16(block);
+    let second = LittleEndian::read_u16(&block[2..]);
+    let (a, b) = (rgb565(first), rgb565(second));
+
+    let palette = if always_four_colors || first > second {
+        [a, b, blend(a, b, 2, 1), blend(a, b, 1, 2)]
+    } else {
+        [a, b, blend(a, b, 1, 1), [0; 4]]
+    };
+
+    let indices = LittleEndian::read_u32(&block[4..]);
+    let mut texels = [[0; 4]; 16];
+    for (i, texel) in texels.iter_mut().enumerate() {
+        *texel = palette[((indices >> (i * 2)) & 0x3) as usize];
+    }
+
+    texels
+}
+
+/// The eight alpha values a DXT5 block interpolates between
+fn alpha_palette(first: u8, second: u8) -> [u8; 8] {
+    let (first, second) = (u32::from(first), u32::from(second));
+    let mut palette = [0; 8];
+    palette[0] = first as u8;
+    palette[1] = second as u8;
+
+    // Six interpolated values, or four plus fully transparent and fully opaque
+    let steps: u32 = if first > second { 7 } else { 5 };
+    for (i, alpha) in (1..).zip(palette[2..=steps as usize].iter_mut()) {
+        *alpha = (((first * (steps - i)) + (second * i)) / steps) as u8;
+    }
+    if steps == 5 {
+        palette[7] = 0xFF;
+    }
+
+    palette
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(width: u32, height: u32, flags: u32, fourcc: &[u8; 4]) -> Vec<u8> {
+        let mut data = vec![0; HEADER_SIZE];
+        data[..4].copy_from_slice(MAGIC);
+        LittleEndian::write_u32(&mut data[4..], 0x7C);
+        LittleEndian::write_u32(&mut data[HEIGHT_OFFSET..], height);
+        LittleEndian::write_u32(&mut data[WIDTH_OFFSET..], width);
+        LittleEndian::write_u32(&mut data[PIXEL_FORMAT_FLAGS_OFFSET..], flags);
+        data[FOURCC_OFFSET..FOURCC_OFFSET + 4].copy_from_slice(fourcc);
+
+        data
+    }
+
+    #[test]
+    fn decodes_dxt1_and_rgb() {
+        // A 2x2 DXT1 image whose texels alternate between pure red and pure blue
+        let mut dxt1 = header(2, 2, PIXEL_FORMAT_FOURCC, b"DXT1");
+        dxt1.extend([0x00, 0xF8, 0x1F, 0x00, 0b0100_0100, 0b0100_0100, 0, 0]);
+
+        let image = decode_dds(&dxt1).unwrap();
+        assert_eq!((image.width, image.height), (2, 2));
+        assert_eq!(
+            image.pixels,
+            [
+                [0xFF, 0, 0, 0xFF],
+                [0, 0, 0xFF, 0xFF],
+                [0xFF, 0, 0, 0xFF],
+                [0, 0, 0xFF, 0xFF],
+            ]
+            .concat()
+        );
+        assert!(matches!(
+            decode_dds(&dxt1[..HEADER_SIZE + 4]),
+            Err(StfsError::Truncated { .. })
+        ));
+
+        // A 1x1 A8R8G8B8 image
+        let mut rgb = header(1, 1, PIXEL_FORMAT_RGB | PIXEL_FORMAT_ALPHA_PIXELS, &[0; 4]);
+        LittleEndian::write_u32(&mut rgb[RGB_BIT_COUNT_OFFSET..], 32);
+        LittleEndian::write_u32(&mut rgb[RED_MASK_OFFSET..], 0x00FF0000);
+        LittleEndian::write_u32(&mut rgb[GREEN_MASK_OFFSET..], 0x0000FF00);
+        LittleEndian::write_u32(&mut rgb[BLUE_MASK_OFFSET..], 0x000000FF);
+        LittleEndian::write_u32(&mut rgb[ALPHA_MASK_OFFSET..], 0xFF000000);
+        rgb.extend([0x30, 0x20, 0x10, 0x80]);
+
+        assert_eq!(decode_dds(&rgb).unwrap().pixels, [0x10, 0x20, 0x30, 0x80]);
+    }
+}
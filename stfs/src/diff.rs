@@ -0,0 +1,226 @@
+//! Comparing the metadata and contents of two packages.
+
+use std::collections::BTreeMap;
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+
+#[cfg(feature = "serde")]
+use crate::serialize::serialize_bytes;
+use crate::{
+    metadata::MetadataField,
+    stfs::{StfsError, StfsPackage},
+};
+
+/// What's known about an entry for the purposes of comparing it
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum EntrySummary {
+    Folder,
+    File {
+        size: usize,
+        #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_bytes"))]
+        digest: [u8; 20],
+    },
+}
+
+/// How an entry or field differs between the two packages
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum Change {
+    Unchanged,
+    /// Only present in the second package
+    Added,
+    /// Only present in the first package
+    Removed,
+    Modified,
+}
+
+/// A header field's value in each package
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct FieldDiff {
+    pub name: &'static str,
+    pub a: String,
+    pub b: String,
+}
+
+impl FieldDiff {
+    pub fn differs(&self) -> bool {
+        self.a != self.b
+    }
+}
+
+/// An entry's summary in each package it appears in
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct EntryDiff {
+    pub path: String,
+    pub a: Option<EntrySummary>,
+    pub b: Option<EntrySummary>,
+}
+
+impl EntryDiff {
+    pub fn change(&self) -> Change {
+        match (&self.a, &self.b) {
+            (Some(a), Some(b)) if a == b => Change::Unchanged,
+            (Some(_), Some(_)) => Change::Modified,
+            (None, _) => Change::Added,
+            (Some(_), None) => Change::Removed,
+        }
+    }
+}
+
+/// Every header field and entry of two packages, paired up for comparison
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct PackageDiff {
+    pub metadata: Vec<FieldDiff>,
+    /// Entries from either package, sorted by path
+    pub entries: Vec<EntryDiff>,
+}
+
+impl PackageDiff {
+    /// Returns true if any header field or entry differs
+    pub fn differs(&self) -> bool {
+        self.metadata.iter().any(FieldDiff::differs)
+            || self
+                .entries
+                .iter()
+                .any(|entry| entry.change() != Change::Unchanged)
+    }
+}
+
+fn metadata(package: &StfsPackage<'_>) -> Vec<(&'static str, String)> {
+    let header = &package.header;
+    let mut fields = vec![
+        ("package_type", header.package_type.to_string()),
+        ("content_type", header.content_type.to_string()),
+        ("version", header.version.to_string()),
+        ("base_version", header.base_version.to_string()),
+    ];
+    fields.extend(
+        MetadataField::ALL
+            .into_iter()
+            .map(|field| (field.name(), field.get(header))),
+    );
+
+    fields
+}
+
+fn entries(package: &StfsPackage<'_>) -> Result<BTreeMap<String, EntrySummary>, StfsError> {
+    let mut buffer = Vec::new();
+    package
+        .walk_entries()
+        .into_iter()
+        .map(|(path, entry)| {
+            if entry.is_folder() {
+                return Ok((path, EntrySummary::Folder));
+            }
+
+            buffer.clear();
+            package.extract_file(&mut buffer, &entry)?;
+            let summary = EntrySummary::File {
+                size: buffer.len(),
+                digest: Sha1::digest(&buffer).into(),
+            };
+
+            Ok((path, summary))
+        })
+        .collect()
+}
+
+/// Pairs up the header fields and entries of `a` and `b`. Files are compared
+/// by size and SHA-1, so every file in both packages is read.
+pub fn diff(a: &StfsPackage<'_>, b: &StfsPackage<'_>) -> Result<PackageDiff, StfsError> {
+    let metadata = metadata(a)
+        .into_iter()
+        .zip(metadata(b))
+        .map(|((name, a), (_, b))| FieldDiff { name, a, b })
+        .collect();
+
+    let mut b_entries = entries(b)?;
+    let mut entries: Vec<EntryDiff> = entries(a)?
+        .into_iter()
+        .map(|(path, a)| {
+            let b = b_entries.remove(&path);
+            EntryDiff {
+                path,
+                a: Some(a),
+                b,
+            }
+        })
+        .collect();
+    entries.extend(b_entries.into_iter().map(|(path, b)| EntryDiff {
+        path,
+        a: None,
+        b: Some(b),
+    }));
+    entries.sort_by(|x, y| x.path.cmp(&y.path));
+
+    Ok(PackageDiff { metadata, entries })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StfsPackageBuilder;
+
+    #[test]
+    fn diff_classifies_entries() {
+        let mut builder = StfsPackageBuilder::new();
+        builder
+            .display_name("Before")
+            .unwrap()
+            .add_file("same.bin", vec![1; 0x10])
+            .unwrap()
+            .add_file("changed.bin", vec![2; 0x10])
+            .unwrap()
+            .add_file("removed.bin", vec![3; 0x10])
+            .unwrap();
+        let a_data = builder.build().unwrap();
+
+        let mut builder = StfsPackageBuilder::new();
+        builder
+            .display_name("After")
+            .unwrap()
+            .add_file("same.bin", vec![1; 0x10])
+            .unwrap()
+            .add_file("changed.bin", vec![2; 0x11])
+            .unwrap()
+            .add_file("added.bin", vec![4; 0x10])
+            .unwrap();
+        let b_data = builder.build().unwrap();
+
+        let a = StfsPackage::try_from(a_data.as_slice()).unwrap();
+        let b = StfsPackage::try_from(b_data.as_slice()).unwrap();
+        let package_diff = diff(&a, &b).unwrap();
+        assert!(package_diff.differs());
+
+        let changes: Vec<(&str, Change)> = package_diff
+            .entries
+            .iter()
+            .map(|entry| (entry.path.as_str(), entry.change()))
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                ("added.bin", Change::Added),
+                ("changed.bin", Change::Modified),
+                ("removed.bin", Change::Removed),
+                ("same.bin", Change::Unchanged),
+            ]
+        );
+
+        let differing: Vec<&str> = package_diff
+            .metadata
+            .iter()
+            .filter(|field| field.differs())
+            .map(|field| field.name)
+            .collect();
+        assert_eq!(differing, [MetadataField::DisplayName.name()]);
+
+        assert!(!diff(&a, &a).unwrap().differs());
+    }
+}
@@ -0,0 +1,237 @@
+//! Text forms of package enums and IDs, for showing to and reading from users.
+
+use std::{fmt, str::FromStr};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    stfs::{ContentType, LicenseType, PackageType, StfsError},
+    titles::title_name,
+};
+
+/// ID of the game a package belongs to. Formats as 8 uppercase hex digits.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
+pub struct TitleId(pub u32);
+
+impl TitleId {
+    /// Name of the game from the bundled title database, if it's known
+    pub fn name(self) -> Option<&'static str> {
+        title_name(self.0)
+    }
+}
+
+/// ID of the disc or media a package's content came from. Formats as 8
+/// uppercase hex digits.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
+pub struct MediaId(pub u32);
+
+/// Parses up to 8 hex digits, with or without a `0x` prefix
+fn parse_hex_u32(kind: &'static str, s: &str) -> Result<u32, StfsError> {
+    let digits = s
+        .strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s);
+    if digits.is_empty() || digits.len() > 8 {
+        return Err(StfsError::InvalidValue {
+            kind,
+            value: s.to_owned(),
+        });
+    }
+
+    u32::from_str_radix(digits, 16).map_err(|_| StfsError::InvalidValue {
+        kind,
+        value: s.to_owned(),
+    })
+}
+
+impl fmt::Display for TitleId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:08X}", self.0)
+    }
+}
+
+impl FromStr for TitleId {
+    type Err = StfsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_hex_u32("title ID", s).map(TitleId)
+    }
+}
+
+impl fmt::Display for MediaId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:08X}", self.0)
+    }
+}
+
+impl FromStr for MediaId {
+    type Err = StfsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_hex_u32("media ID", s).map(MediaId)
+    }
+}
+
+/// Finds the variant in `all` whose name matches `s`, ignoring case
+fn parse_name<T: fmt::Display + Copy>(
+    kind: &'static str,
+    all: &[T],
+    s: &str,
+) -> Result<T, StfsError> {
+    all.iter()
+        .copied()
+        .find(|value| value.to_string().eq_ignore_ascii_case(s))
+        .ok_or_else(|| StfsError::InvalidValue {
+            kind,
+            value: s.to_owned(),
+        })
+}
+
+impl PackageType {
+    pub const ALL: [PackageType; 3] = [PackageType::Con, PackageType::Live, PackageType::Pirs];
+}
+
+/// Formats as the package's magic, e.g. `CON`
+impl fmt::Display for PackageType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let magic = self.magic();
+        f.write_str(std::str::from_utf8(&magic).unwrap_or_default().trim_end())
+    }
+}
+
+impl FromStr for PackageType {
+    type Err = StfsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_name("package type", &PackageType::ALL, s)
+    }
+}
+
+impl ContentType {
+    pub const ALL: [ContentType; 31] = [
+        ContentType::ArcadeGame,
+        ContentType::AvatarAssetPack,
+        ContentType::AvatarItem,
+        ContentType::CacheFile,
+        ContentType::CommunityGame,
+        ContentType::GameDemo,
+        ContentType::GameOnDemand,
+        ContentType::GamerPicture,
+        ContentType::GamerTitle,
+        ContentType::GameTrailer,
+        ContentType::GameVideo,
+        ContentType::InstalledGame,
+        ContentType::Installer,
+        ContentType::IPTVPauseBuffer,
+        ContentType::LicenseStore,
+        ContentType::MarketPlaceContent,
+        ContentType::Movie,
+        ContentType::MusicVideo,
+        ContentType::PodcastVideo,
+        ContentType::Profile,
+        ContentType::Publisher,
+        ContentType::SavedGame,
+        ContentType::StorageDownload,
+        ContentType::Theme,
+        ContentType::Video,
+        ContentType::ViralVideo,
+        ContentType::XboxDownload,
+        ContentType::XboxOriginalGame,
+        ContentType::XboxSavedGame,
+        ContentType::Xbox360Title,
+        ContentType::XNA,
+    ];
+}
+
+/// Formats as the variant name, e.g. `SavedGame`
+impl fmt::Display for ContentType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl FromStr for ContentType {
+    type Err = StfsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_name("content type", &ContentType::ALL, s)
+    }
+}
+
+impl LicenseType {
+    pub const ALL: [LicenseType; 9] = [
+        LicenseType::Unused,
+        LicenseType::Unrestricted,
+        LicenseType::ConsoleProfileLicense,
+        LicenseType::WindowsProfileLicense,
+        LicenseType::ConsoleLicense,
+        LicenseType::MediaFlags,
+        LicenseType::KeyVaultPrivileges,
+        LicenseType::HyperVisorFlags,
+        LicenseType::UserPrivileges,
+    ];
+}
+
+/// Formats as the variant name, e.g. `Unrestricted`
+impl fmt::Display for LicenseType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self, f)
+    }
+}
+
+impl FromStr for LicenseType {
+    type Err = StfsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_name("license type", &LicenseType::ALL, s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn values_round_trip_through_strings() {
+        for package_type in PackageType::ALL {
+            assert_eq!(
+                package_type.to_string().parse::<PackageType>().unwrap(),
+                package_type
+            );
+        }
+        for content_type in ContentType::ALL {
+            assert_eq!(
+                content_type.to_string().parse::<ContentType>().unwrap(),
+                content_type
+            );
+        }
+        for license_type in LicenseType::ALL {
+            assert_eq!(
+                license_type.to_string().parse::<LicenseType>().unwrap(),
+                license_type
+            );
+        }
+
+        assert_eq!(PackageType::Con.to_string(), "CON");
+        assert_eq!("live".parse::<PackageType>().unwrap(), PackageType::Live);
+        assert_eq!(
+            "savedgame".parse::<ContentType>().unwrap(),
+            ContentType::SavedGame
+        );
+        assert!("Sandwich".parse::<ContentType>().is_err());
+
+        assert_eq!(TitleId(0x4D53_07E6).to_string(), "4D5307E6");
+        assert_eq!(MediaId(0x1F).to_string(), "0000001F");
+        assert_eq!(
+            "0x4d5307e6".parse::<TitleId>().unwrap(),
+            TitleId(0x4D53_07E6)
+        );
+        assert_eq!("1F".parse::<MediaId>().unwrap(), MediaId(0x1F));
+        assert!("".parse::<TitleId>().is_err());
+        assert!("123456789".parse::<TitleId>().is_err());
+        assert!("XYZ".parse::<TitleId>().is_err());
+    }
+}
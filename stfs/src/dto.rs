@@ -0,0 +1,195 @@
+//! Owned, versioned views of a package for JSON and JavaScript consumers.
+//!
+//! [`StfsPackage`] and [`StfsFileEntry`] borrow from the package data and
+//! follow its on-disk layout, which changes as the parser does. The types here
+//! copy out the fields that tools display, and only change along with
+//! [`SCHEMA_VERSION`].
+
+use chrono::NaiveDateTime;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    display::{MediaId, TitleId},
+    metadata::hex,
+    stfs::{ContentType, HashTableLevel, PackageType, StfsFileEntry, StfsPackage, StfsPackageSex},
+};
+
+/// Version of the [`PackageInfo`] and [`EntryInfo`] layouts. Bumped whenever a
+/// field is removed, renamed, or changes type; adding a field doesn't bump it.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Who signed a package, as far as can be told without checking the signature
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(rename_all = "snake_case")
+)]
+pub enum SignatureKind {
+    /// The signature region is blank
+    Unsigned,
+    /// CON packages are signed by the console which created them
+    ConsoleSigned,
+    /// LIVE and PIRS packages are signed by Microsoft
+    StrongSigned,
+}
+
+/// A package's header and hash tree details
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PackageInfo {
+    pub schema_version: u32,
+    pub package_type: PackageType,
+    pub signature: SignatureKind,
+    pub content_type: ContentType,
+    pub title_name: String,
+    pub display_name: String,
+    pub display_description: String,
+    pub publisher_name: String,
+    pub title_id: TitleId,
+    /// Name of the game from the bundled title database
+    #[cfg_attr(
+        feature = "serde",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub resolved_title: Option<String>,
+    pub media_id: MediaId,
+    pub version: u32,
+    pub base_version: u32,
+    /// Upper-case hex
+    pub console_id: String,
+    /// Upper-case hex
+    pub profile_id: String,
+    /// Upper-case hex
+    pub device_id: String,
+    pub content_size: u64,
+    pub sex: StfsPackageSex,
+    pub top_hash_table_level: HashTableLevel,
+    pub allocated_block_count: u32,
+    pub unallocated_block_count: u32,
+}
+
+impl PackageInfo {
+    pub fn new(package: &StfsPackage<'_>) -> Self {
+        let header = &package.header;
+        let stfs_vol = header.volume_descriptor.stfs_ref();
+
+        let signature = if header.unsigned {
+            SignatureKind::Unsigned
+        } else if header.package_type == PackageType::Con {
+            SignatureKind::ConsoleSigned
+        } else {
+            SignatureKind::StrongSigned
+        };
+
+        PackageInfo {
+            schema_version: SCHEMA_VERSION,
+            package_type: header.package_type,
+            signature,
+            content_type: header.content_type,
+            title_name: header.title_name.clone(),
+            display_name: header.display_name.clone(),
+            display_description: header.display_description.clone(),
+            publisher_name: header.publisher_name.clone(),
+            title_id: header.title_id,
+            resolved_title: header.title_id.name().map(str::to_owned),
+            media_id: header.media_id,
+            version: header.version,
+            base_version: header.base_version,
+            console_id: hex(&header.console_id),
+            profile_id: hex(&header.profile_id),
+            device_id: hex(header.device_id),
+            content_size: header.content_size,
+            sex: package.sex,
+            top_hash_table_level: package.hash_table_meta.top_table.level,
+            allocated_block_count: stfs_vol.allocated_block_count,
+            unallocated_block_count: stfs_vol.unallocated_block_count,
+        }
+    }
+}
+
+/// A file or folder in a package
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct EntryInfo {
+    /// `/`-separated path from the root
+    pub path: String,
+    pub folder: bool,
+    /// Whether the file's blocks follow each other, so it can be read without
+    /// the hash tables
+    pub consecutive: bool,
+    pub size: usize,
+    pub created: Option<NaiveDateTime>,
+    pub accessed: Option<NaiveDateTime>,
+    pub starting_block: usize,
+    pub block_count: usize,
+    /// Address of the first block, or `None` for entries with no blocks
+    pub offset: Option<u64>,
+}
+
+impl EntryInfo {
+    pub fn new(package: &StfsPackage<'_>, path: String, entry: &StfsFileEntry) -> Self {
+        EntryInfo {
+            path,
+            folder: entry.is_folder(),
+            consecutive: entry.has_consecutive_blocks(),
+            size: entry.file_size,
+            created: entry.created(),
+            accessed: entry.accessed(),
+            starting_block: entry.starting_block_num,
+            block_count: entry.block_count,
+            offset: package
+                .block_to_addr(entry.starting_block_num)
+                .ok()
+                .filter(|_| entry.block_count > 0),
+        }
+    }
+
+    /// Every file and folder in `package`, in the order of
+    /// [`StfsPackage::walk_entries`]
+    pub fn list(package: &StfsPackage<'_>) -> Vec<Self> {
+        package
+            .walk_entries()
+            .into_iter()
+            .map(|(path, entry)| EntryInfo::new(package, path, &entry))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StfsPackageBuilder;
+
+    #[test]
+    fn describes_built_package() {
+        let mut builder = StfsPackageBuilder::new();
+        builder
+            .title_id(0x4D5307E6)
+            .display_name("Saves")
+            .unwrap()
+            .add_file("saves/a.bin", vec![1; 0x1800])
+            .unwrap();
+        let data = builder.build().unwrap();
+        let package = StfsPackage::try_from(data.as_slice()).unwrap();
+
+        let info = PackageInfo::new(&package);
+        assert_eq!(info.schema_version, SCHEMA_VERSION);
+        assert_eq!(info.signature, SignatureKind::Unsigned);
+        assert_eq!(info.display_name, "Saves");
+        assert_eq!(info.title_id, TitleId(0x4D5307E6));
+
+        let entries = EntryInfo::list(&package);
+        let paths: Vec<&str> = entries.iter().map(|entry| entry.path.as_str()).collect();
+        assert_eq!(paths, ["saves", "saves/a.bin"]);
+        let file = &entries[1];
+        assert!(!file.folder && file.consecutive);
+        assert_eq!((file.size, file.block_count), (0x1800, 2));
+        assert_eq!(
+            file.offset,
+            Some(package.block_to_addr(file.starting_block).unwrap())
+        );
+        assert_eq!(entries[0].offset, None);
+    }
+}
@@ -0,0 +1,428 @@
+//! Adding, replacing, and removing files in an existing package.
+//!
+//! Edits reuse free blocks where possible and grow the package with
+//! [`reserve_blocks`] otherwise. The hash tree is regenerated after every edit,
+//! which invalidates any existing signature.
+
+use std::collections::HashSet;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+
+use crate::{
+    stfs::{data_block_address, EntryFlags, StfsError, StfsFileEntry, StfsPackage, BLOCK_SIZE},
+    write::{self, *},
+};
+
+/// Returns the data blocks holding a file's contents, in order. A chain which
+/// leaves the package is cut short.
+pub(crate) fn file_blocks(package: &StfsPackage<'_>, entry: &StfsFileEntry) -> Vec<usize> {
+    let block_count = package.block_allocator().allocated_block_count();
+    if entry.has_consecutive_blocks() {
+        let end = (entry.starting_block_num + entry.block_count).min(block_count);
+        return (entry.starting_block_num..end).collect();
+    }
+
+    let mut blocks = Vec::with_capacity(entry.block_count);
+    let mut block = entry.starting_block_num;
+    for _ in 0..entry.block_count {
+        if block >= block_count {
+            break;
+        }
+        blocks.push(block);
+        block = match package.try_block_hash_entry(block, package.input) {
+            Ok(hash_entry) => hash_entry.next_block as usize,
+            Err(_) => break,
+        };
+    }
+
+    blocks
+}
+
+/// Picks `count` blocks from the sorted `free` list, preferring a consecutive run
+/// so that the file can be read without walking its hash chain
+fn choose_blocks(free: &[usize], count: usize) -> Vec<usize> {
+    if count == 0 {
+        return Vec::new();
+    }
+
+    let run_start = free
+        .windows(count)
+        .position(|window| window[count - 1] - window[0] == count - 1);
+
+    match run_start {
+        Some(start) => free[start..start + count].to_vec(),
+        None => free[..count].to_vec(),
+    }
+}
+
+/// Recounts the free blocks for the volume descriptor and regenerates the hash tree
+fn finish_edit(data: &mut [u8]) -> Result<(), StfsError> {
+    let unallocated_block_count = StfsPackage::try_from(&*data)?
+        .block_allocator()
+        .unallocated_block_count();
+    BigEndian::write_u32(
+        &mut data[UNALLOCATED_BLOCK_COUNT_OFFSET..],
+        unallocated_block_count as u32,
+    );
+
+    rehash(data)
+}
+
+/// Writes `contents` to the file at `path`, replacing it if it already exists.
+/// Missing parent folders are created.
+pub fn inject_file(data: &mut Vec<u8>, path: &str, contents: &[u8]) -> Result<(), StfsError> {
+    let components = write::path_components(path)?;
+    let (name, parents) = components
+        .split_last()
+        .expect("path_components never returns an empty path");
+    if u32::try_from(contents.len()).is_err() {
+        return Err(StfsError::PackageTooLarge);
+    }
+
+    let data_block_count = contents.len().div_ceil(BLOCK_SIZE);
+
+    // Resolve as much of the path as already exists
+    let (mut parent, missing_folders, existing, freed_blocks, free_slots, table_block_count) = {
+        let package = StfsPackage::try_from(data.as_slice())?;
+        let entries = package.walk_entries();
+        let find = |path: &str| {
+            entries
+                .iter()
+                .find(|(entry_path, _)| entry_path == path)
+                .map(|(_, entry)| entry.clone())
+        };
+
+        let mut parent = None;
+        let mut missing_folders = &parents[parents.len()..];
+        for depth in 0..parents.len() {
+            let folder_path = parents[..=depth].join("/");
+            match find(&folder_path) {
+                Some(folder) if folder.is_folder() => parent = Some(folder.index),
+                Some(_) => return Err(StfsError::InvalidFileName(folder_path)),
+                None => {
+                    missing_folders = &parents[depth..];
+                    break;
+                }
+            }
+        }
+
+        let existing = if missing_folders.is_empty() {
+            find(&components.join("/"))
+        } else {
+            None
+        };
+        if existing
+            .as_ref()
+            .is_some_and(|existing| existing.is_folder())
+        {
+            return Err(StfsError::InvalidFileName(path.to_owned()));
+        }
+
+        let freed_blocks = existing
+            .as_ref()
+            .map(|existing| file_blocks(&package, existing))
+            .unwrap_or_default();
+
+        let table_block_count = package.file_table_blocks()?.len();
+        let used_slots: HashSet<usize> = entries.iter().map(|(_, entry)| entry.index).collect();
+        let free_slots: Vec<usize> = (0..table_block_count * FILE_TABLE_ENTRIES_PER_BLOCK)
+            .filter(|slot| !used_slots.contains(slot))
+            .collect();
+
+        (
+            parent,
+            missing_folders,
+            existing,
+            freed_blocks,
+            free_slots,
+            table_block_count,
+        )
+    };
+
+    let new_slot_count = missing_folders.len() + usize::from(existing.is_none());
+    let new_table_block_count = new_slot_count
+        .saturating_sub(free_slots.len())
+        .div_ceil(FILE_TABLE_ENTRIES_PER_BLOCK);
+    let slot_count = (table_block_count + new_table_block_count) * FILE_TABLE_ENTRIES_PER_BLOCK;
+    if slot_count > ROOT_PATH_INDICATOR as usize {
+        return Err(StfsError::PackageTooLarge);
+    }
+
+    // Grow the package if the free blocks plus those of the file being replaced
+    // aren't enough
+    let free_block_count = StfsPackage::try_from(data.as_slice())?
+        .block_allocator()
+        .unallocated_block_count()
+        + freed_blocks.len();
+    let needed_block_count = data_block_count + new_table_block_count;
+    if needed_block_count > free_block_count {
+        reserve_blocks(data, needed_block_count - free_block_count)?;
+    }
+
+    // Work out where everything goes now that the layout is final
+    let package = StfsPackage::try_from(data.as_slice())?;
+    let sex = package.sex;
+    let first_table_address = package.hash_table_meta.first_table_address;
+
+    let mut free_blocks: Vec<usize> = package
+        .block_allocator()
+        .free_blocks()
+        .chain(freed_blocks.iter().copied())
+        .collect();
+    free_blocks.sort_unstable();
+
+    let new_table_blocks = free_blocks[..new_table_block_count].to_vec();
+    let data_blocks = choose_blocks(&free_blocks[new_table_block_count..], data_block_count);
+
+    let mut table_blocks = package.file_table_blocks()?;
+    let last_table_block = *table_blocks
+        .last()
+        .expect("packages always have at least one file table block");
+    table_blocks.extend(&new_table_blocks);
+
+    let slot_address = |slot: usize| -> Result<usize, StfsError> {
+        Ok(data_block_address(
+            table_blocks[slot / FILE_TABLE_ENTRIES_PER_BLOCK],
+            sex,
+            first_table_address,
+        )? as usize
+            + ((slot % FILE_TABLE_ENTRIES_PER_BLOCK) * FILE_TABLE_ENTRY_SIZE))
+    };
+    let mut slots = free_slots
+        .into_iter()
+        .chain(table_block_count * FILE_TABLE_ENTRIES_PER_BLOCK..slot_count);
+
+    let hash_entry_address = |block: usize| -> Result<_, StfsError> {
+        let address = package.block_hash_address(block, package.input)? as usize;
+        Ok(address..address + HASH_ENTRY_SIZE)
+    };
+    let freed_entries = freed_blocks
+        .iter()
+        .map(|block| hash_entry_address(*block))
+        .collect::<Result<Vec<_>, _>>()?;
+    let table_entries = std::iter::once(last_table_block)
+        .chain(new_table_blocks.iter().copied())
+        .map(|block| Ok((block, hash_entry_address(block)?)))
+        .collect::<Result<Vec<_>, StfsError>>()?;
+    let data_entries = data_blocks
+        .iter()
+        .map(|block| hash_entry_address(*block))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut entries = Vec::with_capacity(new_slot_count);
+    for folder in missing_folders {
+        let slot = slots.next().expect("enough file table slots were reserved");
+        entries.push((
+            slot_address(slot)?,
+            StfsFileEntry {
+                name: (*folder).to_owned(),
+                flags: EntryFlags::FOLDER,
+                path_indicator: parent
+                    .map(|parent| parent as u16)
+                    .unwrap_or(ROOT_PATH_INDICATOR),
+                ..Default::default()
+            },
+        ));
+        parent = Some(slot);
+    }
+
+    let is_consecutive = data_blocks
+        .windows(2)
+        .all(|window| window[1] == window[0] + 1);
+    let (file_address, timestamps) = match &existing {
+        Some(existing) => (
+            existing.file_entry_address as usize,
+            (existing.created_time_stamp, existing.access_time_stamp),
+        ),
+        None => (
+            slot_address(slots.next().expect("enough file table slots were reserved"))?,
+            (0, 0),
+        ),
+    };
+    entries.push((
+        file_address,
+        StfsFileEntry {
+            name: (*name).to_owned(),
+            flags: if is_consecutive && !data_blocks.is_empty() {
+                EntryFlags::CONSECUTIVE_BLOCKS
+            } else {
+                EntryFlags::empty()
+            },
+            block_count: data_blocks.len(),
+            starting_block_num: data_blocks.first().copied().unwrap_or_default(),
+            path_indicator: parent
+                .map(|parent| parent as u16)
+                .unwrap_or(ROOT_PATH_INDICATOR),
+            file_size: contents.len(),
+            created_time_stamp: timestamps.0,
+            access_time_stamp: timestamps.1,
+            ..Default::default()
+        },
+    ));
+
+    drop(package);
+
+    // Release the replaced file's blocks. Any which are reused are relinked below.
+    for entry in freed_entries {
+        data[entry][0x14..].fill(0);
+    }
+
+    // Extend the file table's block chain
+    for (i, (block, entry)) in table_entries.iter().enumerate() {
+        let next_block = table_entries.get(i + 1).map(|(next, _)| *next);
+        write::link_hash_entry(&mut data[entry.clone()], next_block);
+
+        if *block != last_table_block {
+            let address = data_block_address(*block, sex, first_table_address)? as usize;
+            data[address..address + BLOCK_SIZE].fill(0);
+        }
+    }
+    LittleEndian::write_u16(
+        &mut data[FILE_TABLE_BLOCK_COUNT_OFFSET..],
+        table_blocks.len() as u16,
+    );
+
+    for (address, entry) in entries {
+        write::write_file_entry(&mut data[address..address + FILE_TABLE_ENTRY_SIZE], &entry);
+    }
+
+    for (i, (block, entry)) in data_blocks.iter().zip(data_entries).enumerate() {
+        let address = data_block_address(*block, sex, first_table_address)? as usize;
+        let chunk = &contents[i * BLOCK_SIZE..contents.len().min((i + 1) * BLOCK_SIZE)];
+        data[address..address + chunk.len()].copy_from_slice(chunk);
+        data[address + chunk.len()..address + BLOCK_SIZE].fill(0);
+
+        write::link_hash_entry(&mut data[entry], data_blocks.get(i + 1).copied());
+    }
+
+    finish_edit(data)
+}
+
+/// Removes the file or folder at `path`. Folders are removed along with
+/// everything inside of them.
+pub fn remove_entry(data: &mut [u8], path: &str) -> Result<(), StfsError> {
+    let path = write::path_components(path)?.join("/");
+
+    let (entry_addresses, freed_entries) = {
+        let package = StfsPackage::try_from(&*data)?;
+        let removed: Vec<StfsFileEntry> = package
+            .walk_entries()
+            .into_iter()
+            .filter(|(entry_path, _)| {
+                entry_path == &path
+                    || (entry_path.starts_with(path.as_str())
+                        && entry_path[path.len()..].starts_with('/'))
+            })
+            .map(|(_, entry)| entry)
+            .collect();
+        if removed.is_empty() {
+            return Err(StfsError::FileNotFound(path));
+        }
+
+        let entry_addresses: Vec<usize> = removed
+            .iter()
+            .map(|entry| entry.file_entry_address as usize)
+            .collect();
+        let freed_entries = removed
+            .iter()
+            .filter(|entry| !entry.is_folder())
+            .flat_map(|entry| file_blocks(&package, entry))
+            .map(|block| Ok(package.block_hash_address(block, package.input)? as usize))
+            .collect::<Result<Vec<usize>, StfsError>>()?;
+
+        (entry_addresses, freed_entries)
+    };
+
+    for address in entry_addresses {
+        data[address..address + FILE_TABLE_ENTRY_SIZE].fill(0);
+    }
+    for address in freed_entries {
+        data[address + 0x14..address + HASH_ENTRY_SIZE].fill(0);
+    }
+
+    finish_edit(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{StfsPackageBuilder, StfsPackageSex};
+
+    fn read(data: &[u8], path: &str) -> Option<Vec<u8>> {
+        let package = StfsPackage::try_from(data).unwrap();
+        package
+            .block_allocator()
+            .check_unallocated_block_count()
+            .unwrap();
+
+        let (_, entry) = package
+            .walk_entries()
+            .into_iter()
+            .find(|(entry_path, _)| entry_path == path)?;
+        let mut contents = Vec::new();
+        package.extract_file(&mut contents, &entry).unwrap();
+        Some(contents)
+    }
+
+    #[test]
+    fn inject_replace_and_remove() {
+        for sex in [StfsPackageSex::Female, StfsPackageSex::Male] {
+            let mut builder = StfsPackageBuilder::new();
+            builder
+                .sex(sex)
+                .add_file("save.bin", vec![0x42; BLOCK_SIZE * 2])
+                .unwrap();
+            let mut data = builder.build().unwrap();
+
+            let small = vec![0x11; 0x1234];
+            inject_file(&mut data, "data/levels/1.bin", &small).unwrap();
+            assert_eq!(read(&data, "data/levels/1.bin").unwrap(), small);
+            assert_eq!(read(&data, "save.bin").unwrap(), vec![0x42; BLOCK_SIZE * 2]);
+
+            let large = (0..BLOCK_SIZE * 5 + 7).map(|i| i as u8).collect::<Vec<_>>();
+            inject_file(&mut data, "data/levels/1.bin", &large).unwrap();
+            assert_eq!(read(&data, "data/levels/1.bin").unwrap(), large);
+
+            // Files can't be created beneath other files or replace folders
+            assert!(inject_file(&mut data, "save.bin/nested", &small).is_err());
+            assert!(inject_file(&mut data, "data/levels", &small).is_err());
+
+            remove_entry(&mut data, "save.bin").unwrap();
+            assert!(read(&data, "save.bin").is_none());
+            assert_eq!(read(&data, "data/levels/1.bin").unwrap(), large);
+
+            remove_entry(&mut data, "data").unwrap();
+            assert!(read(&data, "data/levels/1.bin").is_none());
+            assert!(read(&data, "data").is_none());
+            assert!(matches!(
+                remove_entry(&mut data, "data"),
+                Err(StfsError::FileNotFound(_))
+            ));
+
+            // Only the file table is left
+            let package = StfsPackage::try_from(data.as_slice()).unwrap();
+            assert_eq!(package.block_allocator().allocated_blocks().count(), 1);
+        }
+    }
+
+    #[test]
+    fn inject_grows_file_table() {
+        let mut builder = StfsPackageBuilder::new();
+        builder.add_file("save.bin", vec![0x42; 0x10]).unwrap();
+        let mut data = builder.build().unwrap();
+
+        let file_count = FILE_TABLE_ENTRIES_PER_BLOCK + 1;
+        for i in 0..file_count {
+            inject_file(&mut data, &format!("{i}.bin"), &[i as u8; 0x10]).unwrap();
+        }
+
+        let package = StfsPackage::try_from(data.as_slice()).unwrap();
+        assert_eq!(package.file_table_blocks().unwrap().len(), 2);
+        assert_eq!(package.walk_entries().len(), file_count + 1);
+        drop(package);
+
+        for i in 0..file_count {
+            assert_eq!(read(&data, &format!("{i}.bin")).unwrap(), [i as u8; 0x10]);
+        }
+    }
+}
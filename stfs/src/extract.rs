@@ -0,0 +1,584 @@
+//! Extracting package contents to the local filesystem.
+
+use std::{
+    fs::{self, File, FileTimes},
+    io::{self, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::SystemTime,
+};
+
+use chrono::NaiveDateTime;
+use tracing::{debug, debug_span};
+
+use crate::{
+    progress::{CancellationToken, EntryProgress, Progress, ProgressSink},
+    stfs::{StfsError, StfsFileEntry, StfsPackage},
+};
+
+/// What to do when an extracted file already exists on disk
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum OverwritePolicy {
+    #[default]
+    Overwrite,
+    /// Leave the existing file untouched and move on
+    Skip,
+    /// Stop extracting with [`StfsError::FileExists`]
+    Error,
+}
+
+/// How file contents are written out when extracting
+#[derive(Debug, Default, Clone)]
+pub enum CopyMethod {
+    /// Copy through a buffered writer
+    #[default]
+    Buffered,
+    /// Write each contiguous region of the file straight to disk. Best suited
+    /// to memory-mapped packages, where the regions are already in memory and
+    /// buffering them again only adds a copy.
+    Direct,
+    /// Copy files with consecutive blocks from `source`, the file the package
+    /// was read from. On Linux the kernel copies the data with
+    /// `copy_file_range`, so it never passes through userspace. Elsewhere, or
+    /// when the filesystem doesn't support that, it's read in chunks. Reads
+    /// never use `source`'s cursor, so one handle can be shared by extractions
+    /// running on several threads. Fragmented files, and platforms without
+    /// positional reads, fall back to [`CopyMethod::Direct`].
+    ///
+    /// The package must have been parsed from the start of `source`, e.g. from
+    /// a memory map of it.
+    FromFile(Arc<File>),
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct ExtractOptions {
+    pub overwrite: OverwritePolicy,
+    /// Write every file directly into the output directory instead of
+    /// recreating the package's folder hierarchy
+    pub flatten: bool,
+    /// Set extracted files' modification and access times from their package
+    /// timestamps. Package timestamps carry no time zone and are treated as UTC.
+    pub preserve_timestamps: bool,
+    pub copy_method: CopyMethod,
+}
+
+/// Joins a `/`-separated path from inside of a package onto `root`, rejecting
+/// components which would escape it
+fn output_path(root: &Path, package_path: &str) -> Result<PathBuf, StfsError> {
+    let mut path = root.to_path_buf();
+    for component in package_path.split('/') {
+        if component.is_empty()
+            || component == "."
+            || component == ".."
+            || component.contains(['\\', ':'])
+        {
+            return Err(StfsError::InvalidFileName(package_path.to_owned()));
+        }
+
+        path.push(component);
+    }
+
+    Ok(path)
+}
+
+fn system_time(datetime: NaiveDateTime) -> SystemTime {
+    let since_epoch = datetime.and_utc().timestamp();
+    match u64::try_from(since_epoch) {
+        Ok(secs) => SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(secs),
+        Err(_) => SystemTime::UNIX_EPOCH,
+    }
+}
+
+impl<'a> StfsPackage<'a> {
+    /// Extracts every file in the package to `root`. Returns the paths of the
+    /// files which were written.
+    pub fn extract_all(
+        &self,
+        root: &Path,
+        options: &ExtractOptions,
+    ) -> Result<Vec<PathBuf>, StfsError> {
+        self.extract_entries(root, self.walk_entries(), options)
+    }
+
+    /// Same as [`StfsPackage::extract_all`], reporting to `on_progress` after
+    /// each entry is handled and stopping if `cancel` is cancelled
+    pub fn extract_all_with_progress(
+        &self,
+        root: &Path,
+        options: &ExtractOptions,
+        on_progress: impl ProgressSink,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<PathBuf>, StfsError> {
+        self.extract_entries_with_progress(root, self.walk_entries(), options, on_progress, cancel)
+    }
+
+    /// Extracts the given entries, as returned by [`StfsPackage::walk_entries`],
+    /// to `root`. Folders are created even if they are empty unless
+    /// [`ExtractOptions::flatten`] is set. Returns the paths of the files which
+    /// were written.
+    pub fn extract_entries(
+        &self,
+        root: &Path,
+        entries: impl IntoIterator<Item = (String, StfsFileEntry)>,
+        options: &ExtractOptions,
+    ) -> Result<Vec<PathBuf>, StfsError> {
+        self.extract_entries_with_progress(
+            root,
+            entries,
+            options,
+            |_: Progress<'_>| {},
+            &CancellationToken::default(),
+        )
+    }
+
+    /// Same as [`StfsPackage::extract_entries`], reporting to `on_progress`
+    /// after each entry is handled and stopping if `cancel` is cancelled
+    pub fn extract_entries_with_progress(
+        &self,
+        root: &Path,
+        entries: impl IntoIterator<Item = (String, StfsFileEntry)>,
+        options: &ExtractOptions,
+        mut on_progress: impl ProgressSink,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<PathBuf>, StfsError> {
+        let entries: Vec<_> = entries.into_iter().collect();
+        let _span =
+            debug_span!("extract", root = %root.display(), entries = entries.len()).entered();
+        let mut progress = EntryProgress::new(
+            entries.len(),
+            entries
+                .iter()
+                .map(|(_, entry)| entry.file_size as u64)
+                .sum(),
+        );
+        let mut written = Vec::new();
+
+        for (path, entry) in &entries {
+            cancel.check()?;
+            if let Some(out_path) = self.extract_entry(root, path, entry, options)? {
+                written.push(out_path);
+            }
+
+            on_progress.report(progress.advance(path, entry.file_size as u64));
+        }
+
+        Ok(written)
+    }
+
+    /// Extracts a single entry. Returns the path written to, or `None` for
+    /// folders and skipped files.
+    fn extract_entry(
+        &self,
+        root: &Path,
+        path: &str,
+        entry: &StfsFileEntry,
+        options: &ExtractOptions,
+    ) -> Result<Option<PathBuf>, StfsError> {
+        let out_path = if options.flatten {
+            output_path(root, &entry.name)?
+        } else {
+            output_path(root, path)?
+        };
+
+        if entry.is_folder() {
+            if !options.flatten {
+                fs::create_dir_all(&out_path)?;
+            }
+            return Ok(None);
+        }
+
+        if out_path.exists() {
+            match options.overwrite {
+                OverwritePolicy::Overwrite => {}
+                OverwritePolicy::Skip => return Ok(None),
+                OverwritePolicy::Error => return Err(StfsError::FileExists(out_path)),
+            }
+        }
+
+        if let Some(parent) = out_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        debug!(
+            path,
+            size = entry.file_size,
+            block_count = entry.block_count,
+            starting_block = entry.starting_block_num,
+            consecutive = entry.has_consecutive_blocks(),
+            "extracting file"
+        );
+        let file = File::create(&out_path)?;
+        self.copy_file(&file, entry, &options.copy_method)?;
+
+        if options.preserve_timestamps {
+            let mut times = FileTimes::new();
+            if let Some(modified) = entry.created() {
+                times = times.set_modified(system_time(modified));
+            }
+            if let Some(accessed) = entry.accessed() {
+                times = times.set_accessed(system_time(accessed));
+            }
+            file.set_times(times)?;
+        }
+
+        Ok(Some(out_path))
+    }
+
+    /// Writes the contents of the file described by `entry` to `file` using
+    /// `method`
+    fn copy_file(&self, file: &File, entry: &StfsFileEntry, method: &CopyMethod) -> io::Result<()> {
+        match method {
+            CopyMethod::Buffered => {
+                let mut writer = BufWriter::new(file);
+                self.extract_file(&mut writer, entry)?;
+                writer.flush()
+            }
+            #[cfg(any(unix, windows))]
+            CopyMethod::FromFile(source) if entry.has_consecutive_blocks() => {
+                let mappings = self
+                    .file_mappings(entry)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+                for mapping in mappings {
+                    let offset = mapping.as_ptr() as usize - self.input.as_ptr() as usize;
+                    copy_range(source, offset as u64, mapping.len() as u64, file)?;
+                }
+
+                Ok(())
+            }
+            CopyMethod::Direct | CopyMethod::FromFile(_) => self.extract_file(&mut &*file, entry),
+        }
+    }
+}
+
+/// Bytes copied by `copy_file_range`, so tests can tell the kernel did the copy
+#[cfg(all(test, target_os = "linux"))]
+static KERNEL_COPIED_BYTES: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+/// Appends the `len` bytes of `source` starting at `offset` to `dest`
+#[cfg(any(unix, windows))]
+fn copy_range(source: &File, offset: u64, len: u64, dest: &File) -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    let (offset, len) = {
+        let copied = kernel_copy(source, offset, len, dest)?;
+        (offset + copied, len - copied)
+    };
+    if len == 0 {
+        return Ok(());
+    }
+
+    let mut reader = PositionalReader {
+        file: source,
+        offset,
+        remaining: len,
+    };
+    if io::copy(&mut reader, &mut &*dest)? != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok(())
+}
+
+/// Copies as much of the range as the kernel will with `copy_file_range`,
+/// returning how many bytes were copied. The source offset is passed
+/// explicitly, so `source`'s cursor isn't used. Stops early at the end of
+/// `source`, or if the filesystems involved can't copy in the kernel, leaving
+/// the rest to be copied in userspace.
+#[cfg(target_os = "linux")]
+fn kernel_copy(source: &File, offset: u64, len: u64, dest: &File) -> io::Result<u64> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut off_in = offset as libc::loff_t;
+    let mut copied = 0;
+    while copied < len {
+        // Large requests are fine, but the count is a `size_t` and is clamped
+        // by the kernel anyway
+        let chunk = (len - copied).min(1 << 30) as usize;
+        // SAFETY: both descriptors are open for the duration of the call, and
+        // `off_in` is a valid pointer. A null `off_out` writes at, and
+        // advances, `dest`'s cursor.
+        let result = unsafe {
+            libc::copy_file_range(
+                source.as_raw_fd(),
+                &mut off_in,
+                dest.as_raw_fd(),
+                std::ptr::null_mut(),
+                chunk,
+                0,
+            )
+        };
+        match result {
+            0 => break,
+            n if n > 0 => {
+                copied += n as u64;
+                #[cfg(test)]
+                KERNEL_COPIED_BYTES.fetch_add(n as u64, std::sync::atomic::Ordering::Relaxed);
+            }
+            _ => {
+                let err = io::Error::last_os_error();
+                match err.raw_os_error() {
+                    Some(libc::EINTR) => continue,
+                    Some(
+                        libc::EXDEV | libc::ENOSYS | libc::EINVAL | libc::EOPNOTSUPP | libc::EPERM,
+                    ) => break,
+                    _ => return Err(err),
+                }
+            }
+        }
+    }
+
+    Ok(copied)
+}
+
+/// Reads `remaining` bytes of `file` starting at `offset`, without using or
+/// moving the file's cursor
+#[cfg(any(unix, windows))]
+struct PositionalReader<'a> {
+    file: &'a File,
+    offset: u64,
+    remaining: u64,
+}
+
+#[cfg(any(unix, windows))]
+impl Read for PositionalReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = buf
+            .len()
+            .min(usize::try_from(self.remaining).unwrap_or(usize::MAX));
+        let buf = &mut buf[..len];
+        #[cfg(unix)]
+        let read = std::os::unix::fs::FileExt::read_at(self.file, buf, self.offset)?;
+        #[cfg(windows)]
+        let read = std::os::windows::fs::FileExt::seek_read(self.file, buf, self.offset)?;
+
+        self.offset += read as u64;
+        self.remaining -= read as u64;
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StfsPackageBuilder;
+
+    #[test]
+    fn output_path_rejects_escapes() {
+        let root = Path::new("out");
+        assert_eq!(
+            output_path(root, "a/b.txt").unwrap(),
+            root.join("a").join("b.txt")
+        );
+        assert!(output_path(root, "../b.txt").is_err());
+        assert!(output_path(root, "a//b.txt").is_err());
+        assert!(output_path(root, "C:").is_err());
+    }
+
+    #[test]
+    fn extract_all_respects_options() {
+        let mut builder = StfsPackageBuilder::new();
+        // 2021-06-15 12:30:00
+        builder.timestamp((41 << 25) | (6 << 21) | (15 << 16) | (12 << 11) | (30 << 5));
+        builder.add_file("saves/a.sav", b"first".to_vec()).unwrap();
+        builder.add_file("b.sav", b"second".to_vec()).unwrap();
+        builder.add_folder("empty").unwrap();
+        let data = builder.build().unwrap();
+        let package = StfsPackage::try_from(data.as_slice()).unwrap();
+
+        let root = std::env::temp_dir().join(format!("stfs-extract-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+
+        let options = ExtractOptions {
+            preserve_timestamps: true,
+            ..Default::default()
+        };
+        let written = package.extract_all(&root, &options).unwrap();
+        assert_eq!(written.len(), 2);
+        assert_eq!(
+            fs::read(root.join("saves").join("a.sav")).unwrap(),
+            b"first"
+        );
+        assert!(root.join("empty").is_dir());
+        let modified = fs::metadata(root.join("b.sav"))
+            .unwrap()
+            .modified()
+            .unwrap();
+        assert_eq!(
+            modified
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_secs(),
+            1_623_760_200
+        );
+
+        let options = ExtractOptions {
+            overwrite: OverwritePolicy::Error,
+            ..Default::default()
+        };
+        assert!(matches!(
+            package.extract_all(&root, &options),
+            Err(StfsError::FileExists(_))
+        ));
+
+        let options = ExtractOptions {
+            overwrite: OverwritePolicy::Skip,
+            flatten: true,
+            ..Default::default()
+        };
+        let written = package.extract_all(&root, &options).unwrap();
+        assert_eq!(written, vec![root.join("a.sav")]);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn extract_stops_when_cancelled() {
+        let mut builder = StfsPackageBuilder::new();
+        builder.add_file("a.sav", b"first".to_vec()).unwrap();
+        builder.add_file("b.sav", b"second".to_vec()).unwrap();
+        let data = builder.build().unwrap();
+        let package = StfsPackage::try_from(data.as_slice()).unwrap();
+
+        let root = std::env::temp_dir().join(format!("stfs-cancel-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+
+        let cancel = CancellationToken::new();
+        let result = package.extract_all_with_progress(
+            &root,
+            &ExtractOptions::default(),
+            |_: Progress<'_>| cancel.cancel(),
+            &cancel,
+        );
+        assert!(matches!(result, Err(StfsError::Cancelled)));
+        assert!(root.join("a.sav").exists());
+        assert!(!root.join("b.sav").exists());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn copy_methods_write_identical_files() {
+        // Large enough to cross a hash table
+        let large: Vec<u8> = (0..0xC0_000u32).map(|i| (i % 251) as u8).collect();
+        let mut builder = StfsPackageBuilder::new();
+        builder
+            .add_file("large.bin", large.clone())
+            .unwrap()
+            .add_file("small.bin", b"small".to_vec())
+            .unwrap();
+        let data = builder.build().unwrap();
+        let package = StfsPackage::try_from(data.as_slice()).unwrap();
+
+        let root = std::env::temp_dir().join(format!("stfs-copy-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let package_path = root.join("package");
+        fs::write(&package_path, &data).unwrap();
+        let source = Arc::new(File::open(&package_path).unwrap());
+
+        for (name, copy_method) in [
+            ("buffered", CopyMethod::Buffered),
+            ("direct", CopyMethod::Direct),
+            ("from_file", CopyMethod::FromFile(source)),
+        ] {
+            let options = ExtractOptions {
+                copy_method,
+                ..Default::default()
+            };
+            let out = root.join(name);
+            package.extract_all(&out, &options).unwrap();
+            assert_eq!(fs::read(out.join("large.bin")).unwrap(), large, "{}", name);
+            assert_eq!(
+                fs::read(out.join("small.bin")).unwrap(),
+                b"small",
+                "{}",
+                name
+            );
+        }
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn from_file_copies_in_the_kernel() {
+        use std::sync::atomic::Ordering;
+
+        let contents: Vec<u8> = (0..0x5_0000u32).map(|i| (i % 241) as u8).collect();
+        let mut builder = StfsPackageBuilder::new();
+        builder.add_file("big.bin", contents.clone()).unwrap();
+        let data = builder.build().unwrap();
+        let package = StfsPackage::try_from(data.as_slice()).unwrap();
+
+        let root = std::env::temp_dir().join(format!("stfs-kernel-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let package_path = root.join("package");
+        fs::write(&package_path, &data).unwrap();
+        let options = ExtractOptions {
+            copy_method: CopyMethod::FromFile(Arc::new(File::open(&package_path).unwrap())),
+            ..Default::default()
+        };
+
+        // Other tests may copy at the same time, but never take away from the count
+        let before = KERNEL_COPIED_BYTES.load(Ordering::Relaxed);
+        package.extract_all(&root.join("out"), &options).unwrap();
+        let copied = KERNEL_COPIED_BYTES.load(Ordering::Relaxed) - before;
+
+        assert_eq!(
+            fs::read(root.join("out").join("big.bin")).unwrap(),
+            contents
+        );
+        assert!(copied >= contents.len() as u64, "{} bytes", copied);
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn shared_source_is_safe_across_threads() {
+        let files: Vec<Vec<u8>> = (0..16u8)
+            .map(|seed| (0..0x3000u32).map(|i| (i as u8) ^ seed).collect())
+            .collect();
+        let mut builder = StfsPackageBuilder::new();
+        for (i, contents) in files.iter().enumerate() {
+            builder
+                .add_file(&format!("{}.bin", i), contents.clone())
+                .unwrap();
+        }
+        let data = builder.build().unwrap();
+        let package = StfsPackage::try_from(data.as_slice()).unwrap();
+
+        let root = std::env::temp_dir().join(format!("stfs-shared-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(&root).unwrap();
+        let package_path = root.join("package");
+        fs::write(&package_path, &data).unwrap();
+        let options = ExtractOptions {
+            copy_method: CopyMethod::FromFile(Arc::new(File::open(&package_path).unwrap())),
+            ..Default::default()
+        };
+
+        // Every thread reads every file through the same handle, so any use of
+        // its cursor would interleave their reads
+        std::thread::scope(|scope| {
+            for thread in 0..8 {
+                let (package, options, root) = (&package, &options, &root);
+                scope.spawn(move || {
+                    for _ in 0..16 {
+                        package
+                            .extract_all(&root.join(thread.to_string()), options)
+                            .unwrap();
+                    }
+                });
+            }
+        });
+
+        for thread in 0..8 {
+            for (i, contents) in files.iter().enumerate() {
+                let path = root.join(thread.to_string()).join(format!("{}.bin", i));
+                assert_eq!(&fs::read(path).unwrap(), contents);
+            }
+        }
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,292 @@
+//! The tree of files and folders in a package.
+
+use std::{collections::HashMap, sync::Arc};
+
+use parking_lot::Mutex;
+#[cfg(feature = "serde")]
+use serde::{ser::SerializeStructVariant, Serialize, Serializer};
+
+use crate::stfs::{StfsEntry, StfsEntryRef, StfsFileEntry};
+
+/// Handle to an entry in a [`FileTable`]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct EntryId(usize);
+
+#[derive(Debug)]
+struct Node {
+    entry: StfsFileEntry,
+    parent: Option<EntryId>,
+    /// `None` for files
+    children: Option<Vec<EntryId>>,
+}
+
+/// Every file and folder in a package. Entries are stored in one list and refer
+/// to each other by [`EntryId`], so the tree can be read without locking.
+///
+/// The root folder has no entry in the package's file table and is given a
+/// default [`StfsFileEntry`].
+#[derive(Debug)]
+pub struct FileTable {
+    nodes: Vec<Node>,
+}
+
+impl FileTable {
+    pub const ROOT: EntryId = EntryId(0);
+
+    /// Builds the tree from the entries of a file table. Entries whose folder
+    /// is missing, or which name themselves as their folder, are listed in the
+    /// root so that they aren't lost.
+    pub(crate) fn new(entries: Vec<StfsFileEntry>) -> Self {
+        let mut nodes = Vec::with_capacity(entries.len() + 1);
+        nodes.push(Node {
+            entry: StfsFileEntry::default(),
+            parent: None,
+            children: Some(Vec::new()),
+        });
+
+        let mut folders = HashMap::<u16, EntryId>::new();
+        for entry in entries {
+            let id = EntryId(nodes.len());
+            let children = if entry.is_folder() {
+                folders.insert(entry.index as u16, id);
+                Some(Vec::new())
+            } else {
+                None
+            };
+            nodes.push(Node {
+                entry,
+                parent: None,
+                children,
+            });
+        }
+
+        for index in 1..nodes.len() {
+            let (entry_index, path_indicator) =
+                (nodes[index].entry.index, nodes[index].entry.path_indicator);
+            let parent = folders
+                .get(&path_indicator)
+                .copied()
+                .filter(|_| path_indicator as usize != entry_index)
+                .unwrap_or(Self::ROOT);
+
+            nodes[index].parent = Some(parent);
+            if let Some(children) = &mut nodes[parent.0].children {
+                children.push(EntryId(index));
+            }
+        }
+
+        FileTable { nodes }
+    }
+
+    /// Number of files and folders, not counting the root
+    pub fn len(&self) -> usize {
+        self.nodes.len() - 1
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn entry(&self, id: EntryId) -> &StfsFileEntry {
+        &self.nodes[id.0].entry
+    }
+
+    pub fn name(&self, id: EntryId) -> &str {
+        &self.nodes[id.0].entry.name
+    }
+
+    /// Whether `id` is a folder. Unlike [`StfsFileEntry::is_folder`], this is
+    /// true for the root.
+    pub fn is_folder(&self, id: EntryId) -> bool {
+        self.nodes[id.0].children.is_some()
+    }
+
+    /// The folder containing `id`, or `None` for the root
+    pub fn parent(&self, id: EntryId) -> Option<EntryId> {
+        self.nodes[id.0].parent
+    }
+
+    /// The contents of the folder `id`, in file table order. Files have none.
+    pub fn children(&self, id: EntryId) -> &[EntryId] {
+        self.nodes[id.0].children.as_deref().unwrap_or_default()
+    }
+
+    /// Every file and folder, in file table order
+    pub fn iter(&self) -> impl Iterator<Item = (EntryId, &StfsFileEntry)> + '_ {
+        self.nodes
+            .iter()
+            .enumerate()
+            .skip(1)
+            .map(|(index, node)| (EntryId(index), &node.entry))
+    }
+
+    /// `/`-separated path of `id` from the root. The root's path is empty.
+    pub fn path(&self, id: EntryId) -> String {
+        let mut components = Vec::new();
+        let mut current = id;
+        while let Some(parent) = self.parent(current) {
+            components.push(self.name(current));
+            current = parent;
+        }
+        components.reverse();
+
+        components.join("/")
+    }
+
+    /// Looks up the entry at a `/`-separated path
+    pub fn find(&self, path: &str) -> Option<EntryId> {
+        path.split('/').try_fold(Self::ROOT, |folder, component| {
+            self.children(folder)
+                .iter()
+                .copied()
+                .find(|&child| self.name(child) == component)
+        })
+    }
+
+    /// Every file and folder along with its `/`-separated path. Entries are
+    /// listed depth-first, with each folder preceding its contents.
+    pub fn walk(&self) -> Vec<(String, EntryId)> {
+        fn walk(table: &FileTable, folder: EntryId, path: &str, out: &mut Vec<(String, EntryId)>) {
+            for &child in table.children(folder) {
+                let child_path = if path.is_empty() {
+                    table.name(child).to_owned()
+                } else {
+                    format!("{}/{}", path, table.name(child))
+                };
+                out.push((child_path.clone(), child));
+                walk(table, child, &child_path, out);
+            }
+        }
+
+        let mut entries = Vec::with_capacity(self.len());
+        walk(self, Self::ROOT, "", &mut entries);
+
+        entries
+    }
+
+    /// Builds the shared, lockable tree that was used to represent the file
+    /// table before it moved into an arena, for callers which still expect it
+    pub fn to_tree(&self) -> StfsEntryRef {
+        self.tree_at(Self::ROOT)
+    }
+
+    fn tree_at(&self, id: EntryId) -> StfsEntryRef {
+        let entry = self.entry(id).clone();
+        let entry = match &self.nodes[id.0].children {
+            Some(children) => StfsEntry::Folder {
+                entry,
+                files: children.iter().map(|&child| self.tree_at(child)).collect(),
+            },
+            None => StfsEntry::File(entry),
+        };
+
+        Arc::new(Mutex::new(entry))
+    }
+}
+
+/// Serializes an entry and everything under it in the same shape as
+/// [`StfsEntry`]
+#[cfg(feature = "serde")]
+#[derive(Copy, Clone)]
+struct SerializeEntry<'t> {
+    table: &'t FileTable,
+    id: EntryId,
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for SerializeEntry<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let SerializeEntry { table, id } = *self;
+        if !table.is_folder(id) {
+            return serializer.serialize_newtype_variant("StfsEntry", 0, "File", table.entry(id));
+        }
+
+        let files: Vec<_> = table
+            .children(id)
+            .iter()
+            .map(|&child| SerializeEntry { table, id: child })
+            .collect();
+        let mut state = serializer.serialize_struct_variant("StfsEntry", 1, "Folder", 2)?;
+        state.serialize_field("entry", table.entry(id))?;
+        state.serialize_field("files", &files)?;
+        state.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl Serialize for FileTable {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SerializeEntry {
+            table: self,
+            id: Self::ROOT,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stfs::EntryFlags;
+
+    fn entry(index: usize, name: &str, path_indicator: u16, folder: bool) -> StfsFileEntry {
+        StfsFileEntry {
+            index,
+            name: name.to_owned(),
+            path_indicator,
+            flags: if folder {
+                EntryFlags::FOLDER
+            } else {
+                EntryFlags::empty()
+            },
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn builds_tree_from_table_entries() {
+        let table = FileTable::new(vec![
+            entry(0, "saves", 0xFFFF, true),
+            entry(1, "a.sav", 0, false),
+            entry(2, "nested", 0, true),
+            entry(3, "b.sav", 2, false),
+            entry(4, "orphan", 9, false),
+            entry(5, "self", 5, true),
+        ]);
+
+        assert_eq!(table.len(), 6);
+        let walked: Vec<String> = table.walk().into_iter().map(|(path, _)| path).collect();
+        assert_eq!(
+            walked,
+            [
+                "saves",
+                "saves/a.sav",
+                "saves/nested",
+                "saves/nested/b.sav",
+                "orphan",
+                "self"
+            ]
+        );
+
+        let b = table.find("saves/nested/b.sav").unwrap();
+        assert_eq!(table.entry(b).index, 3);
+        assert_eq!(table.path(b), "saves/nested/b.sav");
+        assert!(table.find("saves/b.sav").is_none());
+        assert!(table.find("saves/a.sav/x").is_none());
+        assert!(table.is_folder(FileTable::ROOT));
+        assert!(table.children(b).is_empty());
+
+        let tree = table.to_tree();
+        let tree = tree.lock();
+        match &*tree {
+            StfsEntry::Folder { files, .. } => {
+                let names: Vec<String> = files
+                    .iter()
+                    .map(|file| file.lock().name().to_owned())
+                    .collect();
+                assert_eq!(names, ["saves", "orphan", "self"]);
+            }
+            StfsEntry::File(_) => panic!("root should be a folder"),
+        }
+    }
+}
@@ -0,0 +1,156 @@
+//! Identifying what a contained file holds from its first few bytes.
+
+use std::io::Read;
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::{
+    stfs::{PackageType, StfsFileEntry, StfsPackage},
+    xdbf::XDBF_MAGIC,
+};
+
+/// Format tags stored in the `fmt ` chunk of RIFF files holding XMA audio
+const XMA_FORMAT_TAGS: [u16; 2] = [0x0165, 0x0166];
+
+/// Kinds of files commonly found inside packages
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum FileType {
+    Png,
+    Jpeg,
+    Dds,
+    /// Xbox 360 executable
+    Xex,
+    /// Xbox database file, such as a GPD holding achievements and settings
+    Xdbf,
+    Wav,
+    /// Xbox Media Audio in a RIFF container
+    Xma,
+    /// Another STFS package
+    Stfs(PackageType),
+    Unknown,
+}
+
+impl FileType {
+    /// Number of bytes [`FileType::detect`] needs to recognize every type
+    pub const HEADER_LEN: usize = 0x16;
+
+    /// Identifies the file which starts with `header`
+    pub fn detect(header: &[u8]) -> FileType {
+        if header.starts_with(b"\x89PNG\r\n\x1a\n") {
+            return FileType::Png;
+        }
+        if header.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            return FileType::Jpeg;
+        }
+        if crate::dds::is_dds(header) {
+            return FileType::Dds;
+        }
+        if header.starts_with(b"XEX") {
+            return FileType::Xex;
+        }
+        if header.starts_with(&XDBF_MAGIC) {
+            return FileType::Xdbf;
+        }
+        if header.starts_with(b"RIFF") && header.get(8..12) == Some(b"WAVE") {
+            let format_tag = header
+                .get(20..22)
+                .filter(|_| header.get(12..16) == Some(b"fmt "))
+                .map(|tag| u16::from_le_bytes([tag[0], tag[1]]));
+            return match format_tag {
+                Some(tag) if XMA_FORMAT_TAGS.contains(&tag) => FileType::Xma,
+                _ => FileType::Wav,
+            };
+        }
+        if let Some(magic) = header.get(..4) {
+            if let Ok(package_type) =
+                PackageType::try_from([magic[0], magic[1], magic[2], magic[3]])
+            {
+                return FileType::Stfs(package_type);
+            }
+        }
+
+        FileType::Unknown
+    }
+
+    /// Short description of the type
+    pub fn name(self) -> &'static str {
+        match self {
+            FileType::Png => "PNG image",
+            FileType::Jpeg => "JPEG image",
+            FileType::Dds => "DDS texture",
+            FileType::Xex => "Executable",
+            FileType::Xdbf => "XDBF database",
+            FileType::Wav => "WAV audio",
+            FileType::Xma => "XMA audio",
+            FileType::Stfs(PackageType::Con) => "CON package",
+            FileType::Stfs(PackageType::Live) => "LIVE package",
+            FileType::Stfs(PackageType::Pirs) => "PIRS package",
+            FileType::Unknown => "Unknown",
+        }
+    }
+}
+
+impl<'a> StfsPackage<'a> {
+    /// Reads the start of the file described by `entry` and identifies its type
+    pub fn file_type(&self, entry: &StfsFileEntry) -> std::io::Result<FileType> {
+        let mut header = Vec::with_capacity(FileType::HEADER_LEN);
+        self.file_reader(entry)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?
+            .take(FileType::HEADER_LEN as u64)
+            .read_to_end(&mut header)?;
+
+        Ok(FileType::detect(&header))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StfsPackageBuilder;
+
+    #[test]
+    fn detects_contained_file_types() {
+        let mut xma = b"RIFF\0\0\0\0WAVEfmt \x20\0\0\0".to_vec();
+        xma.extend_from_slice(&0x0166u16.to_le_bytes());
+        let mut wav = xma.clone();
+        wav[20] = 1;
+        wav[21] = 0;
+        let nested = StfsPackageBuilder::new().build().unwrap();
+
+        let mut builder = StfsPackageBuilder::new();
+        builder
+            .add_file("icon.png", b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR".to_vec())
+            .unwrap()
+            .add_file("default.xex", b"XEX2\0\0\0\0".to_vec())
+            .unwrap()
+            .add_file("music.xma", xma)
+            .unwrap()
+            .add_file("sound.wav", wav)
+            .unwrap()
+            .add_file("nested", nested)
+            .unwrap()
+            .add_file("empty", Vec::new())
+            .unwrap();
+        let data = builder.build().unwrap();
+        let package = StfsPackage::try_from(data.as_slice()).unwrap();
+
+        let types: Vec<(String, FileType)> = package
+            .walk_entries()
+            .into_iter()
+            .map(|(path, entry)| (path, package.file_type(&entry).unwrap()))
+            .collect();
+        assert_eq!(
+            types,
+            [
+                ("icon.png".to_owned(), FileType::Png),
+                ("default.xex".to_owned(), FileType::Xex),
+                ("music.xma".to_owned(), FileType::Xma),
+                ("sound.wav".to_owned(), FileType::Wav),
+                ("nested".to_owned(), FileType::Stfs(PackageType::Con)),
+                ("empty".to_owned(), FileType::Unknown),
+            ]
+        );
+    }
+}
@@ -0,0 +1,547 @@
+//! Typed records from the GPD files of profile packages.
+//!
+//! A profile holds a GPD for each title it has played, named after the title's
+//! ID, with that title's achievements. The dashboard's own GPD, `FFFE07D1.gpd`,
+//! holds the profile's settings and a record for every title played. The
+//! gamertag isn't among the settings; it's stored in the profile's encrypted
+//! `Account` file.
+
+use std::cmp::Reverse;
+
+use byteorder::{BigEndian, ByteOrder};
+use chrono::NaiveDateTime;
+use num_enum::TryFromPrimitive;
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::{
+    display::TitleId,
+    stfs::StfsError,
+    xdbf::{filetime, take_utf16_be_str, Namespace, Setting, SettingValue, Xdbf},
+};
+
+/// File name of the dashboard's GPD within a profile package
+pub const DASHBOARD_GPD: &str = "FFFE07D1.gpd";
+const ACHIEVEMENT_HEADER_SIZE: usize = 0x1C;
+const TITLE_HEADER_SIZE: usize = 0x28;
+
+bitflags::bitflags! {
+    /// Flags of an [`Achievement`]. `TYPE` covers the three bits holding its
+    /// [`AchievementType`].
+    #[derive(Default)]
+    #[cfg_attr(feature = "serde", derive(Serialize), serde(transparent))]
+    pub struct AchievementFlags: u32 {
+        const TYPE = 0x7;
+        /// The achievement is listed before it's unlocked. Secret achievements
+        /// leave this unset.
+        const SHOW_UNACHIEVED = 0x8;
+        const UNLOCKED_ONLINE = 0x10000;
+        const UNLOCKED = 0x20000;
+        /// Changed since the profile last synced with Xbox Live
+        const EDITED = 0x100000;
+    }
+}
+
+/// What an achievement is awarded for
+#[derive(Debug, Copy, Clone, PartialEq, Eq, TryFromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[repr(u8)]
+pub enum AchievementType {
+    Completion = 1,
+    Leveling,
+    Unlock,
+    Event,
+    Tournament,
+    Checkpoint,
+    Other,
+}
+
+/// A record in a GPD's achievement namespace
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Achievement {
+    pub id: u32,
+    /// ID of the achievement's image in the GPD's image namespace
+    pub image_id: u32,
+    pub gamerscore: u32,
+    pub flags: AchievementFlags,
+    pub unlock_time: Option<NaiveDateTime>,
+    pub name: String,
+    pub unlocked_description: String,
+    /// Shown instead of `unlocked_description` until the achievement is unlocked
+    pub locked_description: String,
+}
+
+impl Achievement {
+    fn parse(record: &[u8]) -> Result<Self, StfsError> {
+        if record.len() < ACHIEVEMENT_HEADER_SIZE {
+            return Err(StfsError::InvalidXdbf("achievement record is too short"));
+        }
+
+        let mut strings = &record[ACHIEVEMENT_HEADER_SIZE..];
+        Ok(Achievement {
+            id: BigEndian::read_u32(&record[0x4..]),
+            image_id: BigEndian::read_u32(&record[0x8..]),
+            gamerscore: BigEndian::read_u32(&record[0xC..]),
+            flags: AchievementFlags::from_bits_truncate(BigEndian::read_u32(&record[0x10..])),
+            unlock_time: filetime(BigEndian::read_u64(&record[0x14..])),
+            name: take_utf16_be_str(&mut strings),
+            unlocked_description: take_utf16_be_str(&mut strings),
+            locked_description: take_utf16_be_str(&mut strings),
+        })
+    }
+
+    /// Whether the achievement has been unlocked, online or offline
+    pub fn is_unlocked(&self) -> bool {
+        self.flags
+            .intersects(AchievementFlags::UNLOCKED | AchievementFlags::UNLOCKED_ONLINE)
+    }
+
+    pub fn is_secret(&self) -> bool {
+        !self.flags.contains(AchievementFlags::SHOW_UNACHIEVED)
+    }
+
+    pub fn kind(&self) -> Option<AchievementType> {
+        AchievementType::try_from((self.flags & AchievementFlags::TYPE).bits() as u8).ok()
+    }
+}
+
+/// A title's achievement progress, as recorded in the dashboard GPD
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct TitleRecord {
+    pub title_id: TitleId,
+    pub name: String,
+    pub achievement_count: u32,
+    pub achievements_unlocked: u32,
+    pub achievements_unlocked_online: u16,
+    pub gamerscore_total: u32,
+    pub gamerscore_unlocked: u32,
+    pub avatar_award_count: u8,
+    pub avatar_awards_earned: u8,
+    /// `None` for titles which were never launched, such as those whose
+    /// achievements were only synced from Xbox Live
+    pub last_played: Option<NaiveDateTime>,
+}
+
+impl TitleRecord {
+    fn parse(record: &[u8]) -> Result<Self, StfsError> {
+        if record.len() < TITLE_HEADER_SIZE {
+            return Err(StfsError::InvalidXdbf("title record is too short"));
+        }
+
+        Ok(TitleRecord {
+            title_id: TitleId(BigEndian::read_u32(record)),
+            name: take_utf16_be_str(&mut &record[TITLE_HEADER_SIZE..]),
+            achievement_count: BigEndian::read_u32(&record[0x4..]),
+            achievements_unlocked: BigEndian::read_u32(&record[0x8..]),
+            gamerscore_total: BigEndian::read_u32(&record[0xC..]),
+            gamerscore_unlocked: BigEndian::read_u32(&record[0x10..]),
+            achievements_unlocked_online: BigEndian::read_u16(&record[0x14..]),
+            avatar_awards_earned: record[0x16],
+            avatar_award_count: record[0x17],
+            last_played: filetime(BigEndian::read_u64(&record[0x20..])),
+        })
+    }
+}
+
+/// Which of the Xbox Live communities a gamer belongs to
+#[derive(Debug, Copy, Clone, PartialEq, Eq, TryFromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[repr(i32)]
+pub enum GamerZone {
+    /// Not chosen
+    None = 0,
+    Recreation,
+    Pro,
+    Family,
+    Underground,
+}
+
+/// IDs of the dashboard GPD's gamercard settings
+impl Setting<'_> {
+    pub const GAMERCARD_ZONE: u32 = 0x10040004;
+    pub const GAMERCARD_REGION: u32 = 0x10040005;
+    pub const GAMERCARD_GAMERSCORE: u32 = 0x10040006;
+    pub const GAMERCARD_REPUTATION: u32 = 0x5004000B;
+    pub const GAMERCARD_PICTURE_KEY: u32 = 0x4064000F;
+    pub const GAMERCARD_MOTTO: u32 = 0x402C0011;
+    pub const GAMERCARD_TITLES_PLAYED: u32 = 0x10040012;
+    pub const GAMERCARD_ACHIEVEMENTS_EARNED: u32 = 0x10040013;
+    pub const GAMERCARD_NAME: u32 = 0x41040040;
+    pub const GAMERCARD_LOCATION: u32 = 0x40520041;
+    pub const GAMERCARD_URL: u32 = 0x41900042;
+    pub const GAMERCARD_BIO: u32 = 0x43E80043;
+    /// First half of the avatar's description
+    pub const GAMERCARD_AVATAR_INFO_1: u32 = 0x63E80044;
+    pub const GAMERCARD_AVATAR_INFO_2: u32 = 0x63E80045;
+}
+
+/// The gamercard settings of a dashboard GPD. Settings the profile doesn't
+/// have, or which hold an unexpected type of value, are left as `None`.
+#[derive(Debug, Default, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ProfileSettings {
+    pub gamer_zone: Option<GamerZone>,
+    pub region: Option<i32>,
+    /// Gamerscore as of the last sync with Xbox Live
+    pub gamerscore: Option<i32>,
+    /// Star rating out of 100
+    pub reputation: Option<f32>,
+    pub picture_key: Option<String>,
+    pub motto: Option<String>,
+    pub titles_played: Option<i32>,
+    pub achievements_earned: Option<i32>,
+    pub name: Option<String>,
+    pub location: Option<String>,
+    pub url: Option<String>,
+    pub bio: Option<String>,
+    /// Both halves of the avatar's description, concatenated
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "crate::serialize::serialize_optional_bytes")
+    )]
+    pub avatar_info: Option<Vec<u8>>,
+}
+
+impl ProfileSettings {
+    pub fn new(dashboard: &Xdbf<'_>) -> Result<Self, StfsError> {
+        let mut settings = ProfileSettings::default();
+        let mut avatar_info = [None, None];
+        for setting in dashboard.settings() {
+            let Setting { id, value } = setting?;
+            match (id, value) {
+                (Setting::GAMERCARD_ZONE, SettingValue::Int32(value)) => {
+                    settings.gamer_zone = GamerZone::try_from(value).ok()
+                }
+                (Setting::GAMERCARD_REGION, SettingValue::Int32(value)) => {
+                    settings.region = Some(value)
+                }
+                (Setting::GAMERCARD_GAMERSCORE, SettingValue::Int32(value)) => {
+                    settings.gamerscore = Some(value)
+                }
+                (Setting::GAMERCARD_REPUTATION, SettingValue::Float(value)) => {
+                    settings.reputation = Some(value)
+                }
+                (Setting::GAMERCARD_PICTURE_KEY, SettingValue::String(value)) => {
+                    settings.picture_key = Some(value)
+                }
+                (Setting::GAMERCARD_MOTTO, SettingValue::String(value)) => {
+                    settings.motto = Some(value)
+                }
+                (Setting::GAMERCARD_TITLES_PLAYED, SettingValue::Int32(value)) => {
+                    settings.titles_played = Some(value)
+                }
+                (Setting::GAMERCARD_ACHIEVEMENTS_EARNED, SettingValue::Int32(value)) => {
+                    settings.achievements_earned = Some(value)
+                }
+                (Setting::GAMERCARD_NAME, SettingValue::String(value)) => {
+                    settings.name = Some(value)
+                }
+                (Setting::GAMERCARD_LOCATION, SettingValue::String(value)) => {
+                    settings.location = Some(value)
+                }
+                (Setting::GAMERCARD_URL, SettingValue::String(value)) => settings.url = Some(value),
+                (Setting::GAMERCARD_BIO, SettingValue::String(value)) => settings.bio = Some(value),
+                (Setting::GAMERCARD_AVATAR_INFO_1, SettingValue::Binary(value)) => {
+                    avatar_info[0] = Some(value)
+                }
+                (Setting::GAMERCARD_AVATAR_INFO_2, SettingValue::Binary(value)) => {
+                    avatar_info[1] = Some(value)
+                }
+                _ => {}
+            }
+        }
+
+        if let [Some(first), second] = avatar_info {
+            settings.avatar_info = Some([first, second.unwrap_or_default()].concat());
+        }
+
+        Ok(settings)
+    }
+}
+
+/// Achievement and gamerscore counts summed over a set of achievements or
+/// titles
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct AchievementTotals {
+    pub achievement_count: u32,
+    pub achievements_unlocked: u32,
+    pub gamerscore_total: u32,
+    pub gamerscore_unlocked: u32,
+}
+
+impl AchievementTotals {
+    /// Totals for a single title from its own GPD
+    pub fn from_achievements<'a>(achievements: impl IntoIterator<Item = &'a Achievement>) -> Self {
+        achievements
+            .into_iter()
+            .fold(AchievementTotals::default(), |mut totals, achievement| {
+                totals.achievement_count += 1;
+                totals.gamerscore_total += achievement.gamerscore;
+                if achievement.is_unlocked() {
+                    totals.achievements_unlocked += 1;
+                    totals.gamerscore_unlocked += achievement.gamerscore;
+                }
+                totals
+            })
+    }
+
+    /// Totals for a whole profile from the dashboard GPD's title records
+    pub fn from_titles<'a>(titles: impl IntoIterator<Item = &'a TitleRecord>) -> Self {
+        titles
+            .into_iter()
+            .fold(AchievementTotals::default(), |mut totals, title| {
+                totals.achievement_count += title.achievement_count;
+                totals.achievements_unlocked += title.achievements_unlocked;
+                totals.gamerscore_total += title.gamerscore_total;
+                totals.gamerscore_unlocked += title.gamerscore_unlocked;
+                totals
+            })
+    }
+}
+
+impl<'a> Xdbf<'a> {
+    /// Every achievement in a title's GPD
+    pub fn achievements(&self) -> impl Iterator<Item = Result<Achievement, StfsError>> + '_ {
+        self.namespace(Namespace::ACHIEVEMENT)
+            .map(|(_, record)| Achievement::parse(record))
+    }
+
+    /// The record of every title played, from the dashboard GPD
+    pub fn titles(&self) -> impl Iterator<Item = Result<TitleRecord, StfsError>> + '_ {
+        self.namespace(Namespace::TITLE)
+            .map(|(_, record)| TitleRecord::parse(record))
+    }
+
+    /// The dashboard GPD's record of `title_id`
+    pub fn title(&self, title_id: TitleId) -> Option<Result<TitleRecord, StfsError>> {
+        self.find(Namespace::TITLE, title_id.0.into())
+            .map(TitleRecord::parse)
+    }
+
+    /// The dashboard GPD's title records, most recently played first. Titles
+    /// which were never launched come last.
+    pub fn play_history(&self) -> Result<Vec<TitleRecord>, StfsError> {
+        let mut titles = self.titles().collect::<Result<Vec<_>, _>>()?;
+        titles.sort_by_key(|title| Reverse(title.last_played));
+
+        Ok(titles)
+    }
+}
+
+/// What a dashboard GPD says about its profile
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ProfileSummary {
+    pub settings: ProfileSettings,
+    pub totals: AchievementTotals,
+    /// Every title played, most recently played first
+    pub titles: Vec<TitleRecord>,
+}
+
+impl ProfileSummary {
+    pub fn new(dashboard: &Xdbf<'_>) -> Result<Self, StfsError> {
+        let titles = dashboard.play_history()?;
+        Ok(ProfileSummary {
+            settings: ProfileSettings::new(dashboard)?,
+            totals: AchievementTotals::from_titles(&titles),
+            titles,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::WriteBytesExt;
+
+    use super::*;
+    use crate::xdbf::{
+        tests::{build_xdbf, setting_record, utf16_be},
+        XdbfEntry,
+    };
+
+    fn achievement_record(id: u32, gamerscore: u32, flags: u32, unlock_time: u64) -> Vec<u8> {
+        let mut record = Vec::new();
+        for value in [
+            ACHIEVEMENT_HEADER_SIZE as u32,
+            id,
+            id + 0x100,
+            gamerscore,
+            flags,
+        ] {
+            record.write_u32::<BigEndian>(value).unwrap();
+        }
+        record.write_u64::<BigEndian>(unlock_time).unwrap();
+        for string in ["Name", "Unlocked", "Locked"] {
+            record.extend(utf16_be(&format!("{} {}", string, id)));
+        }
+        record
+    }
+
+    fn title_record(title_id: u32, counts: [u32; 4], last_played: u64, name: &str) -> Vec<u8> {
+        let mut record = Vec::new();
+        record.write_u32::<BigEndian>(title_id).unwrap();
+        for count in counts {
+            record.write_u32::<BigEndian>(count).unwrap();
+        }
+        record.resize(0x20, 0);
+        record.write_u64::<BigEndian>(last_played).unwrap();
+        record.extend(utf16_be(name));
+        record
+    }
+
+    #[test]
+    fn decodes_achievements() {
+        // 2010-01-01 00:00:00
+        let unlocked_at = 0x01CA_8A75_5C6E_0000;
+        let data = build_xdbf(&[
+            (
+                Namespace::ACHIEVEMENT,
+                1,
+                achievement_record(1, 10, 0x20000 | 0x8 | 3, unlocked_at),
+            ),
+            (Namespace::ACHIEVEMENT, 2, achievement_record(2, 40, 1, 0)),
+            (
+                Namespace::ACHIEVEMENT,
+                XdbfEntry::SYNC_DATA_ID,
+                vec![0; 0x18],
+            ),
+        ]);
+        let xdbf = Xdbf::try_from(data.as_slice()).unwrap();
+        let achievements: Vec<Achievement> = xdbf.achievements().collect::<Result<_, _>>().unwrap();
+
+        assert_eq!(achievements.len(), 2);
+        let first = &achievements[0];
+        assert_eq!((first.id, first.image_id, first.gamerscore), (1, 0x101, 10));
+        assert_eq!(first.name, "Name 1");
+        assert_eq!(first.unlocked_description, "Unlocked 1");
+        assert_eq!(first.locked_description, "Locked 1");
+        assert_eq!(first.kind(), Some(AchievementType::Unlock));
+        assert!(first.is_unlocked() && !first.is_secret());
+        assert_eq!(
+            first.unlock_time.unwrap().to_string(),
+            "2010-01-01 00:00:00"
+        );
+        assert!(!achievements[1].is_unlocked() && achievements[1].is_secret());
+        assert_eq!(achievements[1].unlock_time, None);
+
+        assert_eq!(
+            AchievementTotals::from_achievements(&achievements),
+            AchievementTotals {
+                achievement_count: 2,
+                achievements_unlocked: 1,
+                gamerscore_total: 50,
+                gamerscore_unlocked: 10,
+            }
+        );
+    }
+
+    #[test]
+    fn sums_dashboard_titles() {
+        let data = build_xdbf(&[
+            (
+                Namespace::TITLE,
+                0x4D5307E6,
+                title_record(0x4D5307E6, [49, 12, 1000, 250], 0, "Halo 3"),
+            ),
+            (
+                Namespace::TITLE,
+                0x58410889,
+                title_record(0x58410889, [12, 12, 200, 200], 0, "Braid"),
+            ),
+        ]);
+        let xdbf = Xdbf::try_from(data.as_slice()).unwrap();
+        let titles: Vec<TitleRecord> = xdbf.titles().collect::<Result<_, _>>().unwrap();
+
+        assert_eq!(titles[0].name, "Halo 3");
+        assert_eq!(titles[1].achievements_unlocked, 12);
+        assert_eq!(
+            xdbf.title(TitleId(0x58410889)).unwrap().unwrap().name,
+            "Braid"
+        );
+        assert_eq!(
+            AchievementTotals::from_titles(&titles),
+            AchievementTotals {
+                achievement_count: 61,
+                achievements_unlocked: 24,
+                gamerscore_total: 1200,
+                gamerscore_unlocked: 450,
+            }
+        );
+    }
+
+    #[test]
+    fn summarizes_profile() {
+        let motto = utf16_be("Hello");
+        let mut motto_len = [0; 8];
+        BigEndian::write_u32(&mut motto_len, motto.len() as u32);
+        let data = build_xdbf(&[
+            (
+                Namespace::SETTING,
+                Setting::GAMERCARD_ZONE.into(),
+                setting_record(Setting::GAMERCARD_ZONE, 1, [0, 0, 0, 2, 0, 0, 0, 0], &[]),
+            ),
+            (
+                Namespace::SETTING,
+                Setting::GAMERCARD_MOTTO.into(),
+                setting_record(Setting::GAMERCARD_MOTTO, 4, motto_len, &motto),
+            ),
+            (
+                Namespace::SETTING,
+                Setting::GAMERCARD_AVATAR_INFO_1.into(),
+                setting_record(
+                    Setting::GAMERCARD_AVATAR_INFO_1,
+                    6,
+                    [0, 0, 0, 2, 0, 0, 0, 0],
+                    &[0xAB, 0xCD],
+                ),
+            ),
+            (
+                Namespace::TITLE,
+                0x4D5307E6,
+                title_record(
+                    0x4D5307E6,
+                    [49, 12, 1000, 250],
+                    0x01CA_8A75_5C6E_0000,
+                    "Halo 3",
+                ),
+            ),
+            (
+                Namespace::TITLE,
+                0x58410889,
+                title_record(
+                    0x58410889,
+                    [12, 12, 200, 200],
+                    0x01CB_0000_0000_0000,
+                    "Braid",
+                ),
+            ),
+            (
+                Namespace::TITLE,
+                0x584108A9,
+                title_record(0x584108A9, [12, 0, 200, 0], 0, "Unplayed"),
+            ),
+        ]);
+        let xdbf = Xdbf::try_from(data.as_slice()).unwrap();
+        let summary = ProfileSummary::new(&xdbf).unwrap();
+
+        assert_eq!(summary.settings.gamer_zone, Some(GamerZone::Pro));
+        assert_eq!(summary.settings.motto.as_deref(), Some("Hello"));
+        assert_eq!(summary.settings.avatar_info, Some(vec![0xAB, 0xCD]));
+        assert_eq!(summary.settings.bio, None);
+
+        let names: Vec<&str> = summary
+            .titles
+            .iter()
+            .map(|title| title.name.as_str())
+            .collect();
+        assert_eq!(names, ["Braid", "Halo 3", "Unplayed"]);
+        assert_eq!(
+            summary.titles[1].last_played.unwrap().to_string(),
+            "2010-01-01 00:00:00"
+        );
+        assert_eq!(summary.totals.gamerscore_unlocked, 450);
+    }
+}
@@ -0,0 +1,390 @@
+//! Declarative layouts of the fixed-size parts of an XContent header, from the
+//! license table through the title image. Each layout reads and writes the
+//! exact bytes it covers, so parsing a header and writing it back produces
+//! identical data.
+
+use std::io::{Cursor, SeekFrom};
+
+use binrw::{binrw, BinRead, BinResult, BinWrite, Endian};
+
+use crate::{
+    stfs::{ContentType, FileSystemType, StfsError},
+    write::{
+        CONSOLE_ID_OFFSET, CONTENT_METADATA_OFFSET, CONTENT_SIZE_OFFSET, CONTENT_TYPE_OFFSET,
+        DEVICE_ID_OFFSET, DISPLAY_DESCRIPTION_OFFSET, DISPLAY_NAME_OFFSET, FILESYSTEM_TYPE_OFFSET,
+        HEADER_HASH_OFFSET, HEADER_SIZE_OFFSET, LICENSES_OFFSET, LOCALE_COUNT,
+        LOCALIZED_STRING_SIZE, MAX_IMAGE_SIZE, MEDIA_ID_OFFSET, METADATA_VERSION_OFFSET,
+        PROFILE_ID_OFFSET, PUBLISHER_NAME_OFFSET, THUMBNAIL_IMAGE_OFFSET, TITLE_ID_OFFSET,
+        TITLE_NAME_OFFSET, TITLE_THUMBNAIL_IMAGE_OFFSET, TRANSFER_FLAGS_OFFSET,
+        VOLUME_DESCRIPTOR_OFFSET,
+    },
+};
+
+/// Size of both kinds of volume descriptor
+pub(crate) const VOLUME_DESCRIPTOR_SIZE: usize = 0x24;
+/// Size of the avatar item or video metadata
+pub(crate) const CONTENT_METADATA_SIZE: usize = DEVICE_ID_OFFSET - CONTENT_METADATA_OFFSET;
+/// UTF-16 code units in one locale's string
+const LOCALIZED_STRING_LEN: usize = LOCALIZED_STRING_SIZE / 2;
+
+#[binrw::parser(reader, endian)]
+fn read_u24() -> BinResult<u32> {
+    let bytes = <[u8; 3]>::read_options(reader, endian, ())?;
+    Ok(match endian {
+        Endian::Big => u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]),
+        Endian::Little => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], 0]),
+    })
+}
+
+#[binrw::writer(writer, endian)]
+fn write_u24(value: &u32) -> BinResult<()> {
+    let bytes = match endian {
+        Endian::Big => {
+            let [_, b0, b1, b2] = value.to_be_bytes();
+            [b0, b1, b2]
+        }
+        Endian::Little => {
+            let [b0, b1, b2, _] = value.to_le_bytes();
+            [b0, b1, b2]
+        }
+    };
+    bytes.write_options(writer, endian, ())
+}
+
+/// A single entry of the header's license table
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct LicenseLayout {
+    /// License type in the top 16 bits, license data in the rest
+    pub(crate) license: u64,
+    pub(crate) bits: u32,
+    pub(crate) flags: u32,
+}
+
+/// STFS volume descriptor. The block counts and table location are
+/// little-endian, everything else is big-endian.
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct StfsVolumeDescriptorLayout {
+    pub(crate) size: u8,
+    pub(crate) reserved: u8,
+    pub(crate) block_separation: u8,
+    #[brw(little)]
+    pub(crate) file_table_block_count: u16,
+    #[brw(little)]
+    #[br(parse_with = read_u24)]
+    #[bw(write_with = write_u24)]
+    pub(crate) file_table_block_num: u32,
+    pub(crate) top_hash_table_hash: [u8; 0x14],
+    pub(crate) allocated_block_count: u32,
+    pub(crate) unallocated_block_count: u32,
+}
+
+/// SVOD volume descriptor
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct SvodVolumeDescriptorLayout {
+    pub(crate) size: u8,
+    pub(crate) block_cache_element_count: u8,
+    pub(crate) worker_thread_processor: u8,
+    pub(crate) worker_thread_priority: u8,
+    pub(crate) root_hash: [u8; 0x14],
+    pub(crate) flags: u8,
+    #[br(parse_with = read_u24)]
+    #[bw(write_with = write_u24)]
+    pub(crate) data_block_count: u32,
+    #[br(parse_with = read_u24)]
+    #[bw(write_with = write_u24)]
+    pub(crate) data_block_offset: u32,
+    pub(crate) reserved: [u8; 5],
+}
+
+/// The volume descriptor, interpreted according to the header's filesystem type.
+/// Descriptors of unsupported filesystems are kept as raw bytes.
+#[binrw]
+#[brw(big)]
+#[br(import(filesystem_type: u32))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum VolumeDescriptorLayout {
+    #[br(pre_assert(filesystem_type == FileSystemType::STFS as u32))]
+    Stfs(StfsVolumeDescriptorLayout),
+    #[br(pre_assert(filesystem_type == FileSystemType::SVOD as u32))]
+    Svod(SvodVolumeDescriptorLayout),
+    Other([u8; VOLUME_DESCRIPTOR_SIZE]),
+}
+
+impl Default for VolumeDescriptorLayout {
+    fn default() -> Self {
+        VolumeDescriptorLayout::Stfs(Default::default())
+    }
+}
+
+/// Avatar item metadata. Unlike the rest of the header, this is little-endian.
+#[binrw]
+#[brw(little)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct AvatarAssetLayout {
+    pub(crate) subcategory: u32,
+    pub(crate) colorizable: u32,
+    pub(crate) guid: [u8; 0x10],
+    pub(crate) skeleton_version: u8,
+    pub(crate) reserved: [u8; 0xB],
+}
+
+/// Video metadata
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct MediaInformationLayout {
+    pub(crate) series_id: [u8; 0x10],
+    pub(crate) season_id: [u8; 0x10],
+    pub(crate) season_number: u16,
+    pub(crate) episode_number: u16,
+}
+
+/// The content metadata, interpreted according to the header's content type.
+/// Other content types don't use it, so it's kept as raw bytes.
+#[binrw]
+#[brw(big)]
+#[br(import(content_type: u32))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ContentMetadataLayout {
+    #[br(pre_assert(content_type == ContentType::AvatarItem as u32))]
+    AvatarItem(AvatarAssetLayout),
+    #[br(pre_assert(content_type == ContentType::Video as u32))]
+    Video(MediaInformationLayout),
+    Other([u8; CONTENT_METADATA_SIZE]),
+}
+
+impl Default for ContentMetadataLayout {
+    fn default() -> Self {
+        ContentMetadataLayout::Other([0; CONTENT_METADATA_SIZE])
+    }
+}
+
+/// Everything from the license table through the title image. Offsets are
+/// absolute, so this must be read from and written to the start of the package.
+///
+/// Strings are kept as their raw UTF-16 code units, including every locale's
+/// copy of the display name and description, and images as their whole
+/// fixed-size regions, so that unused bytes survive a round trip.
+#[binrw]
+#[brw(big)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub(crate) struct HeaderLayout {
+    /// Read first since it determines how the volume descriptor is parsed
+    #[brw(seek_before = SeekFrom::Start(FILESYSTEM_TYPE_OFFSET as u64), restore_position)]
+    pub(crate) filesystem_type: u32,
+    #[brw(seek_before = SeekFrom::Start(LICENSES_OFFSET as u64))]
+    pub(crate) licenses: [LicenseLayout; 16],
+    #[brw(seek_before = SeekFrom::Start(HEADER_HASH_OFFSET as u64))]
+    pub(crate) header_hash: [u8; 0x14],
+    #[brw(seek_before = SeekFrom::Start(HEADER_SIZE_OFFSET as u64))]
+    pub(crate) header_size: u32,
+    #[brw(seek_before = SeekFrom::Start(CONTENT_TYPE_OFFSET as u64))]
+    pub(crate) content_type: u32,
+    #[brw(seek_before = SeekFrom::Start(METADATA_VERSION_OFFSET as u64))]
+    pub(crate) metadata_version: u32,
+    #[brw(seek_before = SeekFrom::Start(CONTENT_SIZE_OFFSET as u64))]
+    pub(crate) content_size: u64,
+    #[brw(seek_before = SeekFrom::Start(MEDIA_ID_OFFSET as u64))]
+    pub(crate) media_id: u32,
+    pub(crate) version: u32,
+    pub(crate) base_version: u32,
+    #[brw(seek_before = SeekFrom::Start(TITLE_ID_OFFSET as u64))]
+    pub(crate) title_id: u32,
+    pub(crate) platform: u8,
+    pub(crate) executable_type: u8,
+    pub(crate) disc_number: u8,
+    pub(crate) disc_in_set: u8,
+    pub(crate) savegame_id: u32,
+    #[brw(seek_before = SeekFrom::Start(CONSOLE_ID_OFFSET as u64))]
+    pub(crate) console_id: [u8; 5],
+    #[brw(seek_before = SeekFrom::Start(PROFILE_ID_OFFSET as u64))]
+    pub(crate) profile_id: [u8; 8],
+    #[brw(seek_before = SeekFrom::Start(VOLUME_DESCRIPTOR_OFFSET as u64))]
+    #[br(args(filesystem_type))]
+    pub(crate) volume_descriptor: VolumeDescriptorLayout,
+    pub(crate) data_file_count: u32,
+    pub(crate) data_file_combined_size: u64,
+    /// The filesystem type is read up front
+    #[brw(seek_before = SeekFrom::Start(FILESYSTEM_TYPE_OFFSET as u64 + 4))]
+    #[br(count = CONTENT_METADATA_OFFSET - FILESYSTEM_TYPE_OFFSET - 4)]
+    pub(crate) reserved: Vec<u8>,
+    #[brw(seek_before = SeekFrom::Start(CONTENT_METADATA_OFFSET as u64))]
+    #[br(args(content_type))]
+    pub(crate) content_metadata: ContentMetadataLayout,
+    #[brw(seek_before = SeekFrom::Start(DEVICE_ID_OFFSET as u64))]
+    pub(crate) device_id: [u8; 0x14],
+    #[brw(seek_before = SeekFrom::Start(DISPLAY_NAME_OFFSET as u64))]
+    #[br(count = LOCALIZED_STRING_LEN * LOCALE_COUNT)]
+    pub(crate) display_name: Vec<u16>,
+    #[brw(seek_before = SeekFrom::Start(DISPLAY_DESCRIPTION_OFFSET as u64))]
+    #[br(count = LOCALIZED_STRING_LEN * LOCALE_COUNT)]
+    pub(crate) display_description: Vec<u16>,
+    #[brw(seek_before = SeekFrom::Start(PUBLISHER_NAME_OFFSET as u64))]
+    #[br(count = LOCALIZED_STRING_LEN)]
+    pub(crate) publisher_name: Vec<u16>,
+    #[brw(seek_before = SeekFrom::Start(TITLE_NAME_OFFSET as u64))]
+    #[br(count = LOCALIZED_STRING_LEN)]
+    pub(crate) title_name: Vec<u16>,
+    #[brw(seek_before = SeekFrom::Start(TRANSFER_FLAGS_OFFSET as u64))]
+    pub(crate) transfer_flags: u8,
+    pub(crate) thumbnail_image_size: u32,
+    pub(crate) title_thumbnail_image_size: u32,
+    #[brw(seek_before = SeekFrom::Start(THUMBNAIL_IMAGE_OFFSET as u64))]
+    #[br(count = MAX_IMAGE_SIZE)]
+    pub(crate) thumbnail_image: Vec<u8>,
+    #[brw(seek_before = SeekFrom::Start(TITLE_THUMBNAIL_IMAGE_OFFSET as u64))]
+    #[br(count = MAX_IMAGE_SIZE)]
+    pub(crate) title_image: Vec<u8>,
+}
+
+/// Decodes a string stored as null-terminated UTF-16 in `units`. Returns `None`
+/// if it isn't terminated within the field or isn't valid UTF-16.
+pub(crate) fn utf16_field(units: &[u16]) -> Option<String> {
+    let len = units.iter().position(|c| *c == 0)?;
+    String::from_utf16(&units[..len]).ok()
+}
+
+fn layout_error(err: binrw::Error) -> StfsError {
+    match err {
+        binrw::Error::Io(err) => StfsError::IoError(err),
+        _ => StfsError::InvalidHeader,
+    }
+}
+
+impl HeaderLayout {
+    /// Reads the fixed header fields of the package `data`
+    pub(crate) fn parse(data: &[u8]) -> Result<Self, StfsError> {
+        Self::read(&mut Cursor::new(data)).map_err(layout_error)
+    }
+
+    /// Writes the fixed header fields back to the package `data`
+    pub(crate) fn write_to(&self, data: &mut [u8]) -> Result<(), StfsError> {
+        self.write(&mut Cursor::new(data)).map_err(layout_error)
+    }
+}
+
+impl StfsVolumeDescriptorLayout {
+    /// Writes the descriptor to the package `data`
+    pub(crate) fn write_to(&self, data: &mut [u8]) -> Result<(), StfsError> {
+        let mut cursor = Cursor::new(data);
+        cursor.set_position(VOLUME_DESCRIPTOR_OFFSET as u64);
+        self.write(&mut cursor).map_err(layout_error)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{
+        stfs::XContentHeader, write::INSTALLER_TYPE_OFFSET, ContentType, StfsPackageBuilder,
+    };
+
+    /// Checks that writing the parsed layout reproduces the bytes it was read from
+    pub(crate) fn round_trip(data: &[u8]) {
+        let layout = HeaderLayout::parse(data).unwrap();
+        let mut written = vec![0u8; data.len()];
+        layout.write_to(&mut written).unwrap();
+
+        let covered = LICENSES_OFFSET..INSTALLER_TYPE_OFFSET;
+        assert_eq!(written[covered.clone()], data[covered]);
+        assert_eq!(HeaderLayout::parse(&written).unwrap(), layout);
+    }
+
+    #[test]
+    fn full_header_round_trips() {
+        let mut builder = StfsPackageBuilder::new();
+        builder
+            .device_id([0x5A; 0x14])
+            .transfer_flags(0x40)
+            .display_name("Layout")
+            .unwrap()
+            .display_description("Every field")
+            .unwrap()
+            .publisher_name("Publisher")
+            .unwrap()
+            .title_name("Title")
+            .unwrap()
+            .thumbnail_image(&[0x89; 0x100])
+            .unwrap()
+            .title_image(&[0x50; 0x80])
+            .unwrap();
+        let mut data = builder.build().unwrap();
+        // Avatar item metadata with a valid subcategory and skeleton version, and
+        // bytes in every unused region
+        data[CONTENT_TYPE_OFFSET..][..4]
+            .copy_from_slice(&(ContentType::AvatarItem as u32).to_be_bytes());
+        data[CONTENT_METADATA_OFFSET..][..4].copy_from_slice(&0x44Cu32.to_le_bytes());
+        data[CONTENT_METADATA_OFFSET + 0x8..][..0x10].fill(0x11);
+        data[CONTENT_METADATA_OFFSET + 0x18] = 1;
+        data[FILESYSTEM_TYPE_OFFSET + 4..CONTENT_METADATA_OFFSET].fill(0x22);
+        data[DISPLAY_NAME_OFFSET + LOCALIZED_STRING_SIZE..][..4].fill(0x33);
+        data[THUMBNAIL_IMAGE_OFFSET + MAX_IMAGE_SIZE - 1] = 0x44;
+
+        round_trip(&data);
+
+        let layout = HeaderLayout::parse(&data).unwrap();
+        let ContentMetadataLayout::AvatarItem(avatar) = &layout.content_metadata else {
+            panic!("expected avatar item metadata");
+        };
+        assert_eq!(avatar.subcategory, 0x44C);
+        assert_eq!(avatar.guid, [0x11; 0x10]);
+        assert_eq!(utf16_field(&layout.display_name).unwrap(), "Layout");
+        assert_eq!(utf16_field(&layout.title_name).unwrap(), "Title");
+        assert_eq!(layout.thumbnail_image_size, 0x100);
+
+        let header = XContentHeader::parse(&data).unwrap();
+        assert_eq!(header.device_id, [0x5A; 0x14]);
+        assert_eq!(header.display_description, "Every field");
+        assert_eq!(header.publisher_name, "Publisher");
+        assert_eq!(header.thumbnail_image, [0x89; 0x100]);
+        assert_eq!(header.title_image, [0x50; 0x80]);
+        assert!(header.content_metadata.is_some());
+    }
+
+    #[test]
+    fn built_header_round_trips() {
+        let mut builder = StfsPackageBuilder::new();
+        builder
+            .title_id(0x4D5307E6)
+            .add_file("save/slot0.bin", vec![0xAB; 0x2345])
+            .unwrap();
+        let package = builder.build().unwrap();
+
+        round_trip(&package);
+
+        let layout = HeaderLayout::parse(&package).unwrap();
+        assert_eq!(layout.title_id, 0x4D5307E6);
+        let VolumeDescriptorLayout::Stfs(descriptor) = &layout.volume_descriptor else {
+            panic!("expected an STFS volume descriptor");
+        };
+        assert_eq!(usize::from(descriptor.size), VOLUME_DESCRIPTOR_SIZE);
+        assert_eq!(descriptor.file_table_block_count, 1);
+    }
+
+    #[test]
+    fn svod_and_unknown_descriptors_round_trip() {
+        let mut data = StfsPackageBuilder::new().build().unwrap();
+        for filesystem_type in [FileSystemType::SVOD as u32, 0x1234] {
+            data[FILESYSTEM_TYPE_OFFSET..][..4].copy_from_slice(&filesystem_type.to_be_bytes());
+            for (i, b) in data[VOLUME_DESCRIPTOR_OFFSET..][..VOLUME_DESCRIPTOR_SIZE]
+                .iter_mut()
+                .enumerate()
+            {
+                *b = i as u8 * 7;
+            }
+            round_trip(&data);
+        }
+
+        let layout = HeaderLayout::parse(&data).unwrap();
+        assert!(matches!(
+            layout.volume_descriptor,
+            VolumeDescriptorLayout::Other(_)
+        ));
+    }
+}
@@ -1,7 +1,93 @@
+#[cfg(feature = "crypto")]
+mod account;
+mod allocation;
+#[cfg(feature = "zip")]
+mod archive;
+#[cfg(feature = "async")]
+mod async_reader;
+mod builder;
+mod dds;
+mod diff;
+mod display;
+mod dto;
+mod edit;
+mod extract;
+mod file_table;
+mod file_type;
+mod gpd;
+mod layout;
+mod metadata;
+#[cfg(any(feature = "async", feature = "wasm"))]
+mod mirror;
+mod parallel;
+mod progress;
+mod regions;
+mod repair;
+#[cfg(feature = "serde")]
+mod serialize;
+mod shared;
+#[cfg(feature = "sign")]
+mod sign;
 mod sparse_reader;
 pub mod stfs;
+#[cfg(any(test, feature = "testgen"))]
+pub mod testgen;
+mod titles;
+mod verify;
+#[cfg(feature = "wasm")]
+mod wasm;
+mod write;
+mod xdbf;
+mod xex;
 
+#[cfg(feature = "crypto")]
+pub use crate::account::{
+    write_account, Account, AccountFlags, SubscriptionTier, ACCOUNT_FILE, ACCOUNT_SIZE,
+};
+pub use crate::allocation::{BlockAllocator, BlockState};
+#[cfg(feature = "zip")]
+pub use crate::archive::{ZipCompression, ZipOptions};
+#[cfg(feature = "async")]
+pub use crate::async_reader::StfsPackageAsync;
+pub use crate::builder::StfsPackageBuilder;
+pub use crate::dds::{decode_dds, is_dds, RgbaImage};
+pub use crate::diff::{diff, Change, EntryDiff, EntrySummary, FieldDiff, PackageDiff};
+pub use crate::display::{MediaId, TitleId};
+pub use crate::dto::{EntryInfo, PackageInfo, SignatureKind, SCHEMA_VERSION};
+pub use crate::edit::{inject_file, remove_entry};
+pub use crate::extract::{CopyMethod, ExtractOptions, OverwritePolicy};
+pub use crate::file_table::{EntryId, FileTable};
+pub use crate::file_type::FileType;
+pub use crate::gpd::{
+    Achievement, AchievementFlags, AchievementTotals, AchievementType, GamerZone, ProfileSettings,
+    ProfileSummary, TitleRecord, DASHBOARD_GPD,
+};
+pub use crate::metadata::{
+    set_image, set_license, set_metadata, ImageKind, MetadataField, PackageMetadata, LICENSE_COUNT,
+};
+pub use crate::parallel::is_parallel;
+pub use crate::progress::{CancellationToken, Progress, ProgressSink};
+pub use crate::regions::{Region, RegionKind};
+pub use crate::repair::{repair, Repair};
+#[cfg(feature = "serde")]
+pub use crate::serialize::{with_binary_encoding, BinaryEncoding};
+pub use crate::shared::SharedPackage;
+#[cfg(feature = "sign")]
+pub use crate::sign::{resign, verify_signature, KeyVault, SignatureStatus};
+pub use crate::sparse_reader::SparseReader;
 pub use crate::stfs::*;
+pub use crate::titles::title_name;
+pub use crate::verify::{HashLocation, HashMismatch};
+pub use crate::write::{
+    convert, rehash, rehash_with_progress, reserve_blocks, strip_signature, truncate_unused,
+};
+pub use crate::xdbf::{
+    FreeSpaceEntry, Namespace, Setting, SettingValue, Xdbf, XdbfEntry, XDBF_MAGIC,
+};
+pub use crate::xex::{
+    ExecutionInfo, HeaderKey, HeaderValue, ModuleFlags, OptionalHeader, OptionalHeaderInfo, Xex,
+    XexInfo, XexVersion, XEX2_MAGIC,
+};
 
 #[cfg(test)]
 mod tests {
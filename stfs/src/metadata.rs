@@ -0,0 +1,577 @@
+//! Reading and editing individual header metadata fields by name.
+
+use std::{fmt::Write, ops::Range, str::FromStr};
+
+use byteorder::{BigEndian, ByteOrder};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+use crate::{
+    stfs::{ContentType, StfsError, StfsPackage, XContentHeader},
+    write::{self, *},
+};
+
+/// A header field which can be read or written as a string
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize), serde(rename_all = "snake_case"))]
+pub enum MetadataField {
+    DisplayName,
+    DisplayDescription,
+    PublisherName,
+    TitleName,
+    TitleId,
+    MediaId,
+    ConsoleId,
+    ProfileId,
+    DeviceId,
+    TransferFlags,
+}
+
+impl MetadataField {
+    pub const ALL: [MetadataField; 10] = [
+        MetadataField::DisplayName,
+        MetadataField::DisplayDescription,
+        MetadataField::PublisherName,
+        MetadataField::TitleName,
+        MetadataField::TitleId,
+        MetadataField::MediaId,
+        MetadataField::ConsoleId,
+        MetadataField::ProfileId,
+        MetadataField::DeviceId,
+        MetadataField::TransferFlags,
+    ];
+
+    /// The field's snake_case name, as accepted by [`MetadataField::from_str`]
+    pub fn name(self) -> &'static str {
+        match self {
+            MetadataField::DisplayName => "display_name",
+            MetadataField::DisplayDescription => "display_description",
+            MetadataField::PublisherName => "publisher_name",
+            MetadataField::TitleName => "title_name",
+            MetadataField::TitleId => "title_id",
+            MetadataField::MediaId => "media_id",
+            MetadataField::ConsoleId => "console_id",
+            MetadataField::ProfileId => "profile_id",
+            MetadataField::DeviceId => "device_id",
+            MetadataField::TransferFlags => "transfer_flags",
+        }
+    }
+
+    /// Where the field is stored in the header. Localized strings cover the
+    /// copies for every locale, of which only the first is read and written.
+    pub fn range(self) -> Range<usize> {
+        let (offset, len) = match self {
+            MetadataField::DisplayName => {
+                (DISPLAY_NAME_OFFSET, LOCALIZED_STRING_SIZE * LOCALE_COUNT)
+            }
+            MetadataField::DisplayDescription => (
+                DISPLAY_DESCRIPTION_OFFSET,
+                LOCALIZED_STRING_SIZE * LOCALE_COUNT,
+            ),
+            MetadataField::PublisherName => (PUBLISHER_NAME_OFFSET, LOCALIZED_STRING_SIZE),
+            MetadataField::TitleName => (TITLE_NAME_OFFSET, LOCALIZED_STRING_SIZE),
+            MetadataField::TitleId => (TITLE_ID_OFFSET, 4),
+            MetadataField::MediaId => (MEDIA_ID_OFFSET, 4),
+            MetadataField::ConsoleId => (CONSOLE_ID_OFFSET, 5),
+            MetadataField::ProfileId => (PROFILE_ID_OFFSET, 8),
+            MetadataField::DeviceId => (DEVICE_ID_OFFSET, 0x14),
+            MetadataField::TransferFlags => (TRANSFER_FLAGS_OFFSET, 1),
+        };
+
+        offset..offset + len
+    }
+
+    /// Formats the field's current value. Strings are returned as-is while
+    /// numeric and binary fields are uppercase hex.
+    pub fn get(self, header: &XContentHeader<'_>) -> String {
+        match self {
+            MetadataField::DisplayName => header.display_name.clone(),
+            MetadataField::DisplayDescription => header.display_description.clone(),
+            MetadataField::PublisherName => header.publisher_name.clone(),
+            MetadataField::TitleName => header.title_name.clone(),
+            MetadataField::TitleId => header.title_id.to_string(),
+            MetadataField::MediaId => header.media_id.to_string(),
+            MetadataField::ConsoleId => hex(&header.console_id),
+            MetadataField::ProfileId => hex(&header.profile_id),
+            MetadataField::DeviceId => hex(header.device_id),
+            MetadataField::TransferFlags => format!("{:02X}", header.transfer_flags),
+        }
+    }
+}
+
+impl FromStr for MetadataField {
+    type Err = StfsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|field| field.name() == s)
+            .ok_or_else(|| StfsError::UnknownMetadataField(s.to_owned()))
+    }
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut out, b| {
+        let _ = write!(out, "{:02X}", b);
+        out
+    })
+}
+
+/// Parses exactly `N` bytes of hex, with or without a `0x` prefix
+fn parse_hex<const N: usize>(field: MetadataField, value: &str) -> Result<[u8; N], StfsError> {
+    let invalid = || StfsError::InvalidMetadataValue {
+        field: field.name(),
+        value: value.to_owned(),
+    };
+
+    let digits = value
+        .strip_prefix("0x")
+        .or_else(|| value.strip_prefix("0X"))
+        .unwrap_or(value);
+    if digits.len() != N * 2 || !digits.is_ascii() {
+        return Err(invalid());
+    }
+
+    let mut bytes = [0u8; N];
+    for (byte, pair) in bytes.iter_mut().zip(digits.as_bytes().chunks_exact(2)) {
+        let pair = std::str::from_utf8(pair).map_err(|_| invalid())?;
+        *byte = u8::from_str_radix(pair, 16).map_err(|_| invalid())?;
+    }
+
+    Ok(bytes)
+}
+
+/// Overwrites a single header field and updates the header hash.
+///
+/// Numeric and binary fields are given in hex, using the same format returned
+/// by [`MetadataField::get`]. The package signature is left untouched and will
+/// no longer be valid.
+pub fn set_metadata(data: &mut [u8], field: MetadataField, value: &str) -> Result<(), StfsError> {
+    let first_table_address = StfsPackage::try_from(&*data)?
+        .hash_table_meta
+        .first_table_address;
+
+    write_field(data, field, value)?;
+    update_header_hash(data, first_table_address);
+
+    Ok(())
+}
+
+/// Writes a field's value into `data`, which may be a whole package or just
+/// its header
+pub(crate) fn write_field(
+    data: &mut [u8],
+    field: MetadataField,
+    value: &str,
+) -> Result<(), StfsError> {
+    match field {
+        MetadataField::DisplayName => write::write_utf16_str(
+            data,
+            DISPLAY_NAME_OFFSET,
+            LOCALIZED_STRING_SIZE,
+            field.name(),
+            value,
+        )?,
+        MetadataField::DisplayDescription => write::write_utf16_str(
+            data,
+            DISPLAY_DESCRIPTION_OFFSET,
+            LOCALIZED_STRING_SIZE,
+            field.name(),
+            value,
+        )?,
+        MetadataField::PublisherName => write::write_utf16_str(
+            data,
+            PUBLISHER_NAME_OFFSET,
+            LOCALIZED_STRING_SIZE,
+            field.name(),
+            value,
+        )?,
+        MetadataField::TitleName => write::write_utf16_str(
+            data,
+            TITLE_NAME_OFFSET,
+            LOCALIZED_STRING_SIZE,
+            field.name(),
+            value,
+        )?,
+        MetadataField::TitleId => {
+            let title_id = parse_hex::<4>(field, value)?;
+            BigEndian::write_u32(&mut data[TITLE_ID_OFFSET..], u32::from_be_bytes(title_id));
+        }
+        MetadataField::MediaId => {
+            let media_id = parse_hex::<4>(field, value)?;
+            BigEndian::write_u32(&mut data[MEDIA_ID_OFFSET..], u32::from_be_bytes(media_id));
+        }
+        MetadataField::ConsoleId => {
+            let console_id = parse_hex::<5>(field, value)?;
+            data[CONSOLE_ID_OFFSET..CONSOLE_ID_OFFSET + 5].copy_from_slice(&console_id);
+        }
+        MetadataField::ProfileId => {
+            let profile_id = parse_hex::<8>(field, value)?;
+            data[PROFILE_ID_OFFSET..PROFILE_ID_OFFSET + 8].copy_from_slice(&profile_id);
+        }
+        MetadataField::DeviceId => {
+            let device_id = parse_hex::<0x14>(field, value)?;
+            data[DEVICE_ID_OFFSET..DEVICE_ID_OFFSET + 0x14].copy_from_slice(&device_id);
+        }
+        MetadataField::TransferFlags => {
+            let [transfer_flags] = parse_hex::<1>(field, value)?;
+            data[TRANSFER_FLAGS_OFFSET] = transfer_flags;
+        }
+    }
+
+    Ok(())
+}
+
+/// A package's header metadata, in the same text form as [`MetadataField::get`]
+/// so that it can be saved as JSON, edited by hand, and applied to another
+/// package or builder. Fields which are `None` are left alone when applied.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(default, deny_unknown_fields)
+)]
+pub struct PackageMetadata {
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub content_type: Option<ContentType>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub display_name: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub display_description: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub publisher_name: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub title_name: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub title_id: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub media_id: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub console_id: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub profile_id: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub device_id: Option<String>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing_if = "Option::is_none"))]
+    pub transfer_flags: Option<String>,
+}
+
+impl PackageMetadata {
+    /// Every field of `header`
+    pub fn from_header(header: &XContentHeader<'_>) -> Self {
+        let mut metadata = PackageMetadata {
+            content_type: Some(header.content_type),
+            ..Default::default()
+        };
+        for field in MetadataField::ALL {
+            *metadata.field_mut(field) = Some(field.get(header));
+        }
+
+        metadata
+    }
+
+    pub fn get(&self, field: MetadataField) -> Option<&str> {
+        match field {
+            MetadataField::DisplayName => &self.display_name,
+            MetadataField::DisplayDescription => &self.display_description,
+            MetadataField::PublisherName => &self.publisher_name,
+            MetadataField::TitleName => &self.title_name,
+            MetadataField::TitleId => &self.title_id,
+            MetadataField::MediaId => &self.media_id,
+            MetadataField::ConsoleId => &self.console_id,
+            MetadataField::ProfileId => &self.profile_id,
+            MetadataField::DeviceId => &self.device_id,
+            MetadataField::TransferFlags => &self.transfer_flags,
+        }
+        .as_deref()
+    }
+
+    pub fn field_mut(&mut self, field: MetadataField) -> &mut Option<String> {
+        match field {
+            MetadataField::DisplayName => &mut self.display_name,
+            MetadataField::DisplayDescription => &mut self.display_description,
+            MetadataField::PublisherName => &mut self.publisher_name,
+            MetadataField::TitleName => &mut self.title_name,
+            MetadataField::TitleId => &mut self.title_id,
+            MetadataField::MediaId => &mut self.media_id,
+            MetadataField::ConsoleId => &mut self.console_id,
+            MetadataField::ProfileId => &mut self.profile_id,
+            MetadataField::DeviceId => &mut self.device_id,
+            MetadataField::TransferFlags => &mut self.transfer_flags,
+        }
+    }
+
+    /// Writes every field which is set into `data`, which may be a whole
+    /// package or just its header
+    pub(crate) fn write(&self, data: &mut [u8]) -> Result<(), StfsError> {
+        if let Some(content_type) = self.content_type {
+            BigEndian::write_u32(&mut data[CONTENT_TYPE_OFFSET..], content_type as u32);
+        }
+        for field in MetadataField::ALL {
+            if let Some(value) = self.get(field) {
+                write_field(data, field, value)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Overwrites the package's header fields with those which are set and
+    /// updates the header hash. Either every field is written or, if any of
+    /// them is invalid, none are. As with [`set_metadata`], the package
+    /// signature is no longer valid afterwards.
+    pub fn apply(&self, data: &mut [u8]) -> Result<(), StfsError> {
+        let first_table_address = StfsPackage::try_from(&*data)?
+            .hash_table_meta
+            .first_table_address;
+
+        let mut header = data[..first_table_address].to_vec();
+        self.write(&mut header)?;
+        data[..first_table_address].copy_from_slice(&header);
+        update_header_hash(data, first_table_address);
+
+        Ok(())
+    }
+}
+
+/// Number of license entries in the header
+pub const LICENSE_COUNT: usize = 0x10;
+
+/// Overwrites license entry `index`. `license` is the raw 64-bit value, whose
+/// top 16 bits hold the license type (`0xFFFF` for unrestricted), followed by
+/// the entry's `bits` and `flags`.
+///
+/// Licenses aren't covered by the header hash, but they are signed, so CON
+/// packages need to be resigned afterwards.
+pub fn set_license(
+    data: &mut [u8],
+    index: usize,
+    license: u64,
+    bits: u32,
+    flags: u32,
+) -> Result<(), StfsError> {
+    XContentHeader::parse(data)?;
+
+    if index >= LICENSE_COUNT {
+        return Err(StfsError::InvalidMetadataValue {
+            field: "license index",
+            value: index.to_string(),
+        });
+    }
+
+    let entry = &mut data[LICENSES_OFFSET + (index * LICENSE_ENTRY_SIZE)..];
+    BigEndian::write_u64(entry, license);
+    BigEndian::write_u32(&mut entry[8..], bits);
+    BigEndian::write_u32(&mut entry[12..], flags);
+
+    Ok(())
+}
+
+/// One of the two PNG images stored in the header
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageKind {
+    /// The package's own icon
+    Thumbnail,
+    /// The icon of the game the package belongs to
+    Title,
+}
+
+impl ImageKind {
+    pub const ALL: [ImageKind; 2] = [ImageKind::Thumbnail, ImageKind::Title];
+
+    /// The image's name, used for file names when exporting
+    pub fn name(self) -> &'static str {
+        match self {
+            ImageKind::Thumbnail => "thumbnail",
+            ImageKind::Title => "title",
+        }
+    }
+
+    /// The image's current bytes, which are empty if the package has no such image
+    pub fn get<'a>(self, header: &XContentHeader<'a>) -> &'a [u8] {
+        match self {
+            ImageKind::Thumbnail => header.thumbnail_image,
+            ImageKind::Title => header.title_image,
+        }
+    }
+}
+
+/// Replaces one of the header's images and updates the header hash. Images may
+/// be at most 0x4000 bytes. As with [`set_metadata`], the package signature is
+/// no longer valid afterwards.
+pub fn set_image(data: &mut [u8], kind: ImageKind, image: &[u8]) -> Result<(), StfsError> {
+    let first_table_address = StfsPackage::try_from(&*data)?
+        .hash_table_meta
+        .first_table_address;
+
+    match kind {
+        ImageKind::Thumbnail => write::write_image(
+            data,
+            THUMBNAIL_IMAGE_SIZE_OFFSET,
+            THUMBNAIL_IMAGE_OFFSET,
+            "thumbnail_image",
+            image,
+        )?,
+        ImageKind::Title => write::write_image(
+            data,
+            TITLE_THUMBNAIL_IMAGE_SIZE_OFFSET,
+            TITLE_THUMBNAIL_IMAGE_OFFSET,
+            "title_image",
+            image,
+        )?,
+    }
+
+    update_header_hash(data, first_table_address);
+
+    Ok(())
+}
+
+/// None of the metadata fields affect the hash tables, so only the header hash
+/// needs updating after they change
+fn update_header_hash(data: &mut [u8], first_table_address: usize) {
+    let header_hash = Sha1::digest(&data[CONTENT_TYPE_OFFSET..first_table_address]);
+    data[HEADER_HASH_OFFSET..HEADER_HASH_OFFSET + header_hash.len()].copy_from_slice(&header_hash);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StfsPackageBuilder;
+
+    #[test]
+    fn set_and_get_round_trip() {
+        let mut builder = StfsPackageBuilder::new();
+        builder.add_file("save.bin", vec![0x42; 0x10]).unwrap();
+        let mut data = builder.build().unwrap();
+
+        let values = [
+            (MetadataField::DisplayName, "Test save"),
+            (MetadataField::TitleName, "Halo 3"),
+            (MetadataField::TitleId, "4D5307E6"),
+            (MetadataField::ConsoleId, "0102030405"),
+            (MetadataField::ProfileId, "E000123456789ABC"),
+            (
+                MetadataField::DeviceId,
+                "00112233445566778899AABBCCDDEEFF00112233",
+            ),
+            (MetadataField::TransferFlags, "C0"),
+        ];
+        for (field, value) in values {
+            set_metadata(&mut data, field.name().parse().unwrap(), value).unwrap();
+        }
+
+        let package = StfsPackage::try_from(data.as_slice()).unwrap();
+        for (field, value) in values {
+            assert_eq!(field.get(&package.header), value);
+        }
+
+        let first_table_address = package.hash_table_meta.first_table_address;
+        assert_eq!(
+            package.header.header_hash,
+            Sha1::digest(&data[CONTENT_TYPE_OFFSET..first_table_address]).as_slice()
+        );
+    }
+
+    #[test]
+    fn metadata_applies_atomically() {
+        let mut builder = StfsPackageBuilder::new();
+        builder
+            .title_id(0x4D53_07E6)
+            .display_name("Original")
+            .unwrap();
+        let mut data = builder.build().unwrap();
+
+        let package = StfsPackage::try_from(data.as_slice()).unwrap();
+        let mut metadata = PackageMetadata::from_header(&package.header);
+        assert_eq!(metadata.get(MetadataField::TitleId), Some("4D5307E6"));
+        assert_eq!(metadata.content_type, Some(ContentType::SavedGame));
+
+        metadata.content_type = Some(ContentType::Profile);
+        *metadata.field_mut(MetadataField::DisplayName) = Some("Edited".to_owned());
+        *metadata.field_mut(MetadataField::TitleId) = Some("nope".to_owned());
+        let original = data.clone();
+        assert!(metadata.apply(&mut data).is_err());
+        assert!(data == original);
+
+        *metadata.field_mut(MetadataField::TitleId) = None;
+        metadata.apply(&mut data).unwrap();
+        let package = StfsPackage::try_from(data.as_slice()).unwrap();
+        assert_eq!(package.header.display_name, "Edited");
+        assert_eq!(package.header.content_type, ContentType::Profile);
+        assert_eq!(MetadataField::TitleId.get(&package.header), "4D5307E6");
+        assert_eq!(
+            package.header.header_hash,
+            Sha1::digest(&data[CONTENT_TYPE_OFFSET..package.hash_table_meta.first_table_address])
+                .as_slice()
+        );
+
+        let mut builder = StfsPackageBuilder::new();
+        builder.metadata(&metadata).unwrap();
+        let built = builder.build().unwrap();
+        let built = StfsPackage::try_from(built.as_slice()).unwrap();
+        assert_eq!(PackageMetadata::from_header(&built.header), {
+            let mut expected = PackageMetadata::from_header(&package.header);
+            expected.title_id = Some("00000000".to_owned());
+            expected
+        });
+    }
+
+    #[test]
+    fn set_image_round_trip() {
+        let mut builder = StfsPackageBuilder::new();
+        builder.thumbnail_image(&[0x89; 0x100]).unwrap();
+        let mut data = builder.build().unwrap();
+
+        set_image(&mut data, ImageKind::Title, &[0x42; 0x20]).unwrap();
+        set_image(&mut data, ImageKind::Thumbnail, &[0x11; 0x10]).unwrap();
+        assert!(set_image(&mut data, ImageKind::Title, &[0; 0x4001]).is_err());
+
+        let package = StfsPackage::try_from(data.as_slice()).unwrap();
+        assert_eq!(ImageKind::Thumbnail.get(&package.header), [0x11; 0x10]);
+        assert_eq!(ImageKind::Title.get(&package.header), [0x42; 0x20]);
+
+        // The previous, longer thumbnail shouldn't leave anything behind
+        assert!(
+            data[THUMBNAIL_IMAGE_OFFSET + 0x10..TITLE_THUMBNAIL_IMAGE_OFFSET]
+                .iter()
+                .all(|b| *b == 0)
+        );
+        assert_eq!(
+            package.header.header_hash,
+            Sha1::digest(&data[CONTENT_TYPE_OFFSET..package.hash_table_meta.first_table_address])
+                .as_slice()
+        );
+    }
+
+    #[test]
+    fn set_license_writes_entry() {
+        let mut data = StfsPackageBuilder::new().build().unwrap();
+
+        set_license(&mut data, 1, 0x0009_E000_0123_4567, 1, 2).unwrap();
+        assert_eq!(
+            data[LICENSES_OFFSET + LICENSE_ENTRY_SIZE..][..LICENSE_ENTRY_SIZE],
+            [0x00, 0x09, 0xE0, 0x00, 0x01, 0x23, 0x45, 0x67, 0, 0, 0, 1, 0, 0, 0, 2]
+        );
+        StfsPackage::try_from(data.as_slice()).unwrap();
+
+        assert!(set_license(&mut data, LICENSE_COUNT, u64::MAX, 0, 0).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        let mut data = StfsPackageBuilder::new().build().unwrap();
+
+        assert!(matches!(
+            "nonexistent".parse::<MetadataField>(),
+            Err(StfsError::UnknownMetadataField(_))
+        ));
+        for value in ["4D5307", "4D5307E6FF", "XYZW1234"] {
+            assert!(matches!(
+                set_metadata(&mut data, MetadataField::TitleId, value),
+                Err(StfsError::InvalidMetadataValue { .. })
+            ));
+        }
+        assert!(set_metadata(&mut data, MetadataField::TitleName, &"a".repeat(0x80)).is_err());
+    }
+}
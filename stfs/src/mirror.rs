@@ -0,0 +1,178 @@
+//! Reading packages a piece at a time from slow sources such as web servers.
+//!
+//! The package is mirrored into a buffer the size of the whole file, of which
+//! only the blocks the parser needs are fetched. Parsing is repeated until every
+//! block it reads has been fetched, since the hash tables that locate the file
+//! table and fragmented files aren't known until earlier blocks arrive.
+
+use std::{collections::BTreeSet, ops::Range};
+
+use crate::{
+    edit::file_blocks,
+    stfs::{
+        HashTableLevel, StfsError, StfsFileEntry, StfsPackage, BLOCK_SIZE,
+        DATA_BLOCKS_PER_HASH_TREE_LEVEL,
+    },
+};
+
+/// Size of the first fetch, which covers the header of every package the
+/// console creates
+pub(crate) const INITIAL_FETCH_SIZE: usize = 0xA000;
+
+/// A package buffer of which only some pages have been filled in
+pub(crate) struct PartialMirror {
+    pub(crate) data: Vec<u8>,
+    /// Indexes of the `BLOCK_SIZE` pages of `data` which have been fetched
+    fetched: BTreeSet<usize>,
+}
+
+impl PartialMirror {
+    pub(crate) fn new(len: usize) -> Self {
+        PartialMirror {
+            data: vec![0; len],
+            fetched: BTreeSet::new(),
+        }
+    }
+
+    #[cfg(feature = "wasm")]
+    pub(crate) fn fetched_pages(&self) -> usize {
+        self.fetched.len()
+    }
+
+    /// Returns the parts of `ranges` which haven't been fetched yet, rounded out
+    /// to whole pages and merged where adjacent
+    pub(crate) fn missing(
+        &self,
+        ranges: impl IntoIterator<Item = Range<usize>>,
+    ) -> Vec<Range<usize>> {
+        let len = self.data.len();
+        let pages: BTreeSet<usize> = ranges
+            .into_iter()
+            .filter(|range| range.start < len)
+            .flat_map(|range| (range.start / BLOCK_SIZE)..range.end.min(len).div_ceil(BLOCK_SIZE))
+            .filter(|page| !self.fetched.contains(page))
+            .collect();
+
+        let mut missing: Vec<Range<usize>> = Vec::new();
+        for page in pages {
+            let start = page * BLOCK_SIZE;
+            let end = (start + BLOCK_SIZE).min(len);
+            match missing.last_mut() {
+                Some(last) if last.end == start => last.end = end,
+                _ => missing.push(start..end),
+            }
+        }
+
+        missing
+    }
+
+    /// Copies `body`, read from `offset`, into the buffer and marks the pages it
+    /// fully covers as fetched
+    pub(crate) fn fill(&mut self, offset: usize, body: &[u8]) {
+        let len = self.data.len();
+        let start = offset.min(len);
+        let end = (offset + body.len()).min(len);
+        self.data[start..end].copy_from_slice(&body[..end - start]);
+
+        let first_page = start.div_ceil(BLOCK_SIZE);
+        let last_page = if end == len {
+            len.div_ceil(BLOCK_SIZE)
+        } else {
+            end / BLOCK_SIZE
+        };
+        self.fetched.extend(first_page..last_page);
+    }
+}
+
+/// The page holding `address`
+fn page(address: usize) -> Range<usize> {
+    let start = address - (address % BLOCK_SIZE);
+    start..start + BLOCK_SIZE
+}
+
+/// The hash table blocks which must be read to find the block after `block`
+fn hash_ranges(package: &StfsPackage<'_>, block: usize) -> Result<Vec<Range<usize>>, StfsError> {
+    let mut ranges = vec![page(
+        package.block_hash_address(block, package.input)? as usize
+    )];
+    if package.hash_table_meta.top_table.level == HashTableLevel::Third {
+        ranges.push(page(
+            package.level1_table_address(block / DATA_BLOCKS_PER_HASH_TREE_LEVEL[2]),
+        ));
+    }
+
+    Ok(ranges)
+}
+
+/// The header, top hash table, and file table, along with the hash tables
+/// linking the file table's blocks
+pub(crate) fn metadata_ranges(package: &StfsPackage<'_>) -> Result<Vec<Range<usize>>, StfsError> {
+    let meta = &package.hash_table_meta;
+    let mut ranges = vec![
+        0..meta.first_table_address,
+        page(meta.top_table.address_in_file),
+    ];
+    for block in package.file_table_blocks()? {
+        ranges.push(page(package.block_to_addr(block)? as usize));
+        ranges.extend(hash_ranges(package, block)?);
+    }
+
+    Ok(ranges)
+}
+
+/// The hash tables linking the blocks of a fragmented file, which must be
+/// fetched before its contents can be located
+pub(crate) fn file_hash_ranges(
+    package: &StfsPackage<'_>,
+    entry: &StfsFileEntry,
+) -> Result<Vec<Range<usize>>, StfsError> {
+    if entry.has_consecutive_blocks() {
+        return Ok(Vec::new());
+    }
+
+    let mut ranges = Vec::new();
+    for block in file_blocks(package, entry) {
+        ranges.extend(hash_ranges(package, block)?);
+    }
+
+    Ok(ranges)
+}
+
+/// The blocks holding `entry`'s contents, and for fragmented files, the hash
+/// tables linking them
+#[cfg(feature = "wasm")]
+pub(crate) fn file_ranges(
+    package: &StfsPackage<'_>,
+    entry: &StfsFileEntry,
+) -> Result<Vec<Range<usize>>, StfsError> {
+    let mut ranges = file_hash_ranges(package, entry)?;
+    for block in file_blocks(package, entry) {
+        ranges.push(page(package.block_to_addr(block)? as usize));
+    }
+
+    Ok(ranges)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_merges_adjacent_pages() {
+        let mut mirror = PartialMirror::new(BLOCK_SIZE * 4 + 0x10);
+        mirror.fill(BLOCK_SIZE, &[1; BLOCK_SIZE]);
+
+        assert_eq!(
+            mirror.missing([
+                0..0x10,
+                0x10..BLOCK_SIZE * 3,
+                BLOCK_SIZE * 4..BLOCK_SIZE * 5
+            ]),
+            vec![
+                0..BLOCK_SIZE,
+                BLOCK_SIZE * 2..BLOCK_SIZE * 3,
+                BLOCK_SIZE * 4..BLOCK_SIZE * 4 + 0x10,
+            ]
+        );
+    }
+}
@@ -0,0 +1,55 @@
+//! Helpers for work that can optionally be spread across a rayon thread pool.
+//!
+//! Call sites are written once against these functions. With the `parallel`
+//! feature enabled (the default on native targets) they fan out over rayon;
+//! without it they run sequentially on the calling thread, which is what the
+//! wasm and FFI builds want for deterministic behavior. Both variants share the
+//! same trait bounds so code that compiles in one configuration compiles in the
+//! other.
+
+/// Maps `f` over `items`, returning the results in the same order as the input.
+#[cfg(feature = "parallel")]
+pub(crate) fn map_collect<T, U, F>(items: Vec<T>, f: F) -> Vec<U>
+where
+    T: Send,
+    U: Send,
+    F: Fn(T) -> U + Send + Sync,
+{
+    use rayon::prelude::*;
+
+    items.into_par_iter().map(f).collect()
+}
+
+/// Maps `f` over `items`, returning the results in the same order as the input.
+#[cfg(not(feature = "parallel"))]
+pub(crate) fn map_collect<T, U, F>(items: Vec<T>, f: F) -> Vec<U>
+where
+    T: Send,
+    U: Send,
+    F: Fn(T) -> U + Send + Sync,
+{
+    items.into_iter().map(f).collect()
+}
+
+/// Returns whether this build of the library was compiled with the `parallel` feature.
+pub const fn is_parallel() -> bool {
+    cfg!(feature = "parallel")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn map_collect_preserves_order() {
+        let input: Vec<usize> = (0..4096).collect();
+        let output = map_collect(input, |i| i * 2);
+
+        assert_eq!(output, (0..4096).map(|i| i * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn is_parallel_matches_feature() {
+        assert_eq!(is_parallel(), cfg!(feature = "parallel"));
+    }
+}
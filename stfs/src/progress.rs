@@ -0,0 +1,102 @@
+//! Progress reporting and cancellation for long-running operations.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use crate::stfs::StfsError;
+
+/// Number of hashes computed between progress updates when checking or
+/// rebuilding the hash tree
+pub(crate) const HASHES_PER_PROGRESS_UPDATE: usize = 0x100;
+
+/// A snapshot of an operation's progress, passed to the [`ProgressSink`] of
+/// methods like [`crate::StfsPackage::extract_entries_with_progress`]
+#[derive(Debug, Clone, Copy)]
+pub struct Progress<'a> {
+    /// Path of the entry which was just processed, for operations that work
+    /// on the package's files
+    pub entry: Option<&'a str>,
+    pub entries_done: usize,
+    pub entries_total: usize,
+    pub bytes_done: u64,
+    pub bytes_total: u64,
+}
+
+/// Receives progress updates from long-running operations. Implemented for
+/// any `FnMut(Progress)` closure.
+pub trait ProgressSink {
+    fn report(&mut self, progress: Progress<'_>);
+}
+
+impl<F: FnMut(Progress<'_>)> ProgressSink for F {
+    fn report(&mut self, progress: Progress<'_>) {
+        self(progress)
+    }
+}
+
+/// Stops an operation from another thread. Clones share the same flag, so one
+/// can be handed to the operation while another is kept to cancel it.
+///
+/// Operations check the token between entries or batches of hashes, and fail
+/// with [`StfsError::Cancelled`] once it's set. Anything written up to that
+/// point is left as-is.
+#[derive(Debug, Default, Clone)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Fails with [`StfsError::Cancelled`] if the token has been cancelled
+    pub(crate) fn check(&self) -> Result<(), StfsError> {
+        if self.is_cancelled() {
+            Err(StfsError::Cancelled)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Tracks totals for operations over a list of package entries
+pub(crate) struct EntryProgress {
+    entries_done: usize,
+    entries_total: usize,
+    bytes_done: u64,
+    bytes_total: u64,
+}
+
+impl EntryProgress {
+    pub(crate) fn new(entries_total: usize, bytes_total: u64) -> Self {
+        EntryProgress {
+            entries_done: 0,
+            entries_total,
+            bytes_done: 0,
+            bytes_total,
+        }
+    }
+
+    /// Records that `entry`, which was `bytes` bytes long, has been processed
+    pub(crate) fn advance<'a>(&mut self, entry: &'a str, bytes: u64) -> Progress<'a> {
+        self.entries_done += 1;
+        self.bytes_done += bytes;
+
+        Progress {
+            entry: Some(entry),
+            entries_done: self.entries_done,
+            entries_total: self.entries_total,
+            bytes_done: self.bytes_done,
+            bytes_total: self.bytes_total,
+        }
+    }
+}
@@ -0,0 +1,183 @@
+//! Where each part of a package is stored, for annotating the raw file.
+
+use std::ops::Range;
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::{
+    allocation::BlockState,
+    layout::VOLUME_DESCRIPTOR_SIZE,
+    metadata::{MetadataField, LICENSE_COUNT},
+    stfs::{HashTableLevel, PackageType, StfsPackage, BLOCK_SIZE},
+    write::{HEADER_HASH_OFFSET, LICENSES_OFFSET, LICENSE_ENTRY_SIZE, VOLUME_DESCRIPTOR_OFFSET},
+};
+
+/// Size of the console certificate which precedes a CON package's signature
+const CERTIFICATE_SIZE: usize = 0x1A8;
+const CON_SIGNATURE_SIZE: usize = 0x80;
+const STRONG_SIGNATURE_SIZE: usize = 0x100;
+
+/// What a [`Region`] holds
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum RegionKind {
+    /// `CON `, `LIVE`, or `PIRS`
+    Magic,
+    /// The console certificate of a CON package
+    Certificate,
+    Signature,
+    LicenseTable,
+    /// SHA-1 of everything from the content type to the end of the header
+    HeaderHash,
+    Metadata(MetadataField),
+    VolumeDescriptor,
+    ThumbnailImage,
+    TitleImage,
+    /// One copy of a hash table block
+    HashTable(HashTableLevel),
+    /// One block of the file table
+    FileTable,
+}
+
+/// A range of bytes within the package
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Region {
+    pub kind: RegionKind,
+    pub range: Range<usize>,
+}
+
+impl<'a> StfsPackage<'a> {
+    /// Locates the header's signature, licenses and metadata fields along with
+    /// every hash table and file table block, sorted by offset. Unnamed header
+    /// fields, padding, and file contents aren't included, and neither is a
+    /// file table whose block chain is damaged.
+    pub fn region_map(&self) -> Vec<Region> {
+        let header = &self.header;
+        let mut regions = vec![Region {
+            kind: RegionKind::Magic,
+            range: 0..4,
+        }];
+        let signature_size = if header.package_type == PackageType::Con {
+            regions.push(Region {
+                kind: RegionKind::Certificate,
+                range: 4..4 + CERTIFICATE_SIZE,
+            });
+            CON_SIGNATURE_SIZE
+        } else {
+            STRONG_SIGNATURE_SIZE
+        };
+        let signature_start = regions.last().unwrap().range.end;
+        regions.extend([
+            Region {
+                kind: RegionKind::Signature,
+                range: signature_start..signature_start + signature_size,
+            },
+            Region {
+                kind: RegionKind::LicenseTable,
+                range: LICENSES_OFFSET..LICENSES_OFFSET + LICENSE_ENTRY_SIZE * LICENSE_COUNT,
+            },
+            Region {
+                kind: RegionKind::HeaderHash,
+                range: HEADER_HASH_OFFSET..HEADER_HASH_OFFSET + 20,
+            },
+            Region {
+                kind: RegionKind::VolumeDescriptor,
+                range: VOLUME_DESCRIPTOR_OFFSET..VOLUME_DESCRIPTOR_OFFSET + VOLUME_DESCRIPTOR_SIZE,
+            },
+            Region {
+                kind: RegionKind::ThumbnailImage,
+                range: self.within_input(header.thumbnail_image),
+            },
+            Region {
+                kind: RegionKind::TitleImage,
+                range: self.within_input(header.title_image),
+            },
+        ]);
+        regions.extend(MetadataField::ALL.into_iter().map(|field| Region {
+            kind: RegionKind::Metadata(field),
+            range: field.range(),
+        }));
+
+        let first_table_address = self.hash_table_meta.first_table_address;
+        regions.extend(
+            self.block_allocator()
+                .block_map()
+                .into_iter()
+                .enumerate()
+                .filter_map(|(true_block, state)| match state {
+                    BlockState::HashTable(level) => {
+                        let start = first_table_address + true_block * BLOCK_SIZE;
+                        Some(Region {
+                            kind: RegionKind::HashTable(level),
+                            range: start..start + BLOCK_SIZE,
+                        })
+                    }
+                    _ => None,
+                }),
+        );
+        regions.extend(
+            self.file_table_blocks()
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|block| {
+                    let start = self.block_to_addr(block).ok()? as usize;
+                    Some(Region {
+                        kind: RegionKind::FileTable,
+                        range: start..start + BLOCK_SIZE,
+                    })
+                }),
+        );
+
+        regions.sort_by_key(|region| region.range.start);
+        regions
+    }
+
+    /// Offsets of `part`, which must have been borrowed from the package data
+    fn within_input(&self, part: &[u8]) -> Range<usize> {
+        let start = part.as_ptr() as usize - self.input.as_ptr() as usize;
+        start..start + part.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StfsPackageBuilder;
+
+    #[test]
+    fn regions_cover_package_structures() {
+        let mut builder = StfsPackageBuilder::new();
+        builder
+            .title_id(0x4D5307E6)
+            .add_file("a.bin", vec![1; 0x10])
+            .unwrap();
+        let data = builder.build().unwrap();
+        let package = StfsPackage::try_from(data.as_slice()).unwrap();
+        let regions = package.region_map();
+
+        assert!(regions
+            .windows(2)
+            .all(|pair| pair[0].range.end <= pair[1].range.start));
+        let find = |kind| {
+            regions
+                .iter()
+                .find(|region| region.kind == kind)
+                .unwrap()
+                .range
+                .clone()
+        };
+        assert_eq!(find(RegionKind::Signature), 0x1AC..0x22C);
+        assert_eq!(find(RegionKind::LicenseTable), 0x22C..0x32C);
+
+        let title_id = find(RegionKind::Metadata(MetadataField::TitleId));
+        assert_eq!(data[title_id], 0x4D5307E6u32.to_be_bytes());
+
+        let file_table = find(RegionKind::FileTable);
+        assert_eq!(&data[file_table.start..file_table.start + 5], b"a.bin");
+        assert!(regions
+            .iter()
+            .any(|region| region.kind == RegionKind::HashTable(HashTableLevel::First)));
+    }
+}
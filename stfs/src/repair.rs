@@ -0,0 +1,246 @@
+//! Repairing packages whose metadata has been damaged but whose contents are
+//! still intact.
+
+use std::{collections::HashSet, fmt};
+
+use byteorder::{BigEndian, ByteOrder};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::{
+    allocation::BLOCK_STATUS_ALLOCATED,
+    edit::file_blocks,
+    stfs::{StfsError, StfsPackage},
+    write::{self, *},
+};
+
+/// A single change made by [`repair`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum Repair {
+    /// An entry whose folder didn't exist was moved to the root of the package
+    ReattachedOrphan { name: String, missing_folder: u16 },
+    /// A block used by a file or the file table was marked as free
+    MarkedAllocated(usize),
+    /// A block not used by anything was marked as allocated
+    MarkedFree(usize),
+    /// The volume descriptor's count of free blocks was wrong
+    UnallocatedBlockCount { old: usize, new: usize },
+    /// The hash tree was regenerated, fixing this many wrong hashes
+    RebuiltHashes(usize),
+}
+
+impl fmt::Display for Repair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Repair::ReattachedOrphan {
+                name,
+                missing_folder,
+            } => write!(
+                f,
+                "moved {:?} to the root (its folder {:#x} doesn't exist)",
+                name, missing_folder
+            ),
+            Repair::MarkedAllocated(block) => write!(f, "marked block {:#x} as allocated", block),
+            Repair::MarkedFree(block) => write!(f, "marked block {:#x} as free", block),
+            Repair::UnallocatedBlockCount { old, new } => {
+                write!(
+                    f,
+                    "corrected unallocated block count from {} to {}",
+                    old, new
+                )
+            }
+            Repair::RebuiltHashes(count) => write!(f, "rebuilt hash tree, fixing {} hashes", count),
+        }
+    }
+}
+
+/// Fixes what can be recovered in a damaged package, returning a list of
+/// everything that was changed:
+///
+/// - Entries whose folder is missing are moved to the root
+/// - Block allocation is made to match the blocks actually used by files and
+///   the file table, and the free block count is corrected
+/// - The hash tree and header hash are regenerated
+///
+/// File contents are never modified. The signature is left untouched and will
+/// no longer be valid if anything was changed.
+pub fn repair(data: &mut [u8]) -> Result<Vec<Repair>, StfsError> {
+    let mut repairs = Vec::new();
+
+    let (hash_mismatches, orphans, block_fixes, stored_unallocated) = {
+        let package = StfsPackage::try_from(&*data)?;
+        let hash_mismatches = package.verify_hashes()?.len();
+
+        let entries = package.walk_entries();
+        let folders: HashSet<usize> = entries
+            .iter()
+            .filter(|(_, entry)| entry.is_folder())
+            .map(|(_, entry)| entry.index)
+            .collect();
+        let orphans: Vec<_> = entries
+            .iter()
+            .map(|(_, entry)| entry)
+            .filter(|entry| {
+                entry.path_indicator != ROOT_PATH_INDICATOR
+                    && (entry.path_indicator as usize == entry.index
+                        || !folders.contains(&(entry.path_indicator as usize)))
+            })
+            .cloned()
+            .collect();
+
+        let mut used: HashSet<usize> = package.file_table_blocks()?.into_iter().collect();
+        for (_, entry) in &entries {
+            if !entry.is_folder() {
+                used.extend(file_blocks(&package, entry));
+            }
+        }
+
+        let allocator = package.block_allocator();
+        let block_fixes: Vec<_> = (0..allocator.allocated_block_count())
+            .filter(|block| allocator.is_allocated(*block) != used.contains(block))
+            .map(|block| {
+                let address = package.block_hash_address(block, package.input)? as usize;
+                Ok((block, address, used.contains(&block)))
+            })
+            .collect::<Result<_, StfsError>>()?;
+
+        let stored_unallocated = package
+            .header
+            .volume_descriptor
+            .stfs_ref()
+            .unallocated_block_count as usize;
+
+        (hash_mismatches, orphans, block_fixes, stored_unallocated)
+    };
+
+    for mut orphan in orphans {
+        let address = orphan.file_entry_address as usize;
+        repairs.push(Repair::ReattachedOrphan {
+            name: orphan.name.clone(),
+            missing_folder: orphan.path_indicator,
+        });
+
+        orphan.path_indicator = ROOT_PATH_INDICATOR;
+        write::write_file_entry(&mut data[address..address + FILE_TABLE_ENTRY_SIZE], &orphan);
+    }
+
+    for (block, address, used) in block_fixes {
+        let status = &mut data[address + 0x14];
+        if used {
+            *status |= BLOCK_STATUS_ALLOCATED;
+            repairs.push(Repair::MarkedAllocated(block));
+        } else {
+            data[address + 0x14..address + HASH_ENTRY_SIZE].fill(0);
+            repairs.push(Repair::MarkedFree(block));
+        }
+    }
+
+    let unallocated = StfsPackage::try_from(&*data)?
+        .block_allocator()
+        .unallocated_block_count();
+    if unallocated != stored_unallocated {
+        BigEndian::write_u32(
+            &mut data[UNALLOCATED_BLOCK_COUNT_OFFSET..],
+            unallocated as u32,
+        );
+        repairs.push(Repair::UnallocatedBlockCount {
+            old: stored_unallocated,
+            new: unallocated,
+        });
+    }
+
+    // Any of the changes above invalidate the hashes too
+    if hash_mismatches > 0 || !repairs.is_empty() {
+        rehash(data)?;
+        repairs.push(Repair::RebuiltHashes(hash_mismatches));
+    }
+
+    Ok(repairs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{stfs::BLOCK_SIZE, StfsPackageBuilder};
+
+    #[test]
+    fn repair_fixes_metadata_corruption() {
+        let mut builder = StfsPackageBuilder::new();
+        builder
+            .add_file("saves/slot1.bin", vec![0x42; BLOCK_SIZE * 2])
+            .unwrap()
+            .add_file("readme.txt", b"hello".to_vec())
+            .unwrap();
+        let mut data = builder.build().unwrap();
+        let pristine = data.clone();
+
+        assert!(repair(&mut data).unwrap().is_empty());
+        assert_eq!(data, pristine);
+
+        let (folder_address, file_block, file_block_address, free_blocks) = {
+            let package = StfsPackage::try_from(data.as_slice()).unwrap();
+            let entries = package.walk_entries();
+            let entry = |path: &str| {
+                entries
+                    .iter()
+                    .find(|(entry_path, _)| entry_path == path)
+                    .unwrap()
+                    .1
+                    .clone()
+            };
+            let file = entry("saves/slot1.bin");
+            (
+                entry("saves").file_entry_address as usize,
+                file.starting_block_num,
+                package.block_to_addr(file.starting_block_num).unwrap() as usize,
+                package.block_allocator().unallocated_block_count(),
+            )
+        };
+
+        // Lose the folder, free one of the file's blocks, and corrupt its contents
+        // and the free block count
+        data[folder_address..folder_address + FILE_TABLE_ENTRY_SIZE].fill(0);
+        let hash_address = StfsPackage::try_from(data.as_slice())
+            .unwrap()
+            .block_hash_address(file_block, &data)
+            .unwrap() as usize;
+        data[hash_address + 0x14] = 0;
+        data[file_block_address] ^= 0xFF;
+        BigEndian::write_u32(&mut data[UNALLOCATED_BLOCK_COUNT_OFFSET..], 0x1234);
+        let corrupted_byte = data[file_block_address];
+        let mismatches = StfsPackage::try_from(data.as_slice())
+            .unwrap()
+            .verify_hashes()
+            .unwrap()
+            .len();
+
+        assert_eq!(
+            repair(&mut data).unwrap(),
+            [
+                Repair::ReattachedOrphan {
+                    name: "slot1.bin".to_owned(),
+                    missing_folder: 0,
+                },
+                Repair::MarkedAllocated(file_block),
+                Repair::UnallocatedBlockCount {
+                    old: 0x1234,
+                    new: free_blocks,
+                },
+                Repair::RebuiltHashes(mismatches),
+            ]
+        );
+        assert_eq!(data[file_block_address], corrupted_byte);
+
+        let package = StfsPackage::try_from(data.as_slice()).unwrap();
+        assert!(package.verify_hashes().unwrap().is_empty());
+        package
+            .block_allocator()
+            .check_unallocated_block_count()
+            .unwrap();
+        assert!(package
+            .walk_entries()
+            .iter()
+            .any(|(path, _)| path == "slot1.bin"));
+    }
+}
@@ -0,0 +1,130 @@
+//! Serializing the byte fields of parsed structures.
+
+use std::cell::Cell;
+
+use serde::{Serialize, Serializer};
+
+use crate::metadata::hex;
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// How byte fields such as hashes, IDs and images are serialized
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum BinaryEncoding {
+    /// Hashes and IDs become hex strings and images become base64 strings
+    #[default]
+    Text,
+    /// Every byte field is serialized with `serialize_bytes`, so that formats
+    /// with a native byte type (e.g. `Uint8Array` from serde-wasm-bindgen) can
+    /// use it. JSON gets arrays of numbers.
+    Bytes,
+}
+
+thread_local! {
+    static ENCODING: Cell<BinaryEncoding> = const { Cell::new(BinaryEncoding::Text) };
+}
+
+/// Puts the previous encoding back when dropped, even if serializing panics
+struct RestoreEncoding(BinaryEncoding);
+
+impl Drop for RestoreEncoding {
+    fn drop(&mut self) {
+        ENCODING.with(|encoding| encoding.set(self.0));
+    }
+}
+
+/// Calls `f`, serializing byte fields with `encoding` on the current thread
+/// while it runs
+pub fn with_binary_encoding<R>(encoding: BinaryEncoding, f: impl FnOnce() -> R) -> R {
+    let _restore = RestoreEncoding(ENCODING.with(|current| current.replace(encoding)));
+    f()
+}
+
+fn base64(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = (chunk[0] as u32) << 16
+            | (*chunk.get(1).unwrap_or(&0) as u32) << 8
+            | *chunk.get(2).unwrap_or(&0) as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let index = (group >> (18 - 6 * i)) & 0x3F;
+                out.push(BASE64_ALPHABET[index as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+
+    out
+}
+
+/// Serializes a hash or ID as a hex string
+pub(crate) fn serialize_bytes<S: Serializer, B: AsRef<[u8]>>(
+    bytes: &B,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match ENCODING.with(Cell::get) {
+        BinaryEncoding::Text => serializer.serialize_str(&hex(bytes.as_ref())),
+        BinaryEncoding::Bytes => serializer.serialize_bytes(bytes.as_ref()),
+    }
+}
+
+pub(crate) fn serialize_optional_bytes<S: Serializer, B: AsRef<[u8]>>(
+    bytes: &Option<B>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    struct Hex<'b>(&'b [u8]);
+
+    impl Serialize for Hex<'_> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serialize_bytes(&self.0, serializer)
+        }
+    }
+
+    match bytes {
+        Some(bytes) => serializer.serialize_some(&Hex(bytes.as_ref())),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Serializes an image as a base64 string
+pub(crate) fn serialize_image<S: Serializer>(
+    bytes: &&[u8],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match ENCODING.with(Cell::get) {
+        BinaryEncoding::Text => serializer.serialize_str(&base64(bytes)),
+        BinaryEncoding::Bytes => serializer.serialize_bytes(bytes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_pads_partial_groups() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+        assert_eq!(base64(&[0xFF, 0xEE]), "/+4=");
+    }
+
+    #[test]
+    fn binary_encoding_is_scoped() {
+        let current = || ENCODING.with(Cell::get);
+        assert_eq!(current(), BinaryEncoding::Text);
+        with_binary_encoding(BinaryEncoding::Bytes, || {
+            assert_eq!(current(), BinaryEncoding::Bytes);
+            with_binary_encoding(BinaryEncoding::Text, || {
+                assert_eq!(current(), BinaryEncoding::Text)
+            });
+            assert_eq!(current(), BinaryEncoding::Bytes);
+        });
+        assert_eq!(current(), BinaryEncoding::Text);
+    }
+}
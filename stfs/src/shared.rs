@@ -0,0 +1,117 @@
+//! Owned packages which can be shared between threads without locking.
+
+use std::{
+    fmt,
+    sync::{Arc, OnceLock},
+};
+
+use crate::{
+    file_table::FileTable,
+    stfs::{StfsError, StfsPackage},
+};
+
+/// A package which owns its data, for holding onto after the buffer it was
+/// read into would have gone out of scope.
+///
+/// Clones share the same data and file table, so a package can be handed to
+/// any number of threads for extraction or hashing by cloning it. Nothing in a
+/// package changes once parsed, so no locks are needed; edits produce new
+/// data, which is opened as a new `SharedPackage`.
+#[derive(Clone)]
+pub struct SharedPackage {
+    data: Arc<[u8]>,
+    files: Arc<OnceLock<FileTable>>,
+}
+
+impl SharedPackage {
+    /// Takes ownership of `data`, checking that its header and hash tables can
+    /// be parsed. The file table is parsed the first time it's needed.
+    pub fn new(data: impl Into<Arc<[u8]>>) -> Result<Self, StfsError> {
+        let data = data.into();
+        StfsPackage::parse_lazy(&data)?;
+
+        Ok(SharedPackage {
+            data,
+            files: Arc::default(),
+        })
+    }
+
+    /// The package's bytes
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Parses the package's header. This is cheap, since the file table is
+    /// only read once no matter how many times this is called.
+    pub fn package(&self) -> StfsPackage<'_> {
+        StfsPackage::parse_lazy(&self.data)
+            .expect("package data was validated when it was opened and can't change")
+            .with_file_table(Arc::clone(&self.files))
+    }
+
+    /// Whether `a` and `b` are clones of the same package
+    pub fn ptr_eq(a: &SharedPackage, b: &SharedPackage) -> bool {
+        Arc::ptr_eq(&a.data, &b.data)
+    }
+}
+
+impl fmt::Debug for SharedPackage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedPackage")
+            .field("len", &self.data.len())
+            .field("files_loaded", &self.files.get().is_some())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+    use crate::StfsPackageBuilder;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn shares_file_table_between_threads() {
+        assert_send_sync::<StfsPackage<'static>>();
+        assert_send_sync::<SharedPackage>();
+
+        let mut builder = StfsPackageBuilder::new();
+        for i in 0..8 {
+            builder
+                .add_file(&format!("saves/{}.bin", i), vec![i; 0x1800])
+                .unwrap();
+        }
+        let package = SharedPackage::new(builder.build().unwrap()).unwrap();
+
+        let contents: Vec<Vec<u8>> = thread::scope(|scope| {
+            let handles: Vec<_> = (0..8u8)
+                .map(|i| {
+                    let package = package.clone();
+                    scope.spawn(move || {
+                        let parsed = package.package();
+                        let files = parsed.files();
+                        let entry = files.entry(files.find(&format!("saves/{}.bin", i)).unwrap());
+                        let mut contents = Vec::new();
+                        parsed.extract_file(&mut contents, entry).unwrap();
+                        contents
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect()
+        });
+        for (i, contents) in contents.iter().enumerate() {
+            assert_eq!(*contents, vec![i as u8; 0x1800]);
+        }
+
+        let clone = package.clone();
+        let (a, b) = (package.package(), clone.package());
+        assert!(std::ptr::eq(a.loaded_files().unwrap(), b.files()));
+        assert!(SharedPackage::ptr_eq(&package, &clone));
+    }
+}
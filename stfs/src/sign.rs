@@ -0,0 +1,258 @@
+//! Signing CON packages with a console's key vault.
+
+use rsa::{traits::PublicKeyParts, BigUint, Pkcs1v15Sign, RsaPrivateKey, RsaPublicKey};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+
+use crate::{
+    stfs::{PackageType, StfsError, XContentHeader},
+    write,
+};
+
+/// Console certificate, copied verbatim from the key vault into the package
+const CERTIFICATE_OFFSET: usize = 0x4;
+const CERTIFICATE_SIZE: usize = 0x1A8;
+/// Offsets of the console's public key within the certificate
+const CERTIFICATE_EXPONENT_OFFSET: usize = 0x24;
+const CERTIFICATE_MODULUS_OFFSET: usize = 0x28;
+const SIGNATURE_OFFSET: usize = CERTIFICATE_OFFSET + CERTIFICATE_SIZE;
+const SIGNATURE_SIZE: usize = 0x80;
+/// The signature covers the licenses, header hash, and header size
+const SIGNED_DATA_OFFSET: usize = 0x22C;
+const SIGNED_DATA_SIZE: usize = 0x118;
+
+/// Key vaults dumped with their 0x10 byte HMAC/confounder header are 0x4000
+/// bytes. Offsets below are relative to a key vault without that header.
+const KEY_VAULT_SIZE: usize = 0x3FF0;
+const KEY_VAULT_HEADER_SIZE: usize = 0x10;
+const KV_PRIVATE_KEY_OFFSET: usize = 0x288;
+const KV_CERTIFICATE_OFFSET: usize = 0x9B8;
+
+/// Number of bytes in the 1024-bit console key's modulus
+const MODULUS_SIZE: usize = 0x80;
+
+/// Converts between XeCrypt's bignum layout (little-endian order of big-endian
+/// 64-bit words) and a plain big-endian byte string. The conversion is its own
+/// inverse.
+fn swap_qwords(data: &[u8]) -> Vec<u8> {
+    data.rchunks(8).flatten().copied().collect()
+}
+
+/// The parts of a decrypted console key vault which are needed to sign packages
+pub struct KeyVault {
+    certificate: [u8; CERTIFICATE_SIZE],
+    private_key: RsaPrivateKey,
+}
+
+impl KeyVault {
+    /// Parses a decrypted key vault, with or without its 0x10 byte header
+    pub fn parse(data: &[u8]) -> Result<Self, StfsError> {
+        let data = match data.len() {
+            KEY_VAULT_SIZE => data,
+            len if len == KEY_VAULT_SIZE + KEY_VAULT_HEADER_SIZE => &data[KEY_VAULT_HEADER_SIZE..],
+            _ => return Err(StfsError::InvalidKeyVault("unexpected key vault size")),
+        };
+
+        let mut certificate = [0u8; CERTIFICATE_SIZE];
+        certificate.copy_from_slice(
+            &data[KV_CERTIFICATE_OFFSET..KV_CERTIFICATE_OFFSET + CERTIFICATE_SIZE],
+        );
+
+        // XECRYPT_RSAPRV_1024: qword count, public exponent, reserved, then the
+        // modulus, primes, and CRT parameters
+        let key = &data[KV_PRIVATE_KEY_OFFSET..];
+        let public_exponent = BigUint::from_bytes_be(&key[0x4..0x8]);
+        let bignum = |offset: usize, len: usize| {
+            BigUint::from_bytes_be(&swap_qwords(&key[offset..offset + len]))
+        };
+        let modulus = bignum(0x10, MODULUS_SIZE);
+        let p = bignum(0x10 + MODULUS_SIZE, MODULUS_SIZE / 2);
+        let q = bignum(0x10 + MODULUS_SIZE + (MODULUS_SIZE / 2), MODULUS_SIZE / 2);
+
+        let private_key = RsaPrivateKey::from_p_q(p, q, public_exponent)
+            .map_err(|_| StfsError::InvalidKeyVault("invalid console private key"))?;
+        if private_key.n() != &modulus {
+            return Err(StfsError::InvalidKeyVault(
+                "console private key does not match its modulus",
+            ));
+        }
+
+        Ok(KeyVault {
+            certificate,
+            private_key,
+        })
+    }
+
+    /// ID of the console this key vault belongs to
+    pub fn console_id(&self) -> [u8; 5] {
+        let mut console_id = [0u8; 5];
+        console_id.copy_from_slice(&self.certificate[0x2..0x7]);
+        console_id
+    }
+}
+
+/// Rehashes a CON package and signs it with the console key from `key_vault`,
+/// replacing its certificate with the key vault's.
+pub fn resign(data: &mut [u8], key_vault: &KeyVault) -> Result<(), StfsError> {
+    if data.get(..4) != Some(b"CON ".as_slice()) {
+        return Err(StfsError::InvalidPackageType);
+    }
+
+    data[CERTIFICATE_OFFSET..CERTIFICATE_OFFSET + CERTIFICATE_SIZE]
+        .copy_from_slice(&key_vault.certificate);
+
+    write::rehash(data)?;
+
+    let digest = Sha1::digest(&data[SIGNED_DATA_OFFSET..SIGNED_DATA_OFFSET + SIGNED_DATA_SIZE]);
+    let signature = key_vault
+        .private_key
+        .sign(Pkcs1v15Sign::new::<Sha1>(), &digest)
+        .map_err(|err| StfsError::SigningFailed(err.to_string()))?;
+
+    data[SIGNATURE_OFFSET..SIGNATURE_OFFSET + SIGNATURE_SIZE]
+        .copy_from_slice(&swap_qwords(&signature));
+
+    Ok(())
+}
+
+/// The outcome of checking a package's signature
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum SignatureStatus {
+    /// The signature was made by the console whose certificate is in the package
+    Valid,
+    /// The signature doesn't match the signed header data or the certificate
+    Invalid,
+    /// The signature region is blank, e.g. after [`crate::strip_signature`]
+    Unsigned,
+    /// LIVE and PIRS packages are signed by Microsoft, whose public key isn't
+    /// available to check them with
+    Unverifiable,
+}
+
+/// Checks a CON package's signature against the console certificate embedded in
+/// it. This only shows that the header wasn't modified since the package was
+/// signed, not that the certificate itself is genuine.
+pub fn verify_signature(data: &[u8]) -> Result<SignatureStatus, StfsError> {
+    let header = XContentHeader::parse(data)?;
+    if header.unsigned {
+        return Ok(SignatureStatus::Unsigned);
+    }
+    if header.package_type != PackageType::Con {
+        return Ok(SignatureStatus::Unverifiable);
+    }
+
+    let certificate = &data[CERTIFICATE_OFFSET..CERTIFICATE_OFFSET + CERTIFICATE_SIZE];
+    let public_exponent = BigUint::from_bytes_be(
+        &certificate[CERTIFICATE_EXPONENT_OFFSET..CERTIFICATE_EXPONENT_OFFSET + 4],
+    );
+    let modulus = BigUint::from_bytes_be(&swap_qwords(
+        &certificate[CERTIFICATE_MODULUS_OFFSET..CERTIFICATE_MODULUS_OFFSET + MODULUS_SIZE],
+    ));
+    let public_key = match RsaPublicKey::new(modulus, public_exponent) {
+        Ok(public_key) => public_key,
+        Err(_) => return Ok(SignatureStatus::Invalid),
+    };
+
+    let digest = Sha1::digest(&data[SIGNED_DATA_OFFSET..SIGNED_DATA_OFFSET + SIGNED_DATA_SIZE]);
+    let signature = swap_qwords(&data[SIGNATURE_OFFSET..SIGNATURE_OFFSET + SIGNATURE_SIZE]);
+
+    Ok(
+        match public_key.verify(Pkcs1v15Sign::new::<Sha1>(), &digest, &signature) {
+            Ok(()) => SignatureStatus::Valid,
+            Err(_) => SignatureStatus::Invalid,
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use rsa::RsaPublicKey;
+
+    use super::*;
+    use crate::{StfsPackage, StfsPackageBuilder};
+
+    const P: &str = "ef02a1602c6aeed14d31d69d53687928629b1a4cf3e0996798e5bf8ab01d00d831dc4d8b4427610f5de0df80bece24577064553cf764dc2f5808907b239ae417";
+    const Q: &str = "e17546f07ae44a23337992c071012aa8d788f458e29b3e1512d3d9146d41c1ca2c20edc75cf4352b899b5ac4035e4f310ff6b8ccb4d106a10a469628dccae911";
+
+    fn padded(value: &BigUint, len: usize) -> Vec<u8> {
+        let bytes = value.to_bytes_be();
+        let mut out = vec![0u8; len - bytes.len()];
+        out.extend(bytes);
+        out
+    }
+
+    /// Builds a key vault with the 0x10 byte header around a known key
+    fn test_key_vault() -> Vec<u8> {
+        let p = BigUint::parse_bytes(P.as_bytes(), 16).unwrap();
+        let q = BigUint::parse_bytes(Q.as_bytes(), 16).unwrap();
+        let modulus = &p * &q;
+
+        let mut kv = vec![0u8; KEY_VAULT_HEADER_SIZE + KEY_VAULT_SIZE];
+        let key = &mut kv[KEY_VAULT_HEADER_SIZE + KV_PRIVATE_KEY_OFFSET..];
+        key[0x3] = 0x10;
+        key[0x7] = 0x3;
+        key[0x10..0x90].copy_from_slice(&swap_qwords(&padded(&modulus, MODULUS_SIZE)));
+        key[0x90..0xD0].copy_from_slice(&swap_qwords(&padded(&p, MODULUS_SIZE / 2)));
+        key[0xD0..0x110].copy_from_slice(&swap_qwords(&padded(&q, MODULUS_SIZE / 2)));
+
+        let certificate = &mut kv[KEY_VAULT_HEADER_SIZE + KV_CERTIFICATE_OFFSET..];
+        certificate[..2].copy_from_slice(&[0x01, 0xA8]);
+        certificate[2..7].copy_from_slice(&[0x11, 0x22, 0x33, 0x44, 0x55]);
+        certificate[CERTIFICATE_EXPONENT_OFFSET + 3] = 0x3;
+        certificate[CERTIFICATE_MODULUS_OFFSET..CERTIFICATE_MODULUS_OFFSET + MODULUS_SIZE]
+            .copy_from_slice(&swap_qwords(&padded(&modulus, MODULUS_SIZE)));
+
+        kv
+    }
+
+    #[test]
+    fn resign_produces_valid_signature() {
+        let key_vault = KeyVault::parse(&test_key_vault()).unwrap();
+        assert_eq!(key_vault.console_id(), [0x11, 0x22, 0x33, 0x44, 0x55]);
+
+        let mut builder = StfsPackageBuilder::new();
+        builder.add_file("save.bin", vec![0x42; 0x100]).unwrap();
+        let mut data = builder.build().unwrap();
+
+        resign(&mut data, &key_vault).unwrap();
+
+        let package = StfsPackage::try_from(data.as_slice()).unwrap();
+        assert!(!package.header.unsigned);
+
+        let public_key = RsaPublicKey::from(&key_vault.private_key);
+        assert_eq!(public_key.e(), &BigUint::from(3u8));
+        let digest = Sha1::digest(&data[SIGNED_DATA_OFFSET..SIGNED_DATA_OFFSET + SIGNED_DATA_SIZE]);
+        let signature = swap_qwords(&data[SIGNATURE_OFFSET..SIGNATURE_OFFSET + SIGNATURE_SIZE]);
+        public_key
+            .verify(Pkcs1v15Sign::new::<Sha1>(), &digest, &signature)
+            .unwrap();
+    }
+
+    #[test]
+    fn verify_signature_detects_tampering() {
+        let key_vault = KeyVault::parse(&test_key_vault()).unwrap();
+
+        let mut builder = StfsPackageBuilder::new();
+        builder.add_file("save.bin", vec![0x42; 0x100]).unwrap();
+        let mut data = builder.build().unwrap();
+        assert_eq!(verify_signature(&data).unwrap(), SignatureStatus::Unsigned);
+
+        resign(&mut data, &key_vault).unwrap();
+        assert_eq!(verify_signature(&data).unwrap(), SignatureStatus::Valid);
+
+        data[SIGNED_DATA_OFFSET] ^= 1;
+        assert_eq!(verify_signature(&data).unwrap(), SignatureStatus::Invalid);
+    }
+
+    #[test]
+    fn rejects_bad_key_vaults() {
+        assert!(KeyVault::parse(&[0u8; 0x100]).is_err());
+
+        let mut kv = test_key_vault();
+        // Corrupt the modulus
+        kv[KEY_VAULT_HEADER_SIZE + KV_PRIVATE_KEY_OFFSET + 0x10] ^= 1;
+        assert!(KeyVault::parse(&kv).is_err());
+    }
+}
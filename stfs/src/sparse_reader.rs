@@ -1,94 +1,238 @@
-use std::io::Read;
+use std::io::{IoSlice, Read, Seek, SeekFrom, Write};
+
+/// Most mappings handed to a single vectored write by [`SparseReader::copy_to`].
+/// Platforms limit how many buffers one call accepts, commonly to 1024.
+const MAX_WRITE_SLICES: usize = 1024;
 
 /// `SparseReader` helps reading data that is fragmented at various locations and
 /// potentially has chunks of differing sizes.
 ///
+/// Mappings can be borrowed slices, such as regions of a package, or owned
+/// buffers produced on the fly, such as decrypted or decompressed blocks.
+///
 /// # Example:
 ///
-/// ```compile_fail
+/// ```
+/// use std::io::Read;
+///
+/// use stfs::SparseReader;
+///
 /// let first = [0u8, 1, 2, 3];
 /// let second = [4u8];
 /// let third = [5u8];
 /// let mappings = [first.as_slice(), second.as_slice(), third.as_slice()];
-/// let mut reader = SparseReader::new(&mappings);
+/// let mut reader = SparseReader::new(mappings.to_vec());
 /// let mut output = [0u8; 6];
 /// assert!(matches!(reader.read(&mut output), Ok(6)));
 ///
 /// assert_eq!([0u8, 1, 2, 3, 4, 5], output);
+///
+/// let mut owned = SparseReader::new(vec![vec![6u8, 7], vec![8u8]]);
+/// let mut output = Vec::new();
+/// owned.read_to_end(&mut output).unwrap();
+///
+/// assert_eq!([6u8, 7, 8], output.as_slice());
 /// ```
-pub struct SparseReader<'a, 'b> {
+pub struct SparseReader<T> {
     mapping_index: usize,
+    /// Position within the current mapping. Past the last mapping this is the
+    /// distance beyond the end of the data.
     position: usize,
-    mappings: &'b [&'a [u8]],
+    mappings: Vec<T>,
+    /// Offset of each mapping within the data, followed by the total length
+    offsets: Vec<u64>,
 }
 
-impl<'a, 'b> SparseReader<'a, 'b> {
-    pub fn new(mappings: &'b [&'a [u8]]) -> SparseReader<'a, 'b> {
+impl<T: AsRef<[u8]>> SparseReader<T> {
+    pub fn new(mappings: Vec<T>) -> SparseReader<T> {
+        let mut offsets = Vec::with_capacity(mappings.len() + 1);
+        let mut offset = 0;
+        offsets.push(offset);
+        for mapping in &mappings {
+            offset += mapping.as_ref().len() as u64;
+            offsets.push(offset);
+        }
+
         SparseReader {
             mapping_index: 0,
             position: 0,
             mappings,
+            offsets,
         }
     }
-}
 
-impl<'a, 'b> Read for SparseReader<'a, 'b> {
-    fn read(&mut self, mut buf: &mut [u8]) -> std::io::Result<usize> {
-        let mut bytes_remaining = buf.len();
-        let mut bytes_read = 0;
+    /// Total length of the data across every mapping
+    pub fn len(&self) -> u64 {
+        self.offsets[self.mappings.len()]
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
 
-        if self.mapping_index >= self.mappings.len() {
-            return Ok(0);
+    /// Returns the mappings the reader was created from
+    pub fn into_inner(self) -> Vec<T> {
+        self.mappings
+    }
+
+    fn current_offset(&self) -> u64 {
+        self.offsets[self.mapping_index] + self.position as u64
+    }
+
+    /// Moves forward `count` bytes, which must not pass the end of the data.
+    /// Mappings which are exhausted or empty are stepped over so the current
+    /// mapping always has data left to read.
+    fn advance(&mut self, mut count: usize) {
+        while let Some(mapping) = self.mappings.get(self.mapping_index) {
+            let remaining = mapping.as_ref().len() - self.position;
+            if count < remaining {
+                self.position += count;
+                return;
+            }
+
+            count -= remaining;
+            self.mapping_index += 1;
+            self.position = 0;
         }
+    }
+
+    /// Writes everything from the current position to the end of the data to
+    /// `writer`, passing several mappings to each vectored write. Returns the
+    /// number of bytes written.
+    pub fn copy_to<W: Write>(&mut self, writer: &mut W) -> std::io::Result<u64> {
+        let mut written = 0;
+        while self.current_offset() < self.len() {
+            let slices: Vec<IoSlice<'_>> = self.mappings[self.mapping_index..]
+                .iter()
+                .enumerate()
+                .map(|(i, mapping)| {
+                    IoSlice::new(if i == 0 {
+                        &mapping.as_ref()[self.position..]
+                    } else {
+                        mapping.as_ref()
+                    })
+                })
+                .take(MAX_WRITE_SLICES)
+                .collect();
 
-        for (idx, mapping) in self.mappings.iter().enumerate().skip(self.mapping_index) {
-            let (mapping_start, mapping_len) = if idx == self.mapping_index {
-                (self.position, mapping.len() - self.position)
-            } else {
-                (0, mapping.len())
+            let count = match writer.write_vectored(&slices) {
+                Ok(0) => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::WriteZero,
+                        "failed to write whole buffer",
+                    ))
+                }
+                Ok(count) => count,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
             };
+            written += count as u64;
+            self.advance(count);
+        }
 
-            let bytes_to_copy = std::cmp::min(bytes_remaining, mapping_len);
+        Ok(written)
+    }
+}
 
-            buf[..bytes_to_copy]
-                .copy_from_slice(&mapping[mapping_start..(mapping_start + bytes_to_copy)]);
-            buf = &mut buf[bytes_to_copy..];
-            bytes_read += bytes_to_copy;
-            bytes_remaining -= bytes_to_copy;
-
-            if bytes_remaining == 0
-                || (idx == self.mappings.len() - 1
-                    && mapping_start + bytes_to_copy == mapping.len())
-            {
-                self.mapping_index = idx;
-                self.position = mapping_start + bytes_to_copy;
-
-                if self.position == self.mappings[self.mapping_index].len() {
-                    self.mapping_index += 1;
-                    self.position = 0;
-                }
+impl<T: AsRef<[u8]>> Read for SparseReader<T> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut bytes_read = 0;
+        while bytes_read < buf.len() {
+            let mapping = match self.mappings.get(self.mapping_index) {
+                Some(mapping) => &mapping.as_ref()[self.position..],
+                None => break,
+            };
 
-                break;
-            }
+            let bytes_to_copy = std::cmp::min(mapping.len(), buf.len() - bytes_read);
+            buf[bytes_read..(bytes_read + bytes_to_copy)]
+                .copy_from_slice(&mapping[..bytes_to_copy]);
+            bytes_read += bytes_to_copy;
+            self.advance(bytes_to_copy);
         }
 
         Ok(bytes_read)
     }
 }
 
+impl<T: AsRef<[u8]>> Seek for SparseReader<T> {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        let current = self.current_offset();
+        let target = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::End(delta) => self.len().checked_add_signed(delta),
+            SeekFrom::Current(delta) => current.checked_add_signed(delta),
+        }
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )
+        })?;
+
+        // Use the last mapping starting at or before the target so that empty
+        // mappings are skipped. Targets at or past the end land after the last
+        // mapping, with the position holding how far past the end they are.
+        self.mapping_index = self.offsets.partition_point(|&start| start <= target) - 1;
+        self.position = (target - self.offsets[self.mapping_index]) as usize;
+
+        Ok(target)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use std::io::Read;
+    use std::io::{Cursor, Read, Seek, SeekFrom, Write};
 
     use super::SparseReader;
 
+    /// Small deterministic xorshift generator so that failures can be reproduced
+    struct Rng(u64);
+
+    impl Rng {
+        fn below(&mut self, bound: usize) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            (self.0 % bound as u64) as usize
+        }
+    }
+
+    /// Splits `data` into mappings of random lengths, including empty ones
+    fn random_mappings<'a>(rng: &mut Rng, data: &'a [u8]) -> Vec<&'a [u8]> {
+        let mut mappings = Vec::new();
+        let mut rest = data;
+        while !rest.is_empty() {
+            let len = rng.below(24).min(rest.len());
+            let (mapping, remainder) = rest.split_at(len);
+            mappings.push(mapping);
+            rest = remainder;
+        }
+
+        mappings
+    }
+
+    /// Accepts at most a few bytes per write, exercising partial vectored writes
+    struct TrickleWriter(Vec<u8>);
+
+    impl Write for TrickleWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            let count = buf.len().min(3);
+            self.0.extend_from_slice(&buf[..count]);
+            Ok(count)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
     #[test]
     fn sparse_reader_works() {
         let first = [0u8, 1, 2, 3];
         let second = [4u8];
         let third = [5u8];
         let mappings = [first.as_slice(), second.as_slice(), third.as_slice()];
-        let mut reader = SparseReader::new(&mappings);
+        let mut reader = SparseReader::new(mappings.to_vec());
         let mut output = [0u8; 6];
         assert!(matches!(reader.read(&mut output), Ok(6)));
 
@@ -101,7 +245,7 @@ mod tests {
         let second = [4u8];
         let third = [5u8];
         let mappings = [first.as_slice(), second.as_slice(), third.as_slice()];
-        let mut reader = SparseReader::new(&mappings);
+        let mut reader = SparseReader::new(mappings.to_vec());
 
         for i in 0..6 {
             let mut output = [0xFFu8];
@@ -115,4 +259,109 @@ mod tests {
         let mut output = [0xFFu8];
         assert!(matches!(reader.read(&mut output), Ok(0)));
     }
+
+    #[test]
+    fn sparse_reader_seeks_across_mappings() {
+        let first = [0u8, 1, 2, 3];
+        let second = [4u8];
+        let third = [5u8, 6];
+        let mappings = [first.as_slice(), &[], second.as_slice(), third.as_slice()];
+        let mut reader = SparseReader::new(mappings.to_vec());
+        assert_eq!(reader.len(), 7);
+
+        let mut output = [0xFFu8; 2];
+        assert_eq!(reader.seek(SeekFrom::Start(3)).unwrap(), 3);
+        assert!(matches!(reader.read(&mut output), Ok(2)));
+        assert_eq!(output, [3, 4]);
+
+        assert_eq!(reader.seek(SeekFrom::Current(-4)).unwrap(), 1);
+        assert!(matches!(reader.read(&mut output), Ok(2)));
+        assert_eq!(output, [1, 2]);
+
+        assert_eq!(reader.seek(SeekFrom::End(-1)).unwrap(), 6);
+        assert!(matches!(reader.read(&mut output), Ok(1)));
+        assert_eq!(output[0], 6);
+        assert_eq!(reader.stream_position().unwrap(), 7);
+
+        assert_eq!(reader.seek(SeekFrom::End(3)).unwrap(), 10);
+        assert!(matches!(reader.read(&mut output), Ok(0)));
+        assert_eq!(reader.stream_position().unwrap(), 10);
+
+        assert!(reader.seek(SeekFrom::Current(-11)).is_err());
+    }
+
+    #[test]
+    fn sparse_reader_owned_mappings() {
+        let blocks: Vec<Vec<u8>> = (0u8..4).map(|i| vec![i; 3]).collect();
+        let mut reader = SparseReader::new(blocks);
+        reader.seek(SeekFrom::Start(2)).unwrap();
+
+        let mut output = [0xFFu8; 5];
+        assert!(matches!(reader.read(&mut output), Ok(5)));
+        assert_eq!(output, [0, 1, 1, 1, 2]);
+
+        let mut rest = Vec::new();
+        reader.copy_to(&mut rest).unwrap();
+        assert_eq!(rest, [2, 2, 3, 3, 3]);
+        assert_eq!(reader.into_inner().len(), 4);
+    }
+
+    #[test]
+    fn sparse_reader_random_boundaries() {
+        let data: Vec<u8> = (0..0x200).map(|i| i as u8).collect();
+        let mut rng = Rng(0x5EED_1234_ABCD_0001);
+
+        for _ in 0..200 {
+            let mut reader = SparseReader::new(random_mappings(&mut rng, &data));
+            let mut expected = Cursor::new(data.as_slice());
+
+            for _ in 0..20 {
+                if rng.below(4) == 0 {
+                    let target = SeekFrom::Start(rng.below(data.len() + 8) as u64);
+                    assert_eq!(reader.seek(target).unwrap(), expected.seek(target).unwrap());
+                }
+
+                let len = rng.below(48);
+                let mut output = vec![0xFFu8; len];
+                let mut expected_output = vec![0xFFu8; len];
+                let read = reader.read(&mut output).unwrap();
+                assert_eq!(read, expected.read(&mut expected_output).unwrap());
+                assert_eq!(output, expected_output);
+                assert_eq!(
+                    reader.stream_position().unwrap(),
+                    expected.stream_position().unwrap()
+                );
+            }
+
+            let mut rest = Vec::new();
+            reader.read_to_end(&mut rest).unwrap();
+            assert_eq!(
+                rest,
+                &data[(expected.position() as usize).min(data.len())..]
+            );
+        }
+    }
+
+    #[test]
+    fn sparse_reader_copy_to() {
+        let data: Vec<u8> = (0..0x200).map(|i| i as u8).collect();
+        let mut rng = Rng(0x5EED_1234_ABCD_0002);
+
+        for _ in 0..100 {
+            let start = rng.below(data.len() + 1);
+            let mut reader = SparseReader::new(random_mappings(&mut rng, &data));
+            reader.seek(SeekFrom::Start(start as u64)).unwrap();
+
+            let mut output = Vec::new();
+            let written = reader.copy_to(&mut output).unwrap();
+            assert_eq!(written as usize, data.len() - start);
+            assert_eq!(output, &data[start..]);
+
+            reader.seek(SeekFrom::Start(start as u64)).unwrap();
+            let mut trickle = TrickleWriter(Vec::new());
+            reader.copy_to(&mut trickle).unwrap();
+            assert_eq!(trickle.0, &data[start..]);
+            assert_eq!(reader.stream_position().unwrap(), data.len() as u64);
+        }
+    }
 }
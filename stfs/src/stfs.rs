@@ -1,113 +1,269 @@
 use parking_lot::Mutex;
 use std::{
-    collections::HashMap,
-    io::{Read, Write},
-    sync::Arc,
+    io::{Read, Seek, Write},
+    sync::{Arc, OnceLock},
 };
 
 use bitflags::bitflags;
-use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
-use chrono::{DateTime, Utc};
+use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
 use num_enum::TryFromPrimitive;
-use serde::Serialize;
+#[cfg(feature = "serde")]
+use serde::{
+    ser::{Error as _, SerializeStruct},
+    Deserialize, Serialize, Serializer,
+};
 use std::io::Cursor;
 use thiserror::Error;
+use tracing::{debug, debug_span, trace, warn};
+
+#[cfg(feature = "serde")]
+use crate::serialize::{serialize_bytes, serialize_image, serialize_optional_bytes};
+use crate::{
+    allocation::BlockAllocator,
+    display::{MediaId, TitleId},
+    file_table::FileTable,
+    layout::{
+        utf16_field, AvatarAssetLayout, ContentMetadataLayout, HeaderLayout,
+        MediaInformationLayout, StfsVolumeDescriptorLayout, SvodVolumeDescriptorLayout,
+        VolumeDescriptorLayout,
+    },
+    parallel,
+    sparse_reader::SparseReader,
+    write,
+};
 
-use crate::sparse_reader::SparseReader;
-
+/// A shared, lockable tree of entries, built by [`FileTable::to_tree`] for
+/// callers written before the file table was stored as a [`FileTable`]
 pub type StfsEntryRef = Arc<Mutex<StfsEntry>>;
 
-const INVALID_STR: &'static str = "<INVALID>";
-const BLOCK_SIZE: usize = 0x1000;
-
-fn input_byte_ref<'a>(cursor: &mut Cursor<&'a [u8]>, input: &'a [u8], size: usize) -> &'a [u8] {
-    let position: usize = cursor
-        .position()
-        .try_into()
-        .expect("failed to convert position to usize");
-    cursor.set_position(
-        (position + size)
-            .try_into()
-            .expect("failed to convert pos into usize"),
-    );
-    &input[position..position + size]
-}
-
-fn read_utf16_cstr<'a>(cursor: &mut Cursor<&'a [u8]>, input: &'a [u8]) -> String {
-    let position: usize = cursor
-        .position()
-        .try_into()
-        .expect("failed to convert position to usize");
-
-    let mut end_of_str_position = None;
-
-    for i in (0..input.len()).step_by(2) {
-        if input[position + i] == 0 && input[position + i + 1] == 0 {
-            // We found the null terminator
-            end_of_str_position = Some(position + i);
-            break;
-        }
-    }
-
-    let end_of_str_position = end_of_str_position.expect("failed to find null terminator");
-
-    cursor.set_position(
-        (position + end_of_str_position + 2)
-            .try_into()
-            .expect("failed to convert pos into usize"),
-    );
-    let byte_range = &input[position..end_of_str_position];
-
-    let mut utf16_str = Vec::with_capacity(byte_range.len() / 2);
-    for chunk in byte_range.chunks(2) {
-        utf16_str.push(((chunk[0] as u16) << 8) | chunk[1] as u16);
-    }
-
-    String::from_utf16(utf16_str.as_slice()).expect("failed to convert data to utf16")
+const INVALID_STR: &str = "<INVALID>";
+pub(crate) const BLOCK_SIZE: usize = 0x1000;
+/// Certificate and signature for CON packages, or the signature and its padding
+/// for LIVE/PIRS packages
+pub(crate) const SIGNATURE_REGION: std::ops::Range<usize> = 0x4..0x22C;
+/// Every header is at least this long: everything up to the end of the title image
+const MIN_HEADER_SIZE: usize = 0x971A;
+/// Installer type, progress cache fields, and CAB resume data
+const INSTALLER_METADATA_SIZE: usize = 0x15F4;
+
+/// Borrows the `size` bytes at the cursor's position and moves past them
+fn input_byte_ref<'a>(
+    cursor: &mut Cursor<&'a [u8]>,
+    input: &'a [u8],
+    size: usize,
+) -> Result<&'a [u8], StfsError> {
+    let truncated = || StfsError::Truncated {
+        needed: (cursor.position() as usize).saturating_add(size),
+        len: input.len(),
+    };
+    let position = usize::try_from(cursor.position()).map_err(|_| truncated())?;
+    let bytes = input
+        .get(position..)
+        .and_then(|rest| rest.get(..size))
+        .ok_or_else(truncated)?;
+    cursor.set_position((position + size) as u64);
+
+    Ok(bytes)
 }
 
+/// Reads a UTF-8 string stored in a `len` byte field, which is null-terminated
+/// if it's shorter than the field, and moves past the whole field
 fn read_utf8_with_max_len<'a>(
     cursor: &mut Cursor<&'a [u8]>,
     input: &'a [u8],
     len: usize,
-) -> String {
-    let position: usize = cursor
-        .position()
-        .try_into()
-        .expect("failed to convert position to usize");
-
-    let mut end_of_str_position = None;
-
-    for i in (0..input.len()).take(len) {
-        if input[position + i] == 0 {
-            // We found the null terminator
-            end_of_str_position = Some(position + i);
-            break;
-        }
-    }
+    field: &'static str,
+) -> Result<String, StfsError> {
+    let offset = cursor.position();
+    let bytes = input_byte_ref(cursor, input, len)?;
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(len);
+
+    String::from_utf8(bytes[..end].to_owned())
+        .map_err(|_| StfsError::InvalidString { field, offset })
+}
+
+/// Converts a Windows `FILETIME` (100ns intervals since 1601-01-01) to a UTC timestamp
+fn filetime_to_datetime(filetime: u64) -> DateTime<Utc> {
+    const FILETIME_UNIX_EPOCH_DIFF_SECS: i64 = 11_644_473_600;
 
-    let end_of_str_position = end_of_str_position.unwrap_or(position + len);
+    let secs = (filetime / 10_000_000) as i64 - FILETIME_UNIX_EPOCH_DIFF_SECS;
+    let nanos = ((filetime % 10_000_000) * 100) as u32;
+    Utc.timestamp_opt(secs, nanos).single().unwrap_or_default()
+}
+
+/// Converts an MS-DOS/FAT timestamp (date in the upper 16 bits, time in the lower
+/// 16 bits) to a local date and time. Returns `None` if the fields are out of range.
+fn fat_timestamp_to_datetime(timestamp: u32) -> Option<NaiveDateTime> {
+    let date = timestamp >> 16;
+    let time = timestamp & 0xFFFF;
 
-    cursor.set_position(
-        (position + len)
-            .try_into()
-            .expect("failed to convert pos into usize"),
-    );
-    let byte_range = &input[position..end_of_str_position];
-    String::from_utf8(byte_range.to_owned()).expect("failed to convert data to utf8")
+    NaiveDate::from_ymd_opt(1980 + (date >> 9) as i32, (date >> 5) & 0xF, date & 0x1F)?.and_hms_opt(
+        time >> 11,
+        (time >> 5) & 0x3F,
+        (time & 0x1F) * 2,
+    )
 }
 
 #[derive(Error, Debug)]
 pub enum StfsError {
     #[error("Invalid STFS package header")]
     InvalidHeader,
+    #[error("Unknown package magic {found:02X?}")]
+    InvalidMagic { found: [u8; 4] },
     #[error("I/O error")]
     IoError(#[from] std::io::Error),
     #[error("Invalid package type")]
     InvalidPackageType,
+    #[error("Volume descriptor reports {expected} unallocated blocks but {actual} are free")]
+    UnallocatedBlockCountMismatch { expected: usize, actual: usize },
+    #[error("Package is truncated: needed {needed:#x} bytes but only {len:#x} are present")]
+    Truncated { needed: usize, len: usize },
+    #[error("Unknown {field} {value:#x} at offset {offset:#x}")]
+    UnknownFieldValue {
+        field: &'static str,
+        offset: u64,
+        value: u64,
+    },
+    #[error("Block {block:#x} is past the end of the volume ({allocated:#x} blocks allocated)")]
+    BlockOutOfRange { block: usize, allocated: usize },
+    #[error("Hash entry for block {block:#x} at offset {offset:#x} is outside of the package")]
+    BadHashEntry { block: usize, offset: u64 },
+    #[error("{field} is too long (at most {max} bytes)")]
+    FieldTooLong { field: &'static str, max: usize },
+    #[error("Invalid file name {0:?}")]
+    InvalidFileName(String),
+    #[error("Package contents are too large for an STFS volume")]
+    PackageTooLarge,
+    #[error("{0} already exists")]
+    FileExists(std::path::PathBuf),
+    #[error("{0} does not exist in the package")]
+    FileNotFound(String),
+    #[cfg(feature = "zip")]
+    #[error("Failed to write zip archive")]
+    Zip(#[from] zip::result::ZipError),
+    #[error("Unknown metadata field {0:?}")]
+    UnknownMetadataField(String),
+    #[error("Invalid value {value:?} for {field}")]
+    InvalidMetadataValue { field: &'static str, value: String },
+    #[error("Invalid key vault: {0}")]
+    InvalidKeyVault(&'static str),
+    #[error("Failed to sign package: {0}")]
+    SigningFailed(String),
+    #[error("Invalid image: {0}")]
+    InvalidImage(&'static str),
+    #[error("Invalid {kind} {value:?}")]
+    InvalidValue { kind: &'static str, value: String },
+    #[error("Operation was cancelled")]
+    Cancelled,
+    #[error("Invalid XDBF file: {0}")]
+    InvalidXdbf(&'static str),
+    #[error("Invalid account: {0}")]
+    InvalidAccount(&'static str),
+    #[error("Account checksum doesn't match; the key is wrong or the account is damaged")]
+    AccountChecksumMismatch,
+    #[error("Invalid XEX file: {0}")]
+    InvalidXex(&'static str),
+    #[error("Invalid {field} at offset {offset:#x}")]
+    InvalidString { field: &'static str, offset: u64 },
+}
+
+/// Broad groups of [`StfsError`]s, for callers which handle every error in a
+/// group the same way
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum ErrorClass {
+    /// Reading or writing data failed
+    Io,
+    /// The input isn't a valid package
+    Parse,
+    /// The package parsed but its structures are inconsistent
+    Integrity,
+    /// An argument or requested change was invalid
+    InvalidInput,
+    /// Signing or verifying a signature failed
+    Signing,
+    /// The operation was stopped through a
+    /// [`CancellationToken`](crate::CancellationToken)
+    Cancelled,
+}
+
+impl StfsError {
+    /// A stable number identifying the kind of error. Codes are never reused or
+    /// changed once assigned; the hundreds digit matches [`StfsError::class`].
+    pub fn code(&self) -> u16 {
+        match self {
+            StfsError::InvalidHeader => 100,
+            StfsError::InvalidMagic { .. } => 101,
+            StfsError::Truncated { .. } => 102,
+            StfsError::UnknownFieldValue { .. } => 103,
+            StfsError::InvalidXdbf(_) => 104,
+            StfsError::InvalidAccount(_) => 105,
+            StfsError::InvalidXex(_) => 106,
+            StfsError::InvalidString { .. } => 107,
+            StfsError::UnallocatedBlockCountMismatch { .. } => 200,
+            StfsError::BlockOutOfRange { .. } => 201,
+            StfsError::BadHashEntry { .. } => 202,
+            StfsError::AccountChecksumMismatch => 203,
+            StfsError::InvalidPackageType => 300,
+            StfsError::FieldTooLong { .. } => 301,
+            StfsError::InvalidFileName(_) => 302,
+            StfsError::PackageTooLarge => 303,
+            StfsError::FileExists(_) => 304,
+            StfsError::FileNotFound(_) => 305,
+            StfsError::UnknownMetadataField(_) => 306,
+            StfsError::InvalidMetadataValue { .. } => 307,
+            StfsError::InvalidImage(_) => 308,
+            StfsError::InvalidValue { .. } => 309,
+            StfsError::InvalidKeyVault(_) => 400,
+            StfsError::SigningFailed(_) => 401,
+            StfsError::IoError(_) => 500,
+            #[cfg(feature = "zip")]
+            StfsError::Zip(_) => 501,
+            StfsError::Cancelled => 600,
+        }
+    }
+
+    pub fn class(&self) -> ErrorClass {
+        match self.code() / 100 {
+            1 => ErrorClass::Parse,
+            2 => ErrorClass::Integrity,
+            3 => ErrorClass::InvalidInput,
+            4 => ErrorClass::Signing,
+            6 => ErrorClass::Cancelled,
+            _ => ErrorClass::Io,
+        }
+    }
+
+    /// Offset into the package of the data which caused the error, when known
+    pub fn offset(&self) -> Option<u64> {
+        match self {
+            StfsError::InvalidMagic { .. } => Some(0),
+            StfsError::Truncated { len, .. } => Some(*len as u64),
+            StfsError::UnknownFieldValue { offset, .. }
+            | StfsError::BadHashEntry { offset, .. }
+            | StfsError::InvalidString { offset, .. } => Some(*offset),
+            _ => None,
+        }
+    }
 }
 
-#[derive(Debug, Serialize)]
+/// Converts a value read at `offset` into the enum `T`, reporting which field
+/// held an unknown value
+fn known_value<T: TryFrom<V>, V: Copy + Into<u64>>(
+    field: &'static str,
+    offset: u64,
+    value: V,
+) -> Result<T, StfsError> {
+    T::try_from(value).map_err(|_| StfsError::UnknownFieldValue {
+        field,
+        offset,
+        value: value.into(),
+    })
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum PackageType {
     /// User container packages that are created by an Xbox 360 console and
     /// signed by the user's private key.
@@ -118,6 +274,17 @@ pub enum PackageType {
     Pirs,
 }
 
+impl PackageType {
+    /// The magic at the start of packages of this type
+    pub const fn magic(&self) -> [u8; 4] {
+        match self {
+            PackageType::Con => *b"CON ",
+            PackageType::Live => *b"LIVE",
+            PackageType::Pirs => *b"PIRS",
+        }
+    }
+}
+
 impl TryFrom<[u8; 4]> for PackageType {
     type Error = StfsError;
 
@@ -126,12 +293,13 @@ impl TryFrom<[u8; 4]> for PackageType {
             b"CON " => Ok(PackageType::Con),
             b"LIVE" => Ok(PackageType::Live),
             b"PIRS" => Ok(PackageType::Pirs),
-            _ => Err(StfsError::InvalidHeader),
+            _ => Err(StfsError::InvalidMagic { found: value }),
         }
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub enum StfsEntry {
     File(StfsFileEntry),
     Folder {
@@ -154,7 +322,8 @@ impl StfsEntry {
     }
 }
 
-#[derive(Debug, Serialize, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum StfsPackageSex {
     Female = 0,
     Male,
@@ -163,7 +332,7 @@ pub enum StfsPackageSex {
 impl StfsPackageSex {
     /// The "block step" depends on the package's "sex". This basically determines
     /// which hash tables are used.
-    const fn block_step(&self) -> [usize; 2] {
+    pub const fn block_step(&self) -> [usize; 2] {
         match self {
             StfsPackageSex::Female => [0xAB, 0x718F],
             StfsPackageSex::Male => [0xAC, 0x723A],
@@ -186,18 +355,27 @@ impl<'a> TryFrom<&XContentHeader<'a>> for StfsPackageSex {
         }
     }
 }
-#[derive(Default, Debug, Serialize)]
-struct HashEntry<'a> {
-    block_hash: &'a [u8],
-    status: u8,
-    next_block: u32,
+#[derive(Default, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(not(feature = "serde"), allow(dead_code))]
+pub(crate) struct HashEntry<'a> {
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_bytes"))]
+    pub(crate) block_hash: &'a [u8],
+    pub(crate) status: u8,
+    pub(crate) next_block: u32,
 }
 
-#[derive(Default, Debug, Serialize)]
+#[derive(Default, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct HashTableMeta<'a> {
+    /// Number of blocks between consecutive level 0 and level 1 hash tables.
+    /// See [`StfsPackageSex::block_step`].
     pub block_step: [usize; 2],
+    /// Number of hash tables required at each level of the hash tree
     pub tables_per_level: [usize; 3],
     pub top_table: HashTable<'a>,
+    /// Offset of the first hash table in the file. This immediately follows the
+    /// header, rounded up to the next block.
     pub first_table_address: usize,
 }
 
@@ -207,40 +385,19 @@ impl<'a> HashTableMeta<'a> {
         sex: StfsPackageSex,
         header: &XContentHeader,
     ) -> Result<Self, StfsError> {
-        let mut meta = HashTableMeta::default();
-
-        meta.block_step = sex.block_step();
-
-        // Address of the first hash table in the package comes right after the header
-        meta.first_table_address = ((header.header_size as usize) + 0x0FFF) & 0xFFFF_F000;
-
         let stfs_vol = header.volume_descriptor.stfs_ref();
 
         let allocated_block_count = stfs_vol.allocated_block_count as usize;
-        meta.tables_per_level[0] = ((allocated_block_count as usize) / HASHES_PER_HASH_TABLE)
-            + if (allocated_block_count as usize) % HASHES_PER_HASH_TABLE != 0 {
-                1
-            } else {
-                0
-            };
-
-        meta.tables_per_level[1] = (meta.tables_per_level[1] / HASHES_PER_HASH_TABLE)
-            + if meta.tables_per_level[1] % HASHES_PER_HASH_TABLE != 0
-                && allocated_block_count > HASHES_PER_HASH_TABLE
-            {
-                1
-            } else {
-                0
-            };
-
-        meta.tables_per_level[2] = (meta.tables_per_level[2] / HASHES_PER_HASH_TABLE)
-            + if meta.tables_per_level[2] % HASHES_PER_HASH_TABLE != 0
-                && allocated_block_count > DATA_BLOCKS_PER_HASH_TREE_LEVEL[2]
-            {
-                1
-            } else {
-                0
-            };
+        let tables_per_level = tables_per_level(allocated_block_count);
+        let _span = debug_span!("parse_hash_tables", ?sex, allocated_block_count).entered();
+
+        let mut meta = HashTableMeta {
+            block_step: sex.block_step(),
+            tables_per_level,
+            top_table: HashTable::default(),
+            // Address of the first hash table in the package comes right after the header
+            first_table_address: ((header.header_size as usize) + 0x0FFF) & 0xFFFF_F000,
+        };
 
         meta.top_table.level = header.root_hash_table_level()?;
         meta.top_table.true_block_number =
@@ -251,13 +408,13 @@ impl<'a> HashTableMeta<'a> {
         meta.top_table.address_in_file =
             base_address + (((stfs_vol.block_separation as usize) & 2) << 0xB);
 
-        meta.top_table.entry_count = (allocated_block_count as usize)
-            / DATA_BLOCKS_PER_HASH_TREE_LEVEL[meta.top_table.level as usize];
+        meta.top_table.entry_count =
+            allocated_block_count / DATA_BLOCKS_PER_HASH_TREE_LEVEL[meta.top_table.level as usize];
 
         if (allocated_block_count > DATA_BLOCKS_PER_HASH_TREE_LEVEL[2]
-            && allocated_block_count % DATA_BLOCKS_PER_HASH_TREE_LEVEL[2] != 0)
+            && !allocated_block_count.is_multiple_of(DATA_BLOCKS_PER_HASH_TREE_LEVEL[2]))
             || (allocated_block_count > HASHES_PER_HASH_TABLE
-                && allocated_block_count % HASHES_PER_HASH_TABLE != 0)
+                && !allocated_block_count.is_multiple_of(HASHES_PER_HASH_TABLE))
         {
             meta.top_table.entry_count += 1;
         }
@@ -267,20 +424,21 @@ impl<'a> HashTableMeta<'a> {
         let mut reader = Cursor::new(data);
         reader.set_position(meta.top_table.address_in_file as u64);
         for _ in 0..meta.top_table.entry_count {
-            let entry = HashEntry {
-                block_hash: input_byte_ref(&mut reader, data, 0x14),
-                status: reader
-                    .read_u8()
-                    .expect("failed to read hash table entry status"),
-                next_block: reader
-                    .read_u24::<BigEndian>()
-                    .expect("failed to read hash table entry next_block")
-                    as u32,
-            };
-
-            meta.top_table.entries.push(entry);
+            let entry = input_byte_ref(&mut reader, data, 0x18)?;
+            meta.top_table.entries.push(HashEntry {
+                block_hash: &entry[..0x14],
+                status: entry[0x14],
+                next_block: BigEndian::read_u24(&entry[0x15..]),
+            });
         }
 
+        debug!(
+            level = ?meta.top_table.level,
+            address = meta.top_table.address_in_file,
+            entry_count = meta.top_table.entry_count,
+            "read top hash table"
+        );
+
         Ok(meta)
     }
 
@@ -335,75 +493,263 @@ impl<'a> HashTableMeta<'a> {
     }
 }
 
-const HASHES_PER_HASH_TABLE: usize = 0xAA;
-const HASHES_PER_HASH_TABLE_LEVEL: [usize; 3] = [
+/// Computes how many hash tables are required at each level of the hash tree
+/// for a package with `allocated_block_count` data blocks
+pub(crate) fn tables_per_level(allocated_block_count: usize) -> [usize; 3] {
+    let mut tables_per_level = [0usize; 3];
+    tables_per_level[0] = (allocated_block_count / HASHES_PER_HASH_TABLE)
+        + if !allocated_block_count.is_multiple_of(HASHES_PER_HASH_TABLE) {
+            1
+        } else {
+            0
+        };
+
+    tables_per_level[1] = (tables_per_level[0] / HASHES_PER_HASH_TABLE)
+        + if !tables_per_level[0].is_multiple_of(HASHES_PER_HASH_TABLE)
+            && allocated_block_count > HASHES_PER_HASH_TABLE
+        {
+            1
+        } else {
+            0
+        };
+
+    tables_per_level[2] = (tables_per_level[1] / HASHES_PER_HASH_TABLE)
+        + if !tables_per_level[1].is_multiple_of(HASHES_PER_HASH_TABLE)
+            && allocated_block_count > DATA_BLOCKS_PER_HASH_TREE_LEVEL[2]
+        {
+            1
+        } else {
+            0
+        };
+
+    tables_per_level
+}
+
+/// Returns the level of the top hash table for a package with
+/// `allocated_block_count` data blocks, or `None` if that many blocks cannot be
+/// addressed
+pub(crate) fn hash_table_level(allocated_block_count: usize) -> Option<HashTableLevel> {
+    if allocated_block_count <= HASHES_PER_HASH_TABLE {
+        Some(HashTableLevel::First)
+    } else if allocated_block_count <= HASHES_PER_HASH_TABLE_LEVEL[1] {
+        Some(HashTableLevel::Second)
+    } else if allocated_block_count <= HASHES_PER_HASH_TABLE_LEVEL[2] {
+        Some(HashTableLevel::Third)
+    } else {
+        None
+    }
+}
+
+/// Returns the number of blocks following the header which are required to hold
+/// `allocated_block_count` data blocks along with every copy of their hash tables
+pub(crate) fn true_block_count(allocated_block_count: usize, sex: StfsPackageSex) -> usize {
+    let meta = HashTableMeta {
+        block_step: sex.block_step(),
+        ..Default::default()
+    };
+    let tables_per_level = tables_per_level(allocated_block_count);
+    let table_copies = 1usize << (sex as usize);
+
+    // The top level 0 table always exists, even if there are no data blocks
+    let mut true_block_count = table_copies;
+    if allocated_block_count > 0 {
+        true_block_count = true_block_count
+            .max(compute_data_block_num(allocated_block_count - 1, sex) as usize + 1);
+    }
+    for table in 0..tables_per_level[0] {
+        let block =
+            meta.compute_first_level_backing_hash_block_number(table * HASHES_PER_HASH_TABLE, sex);
+        true_block_count = true_block_count.max(block + table_copies);
+    }
+    for table in 0..tables_per_level[1] {
+        let block = meta.compute_second_level_backing_hash_block_number(
+            table * DATA_BLOCKS_PER_HASH_TREE_LEVEL[2],
+            sex,
+        );
+        true_block_count = true_block_count.max(block + table_copies);
+    }
+    if tables_per_level[2] > 0 {
+        let block = meta.compute_third_level_backing_hash_block_number();
+        true_block_count = true_block_count.max(block + table_copies);
+    }
+
+    true_block_count
+}
+
+pub(crate) const HASHES_PER_HASH_TABLE: usize = 0xAA;
+pub(crate) const HASHES_PER_HASH_TABLE_LEVEL: [usize; 3] = [
     HASHES_PER_HASH_TABLE,
     HASHES_PER_HASH_TABLE * HASHES_PER_HASH_TABLE,
     HASHES_PER_HASH_TABLE * HASHES_PER_HASH_TABLE * HASHES_PER_HASH_TABLE,
 ];
-const DATA_BLOCKS_PER_HASH_TREE_LEVEL: [usize; 3] = [
+pub(crate) const DATA_BLOCKS_PER_HASH_TREE_LEVEL: [usize; 3] = [
     1,
     HASHES_PER_HASH_TABLE,
     HASHES_PER_HASH_TABLE * HASHES_PER_HASH_TABLE,
 ];
 
-#[derive(Debug, Serialize)]
+#[derive(Debug)]
 pub struct StfsPackage<'a> {
-    #[serde(skip)]
-    input: &'a [u8],
+    pub(crate) input: &'a [u8],
 
     pub header: XContentHeader<'a>,
     pub sex: StfsPackageSex,
     pub hash_table_meta: HashTableMeta<'a>,
-    pub files: StfsEntryRef,
+    /// Parsed from the file table on first access. Shared with every other
+    /// parse of the same [`SharedPackage`](crate::SharedPackage).
+    files: Arc<OnceLock<FileTable>>,
+}
+
+/// Serializing a lazily parsed package parses its file table, and fails
+/// instead of panicking if the table is damaged.
+#[cfg(feature = "serde")]
+impl Serialize for StfsPackage<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("StfsPackage", 4)?;
+        state.serialize_field("header", &self.header)?;
+        state.serialize_field("sex", &self.sex)?;
+        state.serialize_field("hash_table_meta", &self.hash_table_meta)?;
+        let files = self.try_files().map_err(S::Error::custom)?;
+        state.serialize_field("files", files)?;
+        state.end()
+    }
 }
 
 impl<'a> TryFrom<&'a [u8]> for StfsPackage<'a> {
     type Error = StfsError;
 
     fn try_from(input: &'a [u8]) -> Result<Self, Self::Error> {
-        let mut cursor = Cursor::new(input);
-        let xcontent_header = xcontent_header_parser(&mut cursor, input)?;
-        // TODO: Don't unwrap
-        let package_sex = StfsPackageSex::try_from(&xcontent_header).unwrap();
-        let hash_table_meta = HashTableMeta::parse(input, package_sex, &xcontent_header)?;
+        let package = StfsPackage::parse_lazy(input)?;
+        package.try_files()?;
+
+        Ok(package)
+    }
+}
 
-        let mut package = StfsPackage {
+impl<'a> StfsPackage<'a> {
+    /// Parses only the header and hash table metadata. The file table is parsed
+    /// the first time [`StfsPackage::files`] is called, directly or through
+    /// another method, which makes this much cheaper than
+    /// [`StfsPackage::try_from`] when only metadata is needed.
+    pub fn parse_lazy(input: &'a [u8]) -> Result<Self, StfsError> {
+        StfsPackage::from_header(input, XContentHeader::parse(input)?)
+    }
+
+    /// Continues parsing a package whose header was already parsed with
+    /// [`XContentHeader::parse`], reading the hash table metadata.
+    ///
+    /// Together with [`XContentHeader::parse`] and [`StfsPackage::files`] this
+    /// splits parsing into stages, so that a UI can show a package's metadata
+    /// while its file table is still being read on another thread.
+    pub fn from_header(input: &'a [u8], header: XContentHeader<'a>) -> Result<Self, StfsError> {
+        let package_sex = StfsPackageSex::try_from(&header)?;
+        let hash_table_meta = HashTableMeta::parse(input, package_sex, &header)?;
+
+        Ok(StfsPackage {
             input,
-            header: xcontent_header,
+            header,
             sex: package_sex,
             hash_table_meta,
-            files: Arc::new(Mutex::new(StfsEntry::Folder {
-                entry: Default::default(),
-                files: Default::default(),
-            })),
-        };
+            files: Arc::default(),
+        })
+    }
 
-        package.read_files(input);
+    /// Uses `files` to hold the file table, so that it's only parsed once
+    /// between packages parsed from the same data
+    pub(crate) fn with_file_table(mut self, files: Arc<OnceLock<FileTable>>) -> Self {
+        self.files = files;
+        self
+    }
 
-        Ok(package)
+    /// Returns the package's files and folders, parsing the file table if it
+    /// hasn't been yet. The file table of a package from
+    /// [`StfsPackage::try_from`] has always been parsed already.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the file table is damaged. Packages from
+    /// [`StfsPackage::parse_lazy`] or [`StfsPackage::from_header`] should use
+    /// [`StfsPackage::try_files`] instead.
+    pub fn files(&self) -> &FileTable {
+        self.try_files()
+            .unwrap_or_else(|err| panic!("failed to read the file table: {}", err))
     }
-}
 
-impl<'a> StfsPackage<'a> {
+    /// Returns the package's files and folders, parsing the file table if it
+    /// hasn't been yet, or the error which stopped it from being parsed.
+    /// Failures aren't remembered, so each call after one parses the table again.
+    pub fn try_files(&self) -> Result<&FileTable, StfsError> {
+        if let Some(files) = self.files.get() {
+            return Ok(files);
+        }
+
+        let files = self.read_files()?;
+        // Another thread may have finished parsing first, in which case its
+        // identical table is kept
+        Ok(self.files.get_or_init(|| files))
+    }
+
+    /// Returns the package's files and folders if the file table has already
+    /// been parsed, without ever blocking
+    pub fn loaded_files(&self) -> Option<&FileTable> {
+        self.files.get()
+    }
+
+    /// Returns a view of which blocks in this package are in use
+    pub fn block_allocator(&self) -> BlockAllocator<'_, 'a> {
+        BlockAllocator::new(self)
+    }
+
+    /// Writes the contents of the file described by `entry` to `writer`
     pub fn extract_file<W: Write>(
         &self,
         writer: &mut W,
         entry: &StfsFileEntry,
     ) -> std::io::Result<()> {
+        // Mappings are written straight from the package so no intermediate
+        // buffer is needed
+        let mappings = self
+            .file_mappings(entry)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+        SparseReader::new(mappings).copy_to(writer)?;
+
+        Ok(())
+    }
+
+    /// Returns a reader over the contents of the file described by `entry`,
+    /// which reads directly from the package without buffering the whole file.
+    /// The reader can seek, so parts of a file can be read without reading
+    /// everything before them.
+    pub fn file_reader(&self, entry: &StfsFileEntry) -> Result<impl Read + Seek + 'a, StfsError> {
+        Ok(SparseReader::new(self.file_mappings(entry)?))
+    }
+
+    /// Returns the regions of the package holding the contents of the file
+    /// described by `entry`, in order
+    pub(crate) fn file_mappings(&self, entry: &StfsFileEntry) -> Result<Vec<&'a [u8]>, StfsError> {
+        let mut mappings = Vec::new();
         if entry.file_size == 0 {
-            return Ok(());
+            return Ok(mappings);
         }
 
-        let mut mappings = Vec::new();
+        let input = self.input;
+        let slice = |start: usize, len: usize| {
+            input
+                .get(start..)
+                .and_then(|rest| rest.get(..len))
+                .ok_or(StfsError::Truncated {
+                    needed: start.saturating_add(len),
+                    len: input.len(),
+                })
+        };
 
-        let start_address = self.block_to_addr(entry.starting_block_num) as usize;
+        let start_address = self.block_to_addr(entry.starting_block_num)? as usize;
 
         let mut next_address = start_address;
         let mut data_remaining = entry.file_size;
 
         // Check if we can read consecutive blocks
-        if entry.flags & 1 != 0 {
+        if entry.has_consecutive_blocks() {
             let blocks_until_hash_table = (self
                 .hash_table_meta
                 .compute_first_level_backing_hash_block_number(entry.starting_block_num, self.sex)
@@ -411,19 +757,28 @@ impl<'a> StfsPackage<'a> {
                 - ((start_address - self.hash_table_meta.first_table_address) / BLOCK_SIZE);
 
             if entry.block_count <= blocks_until_hash_table {
-                mappings.push(&self.input[start_address..(start_address + entry.file_size)]);
+                mappings.push(slice(start_address, entry.file_size)?);
             } else {
-                // The file is broken up by hash tables
+                // Read up until the first hash table. The file may not start at the
+                // beginning of a table's range, so this is not necessarily a full
+                // HASHES_PER_HASH_TABLE blocks. A damaged entry may claim more
+                // blocks than its size needs.
+                let read_len = std::cmp::min(blocks_until_hash_table * BLOCK_SIZE, data_remaining);
+                mappings.push(slice(next_address, read_len)?);
+                data_remaining -= read_len;
+                next_address += read_len;
+
+                // The rest of the file is broken up by hash tables
                 while data_remaining > 0 {
+                    next_address += self.hash_table_skip_for_address(next_address);
+
                     let read_len =
                         std::cmp::min(HASHES_PER_HASH_TABLE * BLOCK_SIZE, data_remaining);
 
-                    mappings.push(&self.input[next_address..(next_address + read_len)]);
+                    mappings.push(slice(next_address, read_len)?);
 
-                    let data_read = mappings.last().unwrap().len();
-                    data_remaining -= data_read;
-                    next_address += data_read;
-                    next_address += self.hash_table_skip_for_address(next_address)
+                    data_remaining -= read_len;
+                    next_address += read_len;
                 }
             }
         } else {
@@ -431,7 +786,7 @@ impl<'a> StfsPackage<'a> {
 
             // This file does not have all-consecutive blocks
             let mut block_count = data_remaining / BLOCK_SIZE;
-            if data_remaining % BLOCK_SIZE != 0 {
+            if !data_remaining.is_multiple_of(BLOCK_SIZE) {
                 block_count += 1;
             }
 
@@ -439,25 +794,16 @@ impl<'a> StfsPackage<'a> {
             for _ in 0..block_count {
                 let read_len = std::cmp::min(BLOCK_SIZE, data_remaining);
 
-                let block_address = self.block_to_addr(block) as usize;
-                mappings.push(&self.input[block_address..(block_address + read_len)]);
+                let block_address = self.block_to_addr(block)? as usize;
+                mappings.push(slice(block_address, read_len)?);
 
-                let hash_entry = self.block_hash_entry(block, self.input);
+                let hash_entry = self.try_block_hash_entry(block, self.input)?;
                 block = hash_entry.next_block as usize;
                 data_remaining -= read_len;
             }
         }
 
-        let mut reader = SparseReader::new(mappings.as_ref());
-        let mut data = Vec::new();
-        reader
-            .read_to_end(&mut data)
-            .expect("failed to read STFS file");
-        writer
-            .write_all(data.as_slice())
-            .expect("failed to write to file output");
-
-        Ok(())
+        Ok(mappings)
     }
 
     fn hash_table_skip_for_address(&self, table_address: usize) -> usize {
@@ -479,46 +825,104 @@ impl<'a> StfsPackage<'a> {
 
         // Check if it's at a level 2 table
         if block_number == self.hash_table_meta.block_step[0]
-            || block_number % self.hash_table_meta.block_step[1] == 0
+            || block_number.is_multiple_of(self.hash_table_meta.block_step[1])
         {
             return 0x2000 << self.sex as usize;
         }
 
         // Assume it's the level 0 table
-        return BLOCK_SIZE << self.sex as usize;
+        BLOCK_SIZE << self.sex as usize
     }
 
-    fn block_hash_entry(&self, block: usize, input: &'a [u8]) -> HashEntry {
-        let stfs_vol = self.header.volume_descriptor.stfs_ref();
-        let mut reader = Cursor::new(input);
-        if block > stfs_vol.allocated_block_count as usize {
-            panic!(
-                "Reference to illegal block number: {:#x} ({:#x} allocated)",
-                block, stfs_vol.allocated_block_count
+    /// Reads the hash entry of the given data block, failing if the block or its
+    /// entry is outside of the package
+    pub(crate) fn try_block_hash_entry(
+        &self,
+        block: usize,
+        input: &'a [u8],
+    ) -> Result<HashEntry<'a>, StfsError> {
+        let offset = self.block_hash_address(block, input)?;
+        let Some(entry) = input
+            .get(offset as usize..)
+            .and_then(|entry| entry.get(..0x18))
+        else {
+            warn!(block, offset, "hash entry is outside of the package");
+            return Err(StfsError::BadHashEntry { block, offset });
+        };
+
+        Ok(HashEntry {
+            block_hash: &entry[..0x14],
+            status: entry[0x14],
+            next_block: BigEndian::read_u24(&entry[0x15..]),
+        })
+    }
+
+    /// Returns the data blocks holding the file described by `entry`, in order,
+    /// following its hash chain unless its blocks are consecutive. Unlike
+    /// reading the file, this reports where a damaged chain leads.
+    pub fn file_blocks(&self, entry: &StfsFileEntry) -> Result<Vec<usize>, StfsError> {
+        if entry.has_consecutive_blocks() {
+            return Ok(
+                (entry.starting_block_num..entry.starting_block_num + entry.block_count).collect(),
             );
         }
 
-        reader.set_position(self.block_hash_address(block, input));
-        HashEntry {
-            block_hash: input_byte_ref(&mut reader, input, 0x14),
-            status: reader
-                .read_u8()
-                .expect("failed to read hash table entry status"),
-            next_block: reader
-                .read_u24::<BigEndian>()
-                .expect("failed to read hash table entry next_block")
-                as u32,
+        let mut blocks = Vec::with_capacity(entry.block_count);
+        let mut block = entry.starting_block_num;
+        for _ in 0..entry.block_count {
+            blocks.push(block);
+            block = self.try_block_hash_entry(block, self.input)?.next_block as usize;
         }
+
+        Ok(blocks)
     }
 
-    fn block_hash_address(&self, block: usize, input: &'a [u8]) -> u64 {
+    /// Returns the file offset of the active copy of the given level 1 hash table
+    pub(crate) fn level1_table_address(&self, table: usize) -> usize {
         let stfs_vol = self.header.volume_descriptor.stfs_ref();
-        if block > stfs_vol.allocated_block_count as usize {
-            panic!(
-                "Reference to illegal block number: {:#x} ({:#x} allocated)",
-                block, stfs_vol.allocated_block_count
-            );
+        let base_address = (self
+            .hash_table_meta
+            .compute_second_level_backing_hash_block_number(
+                table * DATA_BLOCKS_PER_HASH_TREE_LEVEL[2],
+                self.sex,
+            )
+            * BLOCK_SIZE)
+            + self.hash_table_meta.first_table_address;
+
+        match self.hash_table_meta.top_table.level {
+            HashTableLevel::Third => {
+                base_address
+                    + ((self.hash_table_meta.top_table.entries[table].status as usize & 0x40) << 6)
+            }
+            _ => base_address + (((stfs_vol.block_separation as usize) & 2) << 0xB),
+        }
+    }
+
+    /// Returns the file offset of the level 0 hash entry for the given data
+    /// block, failing if the block or the hash tables leading to it are outside
+    /// of the package
+    pub(crate) fn block_hash_address(
+        &self,
+        block: usize,
+        input: &'a [u8],
+    ) -> Result<u64, StfsError> {
+        let stfs_vol = self.header.volume_descriptor.stfs_ref();
+        let allocated = stfs_vol.allocated_block_count as usize;
+        let out_of_range = || {
+            warn!(block, allocated, "block is past the end of the package");
+            StfsError::BlockOutOfRange { block, allocated }
+        };
+        if block > allocated {
+            return Err(out_of_range());
         }
+        let top_entry_status = |level: usize| {
+            self.hash_table_meta
+                .top_table
+                .entries
+                .get(block / DATA_BLOCKS_PER_HASH_TREE_LEVEL[level])
+                .map(|entry| entry.status as u64)
+                .ok_or_else(out_of_range)
+        };
 
         let mut hash_addr = (self
             .hash_table_meta
@@ -527,25 +931,13 @@ impl<'a> StfsPackage<'a> {
             + self.hash_table_meta.first_table_address;
         // 0x18 here is the size of the HashEntry structure
         hash_addr += (block % HASHES_PER_HASH_TABLE) * 0x18;
-        match self.hash_table_meta.top_table.level {
+        let address = match self.hash_table_meta.top_table.level {
             HashTableLevel::First => {
                 hash_addr as u64 + (((stfs_vol.block_separation as u64) & 2) << 0xB)
             }
-            HashTableLevel::Second => {
-                hash_addr as u64
-                    + ((self.hash_table_meta.top_table.entries
-                        [block / DATA_BLOCKS_PER_HASH_TREE_LEVEL[1]]
-                        .status as u64
-                        & 0x40)
-                        << 6)
-            }
+            HashTableLevel::Second => hash_addr as u64 + ((top_entry_status(1)? & 0x40) << 6),
             HashTableLevel::Third => {
-                let mut reader = Cursor::new(input);
-                let first_level_offset = (self.hash_table_meta.top_table.entries
-                    [block / DATA_BLOCKS_PER_HASH_TREE_LEVEL[2]]
-                    .status as u64
-                    & 0x40)
-                    << 6;
+                let first_level_offset = (top_entry_status(2)? & 0x40) << 6;
 
                 let position = (self
                     .hash_table_meta
@@ -553,152 +945,201 @@ impl<'a> StfsPackage<'a> {
                     * BLOCK_SIZE)
                     + self.hash_table_meta.first_table_address
                     + first_level_offset as usize
-                    + ((block % DATA_BLOCKS_PER_HASH_TREE_LEVEL[1]) * 0x18);
-                reader.set_position(position as u64 + 0x14);
-
-                hash_addr as u64
-                    + ((reader.read_u8().unwrap_or_else(|_| {
-                        panic!("failed to read hash entry status byte at {:#x}", position)
-                    }) as u64
-                        & 0x40)
-                        << 0x6)
+                    + (((block % DATA_BLOCKS_PER_HASH_TREE_LEVEL[2])
+                        / DATA_BLOCKS_PER_HASH_TREE_LEVEL[1])
+                        * 0x18);
+                let Some(&status) = input.get(position + 0x14) else {
+                    warn!(
+                        block,
+                        position, "level 1 hash entry is outside of the package"
+                    );
+                    return Err(StfsError::BadHashEntry {
+                        block,
+                        offset: position as u64,
+                    });
+                };
+
+                hash_addr as u64 + ((status as u64 & 0x40) << 0x6)
             }
-        }
+        };
+
+        Ok(address)
     }
 
-    fn read_files(&mut self, input: &'a [u8]) {
+    /// Returns the data block numbers which hold the file table, in order
+    pub(crate) fn file_table_blocks(&self) -> Result<Vec<usize>, StfsError> {
         let stfs_vol = self.header.volume_descriptor.stfs_ref();
-        let mut reader = Cursor::new(input);
-        let mut block = stfs_vol.file_table_block_num;
-        let mut folders = HashMap::<u16, StfsEntryRef>::new();
-        let mut files = Vec::new();
-        // Inject a fake root folder
-        folders.insert(
-            0xffff,
-            Arc::new(Mutex::new(StfsEntry::Folder {
-                entry: StfsFileEntry::default(),
-                files: Vec::new(),
-            })),
-        );
 
-        for block_idx in 0..(stfs_vol.file_table_block_count as usize) {
-            let current_addr = self.block_to_addr(block as usize);
-            reader.set_position(current_addr);
-
-            for file_entry_idx in 0..0x40 {
-                let mut entry = StfsFileEntry::default();
-                entry.file_entry_address = current_addr + (file_entry_idx as u64 * 0x40);
-                entry.index = (block_idx * 0x40) + file_entry_idx;
-
-                entry.name = read_utf8_with_max_len(&mut reader, input, 0x28);
-                let name_len = reader.read_u8().unwrap_or_else(|_| {
-                    panic!("failed to read name_len at {:#x}", entry.file_entry_address)
-                });
-                if name_len & 0x3F == 0 {
-                    // Continue to the next entry
-                    reader.set_position(entry.file_entry_address + 0x40);
-                    continue;
-                }
+        let mut table_blocks = Vec::with_capacity(stfs_vol.file_table_block_count as usize);
+        let mut block = stfs_vol.file_table_block_num as usize;
+        for _ in 0..stfs_vol.file_table_block_count {
+            trace!(block, "file table block");
+            table_blocks.push(block);
+            block = self.try_block_hash_entry(block, self.input)?.next_block as usize;
+        }
 
-                if name_len == 0 {
-                    break;
-                }
+        Ok(table_blocks)
+    }
 
-                entry.block_count = reader
-                    .read_u24::<LittleEndian>()
-                    .expect("failed to read blocks_for_file")
-                    as usize;
-
-                reader.set_position(reader.position() + 3);
-
-                entry.starting_block_num = reader
-                    .read_u24::<LittleEndian>()
-                    .expect("failed to read blocks_for_file")
-                    as usize;
-                entry.path_indicator = reader
-                    .read_u16::<BigEndian>()
-                    .expect("failed to read blocks_for_file");
-                entry.file_size = reader
-                    .read_u32::<BigEndian>()
-                    .expect("failed to read file_size") as usize;
-                entry.created_time_stamp = reader
-                    .read_u32::<BigEndian>()
-                    .expect("failed to read created_time_stamp");
-                entry.access_time_stamp = reader
-                    .read_u32::<BigEndian>()
-                    .expect("failed to read access_time_stamp");
-                entry.flags = name_len >> 6;
-
-                if entry.flags & 2 != 0 {
-                    let entry_idx = entry.index;
-                    let folder = Arc::new(Mutex::new(StfsEntry::Folder {
-                        entry,
-                        files: Vec::new(),
-                    }));
-                    folders.insert(entry_idx as u16, folder.clone());
-                    files.push(folder.clone());
-                } else {
-                    files.push(Arc::new(Mutex::new(StfsEntry::File(entry))));
-                }
-            }
+    fn read_files(&self) -> Result<FileTable, StfsError> {
+        let input = self.input;
+        let stfs_vol = self.header.volume_descriptor.stfs_ref();
+        let _span = debug_span!(
+            "read_file_table",
+            first_block = stfs_vol.file_table_block_num,
+            block_count = stfs_vol.file_table_block_count
+        )
+        .entered();
 
-            block = self.block_hash_entry(block as usize, input).next_block;
+        // Walk the file table's block chain up front so that the blocks themselves
+        // can be parsed independently of each other
+        let table_blocks = self.file_table_blocks()?.into_iter().enumerate().collect();
+
+        let table_entries = parallel::map_collect(table_blocks, |(block_idx, block)| {
+            self.read_file_table_block(block_idx, block, input)
+        });
+
+        let mut entries = Vec::new();
+        for block_entries in table_entries {
+            entries.extend(block_entries?);
         }
+        let table = FileTable::new(entries);
+        debug!(entry_count = table.len(), "read file table");
 
-        // Associate each file with the folder it needs to be in
-        for file in files.drain(..) {
-            if let StfsEntry::File(entry) | StfsEntry::Folder { entry, files: _ } = &*file.lock() {
-                let cached_entry = folders.get(&entry.path_indicator);
-                if let Some(entry) = cached_entry {
-                    if let StfsEntry::Folder { entry: _, files } = &mut *entry.lock() {
-                        files.push(file.clone());
-                    }
-                } else {
-                    panic!(
-                        "Corrupt STFS file: missing folder index {:#x}",
-                        entry.path_indicator
-                    );
-                }
+        Ok(table)
+    }
+
+    /// Reads all of the file entries contained in a single file table block
+    fn read_file_table_block(
+        &self,
+        block_idx: usize,
+        block: usize,
+        input: &'a [u8],
+    ) -> Result<Vec<StfsFileEntry>, StfsError> {
+        let current_addr = self.block_to_addr(block)?;
+        let block_data = usize::try_from(current_addr)
+            .ok()
+            .and_then(|start| input.get(start..)?.get(..BLOCK_SIZE))
+            .ok_or(StfsError::Truncated {
+                needed: current_addr as usize + BLOCK_SIZE,
+                len: input.len(),
+            })?;
+
+        let mut entries = Vec::new();
+        for file_entry_idx in 0..0x40 {
+            let mut entry = StfsFileEntry {
+                file_entry_address: current_addr + (file_entry_idx as u64 * 0x40),
+                index: (block_idx * 0x40) + file_entry_idx,
+                ..Default::default()
+            };
+
+            // Each entry is 0x40 bytes and the block holds exactly 0x40 of them
+            let raw = &block_data[file_entry_idx * 0x40..][..0x40];
+            let name_len = raw[0x28];
+            if name_len & 0x3F == 0 {
+                // Continue to the next entry
+                continue;
             }
+
+            let mut reader = Cursor::new(input);
+            reader.set_position(entry.file_entry_address);
+            entry.name = read_utf8_with_max_len(&mut reader, input, 0x28, "file name")?;
+            entry.block_count = LittleEndian::read_u24(&raw[0x29..]) as usize;
+            entry.starting_block_num = LittleEndian::read_u24(&raw[0x2F..]) as usize;
+            entry.path_indicator = BigEndian::read_u16(&raw[0x32..]);
+            entry.file_size = BigEndian::read_u32(&raw[0x34..]) as usize;
+            entry.created_time_stamp = BigEndian::read_u32(&raw[0x38..]);
+            entry.access_time_stamp = BigEndian::read_u32(&raw[0x3C..]);
+            entry.flags = EntryFlags::from_bits_truncate(name_len >> 6);
+
+            entries.push(entry);
         }
 
-        self.files = folders.remove(&0xffff).expect("no root file entry");
+        Ok(entries)
     }
 
-    fn block_to_addr(&self, block: usize) -> u64 {
-        if block > 2usize.pow(24) - 1 {
-            panic!("invalid block: {:#x}", block);
-        }
+    /// Returns every file and folder in the package along with its `/`-separated
+    /// path. Entries are listed depth-first, with each folder preceding its contents.
+    pub fn walk_entries(&self) -> Vec<(String, StfsFileEntry)> {
+        let files = self.files();
+        files
+            .walk()
+            .into_iter()
+            .map(|(path, id)| (path, files.entry(id).clone()))
+            .collect()
+    }
 
-        (self.compute_data_block_num(block) * BLOCK_SIZE)
-            + self.hash_table_meta.first_table_address as u64
+    /// Returns the file offset of the given data block, failing if the block
+    /// number doesn't fit in 24 bits
+    pub fn block_to_addr(&self, block: usize) -> Result<u64, StfsError> {
+        data_block_address(block, self.sex, self.hash_table_meta.first_table_address)
     }
 
-    fn compute_data_block_num(&self, block: usize) -> u64 {
-        let addr = ((((block + HASHES_PER_HASH_TABLE) / HASHES_PER_HASH_TABLE)
-            << (self.sex as usize))
-            + block) as u64;
-        if block < HASHES_PER_HASH_TABLE {
-            addr
-        } else if block < DATA_BLOCKS_PER_HASH_TREE_LEVEL[2] {
-            addr + (((addr + DATA_BLOCKS_PER_HASH_TREE_LEVEL[2] as u64)
-                / DATA_BLOCKS_PER_HASH_TREE_LEVEL[2] as u64)
-                << self.sex as usize)
-        } else {
-            ((1 << self.sex as usize)
-                + ((addr as usize
-                    + ((block + DATA_BLOCKS_PER_HASH_TREE_LEVEL[2])
-                        / DATA_BLOCKS_PER_HASH_TREE_LEVEL[2]))
-                    << self.sex as usize)) as u64
-        }
+    /// Converts a data block number to its "true" block number, which also
+    /// counts the hash table blocks interleaved with the data
+    pub(crate) fn compute_data_block_num(&self, block: usize) -> u64 {
+        compute_data_block_num(block, self.sex)
+    }
+}
+
+/// Largest number of data blocks an STFS volume can address, since block
+/// numbers are stored in 24 bits
+pub(crate) const MAX_BLOCK_COUNT: usize = 1 << 24;
+
+/// Returns the file offset of the given data block
+pub(crate) fn data_block_address(
+    block: usize,
+    sex: StfsPackageSex,
+    first_table_address: usize,
+) -> Result<u64, StfsError> {
+    if block >= MAX_BLOCK_COUNT {
+        return Err(StfsError::BlockOutOfRange {
+            block,
+            allocated: MAX_BLOCK_COUNT,
+        });
+    }
+
+    Ok((compute_data_block_num(block, sex) * BLOCK_SIZE as u64) + first_table_address as u64)
+}
+
+/// Converts a data block number to its "true" block number, which also counts
+/// the hash table blocks interleaved with the data
+pub(crate) fn compute_data_block_num(block: usize, sex: StfsPackageSex) -> u64 {
+    let addr = ((((block + HASHES_PER_HASH_TABLE) / HASHES_PER_HASH_TABLE) << (sex as usize))
+        + block) as u64;
+    let level_one_tables = (((block + DATA_BLOCKS_PER_HASH_TREE_LEVEL[2])
+        / DATA_BLOCKS_PER_HASH_TREE_LEVEL[2])
+        << sex as usize) as u64;
+
+    if block < HASHES_PER_HASH_TABLE {
+        addr
+    } else if block < DATA_BLOCKS_PER_HASH_TREE_LEVEL[2] {
+        addr + level_one_tables
+    } else {
+        // Account for the level 2 table as well
+        (1 << sex as usize) + addr + level_one_tables
+    }
+}
+
+bitflags! {
+    /// Flags stored in the top two bits of a file table entry's name length.
+    /// Both bits are defined, so converting to and from the raw value with
+    /// [`EntryFlags::bits`] and [`EntryFlags::from_bits_truncate`] is lossless.
+    #[derive(Default)]
+    #[cfg_attr(feature = "serde", derive(Serialize), serde(transparent))]
+    pub struct EntryFlags: u8 {
+        /// The file's data is stored in consecutive blocks
+        const CONSECUTIVE_BLOCKS = 1;
+        const FOLDER = 2;
     }
 }
 
-#[derive(Default, Clone, Debug, Serialize)]
+#[derive(Default, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct StfsFileEntry {
     pub index: usize,
     pub name: String,
-    pub flags: u8,
+    pub flags: EntryFlags,
     pub block_count: usize,
     pub starting_block_num: usize,
     pub path_indicator: u16,
@@ -708,12 +1149,35 @@ pub struct StfsFileEntry {
     pub file_entry_address: u64,
 }
 
-#[derive(Debug, Serialize)]
+impl StfsFileEntry {
+    pub fn is_folder(&self) -> bool {
+        self.flags.contains(EntryFlags::FOLDER)
+    }
+
+    /// Whether the file's data is stored in consecutive blocks, allowing the hash
+    /// table chain to be skipped when reading it
+    pub fn has_consecutive_blocks(&self) -> bool {
+        self.flags.contains(EntryFlags::CONSECUTIVE_BLOCKS)
+    }
+
+    pub fn created(&self) -> Option<NaiveDateTime> {
+        fat_timestamp_to_datetime(self.created_time_stamp)
+    }
+
+    pub fn accessed(&self) -> Option<NaiveDateTime> {
+        fat_timestamp_to_datetime(self.access_time_stamp)
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct HashTable<'a> {
-    level: HashTableLevel,
-    true_block_number: usize,
-    entry_count: usize,
-    address_in_file: usize,
+    pub level: HashTableLevel,
+    /// Block number of this table relative to the first hash table, counting
+    /// hash table blocks as well as data blocks
+    pub true_block_number: usize,
+    pub entry_count: usize,
+    pub address_in_file: usize,
     entries: Vec<HashEntry<'a>>,
 }
 
@@ -729,7 +1193,8 @@ impl<'a> Default for HashTable<'a> {
     }
 }
 
-#[derive(Debug, Serialize, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum HashTableLevel {
     First,
     Second,
@@ -744,7 +1209,7 @@ fn certificate_parser<'a>(
     let mut owner_console_id = [0u8; 5];
     cursor.read_exact(&mut owner_console_id)?;
 
-    let owner_console_part_number = input_byte_ref(cursor, input, 0x11);
+    let owner_console_part_number = input_byte_ref(cursor, input, 0x11)?;
     let owner_console_part_number = std::str::from_utf8(
         &owner_console_part_number[..owner_console_part_number
             .iter()
@@ -757,14 +1222,14 @@ fn certificate_parser<'a>(
     let console_type_flags = ConsoleTypeFlags::from_bits(owner_console_type & 0xFFFFFFFC);
     let owner_console_type = ConsoleType::try_from((owner_console_type & 0x3) as u8).ok();
 
-    let date_generation = input_byte_ref(cursor, input, 0x8);
+    let date_generation = input_byte_ref(cursor, input, 0x8)?;
     let date_generation = std::str::from_utf8(date_generation).unwrap_or(INVALID_STR);
 
     let public_exponent = cursor.read_u32::<BigEndian>()?;
 
-    let public_modulus = input_byte_ref(cursor, input, 0x80);
-    let certificate_signature = input_byte_ref(cursor, input, 0x100);
-    let signature = input_byte_ref(cursor, input, 0x80);
+    let public_modulus = input_byte_ref(cursor, input, 0x80)?;
+    let certificate_signature = input_byte_ref(cursor, input, 0x100)?;
+    let signature = input_byte_ref(cursor, input, 0x80)?;
 
     Ok(Certificate {
         pubkey_cert_size,
@@ -788,7 +1253,14 @@ fn xcontent_header_parser<'a>(
     cursor.read_exact(&mut package_type)?;
     let package_type = PackageType::try_from(package_type)?;
 
-    let certificate = if let _package_type = PackageType::Con {
+    if input.len() < MIN_HEADER_SIZE {
+        return Err(StfsError::Truncated {
+            needed: MIN_HEADER_SIZE,
+            len: input.len(),
+        });
+    }
+
+    let certificate = if matches!(package_type, PackageType::Con) {
         Some(certificate_parser(cursor, input)?)
     } else {
         None
@@ -796,117 +1268,150 @@ fn xcontent_header_parser<'a>(
 
     let (input, package_signature) =
         if matches!(package_type, PackageType::Live | PackageType::Pirs) {
-            let sig = input_byte_ref(cursor, input, 0x100);
+            let sig = input_byte_ref(cursor, input, 0x100)?;
             (input, Some(sig))
         } else {
             (input, None)
         };
 
-    cursor.set_position(0x22c);
+    let unsigned = input
+        .get(SIGNATURE_REGION)
+        .is_some_and(|region| region.iter().all(|b| *b == 0));
 
-    let mut license_data = [LicenseEntry::default(); 16];
-    for i in 0..license_data.len() {
-        let license = cursor.read_u64::<BigEndian>()?;
-        license_data[i].ty = LicenseType::try_from(
-            u16::try_from(license >> 48).expect("failed to convert license type to u16"),
-        )
-        .expect("invalid LicenseType");
-        license_data[i].data = license & 0xFFFFFFFFFFFF;
-        license_data[i].bits = cursor.read_u32::<BigEndian>()?;
-        license_data[i].flags = cursor.read_u32::<BigEndian>()?;
-    }
-
-    let header_hash = input_byte_ref(cursor, input, 0x14);
-    let header_size = cursor.read_u32::<BigEndian>()?;
-
-    let content_type =
-        ContentType::try_from(cursor.read_u32::<BigEndian>()?).expect("invalid content type");
-    let metadata_version = cursor.read_u32::<BigEndian>()?;
-    let content_size = cursor.read_u64::<BigEndian>()?;
-    let media_id = cursor.read_u32::<BigEndian>()?;
-    let version = cursor.read_u32::<BigEndian>()?;
-    let base_version = cursor.read_u32::<BigEndian>()?;
-    let title_id = cursor.read_u32::<BigEndian>()?;
-    let platform = cursor.read_u8()?;
-    let executable_type = cursor.read_u8()?;
-    let disc_number = cursor.read_u8()?;
-    let disc_in_set = cursor.read_u8()?;
-    let savegame_id = cursor.read_u32::<BigEndian>()?;
-
-    let mut console_id = [0u8; 5];
-    cursor.read_exact(&mut console_id)?;
-
-    let mut profile_id = [0u8; 8];
-    cursor.read_exact(&mut profile_id)?;
-
-    // read the file system type
-    cursor.set_position(0x3a9);
-    let filesystem_type =
-        FileSystemType::try_from(cursor.read_u32::<BigEndian>()?).expect("invalid filesystem type");
-
-    let volume_descriptor = match filesystem_type {
-        FileSystemType::STFS => {
-            cursor.set_position(0x379);
-            FileSystem::STFS(StfsVolumeDescriptor::parse(cursor, input)?)
-        }
-        FileSystemType::SVOD => FileSystem::SVOD(SvodVolumeDescriptor::parse(cursor, input)?),
-        _ => panic!("Invalid filesystem type"),
-    };
+    let layout = HeaderLayout::parse(input)?;
 
-    let data_file_count = cursor.read_u32::<BigEndian>()?;
-    let data_file_combined_size = cursor.read_u64::<BigEndian>()?;
+    let mut license_data = [LicenseEntry::default(); 16];
+    for (i, (license_entry, license)) in license_data.iter_mut().zip(&layout.licenses).enumerate() {
+        let offset = (write::LICENSES_OFFSET + i * write::LICENSE_ENTRY_SIZE) as u64;
+        license_entry.ty = known_value("license type", offset, (license.license >> 48) as u16)?;
+        license_entry.data = license.license & 0xFFFFFFFFFFFF;
+        license_entry.bits = license.bits;
+        license_entry.flags = license.flags;
+    }
 
-    let content_metadata = match content_type {
-        ContentType::AvatarItem => {
-            cursor.set_position(0x3d9);
-            Some(ContentMetadata::AvatarItem(AvatarAssetInformation::parse(
-                cursor, input,
-            )?))
+    let header_hash = &input[write::HEADER_HASH_OFFSET..][..0x14];
+    let header_size = layout.header_size;
+    // Everything below is read from within the header, so a partial header
+    // is reported as truncated rather than failing on whichever read runs out
+    if input.len() < header_size as usize {
+        return Err(StfsError::Truncated {
+            needed: header_size as usize,
+            len: input.len(),
+        });
+    }
+    let content_type = known_value(
+        "content type",
+        write::CONTENT_TYPE_OFFSET as u64,
+        layout.content_type,
+    )?;
+    let metadata_version = layout.metadata_version;
+    let content_size = layout.content_size;
+    let media_id = MediaId(layout.media_id);
+    let version = layout.version;
+    let base_version = layout.base_version;
+    let title_id = TitleId(layout.title_id);
+    let platform = layout.platform;
+    let executable_type = layout.executable_type;
+    let disc_number = layout.disc_number;
+    let disc_in_set = layout.disc_in_set;
+    let savegame_id = layout.savegame_id;
+    let console_id = layout.console_id;
+    let profile_id = layout.profile_id;
+
+    let filesystem_type: FileSystemType = known_value(
+        "filesystem type",
+        write::FILESYSTEM_TYPE_OFFSET as u64,
+        layout.filesystem_type,
+    )?;
+    let volume_descriptor = match layout.volume_descriptor {
+        VolumeDescriptorLayout::Stfs(descriptor) => {
+            FileSystem::STFS(StfsVolumeDescriptor::from_layout(descriptor, input))
+        }
+        VolumeDescriptorLayout::Svod(descriptor) => {
+            FileSystem::SVOD(SvodVolumeDescriptor::from_layout(descriptor, input))
         }
-        ContentType::Video => {
-            cursor.set_position(0x3d9);
-            Some(ContentMetadata::Video(MediaInformation::parse(
-                cursor, input,
-            )?))
+        VolumeDescriptorLayout::Other(_) => {
+            return Err(StfsError::UnknownFieldValue {
+                field: "filesystem type",
+                offset: write::FILESYSTEM_TYPE_OFFSET as u64,
+                value: filesystem_type as u64,
+            })
         }
-        _ => None,
     };
 
-    cursor.set_position(0x3fd);
-
-    let device_id = input_byte_ref(cursor, input, 0x14);
-
-    let display_name = read_utf16_cstr(cursor, input);
-
-    cursor.set_position(0xD11);
-    let display_description = read_utf16_cstr(cursor, input);
-
-    cursor.set_position(0x1611);
-    let publisher_name = read_utf16_cstr(cursor, input);
-
-    cursor.set_position(0x1691);
-    let title_name = read_utf16_cstr(cursor, input);
-
-    cursor.set_position(0x1711);
-    let transfer_flags = cursor.read_u8()?;
+    let data_file_count = layout.data_file_count;
+    let data_file_combined_size = layout.data_file_combined_size;
+
+    let content_metadata = match &layout.content_metadata {
+        ContentMetadataLayout::AvatarItem(metadata) => Some(ContentMetadata::AvatarItem(
+            AvatarAssetInformation::from_layout(metadata, input)?,
+        )),
+        ContentMetadataLayout::Video(metadata) => Some(ContentMetadata::Video(
+            MediaInformation::from_layout(metadata, input),
+        )),
+        ContentMetadataLayout::Other(_) => None,
+    };
 
-    let thumbnail_image_size = cursor.read_u32::<BigEndian>()? as usize;
-    let title_thumbnail_image_size = cursor.read_u32::<BigEndian>()? as usize;
+    let device_id = &input[write::DEVICE_ID_OFFSET..][..0x14];
 
-    let thumbnail_image = input_byte_ref(cursor, input, thumbnail_image_size);
-    cursor.set_position(0x571a);
+    let string = |units: &[u16], field, offset| {
+        utf16_field(units).ok_or(StfsError::InvalidString {
+            field,
+            offset: offset as u64,
+        })
+    };
+    let display_name = string(
+        &layout.display_name,
+        "display name",
+        write::DISPLAY_NAME_OFFSET,
+    )?;
+    let display_description = string(
+        &layout.display_description,
+        "display description",
+        write::DISPLAY_DESCRIPTION_OFFSET,
+    )?;
+    let publisher_name = string(
+        &layout.publisher_name,
+        "publisher name",
+        write::PUBLISHER_NAME_OFFSET,
+    )?;
+    let title_name = string(&layout.title_name, "title name", write::TITLE_NAME_OFFSET)?;
+
+    let transfer_flags = layout.transfer_flags;
+    let thumbnail_image_size = layout.thumbnail_image_size as usize;
+    let title_thumbnail_image_size = layout.title_thumbnail_image_size as usize;
+    if thumbnail_image_size > write::MAX_IMAGE_SIZE
+        || title_thumbnail_image_size > write::MAX_IMAGE_SIZE
+    {
+        return Err(StfsError::InvalidHeader);
+    }
 
-    let title_image = input_byte_ref(cursor, input, title_thumbnail_image_size);
-    cursor.set_position(0x971a);
+    let thumbnail_image = &input[write::THUMBNAIL_IMAGE_OFFSET..][..thumbnail_image_size];
+    let title_image = &input[write::TITLE_THUMBNAIL_IMAGE_OFFSET..][..title_thumbnail_image_size];
 
+    cursor.set_position(write::INSTALLER_TYPE_OFFSET as u64);
     let mut installer_type = None;
     let mut installer_meta = None;
-    if ((header_size + 0xFFF) & 0xFFFFF000) - 0x971A > 0x15F4 {
-        installer_type = Some(
-            InstallerType::try_from(cursor.read_u32::<BigEndian>()?)
-                .expect("invalid InstallerType"),
-        );
-        installer_meta = match *installer_type.as_ref().unwrap() {
+    // Installer metadata is only present in headers which extend past it
+    let header_end = (u64::from(header_size) + 0xFFF) & !0xFFF;
+    if header_end
+        .checked_sub(write::INSTALLER_TYPE_OFFSET as u64)
+        .is_some_and(|installer_len| installer_len > INSTALLER_METADATA_SIZE as u64)
+    {
+        let installer_end = write::INSTALLER_TYPE_OFFSET + INSTALLER_METADATA_SIZE;
+        if input.len() < installer_end {
+            return Err(StfsError::Truncated {
+                needed: installer_end,
+                len: input.len(),
+            });
+        }
+
+        let ty = known_value(
+            "installer type",
+            write::INSTALLER_TYPE_OFFSET as u64,
+            cursor.read_u32::<BigEndian>()?,
+        )?;
+        installer_meta = match &ty {
             InstallerType::SystemUpdate | InstallerType::TitleUpdate => {
                 let installer_base_version = Version::from(cursor.read_u32::<BigEndian>()?);
                 let installer_version = Version::from(cursor.read_u32::<BigEndian>()?);
@@ -918,18 +1423,19 @@ fn xcontent_header_parser<'a>(
             InstallerType::SystemUpdateProgressCache
             | InstallerType::TitleUpdateProgressCache
             | InstallerType::TitleContentProgressCache => {
-                let resume_state =
-                    OnlineContentResumeState::try_from(cursor.read_u32::<BigEndian>()?)
-                        .expect("invalid resume state");
+                let offset = cursor.position();
+                let resume_state: OnlineContentResumeState =
+                    known_value("resume state", offset, cursor.read_u32::<BigEndian>()?)?;
                 let current_file_index = cursor.read_u32::<BigEndian>()?;
                 let current_file_offset = cursor.read_u64::<BigEndian>()?;
                 let bytes_processed = cursor.read_u64::<BigEndian>()?;
 
-                let _high_date_time = cursor.read_u32::<BigEndian>()?;
-                let _low_date_time = cursor.read_u32::<BigEndian>()?;
+                let high_date_time = cursor.read_u32::<BigEndian>()?;
+                let low_date_time = cursor.read_u32::<BigEndian>()?;
+                let last_modified =
+                    filetime_to_datetime(((high_date_time as u64) << 32) | low_date_time as u64);
 
-                // TODO: Fix
-                let last_modified = Utc::now();
+                let cab_resume_data = input_byte_ref(cursor, input, 0x15D0)?;
 
                 Some(InstallerMeta::InstallerProgressCache(
                     InstallerProgressCache {
@@ -938,15 +1444,16 @@ fn xcontent_header_parser<'a>(
                         current_file_offset,
                         bytes_processed,
                         last_modified,
-                        cab_resume_data: todo!("need to implement CAB resume data"),
+                        cab_resume_data,
                     },
-                ));
+                ))
             }
             _ => {
                 // anything else is ok
                 None
             }
-        }
+        };
+        installer_type = Some(ty);
     }
 
     let enabled = false;
@@ -954,6 +1461,7 @@ fn xcontent_header_parser<'a>(
         package_type,
         certificate,
         package_signature,
+        unsigned,
         license_data,
         header_hash,
         header_size,
@@ -992,30 +1500,39 @@ fn xcontent_header_parser<'a>(
     })
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct XContentHeader<'a> {
     pub package_type: PackageType,
     /// Only present in console-signed packages
     pub certificate: Option<Certificate<'a>>,
     /// Only present in strong-signed packages
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_optional_bytes"))]
     pub package_signature: Option<&'a [u8]>,
+    /// The signature and certificate region is blank, e.g. after
+    /// [`crate::strip_signature`]. Such packages are only accepted by consoles or
+    /// emulators which skip signature checks.
+    pub unsigned: bool,
 
     pub license_data: [LicenseEntry; 0x10],
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_bytes"))]
     pub header_hash: &'a [u8],
     pub header_size: u32,
     pub content_type: ContentType,
     pub metadata_version: u32,
     pub content_size: u64,
-    pub media_id: u32,
+    pub media_id: MediaId,
     pub version: u32,
     pub base_version: u32,
-    pub title_id: u32,
+    pub title_id: TitleId,
     pub platform: u8,
     pub executable_type: u8,
     pub disc_number: u8,
     pub disc_in_set: u8,
     pub savegame_id: u32,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_bytes"))]
     pub console_id: [u8; 5],
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_bytes"))]
     pub profile_id: [u8; 8],
     pub volume_descriptor: FileSystem<'a>,
     pub filesystem_type: FileSystemType,
@@ -1025,6 +1542,7 @@ pub struct XContentHeader<'a> {
     // Start metadata v1
     pub data_file_count: u32,
     pub data_file_combined_size: u64,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_bytes"))]
     pub device_id: &'a [u8],
     pub display_name: String,
     pub display_description: String,
@@ -1032,8 +1550,10 @@ pub struct XContentHeader<'a> {
     pub title_name: String,
     pub transfer_flags: u8,
     pub thumbnail_image_size: usize,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_image"))]
     pub thumbnail_image: &'a [u8],
     pub title_thumbnail_image_size: usize,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_image"))]
     pub title_image: &'a [u8],
     pub installer_type: Option<InstallerType>,
     pub installer_meta: Option<InstallerMeta<'a>>,
@@ -1041,125 +1561,145 @@ pub struct XContentHeader<'a> {
 }
 
 impl<'a> XContentHeader<'a> {
+    /// Parses just the package header, skipping the hash tables and file table.
+    /// This is much cheaper than [`StfsPackage::try_from`] when only metadata
+    /// is needed.
+    pub fn parse(input: &'a [u8]) -> Result<Self, StfsError> {
+        let _span = debug_span!("parse_header", len = input.len()).entered();
+        let header = xcontent_header_parser(&mut Cursor::new(input), input)
+            .inspect_err(|err| debug!(%err, "failed to parse header"))?;
+        debug!(
+            package_type = %header.package_type,
+            content_type = %header.content_type,
+            title_id = %header.title_id,
+            header_size = header.header_size,
+            "parsed header"
+        );
+
+        Ok(header)
+    }
+
     /// Returns which hash table level the root hash is in
     fn root_hash_table_level(&self) -> Result<HashTableLevel, StfsError> {
         if let FileSystem::STFS(volume_descriptor) = &self.volume_descriptor {
-            let level = if volume_descriptor.allocated_block_count as usize <= HASHES_PER_HASH_TABLE
-            {
-                HashTableLevel::First
-            } else if volume_descriptor.allocated_block_count as usize
-                <= HASHES_PER_HASH_TABLE_LEVEL[1]
-            {
-                HashTableLevel::Second
-            } else if volume_descriptor.allocated_block_count as usize
-                <= HASHES_PER_HASH_TABLE_LEVEL[2]
-            {
-                HashTableLevel::Third
-            } else {
-                return Err(StfsError::InvalidHeader);
-            };
-
-            Ok(level)
+            hash_table_level(volume_descriptor.allocated_block_count as usize)
+                .ok_or(StfsError::InvalidHeader)
         } else {
             Err(StfsError::InvalidPackageType)
         }
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(not(feature = "serde"), allow(dead_code))]
 pub struct AvatarAssetInformation<'a> {
     subcategory: AssetSubcategory,
     colorizable: u32,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_bytes"))]
     guid: &'a [u8],
     skeleton_version: SkeletonVersion,
 }
 
 impl<'a> AvatarAssetInformation<'a> {
-    fn parse(
-        cursor: &mut Cursor<&'a [u8]>,
+    /// Checks the parsed `layout`, borrowing the GUID from the package `input`
+    fn from_layout(
+        layout: &AvatarAssetLayout,
         input: &'a [u8],
     ) -> Result<AvatarAssetInformation<'a>, StfsError> {
-        // This data is little endian for some reason
-        let subcategory = AssetSubcategory::try_from(cursor.read_u32::<LittleEndian>()?)
-            .expect("invalid avatar asset subcategory");
-        let colorizable = cursor.read_u32::<LittleEndian>()?;
-        let guid = input_byte_ref(cursor, input, 0x10);
-        let skeleton_version =
-            SkeletonVersion::try_from(cursor.read_u8()?).expect("invalid skeleton version");
-
+        let offset = write::CONTENT_METADATA_OFFSET;
         Ok(AvatarAssetInformation {
-            subcategory,
-            colorizable,
-            guid,
-            skeleton_version,
+            subcategory: known_value(
+                "avatar asset subcategory",
+                offset as u64,
+                layout.subcategory,
+            )?,
+            colorizable: layout.colorizable,
+            guid: &input[offset + 0x8..][..0x10],
+            skeleton_version: known_value(
+                "skeleton version",
+                (offset + 0x18) as u64,
+                layout.skeleton_version,
+            )?,
         })
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(not(feature = "serde"), allow(dead_code))]
 pub struct MediaInformation<'a> {
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_bytes"))]
     series_id: &'a [u8],
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_bytes"))]
     season_id: &'a [u8],
     season_number: u16,
     episode_number: u16,
 }
 
 impl<'a> MediaInformation<'a> {
-    fn parse(
-        cursor: &mut Cursor<&'a [u8]>,
-        input: &'a [u8],
-    ) -> Result<MediaInformation<'a>, StfsError> {
-        let series_id = input_byte_ref(cursor, input, 0x10);
-        let season_id = input_byte_ref(cursor, input, 0x10);
-        let season_number = cursor.read_u16::<BigEndian>()?;
-        let episode_number = cursor.read_u16::<BigEndian>()?;
-
-        Ok(MediaInformation {
-            series_id,
-            season_id,
-            season_number,
-            episode_number,
-        })
+    /// Converts the parsed `layout`, borrowing the IDs from the package `input`
+    fn from_layout(layout: &MediaInformationLayout, input: &'a [u8]) -> MediaInformation<'a> {
+        let offset = write::CONTENT_METADATA_OFFSET;
+        MediaInformation {
+            series_id: &input[offset..][..0x10],
+            season_id: &input[offset + 0x10..][..0x10],
+            season_number: layout.season_number,
+            episode_number: layout.episode_number,
+        }
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(not(feature = "serde"), allow(dead_code))]
 pub struct InstallerProgressCache<'a> {
     resume_state: OnlineContentResumeState,
     current_file_index: u32,
     current_file_offset: u64,
     bytes_processed: u64,
     last_modified: DateTime<Utc>,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_bytes"))]
     cab_resume_data: &'a [u8],
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(not(feature = "serde"), allow(dead_code))]
 pub struct FullInstallerMeta {
     installer_base_version: Version,
     installer_version: Version,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub enum InstallerMeta<'a> {
     FullInstaller(FullInstallerMeta),
     InstallerProgressCache(InstallerProgressCache<'a>),
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(not(feature = "serde"), allow(dead_code))]
 pub struct Certificate<'a> {
     pubkey_cert_size: u16,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_bytes"))]
     owner_console_id: [u8; 5],
     owner_console_part_number: &'a str,
     owner_console_type: Option<ConsoleType>,
     console_type_flags: Option<ConsoleTypeFlags>,
     date_generation: &'a str,
     public_exponent: u32,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_bytes"))]
     public_modulus: &'a [u8],
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_bytes"))]
     certificate_signature: &'a [u8],
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_bytes"))]
     signature: &'a [u8],
 }
 
-#[derive(Debug, Serialize, TryFromPrimitive)]
+#[derive(Debug, TryFromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 #[repr(u8)]
 enum ConsoleType {
     DevKit = 1,
@@ -1167,16 +1707,18 @@ enum ConsoleType {
 }
 
 bitflags! {
-    #[derive(Serialize)]
+    #[cfg_attr(feature = "serde", derive(Serialize))]
     struct ConsoleTypeFlags: u32 {
         const TESTKIT = 0x40000000;
         const RECOVERY_GENERATED = 0x80000000;
     }
 }
 
-#[derive(Debug, Serialize, Clone, Copy, TryFromPrimitive)]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, TryFromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 #[repr(u16)]
-enum LicenseType {
+pub enum LicenseType {
+    #[default]
     Unused = 0x0000,
     Unrestricted = 0xFFFF,
     ConsoleProfileLicense = 0x0009,
@@ -1188,27 +1730,25 @@ enum LicenseType {
     UserPrivileges = 0xB000,
 }
 
-impl Default for LicenseType {
-    fn default() -> Self {
-        Self::Unused
-    }
-}
-
-#[derive(Default, Debug, Serialize, Clone, Copy)]
+#[derive(Default, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct LicenseEntry {
-    ty: LicenseType,
-    data: u64,
-    bits: u32,
-    flags: u32,
+    pub ty: LicenseType,
+    /// Who the license is bound to, such as a profile's XUID or a console ID
+    pub data: u64,
+    pub bits: u32,
+    pub flags: u32,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub enum ContentMetadata<'a> {
     AvatarItem(AvatarAssetInformation<'a>),
     Video(MediaInformation<'a>),
 }
 
-#[derive(Debug, Serialize, TryFromPrimitive)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, TryFromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(u32)]
 pub enum ContentType {
     ArcadeGame = 0xD0000,
@@ -1244,7 +1784,8 @@ pub enum ContentType {
     XNA = 0xE0000,
 }
 
-#[derive(Debug, Serialize, TryFromPrimitive)]
+#[derive(Debug, TryFromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 #[repr(u32)]
 pub enum InstallerType {
     None = 0,
@@ -1255,7 +1796,9 @@ pub enum InstallerType {
     TitleContentProgressCache = 0x50245443,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(not(feature = "serde"), allow(dead_code))]
 pub struct Version {
     major: u16,
     minor: u16,
@@ -1274,7 +1817,8 @@ impl From<u32> for Version {
     }
 }
 
-#[derive(Debug, Serialize, TryFromPrimitive)]
+#[derive(Debug, TryFromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 #[repr(u32)]
 enum OnlineContentResumeState {
     FileHeadersNotReady = 0x46494C48,
@@ -1284,14 +1828,16 @@ enum OnlineContentResumeState {
     NewFolderResumeAttemptUnknown = 0x666F6C3F,
     NewFolderResumeAttemptSpecific = 0x666F6C40,
 }
-#[derive(Debug, Serialize)]
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub enum XContentFlags {
     MetadataIsPEC = 1,
     MetadataSkipRead = 2,
     MetadataDontFreeThumbnails = 4,
 }
 
-#[derive(Debug, Serialize, TryFromPrimitive)]
+#[derive(Debug, TryFromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 #[repr(u32)]
 pub enum FileSystemType {
     STFS = 0,
@@ -1299,7 +1845,8 @@ pub enum FileSystemType {
     FATX,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub enum FileSystem<'a> {
     STFS(StfsVolumeDescriptor<'a>),
     SVOD(SvodVolumeDescriptor<'a>),
@@ -1323,38 +1870,43 @@ impl<'a> FileSystem<'a> {
     }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct StfsVolumeDescriptor<'a> {
-    size: u8,
-    reserved: u8,
-    block_separation: u8,
-    file_table_block_count: u16,
+    pub size: u8,
+    pub reserved: u8,
+    pub block_separation: u8,
+    pub file_table_block_count: u16,
     /// This is encoded as a 24-bit integer
-    file_table_block_num: u32,
-    top_hash_table_hash: &'a [u8],
-    allocated_block_count: u32,
-    unallocated_block_count: u32,
+    pub file_table_block_num: u32,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_bytes"))]
+    pub top_hash_table_hash: &'a [u8],
+    /// Total number of data blocks in the package, whether in use or not
+    pub allocated_block_count: u32,
+    /// Number of data blocks within `allocated_block_count` which are free
+    pub unallocated_block_count: u32,
 }
 
 impl<'a> StfsVolumeDescriptor<'a> {
-    fn parse(
-        cursor: &mut Cursor<&'a [u8]>,
+    fn from_layout(
+        layout: StfsVolumeDescriptorLayout,
         input: &'a [u8],
-    ) -> Result<StfsVolumeDescriptor<'a>, StfsError> {
-        Ok(StfsVolumeDescriptor {
-            size: cursor.read_u8()?,
-            reserved: cursor.read_u8()?,
-            block_separation: cursor.read_u8()?,
-            file_table_block_count: cursor.read_u16::<LittleEndian>()?,
-            file_table_block_num: cursor.read_u24::<LittleEndian>()?,
-            top_hash_table_hash: input_byte_ref(cursor, input, 0x14),
-            allocated_block_count: cursor.read_u32::<BigEndian>()?,
-            unallocated_block_count: cursor.read_u32::<BigEndian>()?,
-        })
+    ) -> StfsVolumeDescriptor<'a> {
+        StfsVolumeDescriptor {
+            size: layout.size,
+            reserved: layout.reserved,
+            block_separation: layout.block_separation,
+            file_table_block_count: layout.file_table_block_count,
+            file_table_block_num: layout.file_table_block_num,
+            top_hash_table_hash: &input[write::TOP_HASH_TABLE_HASH_OFFSET..][..0x14],
+            allocated_block_count: layout.allocated_block_count,
+            unallocated_block_count: layout.unallocated_block_count,
+        }
     }
 }
 
-#[derive(Debug, Serialize, TryFromPrimitive)]
+#[derive(Debug, TryFromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 #[repr(u32)]
 enum AssetSubcategory {
     CarryableCarryable = 0x44c,
@@ -1445,8 +1997,9 @@ enum AssetSubcategory {
     WristwearWatch = 0x321,
 }
 
-#[derive(Debug, Serialize)]
-enum BinaryAssetType {
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum BinaryAssetType {
     Component = 1,
     Texture = 2,
     ShapeOverride = 3,
@@ -1454,7 +2007,8 @@ enum BinaryAssetType {
     ShapeOverridePost = 5,
 }
 
-#[derive(Debug, Serialize, TryFromPrimitive)]
+#[derive(Debug, TryFromPrimitive)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
 #[repr(u8)]
 enum SkeletonVersion {
     Nxe = 1,
@@ -1462,54 +2016,295 @@ enum SkeletonVersion {
     NxeAndNatal,
 }
 
-#[derive(Debug, Serialize)]
-enum AssetGender {
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum AssetGender {
     Male = 1,
     Female,
     Both,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+#[cfg_attr(not(feature = "serde"), allow(dead_code))]
 pub struct SvodVolumeDescriptor<'a> {
     size: u8,
     block_cache_element_count: u8,
     worker_thread_processor: u8,
     worker_thread_priority: u8,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_bytes"))]
     root_hash: &'a [u8],
     flags: u8,
     /// Encoded as an int24
     data_block_count: u32,
     /// Encoded as an int24
     data_block_offset: u32,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_bytes"))]
     reserved: [u8; 5],
 }
 
 impl<'a> SvodVolumeDescriptor<'a> {
-    fn parse(
-        cursor: &mut Cursor<&'a [u8]>,
+    fn from_layout(
+        layout: SvodVolumeDescriptorLayout,
         input: &'a [u8],
-    ) -> Result<SvodVolumeDescriptor<'a>, StfsError> {
-        let size = cursor.read_u8()?;
-        let block_cache_element_count = cursor.read_u8()?;
-        let worker_thread_processor = cursor.read_u8()?;
-        let worker_thread_priority = cursor.read_u8()?;
-        let root_hash = input_byte_ref(cursor, input, 0x14);
-        let flags = cursor.read_u8()?;
-        let data_block_count = cursor.read_u24::<BigEndian>()?;
-        let data_block_offset = cursor.read_u24::<BigEndian>()?;
-        let mut reserved = [0u8; 5];
-        cursor.read_exact(&mut reserved)?;
-
-        Ok(SvodVolumeDescriptor {
-            size,
-            block_cache_element_count,
-            worker_thread_processor,
-            worker_thread_priority,
-            root_hash,
-            flags,
-            data_block_count,
-            data_block_offset,
-            reserved,
-        })
+    ) -> SvodVolumeDescriptor<'a> {
+        SvodVolumeDescriptor {
+            size: layout.size,
+            block_cache_element_count: layout.block_cache_element_count,
+            worker_thread_processor: layout.worker_thread_processor,
+            worker_thread_priority: layout.worker_thread_priority,
+            root_hash: &input[write::VOLUME_DESCRIPTOR_OFFSET + 0x4..][..0x14],
+            flags: layout.flags,
+            data_block_count: layout.data_block_count,
+            data_block_offset: layout.data_block_offset,
+            reserved: layout.reserved,
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    #[test]
+    fn tables_per_level_counts_each_level() {
+        assert_eq!(tables_per_level(1), [1, 0, 0]);
+        assert_eq!(tables_per_level(HASHES_PER_HASH_TABLE), [1, 0, 0]);
+        assert_eq!(tables_per_level(HASHES_PER_HASH_TABLE + 1), [2, 1, 0]);
+        assert_eq!(
+            tables_per_level(DATA_BLOCKS_PER_HASH_TREE_LEVEL[2]),
+            [HASHES_PER_HASH_TABLE, 1, 0]
+        );
+        assert_eq!(
+            tables_per_level(DATA_BLOCKS_PER_HASH_TREE_LEVEL[2] + 1),
+            [HASHES_PER_HASH_TABLE + 1, 2, 1]
+        );
+    }
+
+    #[test]
+    fn fat_timestamp_conversion() {
+        // 2025-01-11 13:33:58
+        let timestamp = (45 << 25) | (1 << 21) | (11 << 16) | (13 << 11) | (33 << 5) | 29;
+        assert_eq!(
+            fat_timestamp_to_datetime(timestamp),
+            NaiveDate::from_ymd_opt(2025, 1, 11).and_then(|date| date.and_hms_opt(13, 33, 58))
+        );
+        assert_eq!(fat_timestamp_to_datetime(0), None);
+    }
+
+    #[test]
+    fn truncated_header_is_an_error() {
+        let mut data = crate::StfsPackageBuilder::new().build().unwrap();
+        assert!(XContentHeader::parse(&data[..0x1000]).is_err());
+
+        data[0x1712..0x1716].copy_from_slice(&0x8000u32.to_be_bytes());
+        assert!(matches!(
+            XContentHeader::parse(&data),
+            Err(StfsError::InvalidHeader)
+        ));
+    }
+
+    /// A title update progress cache package, whose header is large enough to
+    /// hold installer metadata
+    pub(crate) fn installer_package() -> Vec<u8> {
+        let header_size = write::INSTALLER_TYPE_OFFSET + INSTALLER_METADATA_SIZE;
+        let mut builder = crate::StfsPackageBuilder::new();
+        builder.add_file("default.xex", vec![0x58; 0x1800]).unwrap();
+        let mut data = builder.build().unwrap();
+
+        // Make room for the larger header, which moves the hash tables back a block
+        let old_header_end = (MIN_HEADER_SIZE + 0xFFF) & !0xFFF;
+        let new_header_end = (header_size + 0xFFF) & !0xFFF;
+        data.splice(
+            old_header_end..old_header_end,
+            vec![0; new_header_end - old_header_end],
+        );
+        data[write::HEADER_SIZE_OFFSET..][..4].copy_from_slice(&(header_size as u32).to_be_bytes());
+
+        let installer = &mut data[write::INSTALLER_TYPE_OFFSET..];
+        installer[..4]
+            .copy_from_slice(&(InstallerType::TitleUpdateProgressCache as u32).to_be_bytes());
+        installer[4..8]
+            .copy_from_slice(&(OnlineContentResumeState::NewFolder as u32).to_be_bytes());
+        installer[0x24..INSTALLER_METADATA_SIZE].fill(0xCA);
+        data
+    }
+
+    #[test]
+    fn partial_installer_headers_are_truncated() {
+        let data = installer_package();
+        let header = XContentHeader::parse(&data).unwrap();
+        assert!(matches!(
+            header.installer_meta,
+            Some(InstallerMeta::InstallerProgressCache(InstallerProgressCache {
+                cab_resume_data,
+                ..
+            })) if cab_resume_data.len() == 0x15D0 && cab_resume_data.iter().all(|b| *b == 0xCA)
+        ));
+
+        // Only the header itself is needed. Anything shorter is truncated, even
+        // when it extends past the images into the installer metadata.
+        let header_size = write::INSTALLER_TYPE_OFFSET + INSTALLER_METADATA_SIZE;
+        assert!(XContentHeader::parse(&data[..header_size]).is_ok());
+        let package = StfsPackage::try_from(data.as_slice()).unwrap();
+        assert!(package.files().find("default.xex").is_some());
+        for len in (4..header_size)
+            .step_by(0x3FF)
+            .chain([MIN_HEADER_SIZE + 1, header_size - 1])
+        {
+            assert!(
+                matches!(
+                    XContentHeader::parse(&data[..len]),
+                    Err(StfsError::Truncated { .. })
+                ),
+                "{:#X} bytes",
+                len
+            );
+        }
+    }
+
+    #[test]
+    fn malformed_header_fields_are_errors() {
+        let data = crate::StfsPackageBuilder::new().build().unwrap();
+
+        let mut small_header = data.clone();
+        small_header[write::HEADER_SIZE_OFFSET..write::HEADER_SIZE_OFFSET + 4].fill(0);
+        assert!(XContentHeader::parse(&small_header).is_ok());
+
+        let mut fatx = data.clone();
+        fatx[write::FILESYSTEM_TYPE_OFFSET..write::FILESYSTEM_TYPE_OFFSET + 4]
+            .copy_from_slice(&(FileSystemType::FATX as u32).to_be_bytes());
+        assert!(matches!(
+            XContentHeader::parse(&fatx),
+            Err(StfsError::UnknownFieldValue {
+                field: "filesystem type",
+                ..
+            })
+        ));
+
+        // A display name running to the end of the data has no terminator
+        let mut unterminated = data[..write::DISPLAY_NAME_OFFSET + 0x10].to_vec();
+        unterminated[write::DISPLAY_NAME_OFFSET..].fill(0x41);
+        unterminated.resize(MIN_HEADER_SIZE, 0x41);
+        assert!(matches!(
+            XContentHeader::parse(&unterminated),
+            Err(StfsError::InvalidString {
+                field: "display name",
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn damaged_file_table_is_an_error() {
+        let mut data = crate::StfsPackageBuilder::new().build().unwrap();
+        // Point the file table past the end of the volume
+        let offset = write::VOLUME_DESCRIPTOR_OFFSET + 0x5;
+        data[offset..offset + 3].copy_from_slice(&[0xFF, 0xFF, 0x00]);
+
+        let package = StfsPackage::parse_lazy(&data).unwrap();
+        assert!(matches!(
+            package.try_files(),
+            Err(StfsError::BlockOutOfRange { block: 0xFFFF, .. })
+        ));
+        assert!(package.loaded_files().is_none());
+        assert!(matches!(
+            StfsPackage::try_from(data.as_slice()),
+            Err(StfsError::BlockOutOfRange { .. })
+        ));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serializing_a_damaged_file_table_is_an_error() {
+        let mut data = crate::StfsPackageBuilder::new().build().unwrap();
+        let offset = write::VOLUME_DESCRIPTOR_OFFSET + 0x5;
+        data[offset..offset + 3].copy_from_slice(&[0xFF, 0xFF, 0x00]);
+
+        let package = StfsPackage::parse_lazy(&data).unwrap();
+        let err = serde_json::to_string(&package).unwrap_err();
+        assert!(
+            err.to_string().contains("past the end of the volume"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn errors_report_codes_and_offsets() {
+        let data = crate::StfsPackageBuilder::new().build().unwrap();
+
+        let mut bad_magic = data.clone();
+        bad_magic[..4].copy_from_slice(b"NOPE");
+        let err = XContentHeader::parse(&bad_magic).unwrap_err();
+        assert!(matches!(err, StfsError::InvalidMagic { found } if &found == b"NOPE"));
+        assert_eq!((err.code(), err.class()), (101, ErrorClass::Parse));
+        assert_eq!(err.offset(), Some(0));
+
+        let mut bad_content_type = data.clone();
+        bad_content_type[write::CONTENT_TYPE_OFFSET..write::CONTENT_TYPE_OFFSET + 4]
+            .copy_from_slice(&0x1234u32.to_be_bytes());
+        let err = XContentHeader::parse(&bad_content_type).unwrap_err();
+        assert!(matches!(
+            err,
+            StfsError::UnknownFieldValue {
+                field: "content type",
+                value: 0x1234,
+                ..
+            }
+        ));
+        assert_eq!(err.offset(), Some(write::CONTENT_TYPE_OFFSET as u64));
+
+        let package = StfsPackage::try_from(data.as_slice()).unwrap();
+        let entry = StfsFileEntry {
+            block_count: 2,
+            starting_block_num: 0x100,
+            ..Default::default()
+        };
+        let err = package.file_blocks(&entry).unwrap_err();
+        assert!(matches!(
+            err,
+            StfsError::BlockOutOfRange { block: 0x100, .. }
+        ));
+        assert_eq!(err.class(), ErrorClass::Integrity);
+    }
+
+    #[test]
+    fn header_fields_are_read_from_layout_offsets() {
+        let mut data = crate::StfsPackageBuilder::new().build().unwrap();
+        data[write::TITLE_ID_OFFSET..write::TITLE_ID_OFFSET + 4]
+            .copy_from_slice(&0x4D5307E6u32.to_be_bytes());
+        data[write::TRANSFER_FLAGS_OFFSET] = 0x40;
+        let name: Vec<u8> = "Layout".encode_utf16().flat_map(u16::to_be_bytes).collect();
+        data[write::TITLE_NAME_OFFSET..write::TITLE_NAME_OFFSET + name.len()]
+            .copy_from_slice(&name);
+
+        let header = XContentHeader::parse(&data).unwrap();
+        assert_eq!(header.title_id, TitleId(0x4D5307E6));
+        assert_eq!(header.transfer_flags, 0x40);
+        assert_eq!(header.title_name, "Layout");
+    }
+
+    #[test]
+    fn lazy_parse_reads_file_table_on_first_access() {
+        let mut builder = crate::StfsPackageBuilder::new();
+        builder
+            .add_file("saves/slot1.bin", vec![1; 0x10])
+            .unwrap()
+            .add_file("readme.txt", b"hello".to_vec())
+            .unwrap();
+        let data = builder.build().unwrap();
+
+        let header = XContentHeader::parse(&data).unwrap();
+        let package = StfsPackage::from_header(&data, header).unwrap();
+        assert!(package.loaded_files().is_none());
+
+        let paths: Vec<String> = package
+            .walk_entries()
+            .into_iter()
+            .map(|(path, _)| path)
+            .collect();
+        assert_eq!(paths, ["saves", "saves/slot1.bin", "readme.txt"]);
+        assert!(package.loaded_files().is_some());
     }
 }
@@ -0,0 +1,311 @@
+//! Synthesizing small, valid packages in memory, so that tests and fuzzers
+//! have realistic fixtures without shipping real (usually copyrighted)
+//! packages.
+//!
+//! Packages are assembled with [`StfsPackageBuilder`] and then edited with
+//! [`remove_entry`] and [`inject_file`] to lay files out across
+//! non-consecutive blocks, so every part of a generated package was written by
+//! the same code that edits real ones.
+
+use crate::{
+    builder::StfsPackageBuilder,
+    edit::{inject_file, remove_entry},
+    stfs::{ContentType, StfsError, StfsPackageSex, BLOCK_SIZE},
+    write::reserve_blocks,
+};
+
+/// Folder used to hold the files that force fragmentation. It's removed before
+/// the package is returned.
+const SCRATCH_FOLDER: &str = "__testgen";
+
+/// Describes a package for [`generate`] to create
+#[derive(Debug, Clone)]
+pub struct PackageSpec {
+    pub sex: StfsPackageSex,
+    pub title_id: u32,
+    pub content_type: ContentType,
+    pub display_name: String,
+    /// Folders to create, which may be left empty
+    pub folders: Vec<String>,
+    /// `/`-separated paths of files to create, along with their contents
+    pub files: Vec<(String, Vec<u8>)>,
+    /// Paths of entries in `files` to store in non-consecutive blocks, so that
+    /// reading them means following their hash chain. Files which fit in a
+    /// single block are always consecutive.
+    pub fragmented: Vec<String>,
+    /// Number of free blocks to add after the files. More than 170 allocated
+    /// blocks need a second level of hash tables, and more than 28,900 need a
+    /// third.
+    pub spare_blocks: usize,
+}
+
+impl Default for PackageSpec {
+    fn default() -> Self {
+        PackageSpec {
+            sex: StfsPackageSex::Female,
+            title_id: 0,
+            content_type: ContentType::SavedGame,
+            display_name: String::new(),
+            folders: Vec::new(),
+            files: Vec::new(),
+            fragmented: Vec::new(),
+            spare_blocks: 0,
+        }
+    }
+}
+
+/// Builds the package described by `spec`
+pub fn generate(spec: &PackageSpec) -> Result<Vec<u8>, StfsError> {
+    if spec
+        .folders
+        .iter()
+        .chain(spec.files.iter().map(|(path, _)| path))
+        .any(|path| path.split('/').next() == Some(SCRATCH_FOLDER))
+    {
+        return Err(StfsError::InvalidFileName(SCRATCH_FOLDER.to_owned()));
+    }
+
+    let mut builder = StfsPackageBuilder::new();
+    builder
+        .sex(spec.sex)
+        .title_id(spec.title_id)
+        .content_type(spec.content_type)
+        .display_name(&spec.display_name)?;
+    for folder in &spec.folders {
+        builder.add_folder(folder)?;
+    }
+
+    // Fragmented files are first written as single-block placeholders. Each of
+    // their remaining blocks is reserved by a hole, preceded by a one-block
+    // spacer, so that once the holes are removed the free blocks have no
+    // consecutive run for the real contents to be written to.
+    let mut fragmented = Vec::new();
+    for (path, contents) in &spec.files {
+        let block_count = contents.len().div_ceil(BLOCK_SIZE);
+        if block_count < 2 || !spec.fragmented.contains(path) {
+            builder.add_file(path, contents.clone())?;
+            continue;
+        }
+
+        let file = fragmented.len();
+        builder.add_file(path, contents[..BLOCK_SIZE].to_vec())?;
+        let mut holes = Vec::with_capacity(block_count - 1);
+        for block in 0..block_count - 1 {
+            builder.add_file(
+                &format!("{}/spacer{}_{}", SCRATCH_FOLDER, file, block),
+                vec![0],
+            )?;
+            let hole = format!("{}/hole{}_{}", SCRATCH_FOLDER, file, block);
+            builder.add_file(&hole, vec![0; BLOCK_SIZE])?;
+            holes.push(hole);
+        }
+        fragmented.push((path, contents, holes));
+    }
+
+    let used_scratch_folder = !fragmented.is_empty();
+    let mut data = builder.build()?;
+    for (path, contents, holes) in fragmented {
+        for hole in holes {
+            remove_entry(&mut data, &hole)?;
+        }
+        inject_file(&mut data, path, contents)?;
+    }
+    if used_scratch_folder {
+        remove_entry(&mut data, SCRATCH_FOLDER)?;
+    }
+    if spec.spare_blocks > 0 {
+        reserve_blocks(&mut data, spec.spare_blocks)?;
+    }
+
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::{display::TitleId, stfs::StfsPackage, write::rehash};
+
+    /// Every entry's path, whether it's a folder, and its contents
+    fn listing(package: &StfsPackage<'_>) -> Vec<(String, bool, Vec<u8>)> {
+        let files = package.files();
+        files
+            .walk()
+            .into_iter()
+            .map(|(path, id)| {
+                let entry = files.entry(id);
+                let mut contents = Vec::new();
+                package.extract_file(&mut contents, entry).unwrap();
+                (path, entry.is_folder(), contents)
+            })
+            .collect()
+    }
+
+    /// Checks that `data` is a consistent package matching `spec`, and that
+    /// rebuilding it from its parsed contents gives the same package back
+    fn check_round_trip(spec: &PackageSpec, data: &[u8]) {
+        let package = StfsPackage::try_from(data).unwrap();
+        assert_eq!(package.sex, spec.sex);
+        assert_eq!(package.header.title_id, TitleId(spec.title_id));
+        assert!(package.verify_hashes().unwrap().is_empty());
+        package
+            .block_allocator()
+            .check_unallocated_block_count()
+            .unwrap();
+
+        let files = package.files();
+        for (path, contents) in &spec.files {
+            let entry = files.entry(files.find(path).unwrap());
+            let mut read = Vec::new();
+            package.extract_file(&mut read, entry).unwrap();
+            assert_eq!(&read, contents, "{}", path);
+            if spec.fragmented.contains(path) && contents.len() > BLOCK_SIZE {
+                assert!(!entry.has_consecutive_blocks(), "{} is consecutive", path);
+            }
+        }
+        for folder in &spec.folders {
+            assert!(files.is_folder(files.find(folder).unwrap()));
+        }
+
+        let mut rehashed = data.to_vec();
+        rehash(&mut rehashed).unwrap();
+        assert!(rehashed == data, "rehashing changed the package");
+
+        let original = listing(&package);
+        let mut builder = StfsPackageBuilder::from_template(&package);
+        for (path, is_folder, contents) in &original {
+            if *is_folder {
+                builder.add_folder(path).unwrap();
+            } else {
+                builder.add_file(path, contents.clone()).unwrap();
+            }
+        }
+        let rebuilt_data = builder.build().unwrap();
+        let rebuilt = StfsPackage::try_from(rebuilt_data.as_slice()).unwrap();
+        assert_eq!(rebuilt.sex, package.sex);
+        assert_eq!(rebuilt.header.title_id, package.header.title_id);
+        assert_eq!(rebuilt.header.content_type, package.header.content_type);
+        assert_eq!(rebuilt.header.display_name, package.header.display_name);
+        assert_eq!(listing(&rebuilt), original);
+    }
+
+    #[test]
+    fn generates_fragmented_files() {
+        let spec = PackageSpec {
+            sex: StfsPackageSex::Male,
+            display_name: "Fragments".to_owned(),
+            folders: vec!["empty".to_owned()],
+            files: vec![
+                ("a.bin".to_owned(), vec![1; BLOCK_SIZE * 3 + 7]),
+                ("saves/b.bin".to_owned(), vec![2; BLOCK_SIZE * 2]),
+                ("c.bin".to_owned(), vec![3; 10]),
+            ],
+            fragmented: vec!["a.bin".to_owned(), "saves/b.bin".to_owned()],
+            spare_blocks: 200,
+            ..Default::default()
+        };
+        let data = generate(&spec).unwrap();
+
+        check_round_trip(&spec, &data);
+        let package = StfsPackage::try_from(data.as_slice()).unwrap();
+        assert!(package.files().find(SCRATCH_FOLDER).is_none());
+        assert!(package.block_allocator().allocated_block_count() > 170);
+    }
+
+    fn spec() -> impl Strategy<Value = PackageSpec> {
+        let name = prop::collection::vec(prop::char::range('a', 'z'), 1..8)
+            .prop_map(|chars| format!("f_{}", String::from_iter(chars)));
+        let path = (
+            prop::sample::select(vec!["", "saves/", "saves/slot1/"]),
+            name,
+        )
+            .prop_map(|(folder, name)| format!("{}{}", folder, name));
+        let files = prop::collection::btree_map(
+            path,
+            (
+                prop::collection::vec(any::<u8>(), 0..BLOCK_SIZE * 3),
+                any::<bool>(),
+            ),
+            0..6,
+        );
+
+        (
+            prop::bool::ANY,
+            any::<u32>(),
+            files,
+            prop::bool::ANY,
+            0..200usize,
+        )
+            .prop_map(|(male, title_id, files, empty_folder, spare_blocks)| {
+                let fragmented = files
+                    .iter()
+                    .filter(|(_, (_, fragmented))| *fragmented)
+                    .map(|(path, _)| path.clone())
+                    .collect();
+                PackageSpec {
+                    sex: if male {
+                        StfsPackageSex::Male
+                    } else {
+                        StfsPackageSex::Female
+                    },
+                    title_id,
+                    folders: if empty_folder {
+                        vec!["saves/empty".to_owned()]
+                    } else {
+                        Vec::new()
+                    },
+                    files: files
+                        .into_iter()
+                        .map(|(path, (contents, _))| (path, contents))
+                        .collect(),
+                    fragmented,
+                    spare_blocks,
+                    ..Default::default()
+                }
+            })
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn generated_packages_round_trip(spec in spec()) {
+            let data = generate(&spec).unwrap();
+            check_round_trip(&spec, &data);
+        }
+
+        #[test]
+        fn generated_headers_round_trip(spec in spec()) {
+            crate::layout::tests::round_trip(&generate(&spec).unwrap());
+        }
+
+        #[test]
+        fn damaged_packages_never_panic(
+            spec in spec(),
+            header_edits in prop::collection::vec((0x340..0x3ADusize, any::<u8>()), 0..4),
+            edits in prop::collection::vec((any::<prop::sample::Index>(), any::<u8>()), 0..16),
+            truncate in prop::option::of(any::<prop::sample::Index>()),
+        ) {
+            let mut data = generate(&spec).unwrap();
+            // The volume descriptor and the blocks after the header hold
+            // everything the file table is found through
+            for (offset, value) in header_edits {
+                data[offset] = value;
+            }
+            for (offset, value) in edits {
+                let offset = offset.index(data.len());
+                data[offset] = value;
+            }
+            if let Some(len) = truncate {
+                data.truncate(len.index(data.len()));
+            }
+
+            if let Ok(package) = StfsPackage::try_from(data.as_slice()) {
+                for (_, entry) in package.walk_entries() {
+                    let _ = package.extract_file(&mut std::io::sink(), &entry);
+                }
+            }
+        }
+    }
+}
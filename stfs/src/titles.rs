@@ -0,0 +1,60 @@
+//! A small bundled database of title IDs for well-known games, used to show a
+//! name when a package's own title name is missing or localized.
+
+/// Title IDs and names, sorted by title ID
+const TITLES: &[(u32, &str)] = &[
+    (0x415607E6, "Call of Duty 4: Modern Warfare"),
+    (0x41560817, "Call of Duty: Modern Warfare 2"),
+    (0x4156081C, "Call of Duty: World at War"),
+    (0x41560855, "Call of Duty: Black Ops"),
+    (0x415608C3, "Call of Duty: Black Ops II"),
+    (0x415608CB, "Call of Duty: Modern Warfare 3"),
+    (0x425307D6, "Fallout 3"),
+    (0x4D5307E6, "Halo 3"),
+    (0x4D53085B, "Halo: Reach"),
+    (0x4D530877, "Halo 3: ODST"),
+    (0x4D530919, "Halo 4"),
+    (0x545407F2, "Grand Theft Auto IV"),
+    (0x5454082B, "Red Dead Redemption"),
+    (0x545408A7, "Grand Theft Auto V"),
+    (0x584111F7, "Minecraft: Xbox 360 Edition"),
+    (0xFFFE07D1, "Xbox 360 Dashboard"),
+];
+
+/// Looks up the name of the game with the given title ID
+pub fn title_name(title_id: u32) -> Option<&'static str> {
+    TITLES
+        .binary_search_by_key(&title_id, |(id, _)| *id)
+        .ok()
+        .map(|index| TITLES[index].1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn titles_are_sorted() {
+        assert!(TITLES.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert_eq!(title_name(0), None);
+    }
+
+    #[test]
+    fn known_titles() {
+        let known = [
+            (0x415607E6, "Call of Duty 4: Modern Warfare"),
+            (0x41560817, "Call of Duty: Modern Warfare 2"),
+            (0x41560855, "Call of Duty: Black Ops"),
+            (0x415608C3, "Call of Duty: Black Ops II"),
+            (0x4D5307E6, "Halo 3"),
+            (0x545408A7, "Grand Theft Auto V"),
+            (0xFFFE07D1, "Xbox 360 Dashboard"),
+        ];
+        for (title_id, name) in known {
+            assert_eq!(title_name(title_id), Some(name), "{:08X}", title_id);
+        }
+
+        assert_eq!(title_name(0x415608F8), None);
+        assert_eq!(title_name(0x5454087C), None);
+    }
+}
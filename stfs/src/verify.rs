@@ -0,0 +1,164 @@
+//! Checking a package's hash tree against its contents.
+
+#[cfg(feature = "serde")]
+use serde::Serialize;
+use sha1::{Digest, Sha1};
+
+use crate::{
+    parallel,
+    progress::{CancellationToken, Progress, ProgressSink, HASHES_PER_PROGRESS_UPDATE},
+    stfs::{StfsError, StfsPackage},
+    write::{self, HashStep},
+};
+
+/// Where in the hash tree a hash is stored
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum HashLocation {
+    /// Hash of a data block, stored in a level 0 hash table
+    DataBlock(usize),
+    /// Hash of a level 0 hash table, stored in a level 1 hash table
+    Level0Table(usize),
+    /// Hash of a level 1 hash table, stored in the level 2 hash table
+    Level1Table(usize),
+    /// Hash of the top hash table, stored in the volume descriptor
+    TopTable,
+    /// Hash of the header metadata, stored before the metadata
+    Header,
+}
+
+/// A hash which doesn't match the data it covers
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct HashMismatch {
+    pub location: HashLocation,
+    /// The hash stored in the package
+    pub stored: [u8; 20],
+    /// The hash of the data as it currently is
+    pub computed: [u8; 20],
+}
+
+impl<'a> StfsPackage<'a> {
+    /// Checks every hash in the package's hash tree. Hashes of unallocated data
+    /// blocks are not checked since they are commonly stale. Returns the hashes
+    /// which did not match.
+    pub fn verify_hashes(&self) -> Result<Vec<HashMismatch>, StfsError> {
+        self.verify_hashes_with_progress(|_: Progress<'_>| {}, &CancellationToken::default())
+    }
+
+    /// Same as [`StfsPackage::verify_hashes`], periodically reporting the
+    /// number of hashes and bytes checked so far to `on_progress` and stopping
+    /// if `cancel` is cancelled
+    pub fn verify_hashes_with_progress(
+        &self,
+        mut on_progress: impl ProgressSink,
+        cancel: &CancellationToken,
+    ) -> Result<Vec<HashMismatch>, StfsError> {
+        let allocator = self.block_allocator();
+        let steps: Vec<HashStep> = write::hash_tree_plan(self)?
+            .into_iter()
+            .flatten()
+            .filter(|step| match step.location {
+                HashLocation::DataBlock(block) => allocator.is_allocated(block),
+                _ => true,
+            })
+            .collect();
+
+        let input = self.input;
+        if let Some(needed) = steps
+            .iter()
+            .map(|step| step.source.end.max(step.destination + 20))
+            .max()
+        {
+            if needed > input.len() {
+                return Err(StfsError::Truncated {
+                    needed,
+                    len: input.len(),
+                });
+            }
+        }
+
+        let mut progress = Progress {
+            entry: None,
+            entries_done: 0,
+            entries_total: steps.len(),
+            bytes_done: 0,
+            bytes_total: steps.iter().map(|step| step.source.len() as u64).sum(),
+        };
+
+        let mut mismatches = Vec::new();
+        let mut steps = steps.into_iter().peekable();
+        while steps.peek().is_some() {
+            cancel.check()?;
+            let chunk: Vec<HashStep> = steps.by_ref().take(HASHES_PER_PROGRESS_UPDATE).collect();
+            progress.entries_done += chunk.len();
+            progress.bytes_done += chunk
+                .iter()
+                .map(|step| step.source.len() as u64)
+                .sum::<u64>();
+
+            let results = parallel::map_collect(chunk, |step| {
+                let computed: [u8; 20] = Sha1::digest(&input[step.source]).into();
+                let stored: [u8; 20] = input[step.destination..step.destination + 20]
+                    .try_into()
+                    .unwrap();
+
+                (stored != computed).then_some(HashMismatch {
+                    location: step.location,
+                    stored,
+                    computed,
+                })
+            });
+            mismatches.extend(results.into_iter().flatten());
+
+            on_progress.report(progress);
+        }
+
+        Ok(mismatches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{StfsPackageBuilder, BLOCK_SIZE};
+
+    #[test]
+    fn verify_detects_corruption() {
+        let mut builder = StfsPackageBuilder::new();
+        builder
+            .add_file("save.bin", vec![0x42; BLOCK_SIZE * 3])
+            .unwrap();
+        let mut data = builder.build().unwrap();
+
+        let package = StfsPackage::try_from(data.as_slice()).unwrap();
+        assert_eq!(package.verify_hashes().unwrap(), []);
+
+        let address = package.block_to_addr(2).unwrap() as usize;
+        drop(package);
+        data[address] ^= 0xFF;
+
+        let package = StfsPackage::try_from(data.as_slice()).unwrap();
+        let mut last_progress = None;
+        let mismatches = package
+            .verify_hashes_with_progress(
+                |progress: Progress<'_>| {
+                    last_progress = Some((progress.entries_done, progress.entries_total))
+                },
+                &CancellationToken::default(),
+            )
+            .unwrap();
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(mismatches[0].location, HashLocation::DataBlock(2));
+
+        let (done, total) = last_progress.unwrap();
+        assert_eq!(done, total);
+
+        let cancel = CancellationToken::new();
+        cancel.cancel();
+        assert!(matches!(
+            package.verify_hashes_with_progress(|_: Progress<'_>| {}, &cancel),
+            Err(StfsError::Cancelled)
+        ));
+    }
+}
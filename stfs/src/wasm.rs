@@ -0,0 +1,1002 @@
+//! JavaScript bindings for reading packages from wasm.
+//!
+//! Errors are thrown to JavaScript as `Error` objects carrying the
+//! [`crate::StfsError`] message, so a bad file doesn't take down the wasm
+//! instance.
+//!
+//! The TypeScript definitions emitted into the generated `.d.ts` describe the
+//! objects produced by the `Serialize` impls, and must be kept in sync with them
+//! by hand.
+//!
+//! Large packages can exhaust browser memory if they're converted wholesale.
+//! [`StfsHandle::regions`] and [`StfsHandle::read_file_chunk`] let a page view
+//! images and hash tables as offsets into its own copy of the package, and read
+//! files a chunk at a time, so no second full-size copy is ever made.
+
+mod remote;
+
+use std::{
+    collections::BTreeMap,
+    io::{Read, Seek, SeekFrom},
+};
+
+use js_sys::Uint8Array;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::{prelude::*, JsCast};
+
+use crate::{
+    dto::{EntryInfo, PackageInfo},
+    metadata::{hex, ImageKind, MetadataField},
+    regions::RegionKind,
+    serialize::{with_binary_encoding, BinaryEncoding},
+    sign::{verify_signature, KeyVault, SignatureStatus},
+    stfs::{
+        ContentType, HashTableLevel, PackageType, StfsError, StfsFileEntry, StfsPackage,
+        XContentHeader,
+    },
+    verify::HashLocation,
+    write::rehash,
+};
+
+/// Number of bytes read between progress callbacks when reading a single file
+const PROGRESS_CHUNK_SIZE: usize = 0x10000;
+
+#[wasm_bindgen(typescript_custom_section)]
+const TYPESCRIPT_TYPES: &'static str = r#"
+/**
+ * Called while files are read out of a package. `bytesDone` and `bytesTotal`
+ * cover the whole operation, which for `readFile` is the one file.
+ */
+export type ProgressCallback =
+    (currentFile: string, bytesDone: number, bytesTotal: number) => void;
+
+/**
+ * How byte fields such as hashes and images are represented. "hex" gives hex
+ * strings for hashes and IDs and base64 strings for images.
+ */
+export type BinaryFormat = "uint8array" | "array" | "hex";
+
+export interface SerializeOptions {
+    /** Defaults to "uint8array" */
+    binary?: BinaryFormat;
+    /** Leave `thumbnail_image` and `title_image` empty */
+    skipImages?: boolean;
+}
+
+/** A `Uint8Array` unless `SerializeOptions.binary` asks otherwise */
+export type Bytes = Uint8Array | number[] | string;
+
+export type PackageType = "Con" | "Live" | "Pirs";
+
+export type ContentType =
+    | "ArcadeGame" | "AvatarAssetPack" | "AvatarItem" | "CacheFile"
+    | "CommunityGame" | "GameDemo" | "GameOnDemand" | "GamerPicture"
+    | "GamerTitle" | "GameTrailer" | "GameVideo" | "InstalledGame"
+    | "Installer" | "IPTVPauseBuffer" | "LicenseStore" | "MarketPlaceContent"
+    | "Movie" | "MusicVideo" | "PodcastVideo" | "Profile" | "Publisher"
+    | "SavedGame" | "StorageDownload" | "Theme" | "Video" | "ViralVideo"
+    | "XboxDownload" | "XboxOriginalGame" | "XboxSavedGame" | "Xbox360Title"
+    | "XNA";
+
+export type PackageSex = "Female" | "Male";
+
+export type HashTableLevel = "First" | "Second" | "Third";
+
+export interface StfsVolumeDescriptor {
+    size: number;
+    reserved: number;
+    block_separation: number;
+    file_table_block_count: number;
+    file_table_block_num: number;
+    top_hash_table_hash: Bytes;
+    allocated_block_count: number;
+    unallocated_block_count: number;
+}
+
+export type FileSystem =
+    | { STFS: StfsVolumeDescriptor }
+    | { SVOD: Record<string, unknown> };
+
+export interface XContentHeader {
+    package_type: PackageType;
+    certificate?: Record<string, unknown>;
+    package_signature?: Bytes;
+    unsigned: boolean;
+    license_data: Record<string, unknown>[];
+    header_hash: Bytes;
+    header_size: number;
+    content_type: ContentType;
+    metadata_version: number;
+    content_size: number;
+    media_id: number;
+    version: number;
+    base_version: number;
+    title_id: number;
+    platform: number;
+    executable_type: number;
+    disc_number: number;
+    disc_in_set: number;
+    savegame_id: number;
+    console_id: Bytes;
+    profile_id: Bytes;
+    volume_descriptor: FileSystem;
+    filesystem_type: "STFS" | "SVOD" | "FATX";
+    enabled: boolean;
+    data_file_count: number;
+    data_file_combined_size: number;
+    device_id: Bytes;
+    display_name: string;
+    display_description: string;
+    publisher_name: string;
+    title_name: string;
+    transfer_flags: number;
+    thumbnail_image_size: number;
+    thumbnail_image: Bytes;
+    title_thumbnail_image_size: number;
+    title_image: Bytes;
+    installer_type?: string;
+    installer_meta?: Record<string, unknown>;
+    content_metadata?: Record<string, unknown>;
+}
+
+export interface StfsFileEntry {
+    index: number;
+    name: string;
+    flags: number;
+    block_count: number;
+    starting_block_num: number;
+    path_indicator: number;
+    file_size: number;
+    created_time_stamp: number;
+    access_time_stamp: number;
+    file_entry_address: number;
+}
+
+export type StfsEntry =
+    | { File: StfsFileEntry }
+    | { Folder: { entry: StfsFileEntry; files: StfsEntry[] } };
+
+export interface HashTableMeta {
+    block_step: [number, number];
+    tables_per_level: [number, number, number];
+    top_table: {
+        level: HashTableLevel;
+        true_block_number: number;
+        entry_count: number;
+        address_in_file: number;
+    };
+    first_table_address: number;
+}
+
+export interface StfsPackage {
+    header: XContentHeader;
+    sex: PackageSex;
+    hash_table_meta: HashTableMeta;
+    files: StfsEntry;
+}
+
+/** IDs and flags are upper-case hex strings */
+export interface HeaderSummary {
+    package_type: PackageType;
+    content_type: ContentType;
+    display_name: string;
+    display_description: string;
+    publisher_name: string;
+    title_name: string;
+    title_id: string;
+    media_id: string;
+    console_id: string;
+    profile_id: string;
+    device_id: string;
+    transfer_flags: string;
+}
+
+export type SignatureKind = "unsigned" | "console_signed" | "strong_signed";
+
+/** Layout given by `schema_version` */
+export interface PackageInfo {
+    schema_version: number;
+    package_type: PackageType;
+    signature: SignatureKind;
+    content_type: ContentType;
+    title_name: string;
+    display_name: string;
+    display_description: string;
+    publisher_name: string;
+    title_id: number;
+    resolved_title?: string;
+    media_id: number;
+    version: number;
+    base_version: number;
+    /** Upper-case hex */
+    console_id: string;
+    /** Upper-case hex */
+    profile_id: string;
+    /** Upper-case hex */
+    device_id: string;
+    content_size: number;
+    sex: PackageSex;
+    top_hash_table_level: HashTableLevel;
+    allocated_block_count: number;
+    unallocated_block_count: number;
+}
+
+export interface EntryInfo {
+    path: string;
+    folder: boolean;
+    consecutive: boolean;
+    size: number;
+    /** ISO 8601 timestamp without a time zone */
+    created?: string;
+    /** ISO 8601 timestamp without a time zone */
+    accessed?: string;
+    starting_block: number;
+    block_count: number;
+    /** Offset of the first block, absent for entries with no blocks */
+    offset?: number;
+}
+
+/** @deprecated Use `EntryInfo` */
+export type FileSummary = EntryInfo;
+
+/** A range of bytes within the package's own buffer */
+export interface Region {
+    offset: number;
+    length: number;
+}
+
+export interface HashTableRegion extends Region {
+    level: HashTableLevel;
+}
+
+/**
+ * Where the package's images and hash tables are, so they can be viewed with
+ * `new Uint8Array(buffer, offset, length)` instead of being copied
+ */
+export interface PackageRegions {
+    thumbnail_image: Region;
+    title_image: Region;
+    /** Each hash table block, in file order */
+    hash_tables: HashTableRegion[];
+}
+
+export type MetadataField =
+    | "display_name" | "display_description" | "publisher_name" | "title_name"
+    | "title_id" | "media_id" | "console_id" | "profile_id" | "device_id"
+    | "transfer_flags";
+
+export type RegionKind =
+    | "Magic" | "Certificate" | "Signature" | "LicenseTable" | "HeaderHash"
+    | "VolumeDescriptor" | "ThumbnailImage" | "TitleImage" | "FileTable"
+    | { Metadata: MetadataField }
+    | { HashTable: HashTableLevel };
+
+/** Byte offsets are relative to the start of the package */
+export interface RegionMapEntry {
+    kind: RegionKind;
+    range: { start: number; end: number };
+}
+
+export type HashLocation =
+    | "TopTable"
+    | "Header"
+    | { DataBlock: number }
+    | { Level0Table: number }
+    | { Level1Table: number };
+
+export type SignatureStatus = "Valid" | "Invalid" | "Unsigned" | "Unverifiable";
+
+export interface HashMismatch {
+    location: HashLocation;
+    /** Hex-encoded SHA-1 stored in the package */
+    stored: string;
+    /** Hex-encoded SHA-1 of the data as it currently is */
+    computed: string;
+}
+
+export interface VerifyReport {
+    /** No hashes mismatched and the signature isn't invalid */
+    ok: boolean;
+    signature: SignatureStatus;
+    /** Set if the volume descriptor's free block count is wrong */
+    unallocated_block_count_error?: string;
+    mismatches: HashMismatch[];
+}
+
+export interface LicensePatch {
+    /** 0 to 15 */
+    index: number;
+    /** The raw 64-bit license in hex, e.g. "FFFFFFFFFFFFFFFF" for unrestricted */
+    license: string;
+    bits?: number;
+    flags?: number;
+}
+
+/** Fields are given in the same format as `HeaderSummary` */
+export interface MetadataPatch {
+    display_name?: string;
+    display_description?: string;
+    publisher_name?: string;
+    title_name?: string;
+    title_id?: string;
+    media_id?: string;
+    console_id?: string;
+    profile_id?: string;
+    device_id?: string;
+    transfer_flags?: string;
+    licenses?: LicensePatch[];
+}
+
+export interface LoadProgress {
+    bytesReceived: number;
+    totalSize: number;
+    /** Only set by the call which completed the header */
+    header?: HeaderSummary;
+}
+"#;
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(typescript_type = "StfsPackage")]
+    pub type JsStfsPackage;
+
+    #[wasm_bindgen(typescript_type = "SerializeOptions")]
+    pub type JsSerializeOptions;
+
+    #[wasm_bindgen(typescript_type = "MetadataPatch")]
+    pub type JsMetadataPatch;
+
+    #[wasm_bindgen(typescript_type = "HeaderSummary")]
+    pub type JsHeaderSummary;
+
+    #[wasm_bindgen(typescript_type = "PackageInfo")]
+    pub type JsPackageInfo;
+
+    #[wasm_bindgen(typescript_type = "EntryInfo[]")]
+    pub type JsEntryInfos;
+
+    #[wasm_bindgen(typescript_type = "VerifyReport")]
+    pub type JsVerifyReport;
+
+    #[wasm_bindgen(typescript_type = "PackageRegions")]
+    pub type JsPackageRegions;
+
+    #[wasm_bindgen(typescript_type = "RegionMapEntry[]")]
+    pub type JsRegionMap;
+
+    #[wasm_bindgen(typescript_type = "ProgressCallback")]
+    pub type JsProgressCallback;
+
+    #[wasm_bindgen(typescript_type = "ArrayBuffer | Uint8Array")]
+    pub type JsChunk;
+
+    #[wasm_bindgen(typescript_type = "LoadProgress")]
+    pub type JsLoadProgress;
+}
+
+/// Invokes `callback`, if one was given. Exceptions thrown by the callback are
+/// ignored so that they can't interrupt an operation halfway through.
+fn report_progress(callback: Option<&JsProgressCallback>, file: &str, done: u64, total: u64) {
+    if let Some(callback) = callback {
+        let _ = callback.unchecked_ref::<js_sys::Function>().call3(
+            &JsValue::NULL,
+            &JsValue::from_str(file),
+            &JsValue::from_f64(done as f64),
+            &JsValue::from_f64(total as f64),
+        );
+    }
+}
+
+/// Converts `value` to a plain JavaScript object. Maps become objects rather
+/// than `Map`s so that flattened structs match their TypeScript interfaces.
+fn to_js<T: Serialize + ?Sized, J: JsCast>(value: &T) -> Result<J, JsError> {
+    let serializer = serde_wasm_bindgen::Serializer::new().serialize_maps_as_objects(true);
+    let value = with_binary_encoding(BinaryEncoding::Bytes, || value.serialize(&serializer))?;
+
+    Ok(value.unchecked_into())
+}
+
+/// How byte fields such as hashes and images are passed to JavaScript
+#[derive(Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum BinaryFormat {
+    #[default]
+    Uint8Array,
+    Array,
+    Hex,
+}
+
+/// Controls the shape of the object returned by [`read_stfs_package`]
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct SerializeOptions {
+    binary: BinaryFormat,
+    skip_images: bool,
+}
+
+/// Parses `data` as a package and returns its header, hash table metadata, and
+/// file listing. Prefer [`StfsHandle`] when only some of that is needed.
+///
+/// Byte fields are `Uint8Array`s unless `options.binary` asks for plain arrays
+/// or strings, and `options.skipImages` leaves the images empty to keep the
+/// result small.
+#[wasm_bindgen]
+pub fn read_stfs_package(
+    data: &[u8],
+    options: Option<JsSerializeOptions>,
+) -> Result<JsStfsPackage, JsError> {
+    let options: SerializeOptions = match options {
+        Some(options) => serde_wasm_bindgen::from_value(options.into())?,
+        None => SerializeOptions::default(),
+    };
+
+    let mut package = StfsPackage::try_from(data)?;
+    if options.skip_images {
+        package.header.thumbnail_image = &[];
+        package.header.title_image = &[];
+    }
+
+    let serializer = serde_wasm_bindgen::Serializer::new()
+        .serialize_maps_as_objects(true)
+        .serialize_bytes_as_arrays(options.binary == BinaryFormat::Array);
+    let encoding = match options.binary {
+        BinaryFormat::Hex => BinaryEncoding::Text,
+        BinaryFormat::Uint8Array | BinaryFormat::Array => BinaryEncoding::Bytes,
+    };
+    let value = with_binary_encoding(encoding, || package.serialize(&serializer))?;
+
+    Ok(value.unchecked_into())
+}
+
+fn find_file(package: &StfsPackage<'_>, path: &str) -> Result<StfsFileEntry, StfsError> {
+    package
+        .walk_entries()
+        .into_iter()
+        .find(|(entry_path, entry)| entry_path == path && !entry.is_folder())
+        .map(|(_, entry)| entry)
+        .ok_or_else(|| StfsError::FileNotFound(path.to_owned()))
+}
+
+fn read_file(
+    package: &StfsPackage<'_>,
+    path: &str,
+    on_progress: Option<&JsProgressCallback>,
+) -> Result<Vec<u8>, JsError> {
+    let entry = find_file(package, path)?;
+
+    let mut reader = package.file_reader(&entry)?;
+    let mut contents = Vec::with_capacity(entry.file_size);
+    let mut chunk = vec![0u8; PROGRESS_CHUNK_SIZE];
+    loop {
+        let read = reader.read(&mut chunk)?;
+        if read == 0 {
+            break;
+        }
+
+        contents.extend_from_slice(&chunk[..read]);
+        report_progress(
+            on_progress,
+            path,
+            contents.len() as u64,
+            entry.file_size as u64,
+        );
+    }
+
+    Ok(contents)
+}
+
+/// Returns the contents of the file at `path` inside the package in `data`, as
+/// a `Uint8Array`. `on_progress` is called as the file is read.
+/// [`StfsHandle::read_file`] does the same for packages which are already open.
+#[wasm_bindgen(js_name = extractFile)]
+pub fn extract_file(
+    data: &[u8],
+    path: &str,
+    on_progress: Option<JsProgressCallback>,
+) -> Result<Vec<u8>, JsError> {
+    read_file(&StfsPackage::try_from(data)?, path, on_progress.as_ref())
+}
+
+#[cfg(feature = "zip")]
+fn write_zip(
+    package: &StfsPackage<'_>,
+    on_progress: Option<&JsProgressCallback>,
+) -> Result<Vec<u8>, JsError> {
+    let zip = package.write_zip(
+        std::io::Cursor::new(Vec::new()),
+        &crate::ZipOptions::default(),
+        |progress: crate::Progress<'_>| {
+            report_progress(
+                on_progress,
+                progress.entry.unwrap_or_default(),
+                progress.bytes_done,
+                progress.bytes_total,
+            )
+        },
+        &crate::CancellationToken::default(),
+    )?;
+
+    Ok(zip.into_inner())
+}
+
+/// Exports every file in the package in `data` as a zip archive, returning
+/// bytes suitable for a `Blob` download. `on_progress` is called after each
+/// file is added. Requires the `zip` feature.
+#[cfg(feature = "zip")]
+#[wasm_bindgen(js_name = toZip)]
+pub fn to_zip(data: &[u8], on_progress: Option<JsProgressCallback>) -> Result<Vec<u8>, JsError> {
+    write_zip(&StfsPackage::try_from(data)?, on_progress.as_ref())
+}
+
+/// A hash which doesn't match, with the hashes hex-encoded for display
+#[derive(Serialize)]
+struct MismatchSummary {
+    location: HashLocation,
+    stored: String,
+    computed: String,
+}
+
+#[derive(Serialize)]
+struct VerifyReport {
+    ok: bool,
+    signature: SignatureStatus,
+    unallocated_block_count_error: Option<String>,
+    mismatches: Vec<MismatchSummary>,
+}
+
+fn verify_package(data: &[u8]) -> Result<JsVerifyReport, JsError> {
+    let package = StfsPackage::try_from(data)?;
+    let signature = verify_signature(data)?;
+    let mismatches: Vec<MismatchSummary> = package
+        .verify_hashes()?
+        .into_iter()
+        .map(|mismatch| MismatchSummary {
+            location: mismatch.location,
+            stored: hex(&mismatch.stored),
+            computed: hex(&mismatch.computed),
+        })
+        .collect();
+
+    to_js(&VerifyReport {
+        ok: mismatches.is_empty() && signature != SignatureStatus::Invalid,
+        signature,
+        unallocated_block_count_error: package
+            .block_allocator()
+            .check_unallocated_block_count()
+            .err()
+            .map(|e| e.to_string()),
+        mismatches,
+    })
+}
+
+/// Checks the hash tree and signature of the package in `data`. Problems are
+/// reported in the returned object rather than thrown, so only unreadable
+/// packages cause an exception.
+#[wasm_bindgen]
+pub fn verify(data: &[u8]) -> Result<JsVerifyReport, JsError> {
+    verify_package(data)
+}
+
+/// The header fields a web app typically displays
+#[derive(Serialize)]
+struct HeaderSummary {
+    package_type: PackageType,
+    content_type: ContentType,
+    #[serde(flatten)]
+    fields: BTreeMap<&'static str, String>,
+}
+
+impl HeaderSummary {
+    fn new(header: &XContentHeader<'_>) -> Self {
+        HeaderSummary {
+            package_type: header.package_type,
+            content_type: header.content_type,
+            fields: MetadataField::ALL
+                .into_iter()
+                .map(|field| (field.name(), field.get(header)))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct Region {
+    offset: usize,
+    length: usize,
+}
+
+impl Region {
+    /// Locates `part`, which must have been borrowed from `data`
+    fn within(data: &[u8], part: &[u8]) -> Region {
+        Region {
+            offset: part.as_ptr() as usize - data.as_ptr() as usize,
+            length: part.len(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct HashTableRegion {
+    #[serde(flatten)]
+    region: Region,
+    level: HashTableLevel,
+}
+
+/// Returned by [`StfsHandle::regions`]
+#[derive(Serialize)]
+struct PackageRegions {
+    thumbnail_image: Region,
+    title_image: Region,
+    hash_tables: Vec<HashTableRegion>,
+}
+
+impl PackageRegions {
+    fn new(data: &[u8], package: &StfsPackage<'_>) -> Self {
+        let hash_tables = package
+            .region_map()
+            .into_iter()
+            .filter_map(|region| match region.kind {
+                RegionKind::HashTable(level) => Some(HashTableRegion {
+                    region: Region {
+                        offset: region.range.start,
+                        length: region.range.len(),
+                    },
+                    level,
+                }),
+                _ => None,
+            })
+            .collect();
+
+        PackageRegions {
+            thumbnail_image: Region::within(data, package.header.thumbnail_image),
+            title_image: Region::within(data, package.header.title_image),
+            hash_tables,
+        }
+    }
+}
+
+fn header_summary(data: &[u8]) -> Result<JsHeaderSummary, JsError> {
+    to_js(&HeaderSummary::new(&XContentHeader::parse(data)?))
+}
+
+fn package_info(package: &StfsPackage<'_>) -> Result<JsPackageInfo, JsError> {
+    to_js(&PackageInfo::new(package))
+}
+
+fn entry_infos(package: &StfsPackage<'_>) -> Result<JsEntryInfos, JsError> {
+    to_js(&EntryInfo::list(package))
+}
+
+#[derive(Deserialize)]
+struct LicensePatch {
+    index: usize,
+    /// Hex, since license values don't fit in a JavaScript number
+    license: String,
+    #[serde(default)]
+    bits: u32,
+    #[serde(default)]
+    flags: u32,
+}
+
+/// Header fields to overwrite, keyed by [`MetadataField`] name
+#[derive(Deserialize)]
+struct MetadataPatch {
+    #[serde(default)]
+    licenses: Vec<LicensePatch>,
+    #[serde(flatten)]
+    fields: BTreeMap<String, String>,
+}
+
+/// A parsed package which only converts the parts JavaScript asks for.
+///
+/// The package is reparsed on each call rather than kept alive, since
+/// `#[wasm_bindgen]` types can't borrow the data they were created from.
+#[wasm_bindgen]
+pub struct StfsHandle {
+    data: Vec<u8>,
+}
+
+#[wasm_bindgen]
+impl StfsHandle {
+    /// Takes ownership of `data`, checking that it's a valid package
+    #[wasm_bindgen(constructor)]
+    pub fn new(data: Vec<u8>) -> Result<StfsHandle, JsError> {
+        StfsPackage::try_from(data.as_slice())?;
+
+        Ok(StfsHandle { data })
+    }
+
+    /// Parses the package, leaving the file table until something needs it
+    fn package(&self) -> Result<StfsPackage<'_>, JsError> {
+        Ok(StfsPackage::parse_lazy(self.data.as_slice())?)
+    }
+
+    /// The package type, content type, and every [`MetadataField`], without the images
+    pub fn header(&self) -> Result<JsHeaderSummary, JsError> {
+        header_summary(&self.data)
+    }
+
+    /// The package's header and hash tree details, in the same layout as the
+    /// command line tool's `info --format json`
+    pub fn info(&self) -> Result<JsPackageInfo, JsError> {
+        package_info(&self.package()?)
+    }
+
+    /// Every file and folder in the package, with folders preceding their contents
+    #[wasm_bindgen(js_name = listFiles)]
+    pub fn list_files(&self) -> Result<JsEntryInfos, JsError> {
+        entry_infos(&self.package()?)
+    }
+
+    /// The contents of the file at `path`
+    #[wasm_bindgen(js_name = readFile)]
+    pub fn read_file(
+        &self,
+        path: &str,
+        on_progress: Option<JsProgressCallback>,
+    ) -> Result<Vec<u8>, JsError> {
+        read_file(&self.package()?, path, on_progress.as_ref())
+    }
+
+    /// Up to `length` bytes of the file at `path`, starting `offset` bytes in.
+    /// Reading a large file a chunk at a time avoids holding all of it in wasm
+    /// memory at once. The result is empty past the end of the file.
+    #[wasm_bindgen(js_name = readFileChunk)]
+    pub fn read_file_chunk(
+        &self,
+        path: &str,
+        offset: usize,
+        length: usize,
+    ) -> Result<Vec<u8>, JsError> {
+        let package = self.package()?;
+        let entry = find_file(&package, path)?;
+
+        let mut reader = package.file_reader(&entry)?;
+        reader.seek(SeekFrom::Start(offset as u64))?;
+        let mut chunk = Vec::with_capacity(length.min(entry.file_size.saturating_sub(offset)));
+        reader.take(length as u64).read_to_end(&mut chunk)?;
+
+        Ok(chunk)
+    }
+
+    /// Offsets of the images and hash tables within the package's bytes
+    pub fn regions(&self) -> Result<JsPackageRegions, JsError> {
+        to_js(&PackageRegions::new(&self.data, &self.package()?))
+    }
+
+    /// Every structure in the package with its byte range, sorted by offset,
+    /// for annotating a hex view
+    #[wasm_bindgen(js_name = regionMap)]
+    pub fn region_map(&self) -> Result<JsRegionMap, JsError> {
+        to_js(&self.package()?.region_map())
+    }
+
+    /// Exports every file in the package as a zip archive. Requires the `zip` feature.
+    #[cfg(feature = "zip")]
+    #[wasm_bindgen(js_name = toZip)]
+    pub fn to_zip(&self, on_progress: Option<JsProgressCallback>) -> Result<Vec<u8>, JsError> {
+        write_zip(&self.package()?, on_progress.as_ref())
+    }
+
+    /// Checks the package's hash tree and signature, see [`verify`]
+    pub fn verify(&self) -> Result<JsVerifyReport, JsError> {
+        verify_package(&self.data)
+    }
+
+    /// Overwrites the header fields and licenses in `patch`. Either every change
+    /// is applied or, if any of them is invalid, none are.
+    #[wasm_bindgen(js_name = setMetadata)]
+    pub fn set_metadata(&mut self, patch: JsMetadataPatch) -> Result<(), JsError> {
+        let patch: MetadataPatch = serde_wasm_bindgen::from_value(patch.into())?;
+
+        let mut data = self.data.clone();
+        for (name, value) in &patch.fields {
+            crate::set_metadata(&mut data, name.parse()?, value)?;
+        }
+        for license in &patch.licenses {
+            let value = license.license.trim_start_matches("0x");
+            let value =
+                u64::from_str_radix(value, 16).map_err(|_| StfsError::InvalidMetadataValue {
+                    field: "license",
+                    value: license.license.clone(),
+                })?;
+            crate::set_license(&mut data, license.index, value, license.bits, license.flags)?;
+        }
+
+        self.data = data;
+        Ok(())
+    }
+
+    /// Rehashes the package and returns its bytes for download. CON packages
+    /// are resigned if a decrypted key vault is given; otherwise edits leave
+    /// their signature invalid.
+    pub fn save(&mut self, key_vault: Option<Vec<u8>>) -> Result<Vec<u8>, JsError> {
+        match key_vault {
+            Some(key_vault) => crate::resign(&mut self.data, &KeyVault::parse(&key_vault)?)?,
+            None => rehash(&mut self.data)?,
+        }
+
+        Ok(self.data.clone())
+    }
+
+    /// The package's thumbnail as PNG bytes, or an empty array if it has none
+    #[wasm_bindgen(js_name = thumbnailImage)]
+    pub fn thumbnail_image(&self) -> Result<Vec<u8>, JsError> {
+        let header = XContentHeader::parse(&self.data)?;
+
+        Ok(ImageKind::Thumbnail.get(&header).to_vec())
+    }
+
+    /// The game's icon as PNG bytes, or an empty array if the package has none
+    #[wasm_bindgen(js_name = titleImage)]
+    pub fn title_image(&self) -> Result<Vec<u8>, JsError> {
+        let header = XContentHeader::parse(&self.data)?;
+
+        Ok(ImageKind::Title.get(&header).to_vec())
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct LoadProgress {
+    bytes_received: usize,
+    total_size: usize,
+    header: Option<HeaderSummary>,
+}
+
+/// Assembles a package from chunks as they arrive, e.g. inside a Web Worker
+/// which is sent the file's `ArrayBuffer`s by the main thread.
+///
+/// The header is parsed as soon as it has been received, so metadata can be
+/// shown (or a non-package rejected) before the rest of the file arrives. The
+/// hash tables and file table are only parsed by [`StfsLoader::finish`], once
+/// every chunk has arrived. Each [`StfsLoader::push`] returns a plain object
+/// which can be passed straight to `postMessage`.
+#[wasm_bindgen]
+pub struct StfsLoader {
+    data: Vec<u8>,
+    total_size: usize,
+    header_parsed: bool,
+}
+
+#[wasm_bindgen]
+impl StfsLoader {
+    /// Prepares to receive a package which is `total_size` bytes long
+    #[wasm_bindgen(constructor)]
+    pub fn new(total_size: usize) -> StfsLoader {
+        StfsLoader {
+            data: Vec::with_capacity(total_size),
+            total_size,
+            header_parsed: false,
+        }
+    }
+
+    /// Appends the next chunk of the package. The result's `header` is set the
+    /// first time the whole header is available.
+    pub fn push(&mut self, chunk: JsChunk) -> Result<JsLoadProgress, JsError> {
+        let chunk = Uint8Array::new(&chunk);
+        let start = self.data.len();
+        let end = start + chunk.length() as usize;
+        if end > self.total_size {
+            return Err(JsError::new(&format!(
+                "received {} bytes of a {} byte package",
+                end, self.total_size
+            )));
+        }
+
+        self.data.resize(end, 0);
+        chunk.copy_to(&mut self.data[start..]);
+
+        // Parsing stops with `Truncated` once the magic has been read, until all
+        // `header_size` bytes of the header arrive
+        let mut header = None;
+        if !self.header_parsed && self.data.len() >= 4 {
+            match XContentHeader::parse(&self.data) {
+                Ok(parsed) => {
+                    header = Some(HeaderSummary::new(&parsed));
+                    self.header_parsed = true;
+                }
+                Err(StfsError::Truncated { .. }) => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        to_js(&LoadProgress {
+            bytes_received: end,
+            total_size: self.total_size,
+            header,
+        })
+    }
+
+    /// Parses the completed package, consuming the loader
+    pub fn finish(self) -> Result<StfsHandle, JsError> {
+        if self.data.len() < self.total_size {
+            return Err(StfsError::Truncated {
+                needed: self.total_size,
+                len: self.data.len(),
+            }
+            .into());
+        }
+
+        StfsHandle::new(self.data)
+    }
+}
+
+#[cfg(all(test, target_arch = "wasm32"))]
+mod tests {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::*;
+    use crate::{write::VOLUME_DESCRIPTOR_OFFSET, StfsPackageBuilder};
+
+    /// A package whose file table starts past the end of its volume
+    fn damaged_package() -> Vec<u8> {
+        let mut builder = StfsPackageBuilder::new();
+        builder.add_file("a.bin", vec![1; 0x10]).unwrap();
+        let mut data = builder.build().unwrap();
+        let offset = VOLUME_DESCRIPTOR_OFFSET + 0x5;
+        data[offset..offset + 3].copy_from_slice(&[0xFF, 0xFF, 0x00]);
+
+        data
+    }
+
+    fn message(err: JsError) -> String {
+        JsValue::from(err)
+            .dyn_into::<js_sys::Error>()
+            .unwrap()
+            .message()
+            .into()
+    }
+
+    #[wasm_bindgen_test]
+    fn damaged_packages_throw() {
+        let data = damaged_package();
+
+        let err = read_stfs_package(&data, None).err().unwrap();
+        assert!(message(err).contains("past the end of the volume"));
+        assert!(StfsHandle::new(data.clone()).is_err());
+        assert!(extract_file(&data, "a.bin", None).is_err());
+    }
+
+    #[wasm_bindgen_test]
+    fn loader_reports_installer_header_once_received() {
+        let data = crate::stfs::tests::installer_package();
+        let header_size = u32::from_be_bytes(
+            data[crate::write::HEADER_SIZE_OFFSET..][..4]
+                .try_into()
+                .unwrap(),
+        ) as usize;
+
+        let mut loader = StfsLoader::new(data.len());
+        let mut header_received_at = None;
+        for chunk in data.chunks(0x700) {
+            let progress = loader
+                .push(Uint8Array::from(chunk).unchecked_into())
+                .unwrap();
+            let received = js_sys::Reflect::get(&progress, &"bytesReceived".into())
+                .unwrap()
+                .as_f64()
+                .unwrap() as usize;
+            let header = js_sys::Reflect::get(&progress, &"header".into()).unwrap();
+            if header.is_object() {
+                assert!(header_received_at.is_none(), "header reported twice");
+                assert!(received >= header_size);
+                let title_id = js_sys::Reflect::get(&header, &"title_id".into()).unwrap();
+                assert_eq!(title_id.as_string().as_deref(), Some("00000000"));
+                header_received_at = Some(received);
+            }
+        }
+        assert!(header_received_at.unwrap() < header_size + 0x700);
+
+        let handle = loader.finish().unwrap();
+        assert!(handle.read_file("default.xex", None).is_ok());
+    }
+
+    #[wasm_bindgen_test]
+    fn truncated_packages_throw() {
+        let data = damaged_package();
+
+        let err = StfsHandle::new(data[..0x2000].to_vec()).err().unwrap();
+        assert!(message(err).contains("truncated"));
+    }
+}
@@ -0,0 +1,217 @@
+//! Browsing packages on a web server using HTTP range requests, which fill in
+//! a [`PartialMirror`] of the package.
+
+use std::{cell::RefCell, ops::Range, rc::Rc};
+
+use js_sys::{Object, Promise, Reflect, Uint8Array};
+use wasm_bindgen::{prelude::*, JsCast};
+use wasm_bindgen_futures::{future_to_promise, JsFuture};
+
+use super::{
+    entry_infos, find_file, header_summary, package_info, read_file, JsEntryInfos, JsHeaderSummary,
+    JsPackageInfo,
+};
+use crate::{
+    mirror::{file_ranges, metadata_ranges, PartialMirror, INITIAL_FETCH_SIZE},
+    stfs::{StfsError, StfsPackage},
+};
+
+#[wasm_bindgen]
+extern "C" {
+    #[wasm_bindgen(js_name = fetch)]
+    fn fetch_with_init(url: &str, init: &Object) -> Promise;
+
+    type Response;
+
+    #[wasm_bindgen(method, getter)]
+    fn status(this: &Response) -> u16;
+
+    #[wasm_bindgen(method, getter)]
+    fn headers(this: &Response) -> Headers;
+
+    #[wasm_bindgen(method, js_name = arrayBuffer)]
+    fn array_buffer(this: &Response) -> Promise;
+
+    type Headers;
+
+    #[wasm_bindgen(method)]
+    fn get(this: &Headers, name: &str) -> Option<String>;
+
+    #[wasm_bindgen(typescript_type = "Promise<Uint8Array>")]
+    pub type JsBytesPromise;
+}
+
+/// Part of the package returned by the server
+struct Chunk {
+    offset: usize,
+    body: Vec<u8>,
+    /// The package's size, if the server reported it
+    total_len: Option<usize>,
+}
+
+/// Returns the total size from a `Content-Range` value such as `bytes 0-99/1234`
+fn content_range_total(content_range: &str) -> Option<usize> {
+    content_range.rsplit_once('/')?.1.trim().parse().ok()
+}
+
+/// Requests `range` from `url`. Servers which don't support range requests
+/// respond with the entire package, which is returned as a chunk at offset 0.
+async fn fetch_range(url: &str, range: Range<usize>) -> Result<Chunk, JsValue> {
+    let headers = Object::new();
+    Reflect::set(
+        &headers,
+        &"Range".into(),
+        &format!("bytes={}-{}", range.start, range.end - 1).into(),
+    )?;
+    let init = Object::new();
+    Reflect::set(&init, &"headers".into(), &headers)?;
+
+    let response: Response = JsFuture::from(fetch_with_init(url, &init))
+        .await?
+        .unchecked_into();
+    let status = response.status();
+    if status != 200 && status != 206 {
+        return Err(JsError::new(&format!("HTTP {} while fetching {}", status, url)).into());
+    }
+
+    let body = Uint8Array::new(&JsFuture::from(response.array_buffer()).await?).to_vec();
+    Ok(if status == 206 {
+        Chunk {
+            offset: range.start,
+            body,
+            total_len: response
+                .headers()
+                .get("Content-Range")
+                .as_deref()
+                .and_then(content_range_total),
+        }
+    } else {
+        Chunk {
+            offset: 0,
+            total_len: Some(body.len()),
+            body,
+        }
+    })
+}
+
+struct RemoteData {
+    url: String,
+    mirror: PartialMirror,
+}
+
+/// Fetches whatever `needed` asks for until parsing the package and calling
+/// `needed` again asks for nothing new
+async fn ensure(
+    state: &RefCell<RemoteData>,
+    needed: impl Fn(&StfsPackage<'_>) -> Result<Vec<Range<usize>>, StfsError>,
+) -> Result<(), JsValue> {
+    loop {
+        let (url, missing) = {
+            let state = state.borrow();
+            let package =
+                StfsPackage::try_from(state.mirror.data.as_slice()).map_err(JsError::from)?;
+            let needed = needed(&package).map_err(JsError::from)?;
+            (state.url.clone(), state.mirror.missing(needed))
+        };
+        if missing.is_empty() {
+            return Ok(());
+        }
+
+        let fetched_before = state.borrow().mirror.fetched_pages();
+        for range in missing {
+            let chunk = fetch_range(&url, range).await?;
+            state.borrow_mut().mirror.fill(chunk.offset, &chunk.body);
+        }
+        if state.borrow().mirror.fetched_pages() == fetched_before {
+            return Err(JsError::new("the server returned less data than was requested").into());
+        }
+    }
+}
+
+/// A package on a web server, read with HTTP range requests so that only the
+/// metadata and the files asked for are downloaded.
+///
+/// The server must allow the `Range` request header and, for cross-origin
+/// requests, expose the `Content-Range` response header. The package still
+/// needs to fit in wasm's address space, since blocks are stored at their
+/// offsets in the file.
+#[wasm_bindgen]
+pub struct StfsRemote {
+    state: Rc<RefCell<RemoteData>>,
+}
+
+#[wasm_bindgen]
+impl StfsRemote {
+    /// Fetches the header, hash tables, and file table of the package at `url`
+    pub async fn open(url: String) -> Result<StfsRemote, JsValue> {
+        let first = fetch_range(&url, 0..INITIAL_FETCH_SIZE).await?;
+        let total_len = first.total_len.ok_or_else(|| {
+            JsError::new("the server didn't report the package's size in a Content-Range header")
+        })?;
+
+        let state = RefCell::new(RemoteData {
+            url,
+            mirror: PartialMirror::new(total_len),
+        });
+        state.borrow_mut().mirror.fill(first.offset, &first.body);
+        ensure(&state, metadata_ranges).await?;
+
+        Ok(StfsRemote {
+            state: Rc::new(state),
+        })
+    }
+
+    /// The package type, content type, and every metadata field, without the images
+    pub fn header(&self) -> Result<JsHeaderSummary, JsError> {
+        header_summary(&self.state.borrow().mirror.data)
+    }
+
+    /// The package's header and hash tree details
+    pub fn info(&self) -> Result<JsPackageInfo, JsError> {
+        package_info(&StfsPackage::parse_lazy(&self.state.borrow().mirror.data)?)
+    }
+
+    /// Every file and folder in the package, with folders preceding their contents
+    #[wasm_bindgen(js_name = listFiles)]
+    pub fn list_files(&self) -> Result<JsEntryInfos, JsError> {
+        entry_infos(&StfsPackage::try_from(
+            self.state.borrow().mirror.data.as_slice(),
+        )?)
+    }
+
+    /// Downloads the blocks of the file at `path` which haven't been fetched
+    /// yet and resolves to its contents
+    #[wasm_bindgen(js_name = readFile)]
+    pub fn read_file(&self, path: String) -> JsBytesPromise {
+        let state = self.state.clone();
+        let contents = async move {
+            let entry = {
+                let state = state.borrow();
+                let package =
+                    StfsPackage::try_from(state.mirror.data.as_slice()).map_err(JsError::from)?;
+                find_file(&package, &path).map_err(JsError::from)?
+            };
+            ensure(&state, |package| file_ranges(package, &entry)).await?;
+
+            let state = state.borrow();
+            let package =
+                StfsPackage::try_from(state.mirror.data.as_slice()).map_err(JsError::from)?;
+            let contents = read_file(&package, &path, None)?;
+
+            Ok(Uint8Array::from(contents.as_slice()).into())
+        };
+
+        future_to_promise(contents).unchecked_into()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn content_range_reports_total() {
+        assert_eq!(content_range_total("bytes 0-99/1234"), Some(1234));
+        assert_eq!(content_range_total("bytes 0-99/*"), None);
+    }
+}
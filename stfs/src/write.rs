@@ -0,0 +1,670 @@
+//! Low-level helpers for modifying package bytes in place.
+
+use std::ops::Range;
+
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use sha1::{Digest, Sha1};
+
+use crate::{
+    allocation::BLOCK_STATUS_ALLOCATED,
+    parallel,
+    progress::{CancellationToken, Progress, ProgressSink, HASHES_PER_PROGRESS_UPDATE},
+    stfs::{
+        hash_table_level, true_block_count, HashTableLevel, HashTableMeta, PackageType, StfsError,
+        StfsFileEntry, StfsPackage, StfsPackageSex, BLOCK_SIZE, HASHES_PER_HASH_TABLE,
+        HASHES_PER_HASH_TABLE_LEVEL, SIGNATURE_REGION,
+    },
+    verify::HashLocation,
+};
+
+/// 16 license entries of 0x10 bytes each
+pub(crate) const LICENSES_OFFSET: usize = 0x22C;
+pub(crate) const LICENSE_ENTRY_SIZE: usize = 0x10;
+pub(crate) const HEADER_HASH_OFFSET: usize = 0x32C;
+pub(crate) const HEADER_SIZE_OFFSET: usize = 0x340;
+/// Everything from here until the end of the header is covered by the header hash
+pub(crate) const CONTENT_TYPE_OFFSET: usize = 0x344;
+pub(crate) const METADATA_VERSION_OFFSET: usize = 0x348;
+pub(crate) const CONTENT_SIZE_OFFSET: usize = 0x34C;
+pub(crate) const MEDIA_ID_OFFSET: usize = 0x354;
+pub(crate) const TITLE_ID_OFFSET: usize = 0x360;
+pub(crate) const CONSOLE_ID_OFFSET: usize = 0x36C;
+pub(crate) const PROFILE_ID_OFFSET: usize = 0x371;
+pub(crate) const VOLUME_DESCRIPTOR_OFFSET: usize = 0x379;
+pub(crate) const BLOCK_SEPARATION_OFFSET: usize = VOLUME_DESCRIPTOR_OFFSET + 0x2;
+pub(crate) const FILE_TABLE_BLOCK_COUNT_OFFSET: usize = VOLUME_DESCRIPTOR_OFFSET + 0x3;
+pub(crate) const TOP_HASH_TABLE_HASH_OFFSET: usize = VOLUME_DESCRIPTOR_OFFSET + 0x8;
+pub(crate) const ALLOCATED_BLOCK_COUNT_OFFSET: usize = VOLUME_DESCRIPTOR_OFFSET + 0x1C;
+pub(crate) const UNALLOCATED_BLOCK_COUNT_OFFSET: usize = VOLUME_DESCRIPTOR_OFFSET + 0x20;
+pub(crate) const FILESYSTEM_TYPE_OFFSET: usize = 0x3A9;
+/// Avatar item or video metadata, depending on the content type
+pub(crate) const CONTENT_METADATA_OFFSET: usize = 0x3D9;
+pub(crate) const DEVICE_ID_OFFSET: usize = 0x3FD;
+pub(crate) const DISPLAY_NAME_OFFSET: usize = 0x411;
+pub(crate) const DISPLAY_DESCRIPTION_OFFSET: usize = 0xD11;
+pub(crate) const PUBLISHER_NAME_OFFSET: usize = 0x1611;
+pub(crate) const TITLE_NAME_OFFSET: usize = 0x1691;
+pub(crate) const TRANSFER_FLAGS_OFFSET: usize = 0x1711;
+pub(crate) const THUMBNAIL_IMAGE_SIZE_OFFSET: usize = 0x1712;
+pub(crate) const TITLE_THUMBNAIL_IMAGE_SIZE_OFFSET: usize = 0x1716;
+pub(crate) const THUMBNAIL_IMAGE_OFFSET: usize = 0x171A;
+pub(crate) const TITLE_THUMBNAIL_IMAGE_OFFSET: usize = 0x571A;
+/// Only present in headers large enough to hold installer metadata
+pub(crate) const INSTALLER_TYPE_OFFSET: usize = 0x971A;
+
+pub(crate) const HASH_ENTRY_SIZE: usize = 0x18;
+pub(crate) const FILE_TABLE_ENTRY_SIZE: usize = 0x40;
+pub(crate) const FILE_TABLE_ENTRIES_PER_BLOCK: usize = BLOCK_SIZE / FILE_TABLE_ENTRY_SIZE;
+pub(crate) const MAX_FILE_NAME_LEN: usize = 0x28;
+/// Path indicator for entries in the root of the package
+pub(crate) const ROOT_PATH_INDICATOR: u16 = 0xFFFF;
+/// `next_block` of the last block in a chain
+pub(crate) const END_OF_CHAIN: u32 = 0xFF_FFFF;
+
+/// Size of a single locale's string in the display name/description tables
+pub(crate) const LOCALIZED_STRING_SIZE: usize = 0x80;
+/// Display names and descriptions are stored once for each of these locales
+pub(crate) const LOCALE_COUNT: usize = 18;
+pub(crate) const MAX_IMAGE_SIZE: usize = 0x4000;
+
+/// Splits a `/` or `\\` separated package path into its components, checking
+/// that each is a valid file name
+pub(crate) fn path_components(path: &str) -> Result<Vec<&str>, StfsError> {
+    let components: Vec<&str> = path
+        .split(['/', '\\'])
+        .filter(|component| !component.is_empty())
+        .collect();
+
+    let is_valid = |component: &&str| {
+        component.is_ascii()
+            && component.len() <= MAX_FILE_NAME_LEN
+            && *component != "."
+            && *component != ".."
+    };
+    if components.is_empty() || !components.iter().all(is_valid) {
+        return Err(StfsError::InvalidFileName(path.to_owned()));
+    }
+
+    Ok(components)
+}
+
+/// Serializes `entry` into a 0x40 byte file table entry
+pub(crate) fn write_file_entry(entry_data: &mut [u8], entry: &StfsFileEntry) {
+    entry_data.fill(0);
+    entry_data[..entry.name.len()].copy_from_slice(entry.name.as_bytes());
+    entry_data[0x28] = (entry.name.len() as u8) | (entry.flags.bits() << 6);
+    LittleEndian::write_u24(&mut entry_data[0x29..], entry.block_count as u32);
+    LittleEndian::write_u24(&mut entry_data[0x2C..], entry.block_count as u32);
+    LittleEndian::write_u24(&mut entry_data[0x2F..], entry.starting_block_num as u32);
+    BigEndian::write_u16(&mut entry_data[0x32..], entry.path_indicator);
+    BigEndian::write_u32(&mut entry_data[0x34..], entry.file_size as u32);
+    BigEndian::write_u32(&mut entry_data[0x38..], entry.created_time_stamp);
+    BigEndian::write_u32(&mut entry_data[0x3C..], entry.access_time_stamp);
+}
+
+/// Marks the block described by a level 0 hash entry as allocated and links it to
+/// the next block in its chain, or ends the chain if `next_block` is `None`
+pub(crate) fn link_hash_entry(entry: &mut [u8], next_block: Option<usize>) {
+    entry[0x14] = BLOCK_STATUS_ALLOCATED;
+    BigEndian::write_u24(
+        &mut entry[0x15..],
+        next_block.map(|block| block as u32).unwrap_or(END_OF_CHAIN),
+    );
+}
+
+/// Writes `value` as a null-terminated big-endian UTF-16 string, clearing the
+/// rest of the `max_len`-byte field
+pub(crate) fn write_utf16_str(
+    data: &mut [u8],
+    offset: usize,
+    max_len: usize,
+    field: &'static str,
+    value: &str,
+) -> Result<(), StfsError> {
+    let encoded: Vec<u16> = value.encode_utf16().collect();
+    // Leave room for the null terminator
+    if (encoded.len() + 1) * 2 > max_len {
+        return Err(StfsError::FieldTooLong {
+            field,
+            max: max_len - 2,
+        });
+    }
+
+    let field_data = &mut data[offset..offset + max_len];
+    field_data.fill(0);
+    for (chunk, c) in field_data.chunks_exact_mut(2).zip(encoded) {
+        BigEndian::write_u16(chunk, c);
+    }
+
+    Ok(())
+}
+
+/// Replaces one of the header's images and its size field
+pub(crate) fn write_image(
+    data: &mut [u8],
+    size_offset: usize,
+    image_offset: usize,
+    field: &'static str,
+    image: &[u8],
+) -> Result<(), StfsError> {
+    if image.len() > MAX_IMAGE_SIZE {
+        return Err(StfsError::FieldTooLong {
+            field,
+            max: MAX_IMAGE_SIZE,
+        });
+    }
+
+    BigEndian::write_u32(&mut data[size_offset..], image.len() as u32);
+    let image_data = &mut data[image_offset..image_offset + MAX_IMAGE_SIZE];
+    image_data.fill(0);
+    image_data[..image.len()].copy_from_slice(image);
+
+    Ok(())
+}
+
+/// A single step of rebuilding the hash tree: the SHA-1 of `source` is stored at `destination`
+pub(crate) struct HashStep {
+    pub(crate) location: HashLocation,
+    pub(crate) source: Range<usize>,
+    pub(crate) destination: usize,
+}
+
+/// Computes every hash that makes up the package's hash tree, grouped so that
+/// each group only depends on data written by the groups before it.
+pub(crate) fn hash_tree_plan(package: &StfsPackage<'_>) -> Result<Vec<Vec<HashStep>>, StfsError> {
+    let meta = &package.hash_table_meta;
+    let stfs_vol = package.header.volume_descriptor.stfs_ref();
+    let allocated_block_count = stfs_vol.allocated_block_count as usize;
+
+    let mut plan = Vec::with_capacity(5);
+
+    // Data blocks are hashed into the level 0 tables
+    plan.push(
+        (0..allocated_block_count)
+            .map(|block| {
+                let address = package.block_to_addr(block)? as usize;
+                Ok(HashStep {
+                    location: HashLocation::DataBlock(block),
+                    source: address..address + BLOCK_SIZE,
+                    destination: package.block_hash_address(block, package.input)? as usize,
+                })
+            })
+            .collect::<Result<_, StfsError>>()?,
+    );
+
+    // Level 0 tables are hashed into the level 1 tables
+    if !matches!(meta.top_table.level, HashTableLevel::First) {
+        plan.push(
+            (0..meta.tables_per_level[0])
+                .map(|table| {
+                    let address = package
+                        .block_hash_address(table * HASHES_PER_HASH_TABLE, package.input)?
+                        as usize;
+                    Ok(HashStep {
+                        location: HashLocation::Level0Table(table),
+                        source: address..address + BLOCK_SIZE,
+                        destination: package.level1_table_address(table / HASHES_PER_HASH_TABLE)
+                            + ((table % HASHES_PER_HASH_TABLE) * HASH_ENTRY_SIZE),
+                    })
+                })
+                .collect::<Result<_, StfsError>>()?,
+        );
+    }
+
+    // Level 1 tables are hashed into the level 2 table
+    if matches!(meta.top_table.level, HashTableLevel::Third) {
+        plan.push(
+            (0..meta.tables_per_level[1])
+                .map(|table| {
+                    let address = package.level1_table_address(table);
+                    HashStep {
+                        location: HashLocation::Level1Table(table),
+                        source: address..address + BLOCK_SIZE,
+                        destination: meta.top_table.address_in_file + (table * HASH_ENTRY_SIZE),
+                    }
+                })
+                .collect(),
+        );
+    }
+
+    // The top table is hashed into the volume descriptor, which is then covered
+    // by the header hash
+    let top_table_address = meta.top_table.address_in_file;
+    plan.push(vec![HashStep {
+        location: HashLocation::TopTable,
+        source: top_table_address..top_table_address + BLOCK_SIZE,
+        destination: TOP_HASH_TABLE_HASH_OFFSET,
+    }]);
+    plan.push(vec![HashStep {
+        location: HashLocation::Header,
+        source: CONTENT_TYPE_OFFSET..meta.first_table_address,
+        destination: HEADER_HASH_OFFSET,
+    }]);
+
+    Ok(plan)
+}
+
+/// Recomputes every hash table entry, the top hash table hash, and the header hash.
+///
+/// The package signature is left untouched and will no longer be valid if
+/// anything covered by the header hash changed.
+pub fn rehash(data: &mut [u8]) -> Result<(), StfsError> {
+    rehash_with_progress(data, |_: Progress<'_>| {}, &CancellationToken::default())
+}
+
+/// Same as [`rehash`], periodically reporting the number of hashes and bytes
+/// hashed so far to `on_progress` and stopping if `cancel` is cancelled. A
+/// cancelled rehash leaves the hash tree partly updated.
+pub fn rehash_with_progress(
+    data: &mut [u8],
+    mut on_progress: impl ProgressSink,
+    cancel: &CancellationToken,
+) -> Result<(), StfsError> {
+    let plan = {
+        let package = StfsPackage::try_from(&*data)?;
+        hash_tree_plan(&package)?
+    };
+
+    let mut progress = Progress {
+        entry: None,
+        entries_done: 0,
+        entries_total: plan.iter().map(Vec::len).sum(),
+        bytes_done: 0,
+        bytes_total: plan
+            .iter()
+            .flatten()
+            .map(|step| step.source.len() as u64)
+            .sum(),
+    };
+
+    for steps in plan {
+        if let Some(needed) = steps.iter().map(|step| step.source.end).max() {
+            if needed > data.len() {
+                return Err(StfsError::Truncated {
+                    needed,
+                    len: data.len(),
+                });
+            }
+        }
+
+        // Steps within a group don't depend on each other, so the group can be
+        // split up for progress updates
+        let mut steps = steps.into_iter().peekable();
+        while steps.peek().is_some() {
+            cancel.check()?;
+            let chunk: Vec<HashStep> = steps.by_ref().take(HASHES_PER_PROGRESS_UPDATE).collect();
+            progress.entries_done += chunk.len();
+            progress.bytes_done += chunk
+                .iter()
+                .map(|step| step.source.len() as u64)
+                .sum::<u64>();
+
+            let source_data = &*data;
+            let digests = parallel::map_collect(chunk, |step| {
+                (step.destination, Sha1::digest(&source_data[step.source]))
+            });
+
+            for (destination, digest) in digests {
+                data[destination..destination + digest.len()].copy_from_slice(&digest);
+            }
+
+            on_progress.report(progress);
+        }
+    }
+
+    Ok(())
+}
+
+/// Blanks the package's signature and certificate and recomputes the hash tree.
+///
+/// The resulting package is marked as [`crate::XContentHeader::unsigned`] and will
+/// only be accepted by consoles or emulators (e.g. Xenia) which skip signature checks.
+pub fn strip_signature(data: &mut [u8]) -> Result<(), StfsError> {
+    if data.len() < SIGNATURE_REGION.end {
+        return Err(StfsError::Truncated {
+            needed: SIGNATURE_REGION.end,
+            len: data.len(),
+        });
+    }
+
+    data[SIGNATURE_REGION].fill(0);
+
+    rehash(data)
+}
+
+/// Changes the package's type, blanking its signature since it isn't valid for
+/// the new type. CON packages can then be signed with [`crate::resign`];
+/// LIVE and PIRS packages can only be signed by Microsoft and are left unsigned.
+pub fn convert(data: &mut [u8], to: PackageType) -> Result<(), StfsError> {
+    StfsPackage::try_from(&*data)?;
+
+    data[..4].copy_from_slice(&to.magic());
+
+    strip_signature(data)
+}
+
+/// Set in `block_separation` when the second copy of the top hash table is active
+const TOP_TABLE_COPY_FLAG: u8 = 2;
+/// Set in an upper-level hash entry's status when the second copy of the child table is active
+const CHILD_TABLE_COPY_FLAG: u8 = 0x40;
+
+/// Returns the file offset of the first copy of the first hash table at `level`
+fn first_table_address(
+    first_table_address: usize,
+    sex: StfsPackageSex,
+    level: HashTableLevel,
+) -> usize {
+    let meta = HashTableMeta {
+        block_step: sex.block_step(),
+        ..Default::default()
+    };
+
+    first_table_address
+        + (meta.compute_backing_hash_block_number_for_level(0, level, sex) * BLOCK_SIZE)
+}
+
+/// Updates the volume descriptor's block counts and the content size to match the
+/// package's new length
+fn write_block_counts(
+    data: &mut [u8],
+    first_table_address: usize,
+    allocated_block_count: usize,
+    unallocated_block_count: usize,
+) {
+    BigEndian::write_u32(
+        &mut data[ALLOCATED_BLOCK_COUNT_OFFSET..],
+        allocated_block_count as u32,
+    );
+    BigEndian::write_u32(
+        &mut data[UNALLOCATED_BLOCK_COUNT_OFFSET..],
+        unallocated_block_count as u32,
+    );
+
+    let content_size = (data.len() - first_table_address) as u64;
+    BigEndian::write_u64(&mut data[CONTENT_SIZE_OFFSET..], content_size);
+}
+
+/// The parts of a package's layout which are needed to resize it
+struct VolumeLayout {
+    sex: StfsPackageSex,
+    first_table_address: usize,
+    allocated_block_count: usize,
+    unallocated_block_count: usize,
+    top_level: HashTableLevel,
+    /// Whether the second copy of the top hash table is active
+    top_copy: bool,
+}
+
+impl VolumeLayout {
+    fn new(package: &StfsPackage<'_>) -> Self {
+        let stfs_vol = package.header.volume_descriptor.stfs_ref();
+
+        VolumeLayout {
+            sex: package.sex,
+            first_table_address: package.hash_table_meta.first_table_address,
+            allocated_block_count: stfs_vol.allocated_block_count as usize,
+            unallocated_block_count: stfs_vol.unallocated_block_count as usize,
+            top_level: package.hash_table_meta.top_table.level,
+            top_copy: stfs_vol.block_separation & TOP_TABLE_COPY_FLAG != 0,
+        }
+    }
+
+    /// Length of a package using this layout with `allocated_block_count` data blocks
+    fn package_len(&self, allocated_block_count: usize) -> usize {
+        self.first_table_address + (true_block_count(allocated_block_count, self.sex) * BLOCK_SIZE)
+    }
+}
+
+/// Returns the file offsets of the level 0 hash entries for `blocks`
+fn hash_entry_addresses(
+    package: &StfsPackage<'_>,
+    blocks: Range<usize>,
+) -> Result<Vec<Range<usize>>, StfsError> {
+    blocks
+        .map(|block| {
+            let address = package.block_hash_address(block, package.input)? as usize;
+            Ok(address..address + HASH_ENTRY_SIZE)
+        })
+        .collect()
+}
+
+/// Appends `count` free data blocks to the package, growing `data` to make room
+/// for them and for any hash tables they require. The hash tree is regenerated
+/// afterwards.
+///
+/// Returns the data block numbers of the new blocks.
+pub fn reserve_blocks(data: &mut Vec<u8>, count: usize) -> Result<Range<usize>, StfsError> {
+    let layout = VolumeLayout::new(&StfsPackage::try_from(data.as_slice())?);
+    let allocated_block_count = layout.allocated_block_count;
+
+    if count == 0 {
+        return Ok(allocated_block_count..allocated_block_count);
+    }
+
+    let new_allocated_block_count = allocated_block_count
+        .checked_add(count)
+        .filter(|count| *count <= HASHES_PER_HASH_TABLE_LEVEL[2])
+        .ok_or(StfsError::PackageTooLarge)?;
+    let new_top_level =
+        hash_table_level(new_allocated_block_count).ok_or(StfsError::PackageTooLarge)?;
+
+    let len = layout.package_len(new_allocated_block_count);
+    if data.len() < len {
+        data.resize(len, 0);
+    }
+
+    // Growing may require new tables above the old top table. The old top table's
+    // active copy is now selected by its parent entry rather than the volume
+    // descriptor, and the new top table always uses the first copy.
+    if new_top_level > layout.top_level {
+        let levels = [
+            HashTableLevel::First,
+            HashTableLevel::Second,
+            HashTableLevel::Third,
+        ];
+        for level in levels
+            .into_iter()
+            .filter(|level| *level > layout.top_level && *level <= new_top_level)
+        {
+            let table = first_table_address(layout.first_table_address, layout.sex, level);
+            data[table..table + BLOCK_SIZE].fill(0);
+
+            if layout.top_copy && level as usize == layout.top_level as usize + 1 {
+                data[table + 0x14] |= CHILD_TABLE_COPY_FLAG;
+            }
+        }
+
+        data[BLOCK_SEPARATION_OFFSET] &= !TOP_TABLE_COPY_FLAG;
+    }
+
+    write_block_counts(
+        data,
+        layout.first_table_address,
+        new_allocated_block_count,
+        layout.unallocated_block_count + count,
+    );
+
+    // Clear out the new blocks' hash entries so that they're marked as free
+    let entries = hash_entry_addresses(
+        &StfsPackage::try_from(data.as_slice())?,
+        allocated_block_count..new_allocated_block_count,
+    )?;
+    for entry in entries {
+        data[entry].fill(0);
+    }
+
+    rehash(data)?;
+
+    Ok(allocated_block_count..new_allocated_block_count)
+}
+
+/// Removes free data blocks from the end of the package, shrinking `data` and
+/// dropping any hash tables which are no longer required. The hash tree is
+/// regenerated afterwards.
+///
+/// Returns the number of data blocks which were removed.
+pub fn truncate_unused(data: &mut Vec<u8>) -> Result<usize, StfsError> {
+    let (layout, new_allocated_block_count, top_copy, removed_entries) = {
+        let package = StfsPackage::try_from(data.as_slice())?;
+        let layout = VolumeLayout::new(&package);
+        let allocator = package.block_allocator();
+
+        let mut new_allocated_block_count = layout.allocated_block_count;
+        // The file table always occupies at least one block
+        while new_allocated_block_count > 1
+            && !allocator.is_allocated(new_allocated_block_count - 1)
+        {
+            new_allocated_block_count -= 1;
+        }
+
+        if new_allocated_block_count == layout.allocated_block_count {
+            return Ok(0);
+        }
+
+        // If the tree loses a level, the active copy of the new top table was
+        // previously selected by its parent entry
+        let new_top_level =
+            hash_table_level(new_allocated_block_count).ok_or(StfsError::InvalidHeader)?;
+        let top_copy = if new_top_level == layout.top_level {
+            layout.top_copy
+        } else {
+            let active_address = match new_top_level {
+                HashTableLevel::First => package.block_hash_address(0, package.input)? as usize,
+                HashTableLevel::Second => package.level1_table_address(0),
+                HashTableLevel::Third => {
+                    unreachable!("the tree cannot lose a level and remain at the third level")
+                }
+            };
+
+            active_address
+                != first_table_address(layout.first_table_address, layout.sex, new_top_level)
+        };
+
+        let removed_entries = hash_entry_addresses(
+            &package,
+            new_allocated_block_count..layout.allocated_block_count,
+        )?;
+
+        (layout, new_allocated_block_count, top_copy, removed_entries)
+    };
+
+    let removed = layout.allocated_block_count - new_allocated_block_count;
+
+    // Hash tables which survive the truncation may still describe removed blocks
+    for entry in removed_entries {
+        if let Some(entry) = data.get_mut(entry) {
+            entry.fill(0);
+        }
+    }
+
+    data.truncate(layout.package_len(new_allocated_block_count));
+
+    if top_copy {
+        data[BLOCK_SEPARATION_OFFSET] |= TOP_TABLE_COPY_FLAG;
+    } else {
+        data[BLOCK_SEPARATION_OFFSET] &= !TOP_TABLE_COPY_FLAG;
+    }
+
+    write_block_counts(
+        data,
+        layout.first_table_address,
+        new_allocated_block_count,
+        layout.unallocated_block_count.saturating_sub(removed),
+    );
+
+    rehash(data)?;
+
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StfsPackageBuilder;
+
+    #[test]
+    fn strip_signature_marks_package_unsigned() {
+        let mut builder = StfsPackageBuilder::new();
+        builder.add_file("save.bin", vec![0x42; 0x1800]).unwrap();
+        let mut data = builder.build().unwrap();
+
+        // Pretend the package was signed
+        data[SIGNATURE_REGION].fill(0xAB);
+        assert!(
+            !StfsPackage::try_from(data.as_slice())
+                .unwrap()
+                .header
+                .unsigned
+        );
+
+        strip_signature(&mut data).unwrap();
+
+        let package = StfsPackage::try_from(data.as_slice()).unwrap();
+        assert!(package.header.unsigned);
+        assert!(data[SIGNATURE_REGION].iter().all(|b| *b == 0));
+
+        let first_table_address = package.hash_table_meta.first_table_address;
+        assert_eq!(
+            package.header.header_hash,
+            Sha1::digest(&data[CONTENT_TYPE_OFFSET..first_table_address]).as_slice()
+        );
+    }
+
+    #[test]
+    fn convert_changes_package_type() {
+        let mut builder = StfsPackageBuilder::new();
+        builder.add_file("save.bin", vec![0x42; 0x1800]).unwrap();
+        let mut data = builder.build().unwrap();
+
+        for to in [PackageType::Live, PackageType::Pirs, PackageType::Con] {
+            data[SIGNATURE_REGION].fill(0xAB);
+            convert(&mut data, to).unwrap();
+
+            let package = StfsPackage::try_from(data.as_slice()).unwrap();
+            assert_eq!(package.header.package_type, to);
+            assert!(package.header.unsigned);
+            assert!(package.verify_hashes().unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn reserve_and_truncate_round_trip() {
+        for sex in [StfsPackageSex::Female, StfsPackageSex::Male] {
+            let contents = vec![0x42; BLOCK_SIZE * 3];
+            let mut builder = StfsPackageBuilder::new();
+            builder
+                .sex(sex)
+                .add_file("save.bin", contents.clone())
+                .unwrap();
+            let original = builder.build().unwrap();
+
+            let mut data = original.clone();
+            // Enough blocks to require a level 1 hash table
+            let reserved = reserve_blocks(&mut data, 0x200).unwrap();
+            assert_eq!(reserved, 4..0x204);
+            assert!(data.len() > original.len());
+
+            let package = StfsPackage::try_from(data.as_slice()).unwrap();
+            assert_eq!(
+                package.hash_table_meta.top_table.level,
+                HashTableLevel::Second
+            );
+            let allocator = package.block_allocator();
+            assert_eq!(allocator.allocated_block_count(), 0x204);
+            assert_eq!(allocator.unallocated_block_count(), 0x200);
+            allocator.check_unallocated_block_count().unwrap();
+
+            let files = package.files();
+            let entry = files
+                .entry(files.children(crate::FileTable::ROOT)[0])
+                .clone();
+            let mut extracted = Vec::new();
+            package.extract_file(&mut extracted, &entry).unwrap();
+            assert_eq!(extracted, contents);
+
+            assert_eq!(truncate_unused(&mut data).unwrap(), 0x200);
+            assert_eq!(data, original);
+            assert_eq!(truncate_unused(&mut data).unwrap(), 0);
+        }
+    }
+}
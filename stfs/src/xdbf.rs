@@ -0,0 +1,501 @@
+//! Parsing XDBF databases: the GPD files which hold a profile's settings and
+//! per-title progress, and the SPA files which describe a title's achievements.
+//!
+//! An XDBF file starts with a table of entries, each identified by a namespace
+//! and a 64-bit ID, followed by a table of the free space left by deleted
+//! entries. Entries' records follow the two tables.
+
+use std::io::Cursor;
+
+use byteorder::{BigEndian, ByteOrder, ReadBytesExt};
+use chrono::{DateTime, NaiveDateTime};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+#[cfg(feature = "serde")]
+use crate::serialize::serialize_bytes;
+use crate::stfs::StfsError;
+
+pub const XDBF_MAGIC: [u8; 4] = *b"XDBF";
+const HEADER_SIZE: usize = 0x18;
+const ENTRY_SIZE: usize = 0x12;
+const FREE_SPACE_ENTRY_SIZE: usize = 0x8;
+/// The ID, type, and inline value of a setting record. String and binary
+/// values follow it.
+const SETTING_HEADER_SIZE: usize = 0x18;
+/// Seconds between the FILETIME epoch (1601-01-01) and the Unix epoch
+const FILETIME_UNIX_OFFSET: i64 = 11_644_473_600;
+
+/// Namespace of an [`XdbfEntry`]. The constants are the namespaces used by GPD
+/// files; SPA files use 1 for metadata sections, 2 for images, and 3 for string
+/// tables.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Namespace(pub u16);
+
+impl Namespace {
+    pub const ACHIEVEMENT: Namespace = Namespace(1);
+    pub const IMAGE: Namespace = Namespace(2);
+    pub const SETTING: Namespace = Namespace(3);
+    pub const TITLE: Namespace = Namespace(4);
+    pub const STRING: Namespace = Namespace(5);
+    pub const AVATAR_AWARD: Namespace = Namespace(6);
+}
+
+/// A record in an XDBF file
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct XdbfEntry {
+    pub namespace: Namespace,
+    pub id: u64,
+    /// Offset of the record from the end of the free space table
+    pub offset: u32,
+    pub length: u32,
+}
+
+impl XdbfEntry {
+    /// ID of the record in each GPD namespace which lists the entries changed
+    /// since the last sync with Xbox Live
+    pub const SYNC_LIST_ID: u64 = 0x1_0000_0000;
+    /// ID of the record in each GPD namespace which holds its sync counters
+    pub const SYNC_DATA_ID: u64 = 0x2_0000_0000;
+
+    /// Whether this is one of the sync bookkeeping records rather than an
+    /// achievement, setting, etc.
+    pub fn is_sync_record(&self) -> bool {
+        self.id == Self::SYNC_LIST_ID || self.id == Self::SYNC_DATA_ID
+    }
+}
+
+/// A gap left between records by deleted or shrunken entries
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct FreeSpaceEntry {
+    /// Offset from the end of the free space table
+    pub offset: u32,
+    pub length: u32,
+}
+
+/// The value of a [`Setting`]
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub enum SettingValue<'a> {
+    Context(u32),
+    Int32(i32),
+    Int64(i64),
+    Double(f64),
+    String(String),
+    Float(f32),
+    #[cfg_attr(feature = "serde", serde(serialize_with = "serialize_bytes"))]
+    Binary(&'a [u8]),
+    /// `None` if the setting was never set
+    DateTime(Option<NaiveDateTime>),
+    Null,
+}
+
+/// A record in a GPD's setting namespace
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Setting<'a> {
+    pub id: u32,
+    pub value: SettingValue<'a>,
+}
+
+impl<'a> Setting<'a> {
+    /// Parses the record of the setting `id`, found at `offset` in the file
+    fn parse(id: u32, record: &'a [u8], offset: usize) -> Result<Self, StfsError> {
+        if record.len() < SETTING_HEADER_SIZE {
+            return Err(StfsError::InvalidXdbf("setting record is too short"));
+        }
+
+        let inline = &record[0x10..];
+        // Strings and binary data are stored after the header, with their
+        // length in place of an inline value
+        let trailing = || {
+            let len = BigEndian::read_u32(inline) as usize;
+            record
+                .get(SETTING_HEADER_SIZE..SETTING_HEADER_SIZE + len)
+                .ok_or(StfsError::InvalidXdbf(
+                    "setting value is longer than its record",
+                ))
+        };
+
+        let value = match record[0x8] {
+            0 => SettingValue::Context(BigEndian::read_u32(inline)),
+            1 => SettingValue::Int32(BigEndian::read_i32(inline)),
+            2 => SettingValue::Int64(BigEndian::read_i64(inline)),
+            3 => SettingValue::Double(BigEndian::read_f64(inline)),
+            4 => SettingValue::String(utf16_be_str(trailing()?)),
+            5 => SettingValue::Float(BigEndian::read_f32(inline)),
+            6 => SettingValue::Binary(trailing()?),
+            7 => SettingValue::DateTime(filetime(BigEndian::read_u64(inline))),
+            0xFF => SettingValue::Null,
+            kind => {
+                return Err(StfsError::UnknownFieldValue {
+                    field: "setting type",
+                    offset: (offset + 0x8) as u64,
+                    value: kind.into(),
+                })
+            }
+        };
+
+        Ok(Setting { id, value })
+    }
+}
+
+/// Decodes a big-endian UTF-16 string, stopping at the first null character
+pub(crate) fn utf16_be_str(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(BigEndian::read_u16)
+        .take_while(|&unit| unit != 0)
+        .collect();
+
+    String::from_utf16_lossy(&units)
+}
+
+/// Decodes the null-terminated big-endian UTF-16 string at the start of
+/// `bytes`, and moves `bytes` past it. A missing terminator takes the rest of
+/// `bytes`.
+pub(crate) fn take_utf16_be_str(bytes: &mut &[u8]) -> String {
+    let len = bytes
+        .chunks_exact(2)
+        .position(|unit| unit == [0, 0])
+        .map_or(bytes.len(), |units| units * 2 + 2);
+    let (string, rest) = bytes.split_at(len);
+    *bytes = rest;
+
+    utf16_be_str(string)
+}
+
+/// Converts a Windows FILETIME, in 100ns intervals since 1601, to a date. Zero
+/// means unset.
+pub(crate) fn filetime(value: u64) -> Option<NaiveDateTime> {
+    if value == 0 {
+        return None;
+    }
+
+    let secs = (value / 10_000_000) as i64 - FILETIME_UNIX_OFFSET;
+    let nanos = (value % 10_000_000) as u32 * 100;
+    DateTime::from_timestamp(secs, nanos).map(|datetime| datetime.naive_utc())
+}
+
+/// A parsed XDBF file, borrowing its records from the input
+#[derive(Debug, Clone)]
+pub struct Xdbf<'a> {
+    pub version: u32,
+    /// Number of entries the entry table has room for
+    pub entry_table_length: u32,
+    /// Number of entries the free space table has room for
+    pub free_space_table_length: u32,
+    entries: Vec<XdbfEntry>,
+    free_space: Vec<FreeSpaceEntry>,
+    /// Offset of the first record, right after the free space table
+    data_start: usize,
+    data: &'a [u8],
+}
+
+impl<'a> TryFrom<&'a [u8]> for Xdbf<'a> {
+    type Error = StfsError;
+
+    fn try_from(input: &'a [u8]) -> Result<Self, Self::Error> {
+        if input.len() < HEADER_SIZE {
+            return Err(StfsError::Truncated {
+                needed: HEADER_SIZE,
+                len: input.len(),
+            });
+        }
+
+        let magic: [u8; 4] = input[..4].try_into().unwrap();
+        if magic != XDBF_MAGIC {
+            return Err(StfsError::InvalidMagic { found: magic });
+        }
+
+        let mut reader = Cursor::new(&input[4..HEADER_SIZE]);
+        let version = reader.read_u32::<BigEndian>()?;
+        let entry_table_length = reader.read_u32::<BigEndian>()?;
+        let entry_count = reader.read_u32::<BigEndian>()?;
+        let free_space_table_length = reader.read_u32::<BigEndian>()?;
+        let free_space_count = reader.read_u32::<BigEndian>()?;
+        if entry_count > entry_table_length || free_space_count > free_space_table_length {
+            return Err(StfsError::InvalidXdbf(
+                "table holds more entries than it has room for",
+            ));
+        }
+
+        // Computed in 64 bits so that huge table lengths can't overflow on
+        // 32-bit targets
+        let free_space_start =
+            HEADER_SIZE as u64 + u64::from(entry_table_length) * ENTRY_SIZE as u64;
+        let data_start =
+            free_space_start + u64::from(free_space_table_length) * FREE_SPACE_ENTRY_SIZE as u64;
+        if data_start > input.len() as u64 {
+            return Err(StfsError::Truncated {
+                needed: usize::try_from(data_start).unwrap_or(usize::MAX),
+                len: input.len(),
+            });
+        }
+        let free_space_start = free_space_start as usize;
+        let data_start = data_start as usize;
+        let data = &input[data_start..];
+
+        let mut reader = Cursor::new(&input[HEADER_SIZE..free_space_start]);
+        let entries = (0..entry_count)
+            .map(|_| {
+                let entry = XdbfEntry {
+                    namespace: Namespace(reader.read_u16::<BigEndian>()?),
+                    id: reader.read_u64::<BigEndian>()?,
+                    offset: reader.read_u32::<BigEndian>()?,
+                    length: reader.read_u32::<BigEndian>()?,
+                };
+                if u64::from(entry.offset) + u64::from(entry.length) > data.len() as u64 {
+                    return Err(StfsError::InvalidXdbf("entry lies outside of the file"));
+                }
+
+                Ok(entry)
+            })
+            .collect::<Result<Vec<_>, StfsError>>()?;
+
+        let mut reader = Cursor::new(&input[free_space_start..data_start]);
+        let free_space = (0..free_space_count)
+            .map(|_| {
+                Ok(FreeSpaceEntry {
+                    offset: reader.read_u32::<BigEndian>()?,
+                    length: reader.read_u32::<BigEndian>()?,
+                })
+            })
+            .collect::<Result<Vec<_>, StfsError>>()?;
+
+        Ok(Xdbf {
+            version,
+            entry_table_length,
+            free_space_table_length,
+            entries,
+            free_space,
+            data_start,
+            data,
+        })
+    }
+}
+
+impl<'a> Xdbf<'a> {
+    /// Every entry, in the order of the entry table
+    pub fn entries(&self) -> &[XdbfEntry] {
+        &self.entries
+    }
+
+    pub fn free_space(&self) -> &[FreeSpaceEntry] {
+        &self.free_space
+    }
+
+    /// The record of `entry`, which must be one of this file's entries
+    pub fn record(&self, entry: &XdbfEntry) -> &'a [u8] {
+        let start = entry.offset as usize;
+        &self.data[start..start + entry.length as usize]
+    }
+
+    /// The record with the given namespace and ID
+    pub fn find(&self, namespace: Namespace, id: u64) -> Option<&'a [u8]> {
+        self.entries
+            .iter()
+            .find(|entry| entry.namespace == namespace && entry.id == id)
+            .map(|entry| self.record(entry))
+    }
+
+    /// Every entry along with its record
+    pub fn iter(&self) -> impl Iterator<Item = (&XdbfEntry, &'a [u8])> + '_ {
+        self.entries.iter().map(|entry| (entry, self.record(entry)))
+    }
+
+    /// The entries of `namespace` along with their records, leaving out sync
+    /// records
+    pub fn namespace(
+        &self,
+        namespace: Namespace,
+    ) -> impl Iterator<Item = (&XdbfEntry, &'a [u8])> + '_ {
+        self.iter()
+            .filter(move |(entry, _)| entry.namespace == namespace && !entry.is_sync_record())
+    }
+
+    /// Every setting in a GPD
+    pub fn settings(&self) -> impl Iterator<Item = Result<Setting<'a>, StfsError>> + '_ {
+        self.namespace(Namespace::SETTING)
+            .map(|(entry, record)| self.parse_setting(entry, record))
+    }
+
+    /// The setting `id` of a GPD
+    pub fn setting(&self, id: u32) -> Option<Result<Setting<'a>, StfsError>> {
+        self.namespace(Namespace::SETTING)
+            .find(|(entry, _)| entry.id == u64::from(id))
+            .map(|(entry, record)| self.parse_setting(entry, record))
+    }
+
+    /// Every string in a GPD, along with its ID
+    pub fn strings(&self) -> impl Iterator<Item = (u64, String)> + '_ {
+        self.namespace(Namespace::STRING)
+            .map(|(entry, record)| (entry.id, utf16_be_str(record)))
+    }
+
+    pub fn string(&self, id: u64) -> Option<String> {
+        self.find(Namespace::STRING, id).map(utf16_be_str)
+    }
+
+    /// Every image, along with its ID. Images are usually PNGs.
+    pub fn images(&self) -> impl Iterator<Item = (u64, &'a [u8])> + '_ {
+        self.namespace(Namespace::IMAGE)
+            .map(|(entry, record)| (entry.id, record))
+    }
+
+    pub fn image(&self, id: u64) -> Option<&'a [u8]> {
+        self.find(Namespace::IMAGE, id)
+    }
+
+    /// Offset of `entry`'s record from the start of the file
+    pub(crate) fn record_offset(&self, entry: &XdbfEntry) -> usize {
+        self.data_start + entry.offset as usize
+    }
+
+    fn parse_setting(&self, entry: &XdbfEntry, record: &'a [u8]) -> Result<Setting<'a>, StfsError> {
+        let id = u32::try_from(entry.id)
+            .map_err(|_| StfsError::InvalidXdbf("setting ID is wider than 32 bits"))?;
+        Setting::parse(id, record, self.record_offset(entry))
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use byteorder::WriteBytesExt;
+
+    use super::*;
+
+    /// Lays out an XDBF file holding `records`, with room for two more entries
+    /// and one free space entry covering the end of the file
+    pub(crate) fn build_xdbf(records: &[(Namespace, u64, Vec<u8>)]) -> Vec<u8> {
+        let entry_table_length = records.len() as u32 + 2;
+        let mut out = Vec::new();
+        out.extend_from_slice(&XDBF_MAGIC);
+        for value in [0x10000, entry_table_length, records.len() as u32, 1, 1] {
+            out.write_u32::<BigEndian>(value).unwrap();
+        }
+
+        let mut offset = 0;
+        for (namespace, id, record) in records {
+            out.write_u16::<BigEndian>(namespace.0).unwrap();
+            out.write_u64::<BigEndian>(*id).unwrap();
+            out.write_u32::<BigEndian>(offset).unwrap();
+            out.write_u32::<BigEndian>(record.len() as u32).unwrap();
+            offset += record.len() as u32;
+        }
+        out.resize(HEADER_SIZE + entry_table_length as usize * ENTRY_SIZE, 0);
+        out.write_u32::<BigEndian>(offset).unwrap();
+        out.write_u32::<BigEndian>(u32::MAX - offset).unwrap();
+
+        for (_, _, record) in records {
+            out.extend_from_slice(record);
+        }
+
+        out
+    }
+
+    pub(crate) fn utf16_be(value: &str) -> Vec<u8> {
+        value
+            .encode_utf16()
+            .chain([0])
+            .flat_map(u16::to_be_bytes)
+            .collect()
+    }
+
+    /// A setting record of type `kind` with the given inline value and
+    /// trailing data
+    pub(crate) fn setting_record(id: u32, kind: u8, inline: [u8; 8], trailing: &[u8]) -> Vec<u8> {
+        let mut record = Vec::new();
+        record.write_u64::<BigEndian>(id.into()).unwrap();
+        record.push(kind);
+        record.extend_from_slice(&[0; 7]);
+        record.extend_from_slice(&inline);
+        record.extend_from_slice(trailing);
+        record
+    }
+
+    #[test]
+    fn parses_gpd_records() {
+        let gamertag = utf16_be("Major Nelson");
+        let mut gamertag_len = [0; 8];
+        BigEndian::write_u32(&mut gamertag_len, gamertag.len() as u32);
+        // 2010-01-01 00:00:00
+        let filetime = (1_262_304_000 + FILETIME_UNIX_OFFSET as u64) * 10_000_000;
+
+        let data = build_xdbf(&[
+            (Namespace::IMAGE, 0x8000, vec![0x89, b'P', b'N', b'G']),
+            (
+                Namespace::SETTING,
+                0x4064000F,
+                setting_record(0x4064000F, 4, gamertag_len, &gamertag),
+            ),
+            (
+                Namespace::SETTING,
+                0x10040038,
+                setting_record(0x10040038, 1, [0, 0, 0, 42, 0, 0, 0, 0], &[]),
+            ),
+            (
+                Namespace::SETTING,
+                0x70080011,
+                setting_record(0x70080011, 7, filetime.to_be_bytes(), &[]),
+            ),
+            (Namespace::SETTING, XdbfEntry::SYNC_LIST_ID, vec![0; 0x10]),
+            (Namespace::STRING, 1, utf16_be("Hello")),
+        ]);
+        let xdbf = Xdbf::try_from(data.as_slice()).unwrap();
+
+        assert_eq!(xdbf.version, 0x10000);
+        assert_eq!(xdbf.entries().len(), 6);
+        assert_eq!(xdbf.free_space().len(), 1);
+        assert_eq!(xdbf.image(0x8000).unwrap(), b"\x89PNG");
+        assert_eq!(
+            xdbf.strings().collect::<Vec<_>>(),
+            [(1, "Hello".to_owned())]
+        );
+
+        let settings: Vec<Setting> = xdbf.settings().collect::<Result<_, _>>().unwrap();
+        assert_eq!(
+            settings,
+            [
+                Setting {
+                    id: 0x4064000F,
+                    value: SettingValue::String("Major Nelson".to_owned()),
+                },
+                Setting {
+                    id: 0x10040038,
+                    value: SettingValue::Int32(42),
+                },
+                Setting {
+                    id: 0x70080011,
+                    value: SettingValue::DateTime(Some(
+                        DateTime::from_timestamp(1_262_304_000, 0)
+                            .unwrap()
+                            .naive_utc()
+                    )),
+                },
+            ]
+        );
+        assert_eq!(
+            xdbf.setting(0x10040038).unwrap().unwrap().value,
+            SettingValue::Int32(42)
+        );
+    }
+
+    #[test]
+    fn rejects_entries_outside_of_the_file() {
+        let mut data = build_xdbf(&[(Namespace::STRING, 1, utf16_be("Hello"))]);
+        data.truncate(data.len() - 2);
+        assert!(matches!(
+            Xdbf::try_from(data.as_slice()),
+            Err(StfsError::InvalidXdbf(_))
+        ));
+
+        assert!(matches!(
+            Xdbf::try_from(&b"XDBX"[..]),
+            Err(StfsError::Truncated { .. })
+        ));
+    }
+}
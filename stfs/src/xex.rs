@@ -0,0 +1,549 @@
+//! Parsing the headers of XEX2 executables, such as the `default.xex` of
+//! installed games and arcade titles.
+//!
+//! Only the plain header and its optional headers are read. The PE image
+//! following them is usually encrypted and compressed, and isn't touched.
+
+use std::{
+    fmt,
+    io::{Cursor, Read},
+};
+
+use byteorder::{BigEndian, ByteOrder, ReadBytesExt};
+#[cfg(feature = "serde")]
+use serde::Serialize;
+
+use crate::{
+    display::{MediaId, TitleId},
+    stfs::{StfsError, StfsFileEntry, StfsPackage},
+};
+
+pub const XEX2_MAGIC: [u8; 4] = *b"XEX2";
+const HEADER_SIZE: usize = 0x18;
+const OPTIONAL_HEADER_SIZE: usize = 8;
+const EXECUTION_INFO_SIZE: usize = 0x18;
+/// Offset of the image's load address within the security info
+const SECURITY_LOAD_ADDRESS_OFFSET: usize = 0x110;
+
+bitflags::bitflags! {
+    /// What kind of module an executable is
+    #[derive(Default)]
+    #[cfg_attr(feature = "serde", derive(Serialize), serde(transparent))]
+    pub struct ModuleFlags: u32 {
+        const TITLE = 0x1;
+        const EXPORTS_TO_TITLE = 0x2;
+        const SYSTEM_DEBUGGER = 0x4;
+        const DLL_MODULE = 0x8;
+        const MODULE_PATCH = 0x10;
+        const PATCH_FULL = 0x20;
+        const PATCH_DELTA = 0x40;
+        const USER_MODE = 0x80;
+    }
+}
+
+/// Identifies an optional header. The low byte is the size of its data in
+/// 32-bit words: 0 or 1 for values stored in the header directory itself, or
+/// 0xFF for data which starts with its own size.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize), serde(transparent))]
+pub struct HeaderKey(pub u32);
+
+impl HeaderKey {
+    pub const RESOURCE_INFO: HeaderKey = HeaderKey(0x000002FF);
+    pub const FILE_FORMAT_INFO: HeaderKey = HeaderKey(0x000003FF);
+    pub const DELTA_PATCH_DESCRIPTOR: HeaderKey = HeaderKey(0x000005FF);
+    pub const BOUNDING_PATH: HeaderKey = HeaderKey(0x000080FF);
+    pub const ORIGINAL_BASE_ADDRESS: HeaderKey = HeaderKey(0x00010001);
+    pub const ENTRY_POINT: HeaderKey = HeaderKey(0x00010100);
+    pub const IMAGE_BASE_ADDRESS: HeaderKey = HeaderKey(0x00010201);
+    pub const IMPORT_LIBRARIES: HeaderKey = HeaderKey(0x000103FF);
+    pub const CHECKSUM_TIMESTAMP: HeaderKey = HeaderKey(0x00018002);
+    pub const ORIGINAL_PE_NAME: HeaderKey = HeaderKey(0x000183FF);
+    pub const STATIC_LIBRARIES: HeaderKey = HeaderKey(0x000200FF);
+    pub const TLS_INFO: HeaderKey = HeaderKey(0x00020104);
+    pub const DEFAULT_STACK_SIZE: HeaderKey = HeaderKey(0x00020200);
+    pub const DEFAULT_FILESYSTEM_CACHE_SIZE: HeaderKey = HeaderKey(0x00020301);
+    pub const DEFAULT_HEAP_SIZE: HeaderKey = HeaderKey(0x00020401);
+    pub const SYSTEM_FLAGS: HeaderKey = HeaderKey(0x00030000);
+    pub const EXECUTION_INFO: HeaderKey = HeaderKey(0x00040006);
+    pub const TITLE_WORKSPACE_SIZE: HeaderKey = HeaderKey(0x00040201);
+    pub const GAME_RATINGS: HeaderKey = HeaderKey(0x00040310);
+    pub const LAN_KEY: HeaderKey = HeaderKey(0x00040404);
+    pub const XBOX360_LOGO: HeaderKey = HeaderKey(0x000405FF);
+    pub const MULTIDISC_MEDIA_IDS: HeaderKey = HeaderKey(0x000406FF);
+    pub const ALTERNATE_TITLE_IDS: HeaderKey = HeaderKey(0x000407FF);
+    pub const ADDITIONAL_TITLE_MEMORY: HeaderKey = HeaderKey(0x00040801);
+
+    /// Name of the header, if it's one of the known ones
+    pub fn name(self) -> Option<&'static str> {
+        let name = match self {
+            HeaderKey::RESOURCE_INFO => "resource info",
+            HeaderKey::FILE_FORMAT_INFO => "file format info",
+            HeaderKey::DELTA_PATCH_DESCRIPTOR => "delta patch descriptor",
+            HeaderKey::BOUNDING_PATH => "bounding path",
+            HeaderKey::ORIGINAL_BASE_ADDRESS => "original base address",
+            HeaderKey::ENTRY_POINT => "entry point",
+            HeaderKey::IMAGE_BASE_ADDRESS => "image base address",
+            HeaderKey::IMPORT_LIBRARIES => "import libraries",
+            HeaderKey::CHECKSUM_TIMESTAMP => "checksum and timestamp",
+            HeaderKey::ORIGINAL_PE_NAME => "original PE name",
+            HeaderKey::STATIC_LIBRARIES => "static libraries",
+            HeaderKey::TLS_INFO => "TLS info",
+            HeaderKey::DEFAULT_STACK_SIZE => "default stack size",
+            HeaderKey::DEFAULT_FILESYSTEM_CACHE_SIZE => "default filesystem cache size",
+            HeaderKey::DEFAULT_HEAP_SIZE => "default heap size",
+            HeaderKey::SYSTEM_FLAGS => "system flags",
+            HeaderKey::EXECUTION_INFO => "execution info",
+            HeaderKey::TITLE_WORKSPACE_SIZE => "title workspace size",
+            HeaderKey::GAME_RATINGS => "game ratings",
+            HeaderKey::LAN_KEY => "LAN key",
+            HeaderKey::XBOX360_LOGO => "Xbox 360 logo",
+            HeaderKey::MULTIDISC_MEDIA_IDS => "multi-disc media IDs",
+            HeaderKey::ALTERNATE_TITLE_IDS => "alternate title IDs",
+            HeaderKey::ADDITIONAL_TITLE_MEMORY => "additional title memory",
+            _ => return None,
+        };
+
+        Some(name)
+    }
+
+    fn is_inline(self) -> bool {
+        self.0 & 0xFF <= 1
+    }
+}
+
+/// The value of an optional header
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum HeaderValue<'a> {
+    /// A value small enough to be stored in the header directory
+    Inline(u32),
+    /// Data stored elsewhere in the headers. Variable-length data doesn't
+    /// include its size prefix.
+    Data(&'a [u8]),
+}
+
+/// An entry of the optional header directory
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct OptionalHeader<'a> {
+    pub key: HeaderKey,
+    pub value: HeaderValue<'a>,
+}
+
+/// A version number packed as 4 bits of major and minor version, 16 bits of
+/// build number, and 8 bits of QFE. Formats as `major.minor.build.qfe`.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize), serde(transparent))]
+pub struct XexVersion(pub u32);
+
+impl XexVersion {
+    pub fn major(self) -> u8 {
+        (self.0 >> 28) as u8
+    }
+
+    pub fn minor(self) -> u8 {
+        ((self.0 >> 24) & 0xF) as u8
+    }
+
+    pub fn build(self) -> u16 {
+        (self.0 >> 8) as u16
+    }
+
+    pub fn qfe(self) -> u8 {
+        self.0 as u8
+    }
+}
+
+impl fmt::Display for XexVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}.{}.{}.{}",
+            self.major(),
+            self.minor(),
+            self.build(),
+            self.qfe()
+        )
+    }
+}
+
+/// The execution info optional header, identifying the game an executable
+/// belongs to
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct ExecutionInfo {
+    pub media_id: MediaId,
+    pub version: XexVersion,
+    pub base_version: XexVersion,
+    pub title_id: TitleId,
+    pub platform: u8,
+    pub executable_type: u8,
+    /// Starts from 1
+    pub disc_number: u8,
+    pub disc_count: u8,
+    pub savegame_id: u32,
+}
+
+impl ExecutionInfo {
+    fn parse(data: &[u8]) -> Result<Self, StfsError> {
+        if data.len() < EXECUTION_INFO_SIZE {
+            return Err(StfsError::InvalidXex("execution info is too short"));
+        }
+
+        Ok(ExecutionInfo {
+            media_id: MediaId(BigEndian::read_u32(data)),
+            version: XexVersion(BigEndian::read_u32(&data[0x4..])),
+            base_version: XexVersion(BigEndian::read_u32(&data[0x8..])),
+            title_id: TitleId(BigEndian::read_u32(&data[0xC..])),
+            platform: data[0x10],
+            executable_type: data[0x11],
+            disc_number: data[0x12],
+            disc_count: data[0x13],
+            savegame_id: BigEndian::read_u32(&data[0x14..]),
+        })
+    }
+}
+
+/// The parsed headers of an XEX2 executable, borrowing optional header data
+/// from the input
+#[derive(Debug, Clone)]
+pub struct Xex<'a> {
+    pub module_flags: ModuleFlags,
+    /// Offset of the PE image, which is also the size of the headers
+    pub pe_data_offset: u32,
+    pub security_info_offset: u32,
+    optional_headers: Vec<OptionalHeader<'a>>,
+    data: &'a [u8],
+}
+
+impl<'a> TryFrom<&'a [u8]> for Xex<'a> {
+    type Error = StfsError;
+
+    fn try_from(input: &'a [u8]) -> Result<Self, Self::Error> {
+        if input.len() < HEADER_SIZE {
+            return Err(StfsError::Truncated {
+                needed: HEADER_SIZE,
+                len: input.len(),
+            });
+        }
+
+        let magic: [u8; 4] = input[..4].try_into().unwrap();
+        if magic != XEX2_MAGIC {
+            return Err(StfsError::InvalidMagic { found: magic });
+        }
+
+        let mut reader = Cursor::new(&input[4..HEADER_SIZE]);
+        let module_flags = ModuleFlags::from_bits_truncate(reader.read_u32::<BigEndian>()?);
+        let pe_data_offset = reader.read_u32::<BigEndian>()?;
+        let _reserved = reader.read_u32::<BigEndian>()?;
+        let security_info_offset = reader.read_u32::<BigEndian>()?;
+        let optional_header_count = reader.read_u32::<BigEndian>()?;
+
+        let directory_end =
+            HEADER_SIZE as u64 + u64::from(optional_header_count) * OPTIONAL_HEADER_SIZE as u64;
+        if directory_end > input.len() as u64 {
+            return Err(StfsError::Truncated {
+                needed: usize::try_from(directory_end).unwrap_or(usize::MAX),
+                len: input.len(),
+            });
+        }
+
+        let out_of_bounds = || StfsError::InvalidXex("optional header lies outside of the file");
+        let mut reader = Cursor::new(&input[HEADER_SIZE..directory_end as usize]);
+        let optional_headers = (0..optional_header_count)
+            .map(|_| {
+                let key = HeaderKey(reader.read_u32::<BigEndian>()?);
+                let value = reader.read_u32::<BigEndian>()?;
+                if key.is_inline() {
+                    return Ok(OptionalHeader {
+                        key,
+                        value: HeaderValue::Inline(value),
+                    });
+                }
+
+                // Sliced in two steps so that huge offsets can't overflow on
+                // 32-bit targets
+                let data = input.get(value as usize..).ok_or_else(out_of_bounds)?;
+                let data = match key.0 & 0xFF {
+                    0xFF => {
+                        let size = data
+                            .get(..4)
+                            .map(BigEndian::read_u32)
+                            .ok_or_else(out_of_bounds)? as usize;
+                        data[4..].get(..size.saturating_sub(4))
+                    }
+                    words => data.get(..words as usize * 4),
+                }
+                .ok_or_else(out_of_bounds)?;
+
+                Ok(OptionalHeader {
+                    key,
+                    value: HeaderValue::Data(data),
+                })
+            })
+            .collect::<Result<Vec<_>, StfsError>>()?;
+
+        Ok(Xex {
+            module_flags,
+            pe_data_offset,
+            security_info_offset,
+            optional_headers,
+            data: input,
+        })
+    }
+}
+
+impl<'a> Xex<'a> {
+    /// Every optional header, in the order of the header directory
+    pub fn optional_headers(&self) -> &[OptionalHeader<'a>] {
+        &self.optional_headers
+    }
+
+    pub fn optional_header(&self, key: HeaderKey) -> Option<HeaderValue<'a>> {
+        self.optional_headers
+            .iter()
+            .find(|header| header.key == key)
+            .map(|header| header.value)
+    }
+
+    fn inline_value(&self, key: HeaderKey) -> Option<u32> {
+        match self.optional_header(key)? {
+            HeaderValue::Inline(value) => Some(value),
+            HeaderValue::Data(_) => None,
+        }
+    }
+
+    fn data(&self, key: HeaderKey) -> Option<&'a [u8]> {
+        match self.optional_header(key)? {
+            HeaderValue::Data(data) => Some(data),
+            HeaderValue::Inline(_) => None,
+        }
+    }
+
+    pub fn execution_info(&self) -> Option<Result<ExecutionInfo, StfsError>> {
+        self.data(HeaderKey::EXECUTION_INFO)
+            .map(ExecutionInfo::parse)
+    }
+
+    /// Address the image is loaded at, from its optional header or else the
+    /// security info
+    pub fn base_address(&self) -> Option<u32> {
+        self.inline_value(HeaderKey::IMAGE_BASE_ADDRESS)
+            .or_else(|| {
+                self.data
+                    .get(self.security_info_offset as usize..)?
+                    .get(SECURITY_LOAD_ADDRESS_OFFSET..SECURITY_LOAD_ADDRESS_OFFSET + 4)
+                    .map(BigEndian::read_u32)
+            })
+    }
+
+    pub fn entry_point(&self) -> Option<u32> {
+        self.inline_value(HeaderKey::ENTRY_POINT)
+    }
+
+    pub fn system_flags(&self) -> Option<u32> {
+        self.inline_value(HeaderKey::SYSTEM_FLAGS)
+    }
+
+    /// File name of the PE the executable was built from
+    pub fn original_pe_name(&self) -> Option<String> {
+        let data = self.data(HeaderKey::ORIGINAL_PE_NAME)?;
+        let len = data.iter().position(|&b| b == 0).unwrap_or(data.len());
+        Some(String::from_utf8_lossy(&data[..len]).into_owned())
+    }
+}
+
+/// An optional header as listed by [`XexInfo`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct OptionalHeaderInfo {
+    pub key: HeaderKey,
+    pub name: Option<&'static str>,
+    /// The value of headers stored in the header directory
+    pub value: Option<u32>,
+    /// Size of the data of headers stored elsewhere
+    pub size: Option<usize>,
+}
+
+/// Owned summary of an executable's headers, for display
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct XexInfo {
+    pub module_flags: ModuleFlags,
+    pub execution_info: Option<ExecutionInfo>,
+    pub base_address: Option<u32>,
+    pub entry_point: Option<u32>,
+    pub original_pe_name: Option<String>,
+    pub optional_headers: Vec<OptionalHeaderInfo>,
+}
+
+impl XexInfo {
+    pub fn new(xex: &Xex<'_>) -> Result<Self, StfsError> {
+        Ok(XexInfo {
+            module_flags: xex.module_flags,
+            execution_info: xex.execution_info().transpose()?,
+            base_address: xex.base_address(),
+            entry_point: xex.entry_point(),
+            original_pe_name: xex.original_pe_name(),
+            optional_headers: xex
+                .optional_headers()
+                .iter()
+                .map(|header| {
+                    let (value, size) = match header.value {
+                        HeaderValue::Inline(value) => (Some(value), None),
+                        HeaderValue::Data(data) => (None, Some(data.len())),
+                    };
+                    OptionalHeaderInfo {
+                        key: header.key,
+                        name: header.key.name(),
+                        value,
+                        size,
+                    }
+                })
+                .collect(),
+        })
+    }
+}
+
+impl<'a> StfsPackage<'a> {
+    /// Every file whose name ends in `.xex`, along with its path
+    pub fn executables(&self) -> Vec<(String, StfsFileEntry)> {
+        self.walk_entries()
+            .into_iter()
+            .filter(|(_, entry)| {
+                !entry.is_folder() && entry.name.to_ascii_lowercase().ends_with(".xex")
+            })
+            .collect()
+    }
+
+    /// Reads the headers of the executable described by `entry`, stopping
+    /// where its PE image begins. Parse the result with [`Xex::try_from`].
+    pub fn read_xex_headers(&self, entry: &StfsFileEntry) -> Result<Vec<u8>, StfsError> {
+        let mut reader = self.file_reader(entry)?;
+        let mut headers = Vec::with_capacity(HEADER_SIZE);
+        (&mut reader)
+            .take(HEADER_SIZE as u64)
+            .read_to_end(&mut headers)?;
+        if headers.len() < HEADER_SIZE {
+            return Err(StfsError::Truncated {
+                needed: HEADER_SIZE,
+                len: headers.len(),
+            });
+        }
+
+        let pe_data_offset = BigEndian::read_u32(&headers[0x8..]) as u64;
+        reader
+            .take(pe_data_offset.saturating_sub(HEADER_SIZE as u64))
+            .read_to_end(&mut headers)?;
+
+        Ok(headers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::WriteBytesExt;
+
+    use super::*;
+    use crate::StfsPackageBuilder;
+
+    /// Lays out an executable's headers, with the data of non-inline headers
+    /// following the directory and a security info holding `load_address`
+    fn build_xex(headers: &[(HeaderKey, Vec<u8>)], load_address: u32) -> Vec<u8> {
+        let data_start = HEADER_SIZE + headers.len() * OPTIONAL_HEADER_SIZE;
+        let mut directory = Vec::new();
+        let mut data = Vec::new();
+        for (key, value) in headers {
+            directory.write_u32::<BigEndian>(key.0).unwrap();
+            if key.is_inline() {
+                directory
+                    .write_u32::<BigEndian>(BigEndian::read_u32(value))
+                    .unwrap();
+                continue;
+            }
+            directory
+                .write_u32::<BigEndian>((data_start + data.len()) as u32)
+                .unwrap();
+            if key.0 & 0xFF == 0xFF {
+                data.write_u32::<BigEndian>(value.len() as u32 + 4).unwrap();
+            }
+            data.extend_from_slice(value);
+        }
+
+        let security_info_offset = data_start + data.len();
+        let pe_data_offset = security_info_offset + SECURITY_LOAD_ADDRESS_OFFSET + 4;
+        let mut out = XEX2_MAGIC.to_vec();
+        for value in [
+            ModuleFlags::TITLE.bits(),
+            pe_data_offset as u32,
+            0,
+            security_info_offset as u32,
+            headers.len() as u32,
+        ] {
+            out.write_u32::<BigEndian>(value).unwrap();
+        }
+        out.extend(directory);
+        out.extend(data);
+        out.resize(pe_data_offset - 4, 0);
+        out.write_u32::<BigEndian>(load_address).unwrap();
+        out
+    }
+
+    #[test]
+    fn parses_xex_headers() {
+        let mut execution_info = Vec::new();
+        for value in [0x1234_5678, 0x2000_0100, 0x2000_0000, 0x4D53_07E6] {
+            execution_info.write_u32::<BigEndian>(value).unwrap();
+        }
+        execution_info.extend_from_slice(&[0, 0, 1, 2]);
+        execution_info.write_u32::<BigEndian>(0).unwrap();
+        let mut image = build_xex(
+            &[
+                (
+                    HeaderKey::ENTRY_POINT,
+                    0x8200_1000u32.to_be_bytes().to_vec(),
+                ),
+                (HeaderKey::ORIGINAL_PE_NAME, b"default.exe\0".to_vec()),
+                (HeaderKey::EXECUTION_INFO, execution_info),
+            ],
+            0x8200_0000,
+        );
+        let headers_len = image.len();
+        image.extend_from_slice(&[0xAA; 0x100]);
+
+        let xex = Xex::try_from(image.as_slice()).unwrap();
+        let info = XexInfo::new(&xex).unwrap();
+        assert_eq!(info.module_flags, ModuleFlags::TITLE);
+        assert_eq!(info.entry_point, Some(0x8200_1000));
+        assert_eq!(info.base_address, Some(0x8200_0000));
+        assert_eq!(info.original_pe_name.as_deref(), Some("default.exe"));
+        let execution_info = info.execution_info.unwrap();
+        assert_eq!(execution_info.title_id, TitleId(0x4D5307E6));
+        assert_eq!(execution_info.media_id, MediaId(0x12345678));
+        assert_eq!(execution_info.version.to_string(), "2.0.1.0");
+        assert_eq!(
+            (execution_info.disc_number, execution_info.disc_count),
+            (1, 2)
+        );
+        assert_eq!(info.optional_headers[1].name, Some("original PE name"));
+        assert_eq!(info.optional_headers[1].size, Some(12));
+
+        let mut builder = StfsPackageBuilder::new();
+        builder
+            .add_file("default.xex", image.clone())
+            .unwrap()
+            .add_file("media/intro.wmv", vec![0; 8])
+            .unwrap();
+        let data = builder.build().unwrap();
+        let package = StfsPackage::try_from(data.as_slice()).unwrap();
+        let executables = package.executables();
+        assert_eq!(executables.len(), 1);
+        let headers = package.read_xex_headers(&executables[0].1).unwrap();
+        assert_eq!(headers, image[..headers_len]);
+
+        image[HEADER_SIZE + OPTIONAL_HEADER_SIZE + 4] = 0xFF;
+        assert!(matches!(
+            Xex::try_from(image.as_slice()),
+            Err(StfsError::InvalidXex(_))
+        ));
+    }
+}
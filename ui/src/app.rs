@@ -1,38 +1,204 @@
 use std::{
     cell::RefCell,
-    io::{Cursor, Write},
-    path::PathBuf,
-    sync::{
-        mpsc::{channel, Receiver, Sender},
-        Arc,
-    },
+    collections::HashSet,
+    future::Future,
+    io::{Cursor, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::mpsc::{channel, Receiver, Sender},
 };
 
 use clipboard::{ClipboardContext, ClipboardProvider};
-use egui::{Label, Sense, Spinner, TextBuffer};
+use egui::{Label, Sense, TextBuffer};
 use egui_extras::RetainedImage;
 use log::{debug, info};
-use ouroboros::self_referencing;
-use parking_lot::{Mutex, RwLock};
 use rfd::AsyncFileDialog;
 #[cfg(not(target_arch = "wasm32"))]
 use rfd::FileDialog;
-use stfs::{StfsEntry, StfsFileEntry, StfsPackage};
-use zip::write::FileOptions;
+use sha1::{Digest, Sha1};
+#[cfg(not(target_arch = "wasm32"))]
+use stfs::ExtractOptions;
+use stfs::{
+    Achievement, BlockState, CancellationToken, Change, EntryId, EntrySummary, FileTable, FileType,
+    ImageKind, KeyVault, LicenseEntry, LicenseType, MetadataField, PackageDiff, ProfileSummary,
+    SharedPackage, StfsFileEntry, StfsPackage, TitleRecord, Xdbf, Xex, XexInfo, ZipCompression,
+    ZipOptions, DASHBOARD_GPD,
+};
 
 #[cfg(target_arch = "wasm32")]
-use eframe::wasm_bindgen::{self, prelude::*};
+use eframe::wasm_bindgen::prelude::*;
 
-#[cfg(target_arch = "wasm32")]
-#[wasm_bindgen]
-extern "C" {
-    fn download_file(file: &web_sys::File);
+enum BackgroundTaskMessage {
+    /// A package's header was parsed. Its file table is read afterwards.
+    StfsPackageRead(PathBuf, Result<SharedPackage, String>),
+    /// The file table of a package sent with `StfsPackageRead` finished
+    /// parsing, or was too damaged to parse
+    FileTableLoaded(SharedPackage, Result<(), String>),
+    KeyVaultRead(Result<KeyVault, stfs::StfsError>),
+    /// A local file picked to be injected, along with its name and contents
+    InjectFileRead(SharedPackage, InjectTarget, String, Vec<u8>),
+    /// Progress of the background task with the given ID, from 0 to 1, and
+    /// the entry it's working on
+    TaskProgress(u64, f32, Option<String>),
+    /// A local image picked to replace one of the package's header images
+    ImageRead(SharedPackage, ImageKind, Vec<u8>),
+    /// The background task with the given ID finished with a summary or an error
+    TaskDone(u64, Result<String, String>),
+    /// Packages found by scanning a content folder
+    #[cfg(not(target_arch = "wasm32"))]
+    ContentFolderScanned(ContentCatalog),
+    /// Types of the package's files, detected from their contents, by entry index
+    FileTypesRead(SharedPackage, Vec<(usize, FileType)>),
+    /// A package was compared with the one at the given path
+    ComparisonRead(SharedPackage, PathBuf, Result<PackageDiff, String>),
 }
 
-enum BackgroundTaskMessage {
-    StfsPackageRead(PathBuf, Arc<RwLock<StfsPackageReference>>),
-    ZipFileUpdate(PathBuf),
-    ZipDone,
+/// Error reported by background tasks which stop because they were cancelled
+const CANCELLED: &str = "Cancelled";
+
+/// Messages shown in the corner of the window, so failures are reported
+/// instead of crashing the app
+#[derive(Default)]
+struct Notifications {
+    messages: Vec<Notification>,
+}
+
+struct Notification {
+    text: String,
+    is_error: bool,
+    /// When the message was added, in seconds since the app started
+    created_at: f64,
+}
+
+impl Notifications {
+    /// How long informational messages stay visible. Errors stay until dismissed.
+    const INFO_DURATION: f64 = 5.0;
+
+    fn error(&mut self, text: impl Into<String>) {
+        self.push(text.into(), true);
+    }
+
+    fn info(&mut self, text: impl Into<String>) {
+        self.push(text.into(), false);
+    }
+
+    fn push(&mut self, text: String, is_error: bool) {
+        self.messages.push(Notification {
+            text,
+            is_error,
+            // Filled in the next time the notifications are shown
+            created_at: f64::NAN,
+        });
+    }
+
+    fn show(&mut self, ctx: &egui::Context) {
+        let now = ctx.input().time;
+        for message in &mut self.messages {
+            if message.created_at.is_nan() {
+                message.created_at = now;
+            }
+        }
+        self.messages
+            .retain(|message| message.is_error || now - message.created_at < Self::INFO_DURATION);
+        if self.messages.is_empty() {
+            return;
+        }
+
+        let mut dismissed = None;
+        egui::Area::new("notifications")
+            .anchor(egui::Align2::RIGHT_BOTTOM, egui::vec2(-8.0, -8.0))
+            .show(ctx, |ui| {
+                for (index, message) in self.messages.iter().enumerate() {
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            if message.is_error {
+                                ui.colored_label(egui::Color32::RED, message.text.as_str());
+                            } else {
+                                ui.label(message.text.as_str());
+                            }
+                            if ui.small_button("x").on_hover_text("Dismiss").clicked() {
+                                dismissed = Some(index);
+                            }
+                        });
+                    });
+                }
+            });
+
+        if let Some(index) = dismissed {
+            self.messages.remove(index);
+        }
+
+        // Repaint so informational messages disappear on time
+        if self.messages.iter().any(|message| !message.is_error) {
+            ctx.request_repaint();
+        }
+    }
+}
+
+/// Copies `text` to the clipboard, reporting any failure as a notification
+fn copy_to_clipboard(
+    clipboard: &mut Option<ClipboardContext>,
+    notifications: &mut Notifications,
+    text: String,
+) {
+    let result = match clipboard {
+        Some(clipboard) => clipboard.set_contents(text).map_err(|e| e.to_string()),
+        None => Err("the clipboard isn't available".to_owned()),
+    };
+    if let Err(e) = result {
+        notifications.error(format!("Failed to copy to the clipboard: {}", e));
+    }
+}
+
+/// A long-running operation such as creating a zip, shown along with its
+/// progress at the bottom of the window
+struct BackgroundTask {
+    id: u64,
+    name: String,
+    progress: f32,
+    detail: Option<String>,
+    cancel: CancellationToken,
+    /// Set once the task finishes, fails, or is cancelled
+    result: Option<Result<String, String>>,
+}
+
+/// Lets the code running a background task report its progress and check
+/// whether it has been cancelled
+#[derive(Clone)]
+struct TaskHandle {
+    id: u64,
+    sender: Sender<BackgroundTaskMessage>,
+    cancel: CancellationToken,
+}
+
+impl TaskHandle {
+    fn report(&self, progress: stfs::Progress<'_>) {
+        let fraction = if progress.bytes_total > 0 {
+            progress.bytes_done as f32 / progress.bytes_total as f32
+        } else {
+            progress.entries_done as f32 / progress.entries_total.max(1) as f32
+        };
+
+        let _ = self.sender.send(BackgroundTaskMessage::TaskProgress(
+            self.id,
+            fraction,
+            progress.entry.map(str::to_owned),
+        ));
+    }
+
+    fn finish(&self, result: Result<String, String>) {
+        let _ = self
+            .sender
+            .send(BackgroundTaskMessage::TaskDone(self.id, result));
+    }
+}
+
+/// Describes why a background task failed, reporting [`CANCELLED`] for tasks
+/// that stopped because they were cancelled
+fn task_error(action: &str, error: stfs::StfsError) -> String {
+    match error {
+        stfs::StfsError::Cancelled => CANCELLED.to_owned(),
+        e => format!("Failed to {}: {}", action, e),
+    }
 }
 
 /// We derive Deserialize/Serialize so we can persist app state on shutdown.
@@ -41,17 +207,17 @@ enum BackgroundTaskMessage {
 pub struct AccelerationApp {
     active_stfs_file: Option<PathBuf>,
 
+    /// Every package that's currently open, each shown in its own tab
     #[serde(skip)]
-    stfs_package: Option<Arc<RwLock<StfsPackageReference>>>,
-
-    #[serde(skip)]
-    stfs_package_display_image: Option<RetainedImage>,
+    open_packages: Vec<OpenPackage>,
 
+    /// Index into `open_packages` of the tab being shown
     #[serde(skip)]
-    stfs_package_title_image: Option<RetainedImage>,
+    active_package: usize,
 
+    /// `None` if the platform's clipboard couldn't be opened
     #[serde(skip)]
-    clipboard: ClipboardContext,
+    clipboard: Option<ClipboardContext>,
 
     #[serde(skip)]
     send: Sender<BackgroundTaskMessage>,
@@ -59,11 +225,260 @@ pub struct AccelerationApp {
     #[serde(skip)]
     recv: Receiver<BackgroundTaskMessage>,
 
+    /// The running background task, or the last one until its result is dismissed
+    #[serde(skip)]
+    background_task: Option<BackgroundTask>,
+
     #[serde(skip)]
-    status_message: Option<String>,
+    next_task_id: u64,
 
     #[serde(skip)]
+    notifications: Notifications,
+
+    /// Key vault used to resign packages when saving edited metadata
+    #[serde(skip)]
+    key_vault: Option<KeyVault>,
+
+    /// File waiting for the user to confirm it should be injected
+    #[serde(skip)]
+    pending_injection: Option<PendingInjection>,
+
+    /// Packages listed in the catalog panel after opening a content folder
+    #[cfg(not(target_arch = "wasm32"))]
+    #[serde(skip)]
+    content_catalog: Option<ContentCatalog>,
+}
+
+/// Where a file picked for injection is written in the package
+enum InjectTarget {
+    /// Replace the file at this path
+    Replace(String),
+    /// Add the picked file to this folder under its own name
+    AddTo(PathBuf),
+}
+
+/// A file picked for injection, shown in a confirmation window before the
+/// package is saved
+struct PendingInjection {
+    stfs_package: SharedPackage,
+    internal_path: String,
+    /// Size of the file being replaced, or `None` if the file is new
+    replaced_size: Option<usize>,
+    new_size: usize,
+    old_package_size: usize,
+    /// The package with the file injected and rehashed
+    data: Result<Vec<u8>, String>,
+    resign: bool,
+}
+
+/// A package open in a tab, along with the state of its panels
+struct OpenPackage {
+    file_path: PathBuf,
+
+    stfs_package: SharedPackage,
+
+    stfs_package_display_image: Option<RetainedImage>,
+
+    stfs_package_title_image: Option<RetainedImage>,
+
     package_files: RefCell<Vec<StfsFileModel>>,
+    /// Set once the file table has been read and `package_files` filled in
+    files_loaded: bool,
+
+    /// Folder chosen in the folder tree. Only files directly inside it are listed,
+    /// or every file if this is `None`.
+    selected_folder: Option<PathBuf>,
+
+    /// File whose contents are shown in the hex preview
+    selected_file: Option<StfsFileEntry>,
+
+    hex_page: usize,
+
+    hex_preview: Option<HexPreview>,
+
+    image_preview: Option<ImagePreview>,
+
+    text_preview: Option<TextPreview>,
+
+    /// Values being edited in the metadata panel, in the same order as `EDITABLE_FIELDS`
+    metadata_edits: Vec<String>,
+
+    resign_on_save: bool,
+
+    comparison: Option<Comparison>,
+
+    hex_edit: Option<HexEdit>,
+
+    /// Set while the "Block Map" window is open
+    block_map: Option<BlockMapView>,
+
+    /// The dashboard and title GPDs' contents, set while the "Profile" window
+    /// is open
+    profile: Option<ProfileView>,
+
+    /// The headers of each executable, set while the "Executables" window is
+    /// open
+    executables: Option<Vec<(String, Result<XexInfo, String>)>>,
+
+    /// Package contents from before each edit, most recent last
+    undo_stack: Vec<Revision>,
+
+    /// Package contents from before each undo, most recent last
+    redo_stack: Vec<Revision>,
+
+    /// Whether there are edits which haven't been saved to disk
+    modified: bool,
+
+    /// Whether the background pass detecting file types has been started
+    file_types_requested: bool,
+}
+
+/// Header fields which can be edited from the metadata panel, and their labels
+const EDITABLE_FIELDS: [(MetadataField, &str); 7] = [
+    (MetadataField::DisplayName, "Name:"),
+    (MetadataField::DisplayDescription, "Description:"),
+    (MetadataField::TitleId, "Title ID:"),
+    (MetadataField::ProfileId, "Profile ID:"),
+    (MetadataField::ConsoleId, "Console ID:"),
+    (MetadataField::DeviceId, "Device ID:"),
+    (MetadataField::TransferFlags, "Transfer Flags:"),
+];
+
+/// Number of bytes shown on each page of the hex preview
+const HEX_PAGE_SIZE: usize = 0x1000;
+
+/// A page of the selected file, formatted once when the selection or page changes
+struct HexPreview {
+    file_index: usize,
+    page: usize,
+    lines: Vec<String>,
+}
+
+/// A previous version of a package, kept so an edit can be undone or redone
+struct Revision {
+    /// What the edit did, e.g. "Replace save.dat"
+    description: String,
+    data: Vec<u8>,
+}
+
+/// Files larger than this can't be edited in the hex panel
+const MAX_HEX_EDIT_SIZE: usize = 0x100_0000;
+/// Bytes shown on each row of the hex panel
+const HEX_ROW_SIZE: usize = 16;
+
+/// A contained file being edited in the hex panel. Edits overwrite bytes in
+/// place and are saved by injecting the edited contents.
+struct HexEdit {
+    file_index: usize,
+    /// The file's contents with the edits applied
+    contents: Vec<u8>,
+    /// Page that `rows` was built for
+    page: usize,
+    /// Text of each row on the page, as space separated hex bytes
+    rows: Vec<String>,
+    modified: bool,
+}
+
+impl HexEdit {
+    /// Rebuilds the editable rows for `page`
+    fn load_page(&mut self, page: usize) {
+        let start = (page * HEX_PAGE_SIZE).min(self.contents.len());
+        let end = (start + HEX_PAGE_SIZE).min(self.contents.len());
+        self.page = page;
+        self.rows = self.contents[start..end]
+            .chunks(HEX_ROW_SIZE)
+            .map(|chunk| {
+                chunk
+                    .iter()
+                    .map(|b| format!("{:02X}", b))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect();
+    }
+}
+
+/// Parses a row edited in the hex panel. Returns `None` unless every byte is
+/// valid hex.
+fn parse_hex_row(text: &str) -> Option<Vec<u8>> {
+    text.split_whitespace()
+        .map(|byte| {
+            if byte.len() <= 2 {
+                u8::from_str_radix(byte, 16).ok()
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// Header images larger than this are scaled down, matching the console's icons
+const HEADER_IMAGE_DIMENSION: u32 = 64;
+/// Largest image which fits in the package header
+const MAX_HEADER_IMAGE_SIZE: usize = 0x4000;
+
+/// Files larger than this can't be copied to the clipboard as hex
+const MAX_HEX_COPY_SIZE: usize = 0x10_0000;
+
+/// Files larger than this aren't read to check whether they're an image
+const MAX_IMAGE_PREVIEW_SIZE: usize = 0x100_0000;
+
+/// The selected file decoded as an image, or `None` if it isn't one
+struct ImagePreview {
+    file_index: usize,
+    image: Option<RetainedImage>,
+}
+
+/// Files larger than this aren't read to check whether they're text
+const MAX_TEXT_PREVIEW_SIZE: usize = 0x10_0000;
+
+/// The selected file decoded as text, or `None` if it doesn't look like text
+struct TextPreview {
+    file_index: usize,
+    /// The name of the detected encoding and the decoded text
+    text: Option<(&'static str, String)>,
+}
+
+/// Number of cells in each row of the block map
+const BLOCK_MAP_COLUMNS: usize = 64;
+/// Most cells drawn in the block map. Larger packages show several blocks per cell.
+const MAX_BLOCK_MAP_CELLS: usize = BLOCK_MAP_COLUMNS * 64;
+const BLOCK_MAP_CELL_SIZE: f32 = 8.0;
+const BLOCK_MAP_FILE_COLOR: egui::Color32 = egui::Color32::GOLD;
+const BLOCK_MAP_DATA_COLOR: egui::Color32 = egui::Color32::from_rgb(0x40, 0x80, 0xC0);
+const BLOCK_MAP_HASH_COLOR: egui::Color32 = egui::Color32::from_rgb(0xC0, 0x60, 0x30);
+const BLOCK_MAP_FREE_COLOR: egui::Color32 = egui::Color32::DARK_GRAY;
+
+/// The package's blocks as shown in the "Block Map" window
+struct BlockMapView {
+    /// State of every block following the header
+    states: Vec<BlockState>,
+    /// Entry index of the file whose blocks are highlighted, and those blocks
+    highlighted: Option<(usize, HashSet<usize>)>,
+}
+
+/// Which list the "Profile" window shows
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum ProfileTab {
+    Titles,
+    Achievements,
+}
+
+/// Contents of the "Profile" window
+struct ProfileView {
+    summary: ProfileSummary,
+    /// Each played title's achievements, read from the title's own GPD. In the
+    /// same order as `summary.titles`.
+    achievements: Vec<Result<Vec<Achievement>, String>>,
+    tab: ProfileTab,
+}
+
+/// Differences between a package and another one picked with "Compare with…"
+struct Comparison {
+    other_path: PathBuf,
+    diff: PackageDiff,
+    /// Also list entries which are the same in both packages
+    show_unchanged: bool,
 }
 
 #[derive(Debug)]
@@ -71,16 +486,9 @@ struct StfsFileModel {
     name: String,
     path: PathBuf,
     size: String,
-    file_ref: stfs::StfsEntryRef,
-}
-
-#[self_referencing]
-struct StfsPackageReference {
-    stfs_package_data: Vec<u8>,
-
-    #[borrows(stfs_package_data)]
-    #[covariant]
-    parsed_stfs_package: Result<StfsPackage<'this>, stfs::StfsError>,
+    entry: StfsFileEntry,
+    /// Filled in by a background pass once the package is opened
+    file_type: Option<FileType>,
 }
 
 impl<'package> Default for AccelerationApp {
@@ -88,14 +496,18 @@ impl<'package> Default for AccelerationApp {
         let (send, recv) = channel();
         Self {
             active_stfs_file: None,
-            stfs_package: None,
-            stfs_package_display_image: None,
-            stfs_package_title_image: None,
-            clipboard: ClipboardProvider::new().unwrap(),
+            open_packages: Vec::new(),
+            active_package: 0,
+            clipboard: ClipboardProvider::new().ok(),
             send,
             recv,
-            status_message: None,
-            package_files: RefCell::new(Vec::new()),
+            background_task: None,
+            next_task_id: 0,
+            notifications: Notifications::default(),
+            key_vault: None,
+            pending_injection: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            content_catalog: None,
         }
     }
 }
@@ -114,6 +526,259 @@ impl AccelerationApp {
 
         Default::default()
     }
+
+    /// Opens each package in `paths` in its own tab, such as those passed on
+    /// the command line when the app is launched from a file association
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn open_paths(&mut self, paths: impl IntoIterator<Item = PathBuf>) {
+        for file_path in paths {
+            open_path(&self.send, file_path);
+        }
+    }
+}
+
+/// Reads and parses the package at `file_path` in the background
+#[cfg(not(target_arch = "wasm32"))]
+fn open_path(sender: &Sender<BackgroundTaskMessage>, file_path: PathBuf) {
+    let sender = sender.clone();
+    spawn_task(async move {
+        match std::fs::read(&file_path) {
+            Ok(data) => send_stfs_package(&sender, file_path, data),
+            Err(e) => {
+                let message = BackgroundTaskMessage::StfsPackageRead(file_path, Err(e.to_string()));
+                sender
+                    .send(message)
+                    .expect("failed to send parsed STFS package to main thread");
+            }
+        }
+    });
+}
+
+/// Number of bytes read from each file when scanning a content folder. This
+/// covers the header of every package the console creates.
+#[cfg(not(target_arch = "wasm32"))]
+const CATALOG_HEADER_SIZE: u64 = 0xA000;
+
+/// A package found by "Open Content Folder…"
+#[cfg(not(target_arch = "wasm32"))]
+struct CatalogEntry {
+    path: PathBuf,
+    display_name: String,
+    title_name: String,
+    content_type: String,
+    size: String,
+}
+
+/// Packages found under a console's `Content` directory
+#[cfg(not(target_arch = "wasm32"))]
+struct ContentCatalog {
+    root: PathBuf,
+    packages: Vec<CatalogEntry>,
+}
+
+/// Reads just the header of the file at `path`. Returns `None` for files which
+/// aren't packages.
+#[cfg(not(target_arch = "wasm32"))]
+fn scan_package(path: &Path) -> Option<CatalogEntry> {
+    let file = std::fs::File::open(path).ok()?;
+    let size = file.metadata().ok()?.len();
+    let mut data = Vec::new();
+    file.take(CATALOG_HEADER_SIZE).read_to_end(&mut data).ok()?;
+
+    let header = stfs::XContentHeader::parse(&data).ok()?;
+    let title_name = if header.title_name.is_empty() {
+        header
+            .title_id
+            .name()
+            .map(str::to_owned)
+            .unwrap_or_else(|| header.title_id.to_string())
+    } else {
+        header.title_name
+    };
+
+    Some(CatalogEntry {
+        path: path.to_owned(),
+        display_name: header.display_name,
+        title_name,
+        content_type: header.content_type.to_string(),
+        size: human_readable_size(size as usize),
+    })
+}
+
+/// Lists the packages in `catalog` in a panel on the left. Clicking one shows
+/// its tab, opening it first if needed. Returns false once the panel is closed.
+#[cfg(not(target_arch = "wasm32"))]
+fn show_catalog(
+    ctx: &egui::Context,
+    catalog: &ContentCatalog,
+    open_packages: &[OpenPackage],
+    active_package: &mut usize,
+    send: &Sender<BackgroundTaskMessage>,
+) -> bool {
+    let mut open = true;
+    let active_path = open_packages
+        .get(*active_package)
+        .map(|open_package| open_package.file_path.as_path());
+
+    egui::SidePanel::left("catalog_panel")
+        .resizable(true)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                ui.heading("Content");
+                if ui.small_button("x").on_hover_text("Close").clicked() {
+                    open = false;
+                }
+            });
+            ui.label(catalog.root.to_string_lossy().into_owned());
+            ui.separator();
+
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                egui::Grid::new("catalog_grid")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.strong("Name");
+                        ui.strong("Title");
+                        ui.strong("Type");
+                        ui.strong("Size");
+                        ui.end_row();
+
+                        for package in &catalog.packages {
+                            let selected = active_path == Some(package.path.as_path());
+                            if ui
+                                .selectable_label(selected, package.display_name.as_str())
+                                .on_hover_text(package.path.to_string_lossy().into_owned())
+                                .clicked()
+                            {
+                                match open_packages
+                                    .iter()
+                                    .position(|open_package| open_package.file_path == package.path)
+                                {
+                                    Some(index) => *active_package = index,
+                                    None => open_path(send, package.path.clone()),
+                                }
+                            }
+                            ui.label(package.title_name.as_str());
+                            ui.label(package.content_type.as_str());
+                            ui.label(package.size.as_str());
+                            ui.end_row();
+                        }
+                    });
+            });
+        });
+
+    open
+}
+
+/// Walks a `Content/<profile>/<title>/<type>/` tree and lists every package in it
+#[cfg(not(target_arch = "wasm32"))]
+async fn scan_content_folder(sender: Sender<BackgroundTaskMessage>, root: PathBuf) {
+    let mut packages = Vec::new();
+    let mut folders = vec![root.clone()];
+    while let Some(folder) = folders.pop() {
+        let mut children: Vec<PathBuf> = match std::fs::read_dir(&folder) {
+            Ok(entries) => entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .collect(),
+            Err(_) => continue,
+        };
+        children.sort();
+
+        for child in children {
+            if child.is_dir() {
+                folders.push(child);
+            } else if let Some(package) = scan_package(&child) {
+                packages.push(package);
+            }
+        }
+    }
+    packages.sort_by(|a, b| a.path.cmp(&b.path));
+
+    sender
+        .send(BackgroundTaskMessage::ContentFolderScanned(
+            ContentCatalog { root, packages },
+        ))
+        .expect("failed to send content folder to main thread");
+}
+
+#[cfg(target_arch = "wasm32")]
+fn spawn_task(task: impl Future<Output = ()> + 'static) {
+    wasm_bindgen_futures::spawn_local(task);
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn spawn_task(task: impl Future<Output = ()> + Send + 'static) {
+    std::thread::spawn(move || futures::executor::block_on(task));
+}
+
+async fn open_key_vault(sender: Sender<BackgroundTaskMessage>) {
+    let task = AsyncFileDialog::new().pick_file();
+    if let Some(file) = task.await {
+        let key_vault = KeyVault::parse(&file.read().await);
+        sender
+            .send(BackgroundTaskMessage::KeyVaultRead(key_vault))
+            .expect("failed to send key vault to main thread");
+    }
+}
+
+async fn pick_injected_file(
+    sender: Sender<BackgroundTaskMessage>,
+    stfs_package: SharedPackage,
+    target: InjectTarget,
+) {
+    let task = AsyncFileDialog::new().pick_file();
+    if let Some(file) = task.await {
+        let contents = file.read().await;
+        sender
+            .send(BackgroundTaskMessage::InjectFileRead(
+                stfs_package,
+                target,
+                file.file_name(),
+                contents,
+            ))
+            .expect("failed to send injected file to main thread");
+    }
+}
+
+/// Injects `contents` into a copy of the package so the confirmation window
+/// can show how the package will change
+fn prepare_injection(
+    stfs_package: SharedPackage,
+    target: InjectTarget,
+    file_name: String,
+    contents: Vec<u8>,
+) -> PendingInjection {
+    let internal_path = match target {
+        InjectTarget::Replace(path) => path,
+        InjectTarget::AddTo(folder) => folder.join(file_name).to_string_lossy().into_owned(),
+    };
+
+    let (replaced_size, old_package_size, data) = {
+        let normalized_path = internal_path.replace('\\', "/");
+        let replaced_size = stfs_package
+            .package()
+            .walk_entries()
+            .into_iter()
+            .find(|(path, entry)| !entry.is_folder() && *path == normalized_path)
+            .map(|(_, entry)| entry.file_size);
+
+        let mut data = stfs_package.data().to_vec();
+        let old_package_size = data.len();
+        let data = stfs::inject_file(&mut data, &internal_path, &contents)
+            .map(|_| data)
+            .map_err(|e| e.to_string());
+
+        (replaced_size, old_package_size, data)
+    };
+
+    PendingInjection {
+        stfs_package,
+        internal_path,
+        replaced_size,
+        new_size: contents.len(),
+        old_package_size,
+        data,
+        resign: false,
+    }
 }
 
 async fn open_stfs_package(sender: Sender<BackgroundTaskMessage>) {
@@ -124,203 +789,2083 @@ async fn open_stfs_package(sender: Sender<BackgroundTaskMessage>) {
         #[cfg(target_arch = "wasm32")]
         let file_path = PathBuf::from(file.file_name());
 
-        let file_data = file.read().await;
-        let package_reference = StfsPackageReferenceBuilder {
-            stfs_package_data: file_data,
-            parsed_stfs_package_builder: |package_data| {
-                StfsPackage::try_from(package_data.as_slice())
-            },
-        }
-        .build();
+        send_stfs_package(&sender, file_path, file.read().await);
+    }
+}
 
-        if package_reference.borrow_parsed_stfs_package().is_ok() {
-            sender
-                .send(BackgroundTaskMessage::StfsPackageRead(
-                    file_path,
-                    Arc::new(RwLock::new(package_reference)),
-                ))
-                .expect("failed to send parsed STFS package to main thread");
-        }
+/// Parses `data` and sends it to the main thread to be opened in a new tab
+fn send_stfs_package(sender: &Sender<BackgroundTaskMessage>, file_path: PathBuf, data: Vec<u8>) {
+    let package = SharedPackage::new(data).map_err(|e| e.to_string());
+
+    let loaded_package = package.as_ref().ok().cloned();
+    sender
+        .send(BackgroundTaskMessage::StfsPackageRead(file_path, package))
+        .expect("failed to send parsed STFS package to main thread");
+
+    // The header is shown while the file table, which can take a while for
+    // large packages, is read here
+    if let Some(package) = loaded_package {
+        let loaded = package
+            .package()
+            .try_files()
+            .map(|_| ())
+            .map_err(|e| e.to_string());
+        sender
+            .send(BackgroundTaskMessage::FileTableLoaded(package, loaded))
+            .expect("failed to send loaded file table to main thread");
     }
 }
 
 #[cfg(not(target_arch = "wasm32"))]
-fn save_file<'a>(file: StfsFileEntry, stfs_package: &'a StfsPackage<'a>) {
+fn save_file<'a>(file: StfsFileEntry, stfs_package: &'a StfsPackage<'a>) -> std::io::Result<()> {
     if let Some(path) = FileDialog::new()
         .set_file_name(file.name.as_str())
         .save_file()
     {
-        let mut out_file = std::fs::File::create(path).expect("failed to create output file");
-        stfs_package
-            .extract_file(&mut out_file, &file)
-            .expect("failed to save file");
+        let mut out_file = std::fs::File::create(path)?;
+        stfs_package.extract_file(&mut out_file, &file)?;
     }
+
+    Ok(())
 }
 
 #[cfg(target_arch = "wasm32")]
-fn save_file<'a>(file: StfsFileEntry, stfs_package: &'a StfsPackage<'a>) {
+fn save_file<'a>(file: StfsFileEntry, stfs_package: &'a StfsPackage<'a>) -> std::io::Result<()> {
     let mut out = Vec::with_capacity(file.file_size);
-    stfs_package
-        .extract_file(&mut out, &file)
-        .expect("failed to save file");
+    stfs_package.extract_file(&mut out, &file)?;
 
-    unsafe {
-        download_file(gloo_file::File::new(file.name.as_str(), out.as_slice()).as_ref());
-    }
+    download(file.name.as_str(), &out)
 }
 
-#[cfg(not(target_arch = "wasm32"))]
-fn extract_all<'a>(stfs_package: &'a StfsPackage<'a>) {
-    if let Some(folder_root) = FileDialog::new()
-        .set_file_name(stfs_package.header.display_name.as_str())
-        .pick_folder()
-    {
-        let mut path = PathBuf::new();
-        let mut queue = Vec::with_capacity(256);
-        if let StfsEntry::Folder { entry: _, files } = &*stfs_package.files.lock() {
-            queue.extend(std::iter::repeat(0usize).zip(files.iter().cloned()));
-        }
+/// Saves `data` through the browser's downloads by clicking a temporary link
+/// to a blob URL
+#[cfg(target_arch = "wasm32")]
+fn download(file_name: &str, data: &[u8]) -> std::io::Result<()> {
+    let js_error = |e: JsValue| std::io::Error::new(std::io::ErrorKind::Other, format!("{:?}", e));
+
+    let window = web_sys::window().ok_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::Other,
+            "no browser window to download from",
+        )
+    })?;
+    let document = window.document().ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::Other, "no document to download from")
+    })?;
+
+    let file = gloo_file::File::new(file_name, data);
+    let web_file: &web_sys::File = file.as_ref();
+    let url = web_sys::Url::create_object_url_with_blob(web_file).map_err(js_error)?;
+
+    let anchor: web_sys::HtmlAnchorElement = document
+        .create_element("a")
+        .map_err(js_error)?
+        .unchecked_into();
+    anchor.set_href(&url);
+    anchor.set_download(file_name);
+    anchor.click();
+
+    // The download starts after this returns, so the URL can't be revoked yet
+    let revoke = Closure::once_into_js(move || {
+        let _ = web_sys::Url::revoke_object_url(&url);
+    });
+    window
+        .set_timeout_with_callback(revoke.unchecked_ref())
+        .map_err(js_error)?;
+
+    Ok(())
+}
 
-        let mut last_depth = 0;
-        while let Some((depth, file)) = queue.pop() {
-            if depth < last_depth {
-                path.pop();
-                last_depth -= 1;
-            }
+/// Asks where to save `data`, suggesting the name in `file_path`. Returns where
+/// it was saved or `None` if the user cancelled.
+#[cfg(not(target_arch = "wasm32"))]
+fn save_bytes(file_path: &Path, data: &[u8]) -> std::io::Result<Option<PathBuf>> {
+    let mut dialog = FileDialog::new();
+    if let Some(file_name) = file_path.file_name().and_then(|name| name.to_str()) {
+        dialog = dialog.set_file_name(file_name);
+    }
 
-            let file = file.lock();
-            if let StfsEntry::File(entry) = &*file {
-                let file_path = path.join(entry.name.as_str());
-                let mut directory_path = folder_root.join(&path);
-                std::fs::create_dir_all(&directory_path).expect("failed to create path!");
-                directory_path.push(entry.name.as_str());
+    match dialog.save_file() {
+        Some(path) => {
+            std::fs::write(&path, data)?;
+            Ok(Some(path))
+        }
+        None => Ok(None),
+    }
+}
 
-                let mut file =
-                    std::fs::File::create(file_path).expect("failed to create output file");
+#[cfg(target_arch = "wasm32")]
+fn save_bytes(file_path: &Path, data: &[u8]) -> std::io::Result<Option<PathBuf>> {
+    download(&file_path.to_string_lossy(), data)?;
 
-                stfs_package
-                    .extract_file(&mut file, entry)
-                    .expect("failed to save file");
-            }
+    Ok(Some(file_path.to_owned()))
+}
 
-            if let StfsEntry::Folder { entry, files } = &*file {
-                path.push(entry.name.as_str());
-                queue.extend(std::iter::repeat(depth + 1).zip(files.iter().cloned()));
-                last_depth += 1;
-            }
+/// Applies the fields in `edits` which differ from the package's current
+/// values, then rehashes the package or resigns it with `key_vault`
+fn apply_metadata_edits(
+    stfs_package: &SharedPackage,
+    edits: &[String],
+    key_vault: Option<&KeyVault>,
+) -> Result<Vec<u8>, stfs::StfsError> {
+    let parsed_package = stfs_package.package();
+
+    let mut data = stfs_package.data().to_vec();
+    for ((field, _), value) in EDITABLE_FIELDS.iter().zip(edits) {
+        if field.get(&parsed_package.header) != *value {
+            stfs::set_metadata(&mut data, *field, value)?;
         }
     }
+
+    match key_vault {
+        Some(key_vault) => stfs::resign(&mut data, key_vault)?,
+        None => stfs::rehash(&mut data)?,
+    }
+
+    Ok(data)
 }
 
-fn create_zip<'a>(
-    stfs_package: &'a StfsPackage<'a>,
-    sender: Sender<BackgroundTaskMessage>,
-) -> Vec<u8> {
-    let mut zip_contents = Vec::new();
-    let writer = Cursor::new(&mut zip_contents);
-    let mut zip = zip::ZipWriter::new(writer);
-    let options = FileOptions::default()
-        .compression_method(zip::CompressionMethod::Deflated)
-        .unix_permissions(0o755);
+/// Replaces `stfs_package` with an edited copy in the tab it came from. The
+/// edit can be undone until the tab is closed.
+fn apply_package_edit(
+    open_packages: &mut [OpenPackage],
+    stfs_package: &SharedPackage,
+    description: String,
+    data: Vec<u8>,
+) -> Result<(), String> {
+    let open_package = open_packages
+        .iter_mut()
+        .find(|open_package| SharedPackage::ptr_eq(&open_package.stfs_package, stfs_package))
+        .ok_or_else(|| "the package was closed".to_owned())?;
+
+    open_package.apply_edit(description, data);
+
+    Ok(())
+}
 
-    let mut path = PathBuf::new();
-    let mut queue = Vec::with_capacity(256);
-    if let StfsEntry::Folder { entry: _, files } = &*stfs_package.files.lock() {
-        queue.extend(std::iter::repeat(0usize).zip(files.iter().cloned()));
+/// Resigns the injected package if requested and applies it to the tab it
+/// came from
+fn save_injection(
+    injection: PendingInjection,
+    key_vault: Option<&KeyVault>,
+    open_packages: &mut [OpenPackage],
+) -> Result<(), String> {
+    let mut data = injection.data?;
+    if let Some(key_vault) = key_vault.filter(|_| injection.resign) {
+        stfs::resign(&mut data, key_vault).map_err(|e| e.to_string())?;
     }
 
-    let mut last_depth = 0;
-    let mut buffer = Vec::new();
-    while let Some((depth, file)) = queue.pop() {
-        if depth < last_depth {
-            path.pop();
-            last_depth -= 1;
-        }
+    let description = match injection.replaced_size {
+        Some(_) => format!("Replace {}", injection.internal_path),
+        None => format!("Add {}", injection.internal_path),
+    };
+    apply_package_edit(open_packages, &injection.stfs_package, description, data)
+}
 
-        let file = file.lock();
-        if let StfsEntry::File(entry) = &*file {
-            let file_path = path.join(entry.name.as_str());
-            sender
-                .send(BackgroundTaskMessage::ZipFileUpdate(file_path.clone()))
-                .expect("failed to send file update");
-            debug!("Adding file {:?} to zip", file_path);
+/// Reads the start of every file in the package to identify its type
+async fn detect_file_types(sender: Sender<BackgroundTaskMessage>, stfs_package: SharedPackage) {
+    let file_types = {
+        let parsed_package = stfs_package.package();
+        parsed_package
+            .walk_entries()
+            .into_iter()
+            .filter(|(_, entry)| !entry.is_folder())
+            .filter_map(|(_, entry)| {
+                let file_type = parsed_package.file_type(&entry).ok()?;
+                Some((entry.index, file_type))
+            })
+            .collect()
+    };
+
+    sender
+        .send(BackgroundTaskMessage::FileTypesRead(
+            stfs_package,
+            file_types,
+        ))
+        .expect("failed to send file types to main thread");
+}
+
+/// Icon shown next to a file's type in the file list
+fn file_type_icon(file_type: FileType) -> &'static str {
+    match file_type {
+        FileType::Png | FileType::Jpeg | FileType::Dds => "🖼",
+        FileType::Xex => "⚙",
+        FileType::Xdbf => "🗄",
+        FileType::Wav | FileType::Xma => "🎵",
+        FileType::Stfs(_) => "📦",
+        FileType::Unknown => "📄",
+    }
+}
 
-            zip.start_file(file_path.as_os_str().to_str().unwrap(), options)
-                .expect("failed to add file to zip");
+async fn compare_with(sender: Sender<BackgroundTaskMessage>, stfs_package: SharedPackage) {
+    let task = AsyncFileDialog::new().pick_file();
+    if let Some(file) = task.await {
+        #[cfg(not(target_arch = "wasm32"))]
+        let other_path = file.path().to_owned();
+        #[cfg(target_arch = "wasm32")]
+        let other_path = PathBuf::from(file.file_name());
 
-            stfs_package
-                .extract_file(&mut buffer, entry)
-                .expect("failed to extract file");
-            zip.write_all(buffer.as_slice())
-                .expect("failed to write file to zip");
+        let diff = match SharedPackage::new(file.read().await) {
+            Ok(other) => {
+                stfs::diff(&stfs_package.package(), &other.package()).map_err(|e| e.to_string())
+            }
+            Err(e) => Err(e.to_string()),
+        };
+
+        sender
+            .send(BackgroundTaskMessage::ComparisonRead(
+                stfs_package,
+                other_path,
+                diff,
+            ))
+            .expect("failed to send comparison to main thread");
+    }
+}
 
-            buffer.clear();
-        }
+async fn pick_header_image(
+    sender: Sender<BackgroundTaskMessage>,
+    stfs_package: SharedPackage,
+    kind: ImageKind,
+) {
+    let task = AsyncFileDialog::new()
+        .add_filter("Images", &["png", "jpg", "jpeg"])
+        .pick_file();
+    if let Some(file) = task.await {
+        let image = file.read().await;
+        sender
+            .send(BackgroundTaskMessage::ImageRead(stfs_package, kind, image))
+            .expect("failed to send image to main thread");
+    }
+}
 
-        if let StfsEntry::Folder { entry, files } = &*file {
-            path.push(entry.name.as_str());
-            info!("Adding folder {:?} to zip", path);
-            zip.add_directory(path.as_os_str().to_str().unwrap(), options)
-                .expect("failed to create directory");
-            queue.extend(std::iter::repeat(depth + 1).zip(files.iter().cloned()));
-            last_depth += 1;
-        }
+/// Converts a picked image to a PNG which fits in the package header, scaling
+/// it down if needed
+fn header_image_png(image: &[u8]) -> Result<Vec<u8>, String> {
+    if image.starts_with(b"\x89PNG") && image.len() <= MAX_HEADER_IMAGE_SIZE {
+        return Ok(image.to_vec());
     }
 
-    zip.finish().expect("failed to finish zip");
-    drop(zip);
+    let mut decoded =
+        image::load_from_memory(image).map_err(|e| format!("Failed to read image: {}", e))?;
+    if decoded.width() > HEADER_IMAGE_DIMENSION || decoded.height() > HEADER_IMAGE_DIMENSION {
+        decoded = decoded.resize(
+            HEADER_IMAGE_DIMENSION,
+            HEADER_IMAGE_DIMENSION,
+            image::imageops::FilterType::Lanczos3,
+        );
+    }
 
-    sender.send(BackgroundTaskMessage::ZipDone);
+    let mut png = Cursor::new(Vec::new());
+    decoded
+        .write_to(&mut png, image::ImageOutputFormat::Png)
+        .map_err(|e| format!("Failed to encode image: {}", e))?;
 
-    zip_contents
+    Ok(png.into_inner())
 }
 
-#[cfg(not(target_arch = "wasm32"))]
-fn save_as_zip<'a>(stfs_package: &'a StfsPackage<'a>, sender: Sender<BackgroundTaskMessage>) {
-    if let Some(zip_path) = FileDialog::new()
-        .set_file_name(format!("{}.zip", stfs_package.header.display_name).as_str())
-        .save_file()
-    {
-        std::fs::write(zip_path, create_zip(stfs_package, sender).as_slice())
-            .expect("failed to write out zip file");
+/// Replaces one of the header images, resigning the package if its tab asks
+/// for that, then saves and reopens it
+fn replace_header_image(
+    stfs_package: &SharedPackage,
+    kind: ImageKind,
+    image: &[u8],
+    key_vault: Option<&KeyVault>,
+    open_packages: &mut [OpenPackage],
+) -> Result<(), String> {
+    let png = header_image_png(image)?;
+    let mut data = stfs_package.data().to_vec();
+    stfs::set_image(&mut data, kind, &png).map_err(|e| e.to_string())?;
+
+    let resign = open_packages
+        .iter()
+        .find(|open_package| SharedPackage::ptr_eq(&open_package.stfs_package, stfs_package))
+        .map_or(false, |open_package| open_package.resign_on_save);
+    if let Some(key_vault) = key_vault.filter(|_| resign) {
+        stfs::resign(&mut data, key_vault).map_err(|e| e.to_string())?;
     }
+
+    apply_package_edit(
+        open_packages,
+        stfs_package,
+        format!("Replace the {} image", kind.name()),
+        data,
+    )
 }
 
-#[cfg(target_arch = "wasm32")]
-fn save_as_zip<'a>(stfs_package: &'a StfsPackage<'a>, sender: Sender<BackgroundTaskMessage>) {
-    let contents = create_zip(stfs_package, sender);
-    unsafe {
-        download_file(
-            gloo_file::File::new(
-                format!("{}.zip", stfs_package.header.display_name.as_str()).as_str(),
-                contents.as_slice(),
-            )
-            .as_ref(),
-        );
+/// Starts tracking a new background task, replacing any finished one
+fn start_task(
+    background_task: &mut Option<BackgroundTask>,
+    next_task_id: &mut u64,
+    send: &Sender<BackgroundTaskMessage>,
+    name: &str,
+) -> TaskHandle {
+    let id = *next_task_id;
+    *next_task_id += 1;
+
+    let cancel = CancellationToken::new();
+    *background_task = Some(BackgroundTask {
+        id,
+        name: name.to_owned(),
+        progress: 0.0,
+        detail: None,
+        cancel: cancel.clone(),
+        result: None,
+    });
+
+    TaskHandle {
+        id,
+        sender: send.clone(),
+        cancel,
     }
 }
 
-fn human_readable_size(size: usize) -> String {
-    const KB: usize = 1024;
-    const MB: usize = KB * KB;
-    const GB: usize = KB * KB * KB;
+/// Runs `work` against the parsed package, on another thread where threads
+/// are available, and reports its result through `task`
+fn run_task(
+    stfs_package: SharedPackage,
+    task: TaskHandle,
+    work: impl FnOnce(&StfsPackage<'_>, &TaskHandle) -> Result<String, String> + Send + 'static,
+) {
+    let run = move || {
+        let result = work(&stfs_package.package(), &task);
+        task.finish(result);
+    };
+
+    #[cfg(target_arch = "wasm32")]
+    run();
+    #[cfg(not(target_arch = "wasm32"))]
+    std::thread::spawn(run);
+}
 
-    const BYTES_END: usize = KB - 1;
-    const KB_END: usize = MB - 1;
-    const MB_END: usize = GB - 1;
+/// The package's display name, used to name extracted folders and zips
+fn package_display_name(stfs_package: &SharedPackage) -> String {
+    stfs_package.package().header.display_name
+}
 
-    match size {
-        0..=BYTES_END => {
-            format!("{} Bytes", size)
-        }
+/// Extracts every entry to `root`, stopping early if the task is cancelled
+#[cfg(not(target_arch = "wasm32"))]
+fn extract_all<'a>(
+    stfs_package: &'a StfsPackage<'a>,
+    root: &Path,
+    task: &TaskHandle,
+) -> Result<String, String> {
+    let written = stfs_package
+        .extract_all_with_progress(
+            root,
+            &ExtractOptions::default(),
+            |progress: stfs::Progress<'_>| task.report(progress),
+            &task.cancel,
+        )
+        .map_err(|e| task_error("extract", e))?;
+
+    Ok(format!(
+        "Extracted {} files to {}",
+        written.len(),
+        root.display()
+    ))
+}
+
+fn create_zip<'a>(
+    stfs_package: &'a StfsPackage<'a>,
+    compression: ZipCompression,
+    task: &TaskHandle,
+) -> Result<Vec<u8>, String> {
+    let options = ZipOptions {
+        compression,
+        ..Default::default()
+    };
+
+    stfs_package
+        .write_zip(
+            Cursor::new(Vec::new()),
+            &options,
+            |progress: stfs::Progress<'_>| {
+                if let Some(path) = progress.entry {
+                    debug!("Added {:?} to zip", path);
+                }
+                task.report(progress);
+            },
+            &task.cancel,
+        )
+        .map(Cursor::into_inner)
+        .map_err(|e| task_error("create zip", e))
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn save_as_zip<'a>(
+    stfs_package: &'a StfsPackage<'a>,
+    zip_path: &Path,
+    compression: ZipCompression,
+    task: &TaskHandle,
+) -> Result<String, String> {
+    let contents = create_zip(stfs_package, compression, task)?;
+    std::fs::write(zip_path, contents.as_slice())
+        .map_err(|e| format!("Failed to write {}: {}", zip_path.display(), e))?;
+
+    Ok(format!("Saved {}", zip_path.display()))
+}
+
+#[cfg(target_arch = "wasm32")]
+fn save_as_zip<'a>(
+    stfs_package: &'a StfsPackage<'a>,
+    compression: ZipCompression,
+    task: &TaskHandle,
+) -> Result<String, String> {
+    let contents = create_zip(stfs_package, compression, task)?;
+    let zip_name = format!("{}.zip", stfs_package.header.display_name.as_str());
+    download(&zip_name, &contents)
+        .map_err(|e| format!("Failed to download {}: {}", zip_name, e))?;
+
+    Ok(format!("Saved {}", zip_name))
+}
+
+/// Checks every hash in the package, stopping early if the task is cancelled
+fn verify<'a>(stfs_package: &'a StfsPackage<'a>, task: &TaskHandle) -> Result<String, String> {
+    let mismatches = stfs_package
+        .verify_hashes_with_progress(
+            |progress: stfs::Progress<'_>| task.report(progress),
+            &task.cancel,
+        )
+        .map_err(|e| task_error("verify package", e))?;
+
+    if mismatches.is_empty() {
+        Ok("All hashes are valid".to_owned())
+    } else {
+        Err(format!("{} hashes don't match", mismatches.len()))
+    }
+}
+
+/// Shows the folders inside of `folder` as a tree of collapsible headers.
+/// Clicking a folder's name selects it.
+fn folder_tree(
+    ui: &mut egui::Ui,
+    files: &FileTable,
+    folder: EntryId,
+    parent: &Path,
+    selected_folder: &mut Option<PathBuf>,
+) {
+    for &child in files.children(folder) {
+        if files.is_folder(child) {
+            let entry = files.entry(child);
+            let path = parent.join(entry.name.as_str());
+            let selected = selected_folder.as_deref() == Some(path.as_path());
+            let has_subfolders = files
+                .children(child)
+                .iter()
+                .any(|&grandchild| files.is_folder(grandchild));
+
+            if has_subfolders {
+                let id = ui.make_persistent_id(&path);
+                egui::collapsing_header::CollapsingState::load_with_default_open(
+                    ui.ctx(),
+                    id,
+                    false,
+                )
+                .show_header(ui, |ui| {
+                    if ui.selectable_label(selected, entry.name.as_str()).clicked() {
+                        *selected_folder = Some(path.clone());
+                    }
+                })
+                .body(|ui| folder_tree(ui, files, child, &path, selected_folder));
+            } else if ui.selectable_label(selected, entry.name.as_str()).clicked() {
+                *selected_folder = Some(path);
+            }
+        }
+    }
+}
+
+/// Reads one page of `entry` without reading the rest of the file
+fn read_hex_page(
+    stfs_package: &StfsPackage<'_>,
+    entry: &StfsFileEntry,
+    page: usize,
+) -> std::io::Result<Vec<u8>> {
+    let mut reader = stfs_package
+        .file_reader(entry)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+    reader.seek(SeekFrom::Start((page * HEX_PAGE_SIZE) as u64))?;
+
+    let mut bytes = Vec::with_capacity(HEX_PAGE_SIZE);
+    reader.take(HEX_PAGE_SIZE as u64).read_to_end(&mut bytes)?;
+
+    Ok(bytes)
+}
+
+/// Formats `bytes` as rows of 16 hex bytes followed by their ASCII characters
+fn hex_dump(bytes: &[u8], offset: usize) -> Vec<String> {
+    bytes
+        .chunks(HEX_ROW_SIZE)
+        .enumerate()
+        .map(|(row, chunk)| {
+            let hex: String = chunk.iter().map(|b| format!("{:02X} ", b)).collect();
+
+            format!(
+                "{:08X}  {:<48} {}",
+                offset + (row * HEX_ROW_SIZE),
+                hex,
+                ascii_column(chunk)
+            )
+        })
+        .collect()
+}
+
+/// Shows printable ASCII characters in `bytes` and replaces the rest with dots
+fn ascii_column(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|&b| {
+            if b.is_ascii_graphic() || b == b' ' {
+                b as char
+            } else {
+                '.'
+            }
+        })
+        .collect()
+}
+
+/// Decodes `entry` for the image preview if it looks like a PNG, JPEG, or DDS image
+fn read_preview_image(
+    stfs_package: &StfsPackage<'_>,
+    entry: &StfsFileEntry,
+) -> Option<RetainedImage> {
+    if entry.file_size > MAX_IMAGE_PREVIEW_SIZE {
+        return None;
+    }
+
+    let mut bytes = Vec::with_capacity(entry.file_size);
+    stfs_package
+        .file_reader(entry)
+        .ok()?
+        .read_to_end(&mut bytes)
+        .ok()?;
+
+    if stfs::is_dds(&bytes) {
+        let image = stfs::decode_dds(&bytes).ok()?;
+        Some(RetainedImage::from_color_image(
+            entry.name.as_str(),
+            egui::ColorImage::from_rgba_unmultiplied([image.width, image.height], &image.pixels),
+        ))
+    } else if bytes.starts_with(b"\x89PNG") || bytes.starts_with(&[0xFF, 0xD8, 0xFF]) {
+        RetainedImage::from_image_bytes(entry.name.as_str(), &bytes).ok()
+    } else {
+        None
+    }
+}
+
+/// Decodes `entry` for the text preview if it looks like UTF-8 or UTF-16 text
+fn read_preview_text(
+    stfs_package: &StfsPackage<'_>,
+    entry: &StfsFileEntry,
+) -> Option<(&'static str, String)> {
+    if entry.file_size == 0 || entry.file_size > MAX_TEXT_PREVIEW_SIZE {
+        return None;
+    }
+
+    let mut bytes = Vec::with_capacity(entry.file_size);
+    stfs_package
+        .file_reader(entry)
+        .ok()?
+        .read_to_end(&mut bytes)
+        .ok()?;
+
+    decode_text(&bytes)
+}
+
+/// Detects whether `bytes` is UTF-8 or UTF-16 text from its byte order mark,
+/// or failing that, from where its zero bytes are
+fn decode_text(bytes: &[u8]) -> Option<(&'static str, String)> {
+    fn utf16(bytes: &[u8], to_u16: fn([u8; 2]) -> u16) -> Option<String> {
+        if bytes.len() % 2 != 0 {
+            return None;
+        }
+        let units = bytes.chunks_exact(2).map(|unit| to_u16([unit[0], unit[1]]));
+        char::decode_utf16(units)
+            .collect::<Result<String, _>>()
+            .ok()
+    }
+
+    let (encoding, text) = if let Some(rest) = bytes.strip_prefix(&[0xEF, 0xBB, 0xBF]) {
+        ("UTF-8", String::from_utf8(rest.to_vec()).ok()?)
+    } else if let Some(rest) = bytes.strip_prefix(&[0xFF, 0xFE]) {
+        ("UTF-16LE", utf16(rest, u16::from_le_bytes)?)
+    } else if let Some(rest) = bytes.strip_prefix(&[0xFE, 0xFF]) {
+        ("UTF-16BE", utf16(rest, u16::from_be_bytes)?)
+    } else {
+        // ASCII text encoded as UTF-16 has a zero in every other byte
+        let zeros_at = |parity: usize| {
+            bytes
+                .iter()
+                .skip(parity)
+                .step_by(2)
+                .filter(|&&b| b == 0)
+                .count()
+        };
+        let half = bytes.len() / 2;
+        if half > 0 && zeros_at(1) * 4 >= half * 3 {
+            ("UTF-16LE", utf16(bytes, u16::from_le_bytes)?)
+        } else if half > 0 && zeros_at(0) * 4 >= half * 3 {
+            ("UTF-16BE", utf16(bytes, u16::from_be_bytes)?)
+        } else {
+            ("UTF-8", String::from_utf8(bytes.to_vec()).ok()?)
+        }
+    };
+
+    // Strings in save files are often padded with NULs
+    let text = text.trim_end_matches('\0');
+    let is_text = !text.is_empty()
+        && text
+            .chars()
+            .all(|c| !c.is_control() || matches!(c, '\t' | '\n' | '\r'));
+
+    is_text.then(|| (encoding, text.to_owned()))
+}
+
+/// Reads the entire contents of `entry` for the clipboard actions
+fn read_entry(stfs_package: &SharedPackage, entry: &StfsFileEntry) -> Result<Vec<u8>, String> {
+    let parsed_package = stfs_package.package();
+
+    let mut contents = Vec::with_capacity(entry.file_size);
+    parsed_package
+        .extract_file(&mut contents, entry)
+        .map_err(|e| format!("Failed to read {}: {}", entry.name, e))?;
+
+    Ok(contents)
+}
+
+/// Describes who may use a package given its license entries
+fn license_summary(licenses: &[LicenseEntry]) -> &'static str {
+    let has = |ty: LicenseType| licenses.iter().any(|license| license.ty == ty);
+    if has(LicenseType::Unrestricted) {
+        "Usable by any profile on any console"
+    } else if has(LicenseType::ConsoleProfileLicense) || has(LicenseType::WindowsProfileLicense) {
+        "Locked to a profile"
+    } else if has(LicenseType::ConsoleLicense) {
+        "Locked to a console"
+    } else {
+        "No licenses"
+    }
+}
+
+/// Type, bound XUID or console, and flags of a license entry as shown in the
+/// license table
+fn license_columns(license: &LicenseEntry) -> [String; 3] {
+    let bound_to = match license.ty {
+        LicenseType::Unused => String::new(),
+        LicenseType::ConsoleProfileLicense | LicenseType::WindowsProfileLicense => {
+            format!("XUID {:012X}", license.data)
+        }
+        LicenseType::ConsoleLicense => format!("Console {:010X}", license.data),
+        _ => format!("{:012X}", license.data),
+    };
+
+    [
+        license.ty.to_string(),
+        bound_to,
+        format!("{:08X}", license.flags),
+    ]
+}
+
+fn block_state_name(state: BlockState) -> String {
+    match state {
+        BlockState::Allocated => "data".to_owned(),
+        BlockState::Free => "free".to_owned(),
+        BlockState::HashTable(level) => format!("{:?} level hash table", level).to_lowercase(),
+    }
+}
+
+/// Draws every block in the package as a colored cell, highlighting the
+/// selected file's blocks. Returns false once the window has been closed.
+fn show_block_map(
+    ctx: &egui::Context,
+    view: &mut BlockMapView,
+    stfs_package: &StfsPackage<'_>,
+    selected_file: Option<&StfsFileEntry>,
+) -> bool {
+    if view.highlighted.as_ref().map(|(index, _)| *index) != selected_file.map(|file| file.index) {
+        view.highlighted = selected_file.map(|file| {
+            let blocks = stfs_package.block_allocator().file_block_map_indexes(file);
+            (file.index, blocks.into_iter().collect())
+        });
+    }
+
+    let BlockMapView {
+        states,
+        highlighted,
+    } = view;
+    let highlighted = highlighted.as_ref().map(|(_, blocks)| blocks);
+
+    let blocks_per_cell = ((states.len() + MAX_BLOCK_MAP_CELLS - 1) / MAX_BLOCK_MAP_CELLS).max(1);
+    let cell_count = (states.len() + blocks_per_cell - 1) / blocks_per_cell;
+    let rows = (cell_count + BLOCK_MAP_COLUMNS - 1) / BLOCK_MAP_COLUMNS;
+    let cell_blocks =
+        |cell: usize| cell * blocks_per_cell..((cell + 1) * blocks_per_cell).min(states.len());
+
+    let mut open = true;
+    egui::Window::new("Block Map")
+        .open(&mut open)
+        .resizable(false)
+        .show(ctx, |ui| {
+            ui.horizontal(|ui| {
+                let legend = [
+                    (BLOCK_MAP_FILE_COLOR, "Selected file"),
+                    (BLOCK_MAP_DATA_COLOR, "Data"),
+                    (BLOCK_MAP_HASH_COLOR, "Hash table"),
+                    (BLOCK_MAP_FREE_COLOR, "Free"),
+                ];
+                for (color, label) in legend {
+                    let (rect, _) = ui.allocate_exact_size(
+                        egui::vec2(BLOCK_MAP_CELL_SIZE, BLOCK_MAP_CELL_SIZE),
+                        Sense::hover(),
+                    );
+                    ui.painter().rect_filled(rect, 0.0, color);
+                    ui.label(label);
+                }
+            });
+            if blocks_per_cell > 1 {
+                ui.label(format!("Each cell shows {} blocks", blocks_per_cell));
+            }
+
+            let (response, painter) = ui.allocate_painter(
+                egui::vec2(
+                    BLOCK_MAP_COLUMNS as f32 * BLOCK_MAP_CELL_SIZE,
+                    rows as f32 * BLOCK_MAP_CELL_SIZE,
+                ),
+                Sense::hover(),
+            );
+            for cell in 0..cell_count {
+                let blocks = cell_blocks(cell);
+                let cell_states = &states[blocks.clone()];
+                let color = if highlighted.map_or(false, |highlighted| {
+                    blocks.clone().any(|block| highlighted.contains(&block))
+                }) {
+                    BLOCK_MAP_FILE_COLOR
+                } else if cell_states.contains(&BlockState::Allocated) {
+                    BLOCK_MAP_DATA_COLOR
+                } else if cell_states
+                    .iter()
+                    .any(|state| matches!(state, BlockState::HashTable(_)))
+                {
+                    BLOCK_MAP_HASH_COLOR
+                } else {
+                    BLOCK_MAP_FREE_COLOR
+                };
+
+                let min = response.rect.min
+                    + egui::vec2(
+                        (cell % BLOCK_MAP_COLUMNS) as f32 * BLOCK_MAP_CELL_SIZE,
+                        (cell / BLOCK_MAP_COLUMNS) as f32 * BLOCK_MAP_CELL_SIZE,
+                    );
+                painter.rect_filled(
+                    egui::Rect::from_min_size(
+                        min,
+                        egui::vec2(BLOCK_MAP_CELL_SIZE - 1.0, BLOCK_MAP_CELL_SIZE - 1.0),
+                    ),
+                    0.0,
+                    color,
+                );
+            }
+
+            if let Some(position) = response.hover_pos() {
+                let offset = position - response.rect.min;
+                let cell = (offset.y / BLOCK_MAP_CELL_SIZE) as usize * BLOCK_MAP_COLUMNS
+                    + (offset.x / BLOCK_MAP_CELL_SIZE) as usize;
+                if cell < cell_count {
+                    let blocks = cell_blocks(cell);
+                    let text = if blocks.len() == 1 {
+                        format!(
+                            "Block {}: {}",
+                            blocks.start,
+                            block_state_name(states[blocks.start])
+                        )
+                    } else {
+                        format!("Blocks {}-{}", blocks.start, blocks.end - 1)
+                    };
+                    response.on_hover_text(text);
+                }
+            }
+        });
+
+    open
+}
+
+/// Describes an entry in one side of a comparison, or nothing if it's missing
+fn entry_summary_text(summary: Option<&EntrySummary>) -> String {
+    match summary {
+        None => String::new(),
+        Some(EntrySummary::Folder) => "Folder".to_owned(),
+        Some(EntrySummary::File { size, digest }) => {
+            format!(
+                "{} ({})",
+                human_readable_size(*size),
+                hex_string(&digest[..4])
+            )
+        }
+    }
+}
+
+/// Shows the metadata fields and entries which differ between the packages in
+/// `comparison`. Returns false once the window has been closed.
+fn show_comparison(ctx: &egui::Context, comparison: &mut Comparison) -> bool {
+    use egui_extras::{Size, TableBuilder};
+
+    let mut open = true;
+    let Comparison {
+        other_path,
+        diff,
+        show_unchanged,
+    } = comparison;
+
+    egui::Window::new(format!("Compare with {}", other_path.display()))
+        .id(egui::Id::new("comparison_window"))
+        .open(&mut open)
+        .default_size([640.0, 480.0])
+        .show(ctx, |ui| {
+            if !diff.differs() {
+                ui.label("The packages are identical.");
+            }
+
+            if diff.metadata.iter().any(|field| field.differs()) {
+                ui.heading("Metadata");
+                egui::Grid::new("comparison_metadata")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        ui.strong("Field");
+                        ui.strong("This package");
+                        ui.strong("Other package");
+                        ui.end_row();
+
+                        for field in diff.metadata.iter().filter(|field| field.differs()) {
+                            ui.label(field.name);
+                            ui.label(field.a.as_str());
+                            ui.label(field.b.as_str());
+                            ui.end_row();
+                        }
+                    });
+                ui.separator();
+            }
+
+            ui.checkbox(show_unchanged, "Show unchanged files");
+
+            TableBuilder::new(ui)
+                .striped(true)
+                .cell_layout(egui::Layout::left_to_right().with_cross_align(egui::Align::Center))
+                .column(Size::initial(20.0).at_least(20.0))
+                .column(Size::initial(240.0).at_least(60.0))
+                .column(Size::initial(160.0).at_least(60.0))
+                .column(Size::remainder().at_least(60.0))
+                .resizable(true)
+                .header(20.0, |mut header| {
+                    for title in ["", "Path", "This package", "Other package"] {
+                        header.col(|ui| {
+                            ui.heading(title);
+                        });
+                    }
+                })
+                .body(|mut body| {
+                    let visible_entries = diff
+                        .entries
+                        .iter()
+                        .filter(|entry| *show_unchanged || entry.change() != Change::Unchanged);
+                    for entry in visible_entries {
+                        let (marker, color) = match entry.change() {
+                            Change::Added => ("+", egui::Color32::GREEN),
+                            Change::Removed => ("-", egui::Color32::RED),
+                            Change::Modified => ("~", egui::Color32::YELLOW),
+                            Change::Unchanged => ("", egui::Color32::GRAY),
+                        };
+                        let columns = [
+                            marker.to_owned(),
+                            entry.path.clone(),
+                            entry_summary_text(entry.a.as_ref()),
+                            entry_summary_text(entry.b.as_ref()),
+                        ];
+                        body.row(18.0, |mut row| {
+                            for text in columns {
+                                row.col(|ui| {
+                                    ui.colored_label(color, text.as_str());
+                                });
+                            }
+                        });
+                    }
+                });
+        });
+
+    open
+}
+
+/// Shows the gamercard, achievement totals, and play history from a profile's
+/// dashboard GPD, and the achievements from each title's GPD. Returns false once
+/// the window has been closed.
+fn show_profile(ctx: &egui::Context, view: &mut ProfileView) -> bool {
+    use egui_extras::{Size, TableBuilder};
+
+    let mut open = true;
+    let ProfileView {
+        summary:
+            ProfileSummary {
+                settings,
+                totals,
+                titles,
+            },
+        achievements,
+        tab,
+    } = view;
+
+    egui::Window::new("Profile")
+        .open(&mut open)
+        .default_size([640.0, 480.0])
+        .show(ctx, |ui| {
+            let rows = [
+                ("Name:", settings.name.clone()),
+                ("Motto:", settings.motto.clone()),
+                ("Location:", settings.location.clone()),
+                ("URL:", settings.url.clone()),
+                ("Bio:", settings.bio.clone()),
+                (
+                    "Zone:",
+                    settings.gamer_zone.map(|zone| format!("{:?}", zone)),
+                ),
+                (
+                    "Gamerscore:",
+                    settings.gamerscore.map(|score| score.to_string()),
+                ),
+                (
+                    "Reputation:",
+                    settings.reputation.map(|rep| format!("{:.1}%", rep)),
+                ),
+            ];
+            egui::Grid::new("profile_settings").show(ui, |ui| {
+                for (label, value) in rows {
+                    if let Some(value) = value {
+                        ui.label(label);
+                        ui.label(value);
+                        ui.end_row();
+                    }
+                }
+                ui.label("Achievements:");
+                ui.label(format!(
+                    "{} of {} unlocked, {} of {} gamerscore",
+                    totals.achievements_unlocked,
+                    totals.achievement_count,
+                    totals.gamerscore_unlocked,
+                    totals.gamerscore_total
+                ));
+                ui.end_row();
+            });
+            ui.separator();
+
+            ui.horizontal(|ui| {
+                ui.selectable_value(tab, ProfileTab::Titles, "Titles");
+                ui.selectable_value(tab, ProfileTab::Achievements, "Achievements");
+            });
+            ui.separator();
+
+            if *tab == ProfileTab::Achievements {
+                show_title_achievements(ui, titles, achievements);
+                return;
+            }
+
+            TableBuilder::new(ui)
+                .striped(true)
+                .cell_layout(egui::Layout::left_to_right().with_cross_align(egui::Align::Center))
+                .column(Size::initial(80.0).at_least(60.0))
+                .column(Size::initial(200.0).at_least(60.0))
+                .column(Size::initial(100.0).at_least(60.0))
+                .column(Size::initial(100.0).at_least(60.0))
+                .column(Size::remainder().at_least(60.0))
+                .resizable(true)
+                .header(20.0, |mut header| {
+                    for title in [
+                        "Title ID",
+                        "Name",
+                        "Achievements",
+                        "Gamerscore",
+                        "Last Played",
+                    ] {
+                        header.col(|ui| {
+                            ui.heading(title);
+                        });
+                    }
+                })
+                .body(|mut body| {
+                    for title in titles {
+                        let columns = [
+                            title.title_id.to_string(),
+                            title.name.clone(),
+                            format!(
+                                "{} / {}",
+                                title.achievements_unlocked, title.achievement_count
+                            ),
+                            format!("{} / {}", title.gamerscore_unlocked, title.gamerscore_total),
+                            title
+                                .last_played
+                                .map_or_else(|| "Never".to_owned(), |time| time.to_string()),
+                        ];
+                        body.row(18.0, |mut row| {
+                            for text in columns {
+                                row.col(|ui| {
+                                    ui.label(text);
+                                });
+                            }
+                        });
+                    }
+                });
+        });
+
+    open
+}
+
+/// Lists each title's achievements and when they were unlocked, one
+/// collapsible section per title
+fn show_title_achievements(
+    ui: &mut egui::Ui,
+    titles: &[TitleRecord],
+    achievements: &[Result<Vec<Achievement>, String>],
+) {
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        for (title, achievements) in titles.iter().zip(achievements) {
+            let heading = format!(
+                "{} ({}) - {} / {}",
+                title.name, title.title_id, title.achievements_unlocked, title.achievement_count
+            );
+            egui::CollapsingHeader::new(heading)
+                .id_source(("profile_achievements", title.title_id.0))
+                .show(ui, |ui| {
+                    let achievements = match achievements {
+                        Ok(achievements) => achievements,
+                        Err(e) => {
+                            ui.colored_label(egui::Color32::RED, e.as_str());
+                            return;
+                        }
+                    };
+                    if achievements.is_empty() {
+                        ui.label("No achievements");
+                        return;
+                    }
+
+                    egui::Grid::new(("achievement_grid", title.title_id.0))
+                        .striped(true)
+                        .show(ui, |ui| {
+                            for achievement in achievements {
+                                let unlocked =
+                                    match (achievement.is_unlocked(), achievement.unlock_time) {
+                                        (true, Some(time)) => time.to_string(),
+                                        (true, None) => "Unlocked".to_owned(),
+                                        (false, _) => "Locked".to_owned(),
+                                    };
+                                let description = if achievement.is_unlocked() {
+                                    &achievement.unlocked_description
+                                } else {
+                                    &achievement.locked_description
+                                };
+
+                                ui.label(achievement.name.as_str());
+                                ui.label(format!("{} G", achievement.gamerscore));
+                                ui.label(unlocked);
+                                ui.label(description.as_str());
+                                ui.end_row();
+                            }
+                        });
+                });
+        }
+    });
+}
+
+/// Shows the headers of every executable in the package. Returns false once
+/// the window has been closed.
+fn show_executables(ctx: &egui::Context, headers: &[(String, Result<XexInfo, String>)]) -> bool {
+    let mut open = true;
+    egui::Window::new("Executables")
+        .open(&mut open)
+        .default_size([480.0, 360.0])
+        .show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                for (path, info) in headers {
+                    ui.heading(path.as_str());
+                    let info = match info {
+                        Ok(info) => info,
+                        Err(e) => {
+                            ui.colored_label(egui::Color32::RED, format!("Failed to read: {}", e));
+                            continue;
+                        }
+                    };
+
+                    let mut rows = Vec::new();
+                    if let Some(execution) = &info.execution_info {
+                        let title = match execution.title_id.name() {
+                            Some(name) => format!("{} ({})", execution.title_id, name),
+                            None => execution.title_id.to_string(),
+                        };
+                        rows.extend([
+                            ("Title ID:", title),
+                            ("Media ID:", execution.media_id.to_string()),
+                            (
+                                "Version:",
+                                format!("{} (base {})", execution.version, execution.base_version),
+                            ),
+                            (
+                                "Disc:",
+                                format!("{} of {}", execution.disc_number, execution.disc_count),
+                            ),
+                        ]);
+                    }
+                    rows.push(("Module flags:", format!("{:?}", info.module_flags)));
+                    if let Some(address) = info.base_address {
+                        rows.push(("Base address:", format!("{:08X}", address)));
+                    }
+                    if let Some(address) = info.entry_point {
+                        rows.push(("Entry point:", format!("{:08X}", address)));
+                    }
+                    if let Some(name) = &info.original_pe_name {
+                        rows.push(("PE name:", name.clone()));
+                    }
+
+                    egui::Grid::new(("xex_headers", path)).show(ui, |ui| {
+                        for (label, value) in rows {
+                            ui.label(label);
+                            ui.label(value);
+                            ui.end_row();
+                        }
+                    });
+                    ui.collapsing(
+                        format!("{} optional headers", info.optional_headers.len()),
+                        |ui| {
+                            egui::Grid::new(("xex_optional_headers", path))
+                                .striped(true)
+                                .show(ui, |ui| {
+                                    for header in &info.optional_headers {
+                                        ui.monospace(format!("{:08X}", header.key.0));
+                                        ui.label(header.name.unwrap_or("Unknown"));
+                                        ui.label(match (header.value, header.size) {
+                                            (Some(value), _) => format!("{:#X}", value),
+                                            (None, Some(size)) => human_readable_size(size),
+                                            (None, None) => String::new(),
+                                        });
+                                        ui.end_row();
+                                    }
+                                });
+                        },
+                    );
+                    ui.separator();
+                }
+            });
+        });
+
+    open
+}
+
+fn hex_string(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |display_str, b| {
+        display_str + &format!("{:02x}", *b)
+    })
+}
+
+/// Formats the change from `old` to `new` bytes with a leading sign
+fn size_delta(old: usize, new: usize) -> String {
+    if new >= old {
+        format!("+{}", human_readable_size(new - old))
+    } else {
+        format!("-{}", human_readable_size(old - new))
+    }
+}
+
+fn human_readable_size(size: usize) -> String {
+    const KB: usize = 1024;
+    const MB: usize = KB * KB;
+    const GB: usize = KB * KB * KB;
+
+    const BYTES_END: usize = KB - 1;
+    const KB_END: usize = MB - 1;
+    const MB_END: usize = GB - 1;
+
+    match size {
+        0..=BYTES_END => {
+            format!("{} Bytes", size)
+        }
         KB..=KB_END => {
             format!("{} KB", size / KB)
         }
-        MB..=MB_END => {
-            format!("{} MB", size / MB)
+        MB..=MB_END => {
+            format!("{} MB", size / MB)
+        }
+        _default => {
+            format!("{} GB", size / GB)
+        }
+    }
+}
+
+impl OpenPackage {
+    fn new(file_path: PathBuf, stfs_package: SharedPackage) -> Self {
+        let (stfs_package_display_image, stfs_package_title_image, metadata_edits) = {
+            let header = &stfs_package.package().header;
+            (
+                RetainedImage::from_image_bytes("display_image", header.thumbnail_image).ok(),
+                RetainedImage::from_image_bytes("title_image", header.title_image).ok(),
+                EDITABLE_FIELDS
+                    .iter()
+                    .map(|(field, _)| field.get(header))
+                    .collect(),
+            )
+        };
+
+        let mut open_package = OpenPackage {
+            file_path,
+            stfs_package,
+            stfs_package_display_image,
+            stfs_package_title_image,
+            package_files: RefCell::new(Vec::new()),
+            files_loaded: false,
+            selected_folder: None,
+            selected_file: None,
+            hex_page: 0,
+            hex_preview: None,
+            image_preview: None,
+            text_preview: None,
+            metadata_edits,
+            resign_on_save: false,
+            comparison: None,
+            hex_edit: None,
+            block_map: None,
+            profile: None,
+            executables: None,
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            modified: false,
+            file_types_requested: false,
+        };
+        open_package.load_files();
+
+        open_package
+    }
+
+    /// Fills in the file list if the package's file table has been read
+    fn load_files(&mut self) {
+        let parsed_package = self.stfs_package.package();
+        if parsed_package.loaded_files().is_none() {
+            return;
+        }
+
+        let mut package_files: Vec<StfsFileModel> = parsed_package
+            .walk_entries()
+            .into_iter()
+            .filter(|(_, entry)| !entry.is_folder())
+            .map(|(path, entry)| StfsFileModel {
+                name: entry.name.clone(),
+                path: PathBuf::from(path),
+                size: human_readable_size(entry.file_size),
+                entry,
+                file_type: None,
+            })
+            .collect();
+
+        // Sort the package files by their entry ID
+        package_files.sort_by_key(|file| file.entry.index);
+
+        *self.package_files.get_mut() = package_files;
+        self.files_loaded = true;
+    }
+
+    /// Name shown on the package's tab, marked if it has unsaved edits
+    fn tab_title(&self) -> String {
+        let name = self
+            .file_path
+            .file_name()
+            .unwrap_or(self.file_path.as_os_str())
+            .to_string_lossy();
+        if self.modified {
+            format!("{}*", name)
+        } else {
+            name.into_owned()
+        }
+    }
+
+    /// Shows `data` in place of the current package, keeping the tab's
+    /// history and settings. Returns the contents it replaced.
+    fn replace_data(&mut self, data: Vec<u8>) -> Vec<u8> {
+        let previous = self.stfs_package.data().to_vec();
+
+        // Edits are applied on the UI thread, so the file table is read right
+        // away rather than in the background
+        let package = match SharedPackage::new(data) {
+            Ok(package) if package.package().try_files().is_ok() => package,
+            // Every edit is made with the library, which only writes valid
+            // packages, so there's nothing better to show than the original
+            _ => return previous,
+        };
+        let mut reopened = OpenPackage::new(self.file_path.clone(), package);
+        reopened.selected_folder = self.selected_folder.take();
+        reopened.resign_on_save = self.resign_on_save;
+        reopened.undo_stack = std::mem::take(&mut self.undo_stack);
+        reopened.redo_stack = std::mem::take(&mut self.redo_stack);
+        reopened.modified = true;
+        *self = reopened;
+
+        previous
+    }
+
+    fn open_block_map(&mut self) {
+        let states = self.stfs_package.package().block_allocator().block_map();
+        self.block_map = Some(BlockMapView {
+            states,
+            highlighted: None,
+        });
+    }
+
+    /// The file at `path`, if the package's file table has been read
+    fn loaded_file(&self, path: &str) -> Option<StfsFileEntry> {
+        let parsed_package = self.stfs_package.package();
+        let files = parsed_package.loaded_files()?;
+        files.find(path).map(|id| files.entry(id).clone())
+    }
+
+    /// The dashboard GPD, if this is a profile package whose file table has
+    /// been read
+    fn dashboard_gpd(&self) -> Option<StfsFileEntry> {
+        self.loaded_file(DASHBOARD_GPD)
+    }
+
+    /// Reads the achievements from the GPD named after `title`'s ID
+    fn title_achievements(&self, title: &TitleRecord) -> Result<Vec<Achievement>, String> {
+        let name = format!("{:08X}.gpd", title.title_id.0);
+        let entry = self
+            .loaded_file(&name)
+            .ok_or_else(|| format!("{} does not exist in the package", name))?;
+        let data = read_entry(&self.stfs_package, &entry)?;
+        let gpd = Xdbf::try_from(data.as_slice())
+            .map_err(|e| format!("Failed to read {}: {}", name, e))?;
+        let achievements = gpd.achievements().collect::<Result<Vec<_>, _>>();
+        achievements.map_err(|e| format!("Failed to read {}: {}", name, e))
+    }
+
+    fn open_profile(&mut self) -> Result<(), String> {
+        let entry = self
+            .dashboard_gpd()
+            .ok_or_else(|| format!("{} does not exist in the package", DASHBOARD_GPD))?;
+        let data = read_entry(&self.stfs_package, &entry)?;
+        let summary = Xdbf::try_from(data.as_slice())
+            .and_then(|gpd| ProfileSummary::new(&gpd))
+            .map_err(|e| format!("Failed to read {}: {}", DASHBOARD_GPD, e))?;
+        let achievements = summary
+            .titles
+            .iter()
+            .map(|title| self.title_achievements(title))
+            .collect();
+        self.profile = Some(ProfileView {
+            summary,
+            achievements,
+            tab: ProfileTab::Titles,
+        });
+
+        Ok(())
+    }
+
+    /// Whether the package's file table has been read and holds an executable
+    fn has_executables(&self) -> bool {
+        let parsed_package = self.stfs_package.package();
+        parsed_package.loaded_files().is_some() && !parsed_package.executables().is_empty()
+    }
+
+    fn open_executables(&mut self) {
+        let parsed_package = self.stfs_package.package();
+        let headers = parsed_package
+            .executables()
+            .into_iter()
+            .map(|(path, entry)| {
+                let info = parsed_package
+                    .read_xex_headers(&entry)
+                    .and_then(|headers| XexInfo::new(&Xex::try_from(headers.as_slice())?))
+                    .map_err(|e| e.to_string());
+                (path, info)
+            })
+            .collect();
+        self.executables = Some(headers);
+    }
+
+    /// Replaces the package with an edited copy which hasn't been saved yet
+    fn apply_edit(&mut self, description: String, data: Vec<u8>) {
+        let previous = self.replace_data(data);
+        self.undo_stack.push(Revision {
+            description,
+            data: previous,
+        });
+        self.redo_stack.clear();
+    }
+
+    fn undo(&mut self) {
+        if let Some(revision) = self.undo_stack.pop() {
+            let current = self.replace_data(revision.data);
+            self.redo_stack.push(Revision {
+                description: revision.description,
+                data: current,
+            });
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Some(revision) = self.redo_stack.pop() {
+            let current = self.replace_data(revision.data);
+            self.undo_stack.push(Revision {
+                description: revision.description,
+                data: current,
+            });
+        }
+    }
+
+    /// Asks where to save the package and writes it there
+    fn save(&mut self) -> std::io::Result<()> {
+        let saved_path = save_bytes(&self.file_path, self.stfs_package.data())?;
+        if let Some(path) = saved_path {
+            self.file_path = path;
+            self.modified = false;
+        }
+
+        Ok(())
+    }
+
+    /// Shows the metadata, folder, preview, and file list panels for this package
+    fn show(
+        &mut self,
+        ctx: &egui::Context,
+        clipboard: &mut Option<ClipboardContext>,
+        key_vault: Option<&KeyVault>,
+        notifications: &mut Notifications,
+        send: &Sender<BackgroundTaskMessage>,
+    ) {
+        let mut edit = None;
+        let mut save_requested = false;
+
+        let OpenPackage {
+            file_path: _,
+            stfs_package,
+            stfs_package_display_image,
+            stfs_package_title_image,
+            package_files,
+            files_loaded,
+            selected_folder,
+            selected_file,
+            hex_page,
+            hex_preview,
+            image_preview,
+            text_preview,
+            metadata_edits,
+            resign_on_save,
+            comparison,
+            hex_edit,
+            block_map,
+            profile,
+            executables,
+            undo_stack: _,
+            redo_stack: _,
+            modified,
+            file_types_requested,
+        } = self;
+        let stfs_package: &SharedPackage = stfs_package;
+
+        if !*file_types_requested && *files_loaded {
+            *file_types_requested = true;
+            spawn_task(detect_file_types(send.clone(), stfs_package.clone()));
+        }
+
+        if let Some(open_comparison) = comparison.as_mut() {
+            if !show_comparison(ctx, open_comparison) {
+                *comparison = None;
+            }
+        }
+
+        if let Some(view) = block_map.as_mut() {
+            let parsed_package = &stfs_package.package();
+            if !show_block_map(ctx, view, parsed_package, selected_file.as_ref()) {
+                *block_map = None;
+            }
+        }
+
+        if let Some(view) = profile.as_mut() {
+            if !show_profile(ctx, view) {
+                *profile = None;
+            }
+        }
+
+        if let Some(headers) = executables.as_ref() {
+            if !show_executables(ctx, headers) {
+                *executables = None;
+            }
+        }
+
+        egui::SidePanel::left("side_panel").show(ctx, |ui| {
+            ui.heading("STFS Metadata");
+
+            let images = [
+                (ImageKind::Thumbnail, &*stfs_package_display_image),
+                (ImageKind::Title, &*stfs_package_title_image),
+            ];
+            for (kind, image) in images {
+                if let Some(image) = image {
+                    image.show_max_size(ui, ui.available_size());
+                }
+
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(image.is_some(), egui::Button::new("Save PNG…"))
+                        .on_hover_text(format!("Save the {} image", kind.name()))
+                        .clicked()
+                    {
+                        let result = save_bytes(
+                            Path::new(&format!("{}.png", kind.name())),
+                            kind.get(&stfs_package.package().header),
+                        )
+                        .map_err(|e| e.to_string());
+                        if let Err(e) = result {
+                            notifications.error(format!(
+                                "Failed to save the {} image: {}",
+                                kind.name(),
+                                e
+                            ));
+                        }
+                    }
+                    if ui
+                        .button("Replace…")
+                        .on_hover_text(format!("Replace the {} image", kind.name()))
+                        .clicked()
+                    {
+                        spawn_task(pick_header_image(send.clone(), stfs_package.clone(), kind));
+                    }
+                });
+            }
+
+            let parsed_package = &stfs_package.package();
+            for ((field, label), value) in EDITABLE_FIELDS.iter().zip(metadata_edits.iter_mut()) {
+                if *field == MetadataField::DisplayDescription {
+                    ui.label(*label);
+                    ui.text_edit_multiline(value);
+                } else {
+                    ui.horizontal(|ui| {
+                        ui.label(*label);
+                        ui.text_edit_singleline(value);
+                    });
+                }
+            }
+
+            ui.horizontal(|ui| {
+                ui.label("Content Type:");
+                let content_type = parsed_package.header.content_type.to_string();
+                if ui
+                    .add(Label::new(&content_type).sense(Sense::click()))
+                    .double_clicked()
+                {
+                    copy_to_clipboard(clipboard, notifications, content_type);
+                }
+            });
+
+            let licenses = &parsed_package.header.license_data;
+            egui::CollapsingHeader::new("Licenses").show(ui, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(license_summary(licenses));
+                    if ui
+                        .button("Copy")
+                        .on_hover_text("Copy the license table")
+                        .clicked()
+                    {
+                        let table = licenses
+                            .iter()
+                            .map(|license| license_columns(license).join("\t"))
+                            .collect::<Vec<_>>()
+                            .join("\n");
+                        copy_to_clipboard(clipboard, notifications, table);
+                    }
+                });
+
+                egui::Grid::new("license_grid")
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for heading in ["Type", "Bound To", "Flags"] {
+                            ui.strong(heading);
+                        }
+                        ui.end_row();
+
+                        for license in licenses {
+                            for column in license_columns(license) {
+                                if ui
+                                    .add(Label::new(column.as_str()).sense(Sense::click()))
+                                    .double_clicked()
+                                {
+                                    copy_to_clipboard(clipboard, notifications, column);
+                                }
+                            }
+                            ui.end_row();
+                        }
+                    });
+            });
+
+            ui.separator();
+
+            match key_vault {
+                Some(key_vault) => {
+                    let console_id = key_vault
+                        .console_id()
+                        .iter()
+                        .fold(String::new(), |display_str, b| {
+                            display_str + &format!("{:02x}", *b)
+                        });
+                    ui.checkbox(
+                        resign_on_save,
+                        format!("Resign with console {}", console_id),
+                    );
+                }
+                None => {
+                    ui.add_enabled(
+                        false,
+                        egui::Checkbox::new(resign_on_save, "Resign (load a key vault first)"),
+                    );
+                }
+            }
+
+            ui.horizontal(|ui| {
+                if ui
+                    .button("Apply")
+                    .on_hover_text("Apply the edited metadata")
+                    .clicked()
+                {
+                    let resign_key_vault = key_vault.filter(|_| *resign_on_save);
+                    match apply_metadata_edits(stfs_package, metadata_edits, resign_key_vault) {
+                        Ok(data) => edit = Some(("Edit metadata".to_owned(), data)),
+                        Err(e) => notifications.error(format!("Failed to edit metadata: {}", e)),
+                    }
+                }
+                if ui
+                    .add_enabled(*modified, egui::Button::new("Save…"))
+                    .on_hover_text("Save the edited package")
+                    .clicked()
+                {
+                    save_requested = true;
+                }
+            });
+        });
+
+        let parsed_package = &stfs_package.package();
+        egui::SidePanel::left("folder_panel")
+            .resizable(true)
+            .show(ctx, |ui| {
+                ui.heading("Folders");
+
+                egui::ScrollArea::vertical().show(ui, |ui| {
+                    if ui
+                        .selectable_label(selected_folder.is_none(), "All files")
+                        .clicked()
+                    {
+                        *selected_folder = None;
+                    }
+
+                    match parsed_package.loaded_files() {
+                        Some(files) => {
+                            folder_tree(ui, files, FileTable::ROOT, Path::new(""), selected_folder)
+                        }
+                        None => {
+                            ui.spinner();
+                        }
+                    }
+                });
+            });
+
+        if let Some(file) = selected_file.as_ref() {
+            let parsed_package = &stfs_package.package();
+            let page_count = ((file.file_size + HEX_PAGE_SIZE - 1) / HEX_PAGE_SIZE).max(1);
+            let stale = hex_preview.as_ref().map_or(true, |preview| {
+                preview.file_index != file.index || preview.page != *hex_page
+            });
+            if stale {
+                let lines = match read_hex_page(parsed_package, file, *hex_page) {
+                    Ok(bytes) => hex_dump(&bytes, *hex_page * HEX_PAGE_SIZE),
+                    Err(e) => vec![format!("Failed to read {}: {}", file.name, e)],
+                };
+                *hex_preview = Some(HexPreview {
+                    file_index: file.index,
+                    page: *hex_page,
+                    lines,
+                });
+            }
+
+            if image_preview
+                .as_ref()
+                .map_or(true, |preview| preview.file_index != file.index)
+            {
+                *image_preview = Some(ImagePreview {
+                    file_index: file.index,
+                    image: read_preview_image(parsed_package, file),
+                });
+            }
+
+            let image = image_preview
+                .as_ref()
+                .and_then(|preview| preview.image.as_ref());
+
+            if image.is_none()
+                && text_preview
+                    .as_ref()
+                    .map_or(true, |preview| preview.file_index != file.index)
+            {
+                *text_preview = Some(TextPreview {
+                    file_index: file.index,
+                    text: read_preview_text(parsed_package, file),
+                });
+            }
+
+            if let Some(image) = image {
+                egui::SidePanel::right("image_panel")
+                    .resizable(true)
+                    .show(ctx, |ui| {
+                        ui.heading("Preview");
+                        image.show_max_size(ui, ui.available_size());
+                    });
+            } else if let Some((encoding, text)) = text_preview
+                .as_ref()
+                .filter(|preview| preview.file_index == file.index)
+                .and_then(|preview| preview.text.as_ref())
+            {
+                egui::SidePanel::right("text_panel")
+                    .resizable(true)
+                    .default_width(320.0)
+                    .show(ctx, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.heading("Preview");
+                            ui.label(*encoding);
+                        });
+                        egui::ScrollArea::both()
+                            .auto_shrink([false; 2])
+                            .show(ui, |ui| {
+                                ui.add(
+                                    egui::TextEdit::multiline(&mut text.as_str())
+                                        .font(egui::TextStyle::Monospace)
+                                        .desired_width(f32::INFINITY),
+                                );
+                            });
+                    });
+            }
+
+            egui::TopBottomPanel::bottom("hex_panel")
+                .resizable(true)
+                .default_height(200.0)
+                .show(ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        ui.heading(file.name.as_str());
+
+                        if ui
+                            .add_enabled(*hex_page > 0, egui::Button::new("Previous"))
+                            .clicked()
+                        {
+                            *hex_page -= 1;
+                            ctx.request_repaint();
+                        }
+                        ui.label(format!("Page {} of {}", *hex_page + 1, page_count));
+                        if ui
+                            .add_enabled(*hex_page + 1 < page_count, egui::Button::new("Next"))
+                            .clicked()
+                        {
+                            *hex_page += 1;
+                            ctx.request_repaint();
+                        }
+
+                        ui.separator();
+
+                        match hex_edit
+                            .as_mut()
+                            .filter(|edit| edit.file_index == file.index)
+                        {
+                            Some(edit) => {
+                                if ui
+                                    .add_enabled(edit.modified, egui::Button::new("Save…"))
+                                    .on_hover_text("Write the edited file into the package")
+                                    .clicked()
+                                {
+                                    let internal_path = package_files
+                                        .borrow()
+                                        .iter()
+                                        .find(|model| model.entry.index == file.index)
+                                        .map(|model| model.path.to_string_lossy().into_owned());
+                                    if let Some(internal_path) = internal_path {
+                                        send.send(BackgroundTaskMessage::InjectFileRead(
+                                            stfs_package.clone(),
+                                            InjectTarget::Replace(internal_path),
+                                            file.name.clone(),
+                                            edit.contents.clone(),
+                                        ))
+                                        .expect("failed to send edited file to main thread");
+                                    }
+                                }
+                                if ui.button("Discard").clicked() {
+                                    *hex_edit = None;
+                                }
+                            }
+                            None => {
+                                if ui
+                                    .add_enabled(
+                                        file.file_size <= MAX_HEX_EDIT_SIZE,
+                                        egui::Button::new("Edit"),
+                                    )
+                                    .on_disabled_hover_text("The file is too large to edit")
+                                    .clicked()
+                                {
+                                    let mut contents = Vec::with_capacity(file.file_size);
+                                    match parsed_package.extract_file(&mut contents, file) {
+                                        Ok(()) => {
+                                            *hex_edit = Some(HexEdit {
+                                                file_index: file.index,
+                                                contents,
+                                                page: 0,
+                                                rows: Vec::new(),
+                                                modified: false,
+                                            });
+                                        }
+                                        Err(e) => notifications
+                                            .error(format!("Failed to read {}: {}", file.name, e)),
+                                    }
+                                }
+                            }
+                        }
+                    });
+
+                    egui::ScrollArea::vertical()
+                        .auto_shrink([false; 2])
+                        .show(ui, |ui| {
+                            match hex_edit
+                                .as_mut()
+                                .filter(|edit| edit.file_index == file.index)
+                            {
+                                Some(edit) => {
+                                    if edit.page != *hex_page || edit.rows.is_empty() {
+                                        edit.load_page(*hex_page);
+                                    }
+
+                                    let HexEdit {
+                                        file_index,
+                                        contents,
+                                        page,
+                                        rows,
+                                        modified,
+                                    } = edit;
+                                    let page_start = *page * HEX_PAGE_SIZE;
+                                    for (row, text) in rows.iter_mut().enumerate() {
+                                        let offset = page_start + row * HEX_ROW_SIZE;
+                                        let row_len = HEX_ROW_SIZE.min(contents.len() - offset);
+                                        let valid = parse_hex_row(text)
+                                            .map_or(false, |bytes| bytes.len() == row_len);
+
+                                        ui.horizontal(|ui| {
+                                            ui.monospace(format!("{:08X}", offset));
+                                            let response = ui.add(
+                                                egui::TextEdit::singleline(text)
+                                                    .id_source((*file_index, offset))
+                                                    .font(egui::TextStyle::Monospace)
+                                                    .desired_width(360.0)
+                                                    .text_color_opt(
+                                                        (!valid).then(|| egui::Color32::RED),
+                                                    ),
+                                            );
+                                            if response.changed() {
+                                                if let Some(bytes) = parse_hex_row(text)
+                                                    .filter(|bytes| bytes.len() == row_len)
+                                                {
+                                                    contents[offset..offset + row_len]
+                                                        .copy_from_slice(&bytes);
+                                                    *modified = true;
+                                                }
+                                            }
+                                            ui.monospace(ascii_column(
+                                                &contents[offset..offset + row_len],
+                                            ));
+                                        });
+                                    }
+                                }
+                                None => {
+                                    if let Some(preview) = hex_preview {
+                                        for line in &preview.lines {
+                                            ui.monospace(line);
+                                        }
+                                    }
+                                }
+                            }
+                        });
+                });
+        }
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            use egui_extras::{Size, TableBuilder};
+
+            if !*files_loaded {
+                ui.horizontal(|ui| {
+                    ui.spinner();
+                    ui.label("Loading files…");
+                });
+                return;
+            }
+
+            ui.vertical(|ui| {
+                TableBuilder::new(ui)
+                    .striped(true)
+                    .cell_layout(
+                        egui::Layout::left_to_right().with_cross_align(egui::Align::Center),
+                    )
+                    .column(Size::initial(60.0).at_least(40.0))
+                    .column(Size::initial(100.0).at_least(40.0))
+                    .column(Size::initial(60.0).at_least(40.0))
+                    .column(Size::remainder().at_least(60.0))
+                    .resizable(true)
+                    .header(20.0, |mut header| {
+                        header.col(|ui| {
+                            ui.heading("Name");
+                        });
+                        header.col(|ui| {
+                            ui.heading("Type");
+                        });
+                        header.col(|ui| {
+                            ui.heading("Size");
+                        });
+                        header.col(|ui| {
+                            ui.heading("Path");
+                        });
+                    })
+                    .body(|mut body| {
+                        let package_files = package_files.borrow();
+                        let visible_files = package_files.iter().filter(|file| {
+                            selected_folder
+                                .as_ref()
+                                .map_or(true, |folder| file.path.parent() == Some(folder.as_path()))
+                        });
+                        for file in visible_files {
+                            body.row(18.0, |mut row| {
+                                row.col(|ui| {
+                                    let selected =
+                                        selected_file.as_ref().map_or(false, |selected| {
+                                            selected.index == file.entry.index
+                                        });
+                                    if ui.selectable_label(selected, file.name.as_str()).clicked() {
+                                        *selected_file = Some(file.entry.clone());
+                                        *hex_page = 0;
+                                    }
+                                })
+                                .context_menu(|ui| {
+                                    if ui.button("Extract").clicked() {
+                                        let result =
+                                            save_file(file.entry.clone(), &stfs_package.package())
+                                                .map_err(|e| e.to_string());
+                                        if let Err(e) = result {
+                                            notifications.error(format!(
+                                                "Failed to extract {}: {}",
+                                                file.name, e
+                                            ));
+                                        }
+
+                                        ui.close_menu();
+                                    }
+                                    if ui.button("Open in hex view").clicked() {
+                                        *selected_file = Some(file.entry.clone());
+                                        *hex_page = 0;
+
+                                        ui.close_menu();
+                                    }
+                                    if ui.button("Copy path").clicked() {
+                                        copy_to_clipboard(
+                                            clipboard,
+                                            notifications,
+                                            file.path.to_string_lossy().into_owned(),
+                                        );
+
+                                        ui.close_menu();
+                                    }
+                                    if ui.button("Copy SHA-1").clicked() {
+                                        match read_entry(stfs_package, &file.entry) {
+                                            Ok(contents) => copy_to_clipboard(
+                                                clipboard,
+                                                notifications,
+                                                hex_string(&Sha1::digest(&contents)),
+                                            ),
+                                            Err(e) => notifications.error(e),
+                                        }
+
+                                        ui.close_menu();
+                                    }
+                                    if ui
+                                        .add_enabled(
+                                            file.entry.file_size <= MAX_HEX_COPY_SIZE,
+                                            egui::Button::new("Copy as hex"),
+                                        )
+                                        .on_disabled_hover_text("The file is too large to copy")
+                                        .clicked()
+                                    {
+                                        match read_entry(stfs_package, &file.entry) {
+                                            Ok(contents) => copy_to_clipboard(
+                                                clipboard,
+                                                notifications,
+                                                hex_string(&contents),
+                                            ),
+                                            Err(e) => notifications.error(e),
+                                        }
+
+                                        ui.close_menu();
+                                    }
+                                    if ui.button("Replace with…").clicked() {
+                                        spawn_task(pick_injected_file(
+                                            send.clone(),
+                                            stfs_package.clone(),
+                                            InjectTarget::Replace(
+                                                file.path.to_string_lossy().into_owned(),
+                                            ),
+                                        ));
+
+                                        ui.close_menu();
+                                    }
+                                    if ui.button("Delete").clicked() {
+                                        let internal_path =
+                                            file.path.to_string_lossy().into_owned();
+                                        let mut data = stfs_package.data().to_vec();
+                                        match stfs::remove_entry(&mut data, &internal_path) {
+                                            Ok(()) => {
+                                                edit = Some((
+                                                    format!("Delete {}", internal_path),
+                                                    data,
+                                                ))
+                                            }
+                                            Err(e) => notifications.error(format!(
+                                                "Failed to delete {}: {}",
+                                                internal_path, e
+                                            )),
+                                        }
+
+                                        ui.close_menu();
+                                    }
+                                });
+
+                                row.col(|ui| {
+                                    if let Some(file_type) = file.file_type {
+                                        ui.label(format!(
+                                            "{} {}",
+                                            file_type_icon(file_type),
+                                            file_type.name()
+                                        ));
+                                    }
+                                });
+
+                                row.col(|ui| {
+                                    ui.label(file.size.as_str());
+                                });
+
+                                row.col(|ui| {
+                                    ui.label(file.path.to_string_lossy().into_owned());
+                                });
+                            })
+                        }
+                    });
+
+                // ui.with_layout(egui::Layout::bottom_up(egui::Align::LEFT), |ui| {
+            });
+        });
+
+        if let Some((description, data)) = edit {
+            self.apply_edit(description, data);
         }
-        _default => {
-            format!("{} GB", size / GB)
+        if save_requested {
+            if let Err(e) = self.save() {
+                notifications.error(format!("Failed to save package: {}", e));
+            }
         }
     }
 }
@@ -336,96 +2881,162 @@ impl eframe::App for AccelerationApp {
     fn update(&mut self, ctx: &egui::Context, frame: &mut eframe::Frame) {
         let Self {
             active_stfs_file,
-            stfs_package,
-            stfs_package_display_image,
-            stfs_package_title_image,
+            open_packages,
+            active_package,
             clipboard,
             send,
             recv,
-            status_message,
-            package_files,
+            background_task,
+            next_task_id,
+            notifications,
+            key_vault,
+            pending_injection,
+            #[cfg(not(target_arch = "wasm32"))]
+            content_catalog,
         } = self;
 
-        // We open the file on another thread. Check if that thread has sent us any data yet.
-        match recv.try_recv() {
-            Ok(BackgroundTaskMessage::StfsPackageRead(file_path, received_stfs_package)) => {
-                // We have a file!
-                *active_stfs_file = Some(file_path);
-                if let Ok(parsed_package) = received_stfs_package
-                    .read()
-                    .borrow_parsed_stfs_package()
-                    .as_ref()
+        // Files are opened and long tasks run on other threads. Handle anything they've sent us.
+        while let Ok(message) = recv.try_recv() {
+            match message {
+                BackgroundTaskMessage::StfsPackageRead(file_path, received_stfs_package) => {
+                    match received_stfs_package {
+                        Ok(received_stfs_package) => {
+                            // We have a file! Open it in a new tab.
+                            open_packages.push(OpenPackage::new(file_path, received_stfs_package));
+                            *active_package = open_packages.len() - 1;
+                        }
+                        Err(e) => notifications.error(format!(
+                            "Failed to open {}: {}",
+                            file_path.display(),
+                            e
+                        )),
+                    }
+                }
+                BackgroundTaskMessage::KeyVaultRead(received_key_vault) => match received_key_vault
                 {
-                    *stfs_package_display_image = RetainedImage::from_image_bytes(
-                        "display_image",
-                        parsed_package.header.thumbnail_image,
-                    )
-                    .ok();
-
-                    *stfs_package_display_image = RetainedImage::from_image_bytes(
-                        "display_image",
-                        parsed_package.header.title_image,
-                    )
-                    .ok();
-
-                    // Populate the files
-                    let mut path = PathBuf::new();
-                    let mut queue = Vec::with_capacity(256);
-                    if let StfsEntry::Folder { entry: _, files } = &*parsed_package.files.lock() {
-                        queue.extend(std::iter::repeat(0usize).zip(files.iter().cloned()));
-                    }
-
-                    let mut last_depth = 0;
-                    while let Some((depth, file)) = queue.pop() {
-                        if depth < last_depth {
-                            path.pop();
-                            last_depth -= 1;
-                        }
-
-                        let arc_file = file.clone();
-                        let file = file.lock();
-                        if let StfsEntry::File(entry) = &*file {
-                            let mut package_files = package_files.borrow_mut();
-                            package_files.push(StfsFileModel {
-                                name: entry.name.clone(),
-                                path: path.join(entry.name.as_str()),
-                                size: human_readable_size(entry.file_size),
-                                file_ref: arc_file,
+                    Ok(received_key_vault) => {
+                        *key_vault = Some(received_key_vault);
+                        notifications.info("Loaded key vault");
+                    }
+                    Err(e) => notifications.error(format!("Failed to load key vault: {}", e)),
+                },
+                BackgroundTaskMessage::InjectFileRead(
+                    stfs_package,
+                    target,
+                    file_name,
+                    contents,
+                ) => {
+                    *pending_injection =
+                        Some(prepare_injection(stfs_package, target, file_name, contents));
+                }
+                BackgroundTaskMessage::ImageRead(stfs_package, kind, image) => {
+                    if let Err(e) = replace_header_image(
+                        &stfs_package,
+                        kind,
+                        &image,
+                        key_vault.as_ref(),
+                        open_packages,
+                    ) {
+                        notifications.error(format!(
+                            "Failed to replace the {} image: {}",
+                            kind.name(),
+                            e
+                        ));
+                    }
+                }
+                #[cfg(not(target_arch = "wasm32"))]
+                BackgroundTaskMessage::ContentFolderScanned(catalog) => {
+                    if catalog.packages.is_empty() {
+                        notifications
+                            .info(format!("No packages found in {}", catalog.root.display()));
+                    }
+                    *content_catalog = Some(catalog);
+                }
+                BackgroundTaskMessage::FileTableLoaded(stfs_package, loaded) => {
+                    let index = open_packages.iter().position(|open_package| {
+                        SharedPackage::ptr_eq(&open_package.stfs_package, &stfs_package)
+                    });
+                    match (index, loaded) {
+                        (Some(index), Ok(())) => open_packages[index].load_files(),
+                        (Some(index), Err(e)) => {
+                            // Without a file table there's nothing to browse
+                            let closed = open_packages.remove(index);
+                            if *active_package > index || *active_package == open_packages.len() {
+                                *active_package = active_package.saturating_sub(1);
+                            }
+                            notifications.error(format!(
+                                "Failed to open {}: {}",
+                                closed.file_path.display(),
+                                e
+                            ));
+                        }
+                        (None, _) => {}
+                    }
+                }
+                BackgroundTaskMessage::FileTypesRead(stfs_package, file_types) => {
+                    if let Some(open_package) = open_packages.iter().find(|open_package| {
+                        SharedPackage::ptr_eq(&open_package.stfs_package, &stfs_package)
+                    }) {
+                        let mut package_files = open_package.package_files.borrow_mut();
+                        for (index, file_type) in file_types {
+                            if let Some(file) = package_files
+                                .iter_mut()
+                                .find(|file| file.entry.index == index)
+                            {
+                                file.file_type = Some(file_type);
+                            }
+                        }
+                    }
+                }
+                BackgroundTaskMessage::ComparisonRead(stfs_package, other_path, diff) => match diff
+                {
+                    Ok(diff) => {
+                        if let Some(open_package) = open_packages.iter_mut().find(|open_package| {
+                            SharedPackage::ptr_eq(&open_package.stfs_package, &stfs_package)
+                        }) {
+                            open_package.comparison = Some(Comparison {
+                                other_path,
+                                diff,
+                                show_unchanged: false,
                             });
                         }
-
-                        if let StfsEntry::Folder { entry, files } = &*file {
-                            path.push(entry.name.as_str());
-                            queue.extend(std::iter::repeat(depth + 1).zip(files.iter().cloned()));
-                            last_depth += 1;
+                    }
+                    Err(e) => notifications.error(format!(
+                        "Failed to compare with {}: {}",
+                        other_path.display(),
+                        e
+                    )),
+                },
+                BackgroundTaskMessage::TaskProgress(id, progress, detail) => {
+                    if let Some(task) = background_task
+                        .as_mut()
+                        .filter(|task| task.id == id && task.result.is_none())
+                    {
+                        task.progress = progress;
+                        if detail.is_some() {
+                            task.detail = detail;
                         }
                     }
-
-                    // Sort the package files by their entry ID
-                    let mut package_files = package_files.borrow_mut();
-                    package_files.sort_by(|a, b| {
-                        a.file_ref
-                            .lock()
-                            .entry()
-                            .index
-                            .cmp(&b.file_ref.lock().entry().index)
-                    });
                 }
-
-                *stfs_package = Some(received_stfs_package);
-            }
-            Ok(BackgroundTaskMessage::ZipFileUpdate(path)) => {
-                *status_message =
-                    Some(format!("Extracting {}", path.as_os_str().to_str().unwrap()));
-            }
-            Ok(BackgroundTaskMessage::ZipDone) => {
-                *status_message = None;
-            }
-            Err(_) => {
-                // Do nothing
+                BackgroundTaskMessage::TaskDone(id, result) => {
+                    if let Some(task) = background_task
+                        .as_mut()
+                        .filter(|task| task.id == id && task.result.is_none())
+                    {
+                        if let Err(e) = &result {
+                            if e != CANCELLED {
+                                notifications.error(format!("{} failed: {}", task.name, e));
+                            }
+                        }
+                        task.result = Some(result);
+                    }
+                }
             }
         }
 
+        *active_stfs_file = open_packages
+            .get(*active_package)
+            .map(|package| package.file_path.clone());
         if let Some(file_path) = active_stfs_file.as_ref() {
             frame.set_window_title(&format!("acceleration - {:?}", file_path));
         }
@@ -435,62 +3046,183 @@ impl eframe::App for AccelerationApp {
         // Tip: a good default choice is to just keep the `CentralPanel`.
         // For inspiration and more examples, go to https://emilk.github.io/egui
 
+        // Undo and redo edits to the active package, unless a text field is
+        // using the keys for its own undo
+        if !ctx.wants_keyboard_input() {
+            let (undo, redo) = {
+                let input = ctx.input();
+                let command = input.modifiers.command;
+                let shift = input.modifiers.shift;
+                (
+                    command && !shift && input.key_pressed(egui::Key::Z),
+                    command
+                        && ((shift && input.key_pressed(egui::Key::Z))
+                            || input.key_pressed(egui::Key::Y)),
+                )
+            };
+            if let Some(open_package) = open_packages.get_mut(*active_package) {
+                if undo {
+                    open_package.undo();
+                } else if redo {
+                    open_package.redo();
+                }
+            }
+        }
+
         egui::TopBottomPanel::top("top_panel").show(ctx, |ui| {
             // The top panel is often a good place for a menu bar:
             egui::menu::bar(ui, |ui| {
                 ui.menu_button("File", |ui| {
                     if ui.button("Open").clicked() {
-                        let task = open_stfs_package(send.clone());
+                        spawn_task(open_stfs_package(send.clone()));
 
-                        #[cfg(target_arch = "wasm32")]
-                        wasm_bindgen_futures::spawn_local(task);
-                        #[cfg(not(target_arch = "wasm32"))]
-                        std::thread::spawn(move || futures::executor::block_on(task));
+                        ui.close_menu();
+                    }
+                    #[cfg(not(target_arch = "wasm32"))]
+                    if ui.button("Open Content Folder…").clicked() {
+                        if let Some(root) = FileDialog::new().pick_folder() {
+                            spawn_task(scan_content_folder(send.clone(), root));
+                        }
+
+                        ui.close_menu();
+                    }
+                    if ui.button("Load Key Vault").clicked() {
+                        spawn_task(open_key_vault(send.clone()));
 
                         ui.close_menu();
                     }
-                    if let Some(stfs_package) = stfs_package.as_ref() {
+                    if let Some(open_package) = open_packages.get_mut(*active_package) {
+                        if ui
+                            .add_enabled(open_package.modified, egui::Button::new("Save…"))
+                            .clicked()
+                        {
+                            if let Err(e) = open_package.save() {
+                                notifications.error(format!("Failed to save package: {}", e));
+                            }
+
+                            ui.close_menu();
+                        }
+                    }
+                    if let Some(open_package) = open_packages.get(*active_package) {
+                        let stfs_package = &open_package.stfs_package;
+
+                        if ui.button("Add File…").clicked() {
+                            spawn_task(pick_injected_file(
+                                send.clone(),
+                                stfs_package.clone(),
+                                InjectTarget::AddTo(
+                                    open_package.selected_folder.clone().unwrap_or_default(),
+                                ),
+                            ));
+
+                            ui.close_menu();
+                        }
+                        if ui.button("Compare with…").clicked() {
+                            spawn_task(compare_with(send.clone(), stfs_package.clone()));
+
+                            ui.close_menu();
+                        }
+
+                        let task_running = background_task
+                            .as_ref()
+                            .map_or(false, |task| task.result.is_none());
+
                         #[cfg(not(target_arch = "wasm32"))]
-                        if ui.button("Extract All").clicked() {
-                            extract_all(
-                                stfs_package
-                                    .read()
-                                    .borrow_parsed_stfs_package()
-                                    .as_ref()
-                                    .unwrap(),
-                            );
+                        if ui
+                            .add_enabled(!task_running, egui::Button::new("Extract All"))
+                            .clicked()
+                        {
+                            if let Some(root) = FileDialog::new()
+                                .set_file_name(package_display_name(stfs_package).as_str())
+                                .pick_folder()
+                            {
+                                let task =
+                                    start_task(background_task, next_task_id, send, "Extracting");
+                                run_task(stfs_package.clone(), task, move |stfs_package, task| {
+                                    extract_all(stfs_package, &root, task)
+                                });
+                            }
 
                             ui.close_menu();
                         }
-                        if ui.button("Save As Zip").clicked() {
-                            let stfs_package = stfs_package.clone();
-                            let sender = send.clone();
-                            info!("Spawning thread...");
-
-                            #[cfg(target_arch = "wasm32")]
-                            // wasm_bindgen_futures::spawn_local(async move {
-                            save_as_zip(
-                                stfs_package
-                                    .read()
-                                    .borrow_parsed_stfs_package()
-                                    .as_ref()
-                                    .unwrap(),
-                                sender,
-                            );
-                            // });
-
-                            #[cfg(not(target_arch = "wasm32"))]
-                            std::thread::spawn(move || {
-                                save_as_zip(
-                                    stfs_package
-                                        .read()
-                                        .borrow_parsed_stfs_package()
-                                        .as_ref()
-                                        .unwrap(),
-                                    sender,
-                                )
+                        let zip_kinds = [
+                            (
+                                "Save As Zip",
+                                "Compress the files into a zip archive",
+                                ZipCompression::Deflated,
+                            ),
+                            (
+                                "Save As Uncompressed Zip",
+                                "Copy the files into a zip archive as-is, which is much faster",
+                                ZipCompression::Stored,
+                            ),
+                        ];
+                        for (label, hover_text, compression) in zip_kinds {
+                            if ui
+                                .add_enabled(!task_running, egui::Button::new(label))
+                                .on_hover_text(hover_text)
+                                .clicked()
+                            {
+                                info!("Spawning thread...");
+
+                                #[cfg(target_arch = "wasm32")]
+                                {
+                                    let task = start_task(
+                                        background_task,
+                                        next_task_id,
+                                        send,
+                                        "Creating zip",
+                                    );
+                                    run_task(
+                                        stfs_package.clone(),
+                                        task,
+                                        move |stfs_package, task| {
+                                            save_as_zip(stfs_package, compression, task)
+                                        },
+                                    );
+                                }
+
+                                #[cfg(not(target_arch = "wasm32"))]
+                                if let Some(zip_path) = FileDialog::new()
+                                    .set_file_name(
+                                        format!("{}.zip", package_display_name(stfs_package))
+                                            .as_str(),
+                                    )
+                                    .save_file()
+                                {
+                                    let task = start_task(
+                                        background_task,
+                                        next_task_id,
+                                        send,
+                                        "Creating zip",
+                                    );
+                                    run_task(
+                                        stfs_package.clone(),
+                                        task,
+                                        move |stfs_package, task| {
+                                            save_as_zip(stfs_package, &zip_path, compression, task)
+                                        },
+                                    );
+                                }
+
+                                ui.close_menu();
+                            }
+                        }
+                        if ui
+                            .add_enabled(!task_running, egui::Button::new("Verify"))
+                            .clicked()
+                        {
+                            let task = start_task(background_task, next_task_id, send, "Verifying");
+                            run_task(stfs_package.clone(), task, |stfs_package, task| {
+                                verify(stfs_package, task)
                             });
 
+                            ui.close_menu();
+                        }
+                        if ui.button("Close").clicked() {
+                            open_packages.remove(*active_package);
+                            *active_package = active_package.saturating_sub(1);
+
                             ui.close_menu();
                         }
                     }
@@ -498,182 +3230,243 @@ impl eframe::App for AccelerationApp {
                         frame.quit();
                     }
                 });
-            });
-        });
-
-        egui::SidePanel::left("side_panel").show(ctx, |ui| {
-            ui.heading("STFS Metadata");
 
-            if let Some(image) = stfs_package_display_image {
-                image.show_max_size(ui, ui.available_size());
-            }
-
-            if let Some(image) = stfs_package_title_image {
-                image.show_max_size(ui, ui.available_size());
-            }
+                ui.menu_button("View", |ui| {
+                    if let Some(open_package) = open_packages.get_mut(*active_package) {
+                        if ui.button("Block Map").clicked() {
+                            open_package.open_block_map();
 
-            if let Some(stfs_package_ref) = stfs_package.as_ref() {
-                if let Ok(parsed_package) = stfs_package_ref.read().borrow_parsed_stfs_package() {
-                    ui.horizontal(|ui| {
-                        ui.label("Name:");
+                            ui.close_menu();
+                        }
+                        let is_profile = open_package.dashboard_gpd().is_some();
                         if ui
-                            .add(
-                                Label::new(parsed_package.header.display_name.as_str())
-                                    .sense(Sense::click()),
-                            )
-                            .double_clicked()
+                            .add_enabled(is_profile, egui::Button::new("Profile"))
+                            .clicked()
                         {
-                            let _ = clipboard
-                                .set_contents(parsed_package.header.display_name.to_owned());
-                        }
-                    });
+                            if let Err(e) = open_package.open_profile() {
+                                notifications.error(e);
+                            }
 
-                    ui.horizontal(|ui| {
-                        ui.label("Description:");
+                            ui.close_menu();
+                        }
+                        let has_executables = open_package.has_executables();
                         if ui
-                            .add(
-                                Label::new(parsed_package.header.display_description.as_str())
-                                    .wrap(true)
-                                    .sense(Sense::click()),
-                            )
-                            .double_clicked()
+                            .add_enabled(has_executables, egui::Button::new("Executables"))
+                            .clicked()
                         {
-                            let _ = clipboard
-                                .set_contents(parsed_package.header.display_description.to_owned());
+                            open_package.open_executables();
+
+                            ui.close_menu();
                         }
-                    });
+                    }
+                });
 
-                    ui.horizontal(|ui| {
-                        ui.label("Title ID:");
-                        let label_str = format!("{:#X}", parsed_package.header.title_id);
+                ui.menu_button("Edit", |ui| {
+                    let open_package = open_packages.get_mut(*active_package);
+                    let (undo, redo) = match open_package.as_ref() {
+                        Some(open_package) => (
+                            open_package.undo_stack.last(),
+                            open_package.redo_stack.last(),
+                        ),
+                        None => (None, None),
+                    };
+                    let undo_text = undo.map_or("Undo".to_owned(), |revision| {
+                        format!("Undo {}", revision.description)
+                    });
+                    let redo_text = redo.map_or("Redo".to_owned(), |revision| {
+                        format!("Redo {}", revision.description)
+                    });
+                    let (can_undo, can_redo) = (undo.is_some(), redo.is_some());
 
+                    if let Some(open_package) = open_package {
                         if ui
-                            .add(Label::new(&label_str).sense(Sense::click()))
-                            .double_clicked()
+                            .add_enabled(can_undo, egui::Button::new(undo_text))
+                            .clicked()
                         {
-                            let _ = clipboard.set_contents(label_str);
-                        }
-                    });
+                            open_package.undo();
 
-                    ui.horizontal(|ui| {
-                        ui.label("Profile ID:");
-                        let profile_id = parsed_package
-                            .header
-                            .profile_id
-                            .iter()
-                            .fold(String::new(), |display_str, b| {
-                                display_str + &format!("{:02x}", *b)
-                            });
+                            ui.close_menu();
+                        }
                         if ui
-                            .add(Label::new(&profile_id).sense(Sense::click()))
-                            .double_clicked()
+                            .add_enabled(can_redo, egui::Button::new(redo_text))
+                            .clicked()
                         {
-                            let _ = clipboard.set_contents(profile_id);
+                            open_package.redo();
+
+                            ui.close_menu();
                         }
-                    });
+                    }
+                });
+            });
+        });
 
-                    ui.horizontal(|ui| {
-                        ui.label("Console ID:");
-                        let console_id = parsed_package
-                            .header
-                            .console_id
-                            .iter()
-                            .fold(String::new(), |display_str, b| {
-                                display_str + &format!("{:02x}", *b)
-                            });
+        if !open_packages.is_empty() {
+            egui::TopBottomPanel::top("tab_panel").show(ctx, |ui| {
+                ui.horizontal_wrapped(|ui| {
+                    let mut closed_tab = None;
+                    for (index, open_package) in open_packages.iter().enumerate() {
                         if ui
-                            .add(Label::new(&console_id).sense(Sense::click()))
-                            .double_clicked()
+                            .selectable_label(index == *active_package, open_package.tab_title())
+                            .on_hover_text(open_package.file_path.to_string_lossy().into_owned())
+                            .clicked()
                         {
-                            let _ = clipboard.set_contents(console_id);
+                            *active_package = index;
                         }
-                    });
+                        if ui.small_button("x").on_hover_text("Close").clicked() {
+                            closed_tab = Some(index);
+                        }
+                        ui.separator();
+                    }
 
-                    ui.horizontal(|ui| {
-                        ui.label("Content Type:");
-                        let content_type = format!("{:?}", parsed_package.header.content_type);
-                        if ui
-                            .add(Label::new(&content_type).sense(Sense::click()))
-                            .double_clicked()
-                        {
-                            let _ = clipboard.set_contents(content_type);
+                    if let Some(index) = closed_tab {
+                        open_packages.remove(index);
+                        if *active_package > index || *active_package == open_packages.len() {
+                            *active_package = active_package.saturating_sub(1);
                         }
-                    });
-                }
-            }
-        });
+                    }
+                });
+            });
+        }
 
-        egui::CentralPanel::default().show(ctx, |ui| {
-            use egui_extras::{Size, TableBuilder};
+        let mut dismiss_task = false;
+        if let Some(task) = background_task.as_mut() {
+            let mut cancel_task = false;
+            egui::TopBottomPanel::bottom("task_panel").show(ctx, |ui| {
+                ui.horizontal(|ui| {
+                    ui.label(task.name.as_str());
+
+                    match &task.result {
+                        None => {
+                            ui.add(
+                                egui::ProgressBar::new(task.progress)
+                                    .desired_width(200.0)
+                                    .show_percentage(),
+                            );
+                            cancel_task = ui.button("Cancel").clicked();
+                            if let Some(detail) = task.detail.as_ref() {
+                                ui.label(detail);
+                            }
+                        }
+                        Some(result) => {
+                            match result {
+                                Ok(summary) => ui.label(summary),
+                                Err(e) => ui.colored_label(egui::Color32::RED, e),
+                            };
+                            if ui.small_button("Dismiss").clicked() {
+                                dismiss_task = true;
+                            }
+                        }
+                    }
+                });
+            });
 
-            ui.vertical(|ui| {
-                if let Some(status_message) = status_message.as_ref() {
-                    ui.horizontal(|ui| {
-                        // ui.spacing_mut().item_spacing.x = 0.0;
-                        ui.add(Spinner::new());
+            if cancel_task {
+                task.cancel.cancel();
+                task.result = Some(Err(CANCELLED.to_owned()));
+            }
 
-                        ui.label(status_message);
-                    });
-                }
+            // Keep repainting so progress sent from other threads is shown
+            if task.result.is_none() {
+                ctx.request_repaint();
+            }
+        }
+        if dismiss_task {
+            *background_task = None;
+        }
 
-                TableBuilder::new(ui)
-                    .striped(true)
-                    .cell_layout(
-                        egui::Layout::left_to_right().with_cross_align(egui::Align::Center),
-                    )
-                    .column(Size::initial(60.0).at_least(40.0))
-                    .column(Size::initial(60.0).at_least(40.0))
-                    .column(Size::remainder().at_least(60.0))
-                    .resizable(true)
-                    .header(20.0, |mut header| {
-                        header.col(|ui| {
-                            ui.heading("Name");
-                        });
-                        header.col(|ui| {
-                            ui.heading("Size");
-                        });
-                        header.col(|ui| {
-                            ui.heading("Path");
-                        });
-                    })
-                    .body(|mut body| {
-                        if let Some(stfs_package) = stfs_package {
-                            let package_files = package_files.borrow();
-                            for file in &*package_files {
-                                body.row(18.0, |mut row| {
-                                    row.col(|ui| {
-                                        ui.label(file.name.as_str());
-                                    })
-                                    .context_menu(|ui| {
-                                        if ui.button("Extract").clicked() {
-                                            let stfs_package = stfs_package.read();
-                                            save_file(
-                                                file.file_ref.lock().entry().clone(),
-                                                stfs_package
-                                                    .borrow_parsed_stfs_package()
-                                                    .as_ref()
-                                                    .unwrap(),
-                                            );
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(catalog) = content_catalog.as_ref() {
+            if !show_catalog(ctx, catalog, open_packages, active_package, send) {
+                *content_catalog = None;
+            }
+        }
 
-                                            ui.close_menu();
-                                        }
-                                    });
+        match open_packages.get_mut(*active_package) {
+            Some(open_package) => {
+                open_package.show(ctx, clipboard, key_vault.as_ref(), notifications, send)
+            }
+            None => {
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    ui.label("Open a package from the File menu to get started.");
+                });
+            }
+        }
 
-                                    row.col(|ui| {
-                                        ui.label(file.size.as_str());
-                                    });
+        if let Some(injection) = pending_injection.as_mut() {
+            let mut confirmed = None;
+
+            egui::Window::new("Confirm Changes")
+                .collapsible(false)
+                .resizable(false)
+                .show(ctx, |ui| {
+                    match injection.replaced_size {
+                        Some(replaced_size) => ui.label(format!(
+                            "Replace {}: {} → {} ({})",
+                            injection.internal_path,
+                            human_readable_size(replaced_size),
+                            human_readable_size(injection.new_size),
+                            size_delta(replaced_size, injection.new_size),
+                        )),
+                        None => ui.label(format!(
+                            "Add {} ({})",
+                            injection.internal_path,
+                            human_readable_size(injection.new_size),
+                        )),
+                    };
+
+                    match &injection.data {
+                        Ok(data) => {
+                            ui.label(format!(
+                                "Package size: {} → {} ({})",
+                                human_readable_size(injection.old_package_size),
+                                human_readable_size(data.len()),
+                                size_delta(injection.old_package_size, data.len()),
+                            ));
+
+                            ui.add_enabled(
+                                key_vault.is_some(),
+                                egui::Checkbox::new(&mut injection.resign, "Resign with key vault"),
+                            );
+                            if injection.resign && key_vault.is_some() {
+                                ui.label("The package will be rehashed and resigned.");
+                            } else {
+                                ui.label(
+                                    "The package will be rehashed. Its signature will no longer be valid.",
+                                );
+                            }
 
-                                    row.col(|ui| {
-                                        ui.label(file.path.as_os_str().to_str().unwrap());
-                                    });
-                                })
+                            ui.horizontal(|ui| {
+                                if ui.button("Save").clicked() {
+                                    confirmed = Some(true);
+                                }
+                                if ui.button("Cancel").clicked() {
+                                    confirmed = Some(false);
+                                }
+                            });
+                        }
+                        Err(e) => {
+                            ui.colored_label(
+                                egui::Color32::RED,
+                                format!("The file can't be injected: {}", e),
+                            );
+                            if ui.button("Cancel").clicked() {
+                                confirmed = Some(false);
                             }
                         }
-                    });
+                    }
+                });
 
-                // ui.with_layout(egui::Layout::bottom_up(egui::Align::LEFT), |ui| {
-            });
-        });
+            if let Some(confirmed) = confirmed {
+                let injection = pending_injection.take().unwrap();
+                if confirmed {
+                    if let Err(e) = save_injection(injection, key_vault.as_ref(), open_packages) {
+                        notifications.error(format!("Failed to inject file: {}", e));
+                    }
+                }
+            }
+        }
+
+        notifications.show(ctx);
 
         if false {
             egui::Window::new("Window").show(ctx, |ui| {
@@ -11,6 +11,11 @@ fn main() {
     eframe::run_native(
         "acceleration",
         native_options,
-        Box::new(|cc| Box::new(acceleration_ui::AccelerationApp::new(cc))),
+        Box::new(|cc| {
+            let mut app = acceleration_ui::AccelerationApp::new(cc);
+            // Packages to open, e.g. from "Open with" or a file association
+            app.open_paths(std::env::args_os().skip(1).map(std::path::PathBuf::from));
+            Box::new(app)
+        }),
     );
 }